@@ -0,0 +1,14 @@
+use std::collections::BTreeMap;
+
+/// Alias names and their replacement text, defined with `alias` and
+/// expanded by the parser in command position.
+pub type Aliases = BTreeMap<String, String>;
+
+/// Abbreviations defined with `abbr`: words the line editor replaces
+/// with their text when typed in command position, so that the text is
+/// what runs and what history keeps.
+pub type Abbreviations = BTreeMap<String, String>;
+
+/// Keyword macros defined with `keyword`: words the lexer turns into an
+/// operator wherever they appear unquoted, such as `andthen` for `&&`.
+pub type Keywords = BTreeMap<String, &'static str>;
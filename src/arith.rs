@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A value in an arithmetic expression.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+/// Whether expressions are worked out with integers, as in `$(( ))`, or
+/// with floating point numbers, as in `math`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Integer,
+    Float,
+}
+
+impl Number {
+    pub fn as_float(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Float(x) => x,
+        }
+    }
+
+    fn as_int(self) -> i64 {
+        match self {
+            Number::Int(n) => n,
+            Number::Float(x) => x as i64,
+        }
+    }
+
+    pub fn is_true(self) -> bool {
+        self.as_float() != 0.0
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Int(n) => write!(f, "{}", n),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// A parsed expression.
+enum Expr {
+    Number(Number),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `name = value`, or `name op= value` with the binary operator `op`.
+    Assign(String, Option<&'static str>, Box<Expr>),
+    /// `++name` or `--name`, or with `postfix`, `name++` or `name--`.
+    Step(String, &'static str, bool),
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(String),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// Operators, longest first so that `**` isn't read as two `*`.
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "++", "--",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+    "+", "-", "*", "/", "%", "(", ")", "<", ">", "!", "~", "&", "|", "^", "?", ":", "=",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(ch) = rest.chars().next() {
+        let len = if ch.is_ascii_digit() || ch == '.' {
+            let mut len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '.').unwrap_or(rest.len());
+            // The sign of an exponent, as in `1e-3`.
+            if !rest.starts_with("0x") && rest[..len].ends_with(['e', 'E']) && rest[len..].starts_with(['+', '-']) {
+                len += 1 + rest[len + 1..].find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len() - len - 1);
+            }
+            tokens.push(Token::Number(rest[..len].to_owned()));
+            len
+        } else if ch.is_ascii_alphabetic() || ch == '_' {
+            let len = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_owned()));
+            len
+        } else {
+            let op = OPERATORS.iter().find(|op| rest.starts_with(**op))
+                .ok_or_else(|| format!("{}: syntax error: invalid character", rest))?;
+            tokens.push(Token::Operator(op));
+            op.len()
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// The binding power of each binary operator; `**` groups to the right.
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        "**" => 11,
+        _ => return None,
+    })
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    mode: Mode,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.pos += 1;
+        self.tokens.get(self.pos - 1)
+    }
+
+    fn error(&self) -> String {
+        match self.peek() {
+            Some(Token::Number(word) | Token::Name(word)) => format!("syntax error near `{}'", word),
+            Some(Token::Operator(op)) => format!("syntax error near `{}'", op),
+            None => String::from("syntax error: operand expected"),
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.peek() {
+            Some(Token::Operator(found)) if *found == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    /// An assignment to a variable, which groups to the right, or a
+    /// conditional expression.
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let [Token::Name(name), Token::Operator(op), ..] = &self.tokens[self.pos.min(self.tokens.len())..] {
+            if op.ends_with('=') && !matches!(*op, "==" | "!=" | "<=" | ">=") {
+                let name = name.clone();
+                let op = OPERATORS.iter().find(|&&binary| binary.len() + 1 == op.len() && op.starts_with(binary)).copied();
+                self.pos += 2;
+                return Ok(Expr::Assign(name, op, Box::new(self.assignment()?)));
+            }
+        }
+        self.conditional()
+    }
+
+    /// A conditional expression: `a ? b : c`, or a binary one.
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(1)?;
+        if self.peek() != Some(&Token::Operator("?")) {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.assignment()?;
+        self.expect(":")?;
+        let otherwise = self.assignment()?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    /// Binary operators binding at least as tightly as `min`.
+    fn binary(&mut self, min: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(&Token::Operator(op)) = self.peek() {
+            let Some(power) = precedence(op).filter(|&power| power >= min) else {
+                break;
+            };
+            self.pos += 1;
+            let rhs = self.binary(if op == "**" { power } else { power + 1 })?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let mode = self.mode;
+        let error = self.error();
+        match self.next().cloned() {
+            Some(Token::Operator(op @ ("++" | "--"))) => match self.next().cloned() {
+                Some(Token::Name(name)) => Ok(Expr::Step(name, op, false)),
+                _ => Err(error),
+            },
+            Some(Token::Operator(op @ ("+" | "-" | "!" | "~"))) => Ok(Expr::Unary(op, Box::new(self.unary()?))),
+            Some(Token::Operator("(")) => {
+                let expr = self.assignment()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Number(word)) => parse_number(&word, mode).map(Expr::Number),
+            Some(Token::Name(name)) => match self.peek() {
+                Some(&Token::Operator(op @ ("++" | "--"))) => {
+                    self.pos += 1;
+                    Ok(Expr::Step(name, op, true))
+                }
+                _ => Ok(Expr::Variable(name)),
+            },
+            _ => Err(error),
+        }
+    }
+}
+
+/// Parse a numeric literal: decimal or `0x` hexadecimal integers, and in
+/// float mode decimals with a fraction or exponent.
+fn parse_number(word: &str, mode: Mode) -> Result<Number, String> {
+    let int = match word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => word.parse().ok(),
+    };
+    match (int, mode) {
+        (Some(n), Mode::Integer) => Ok(Number::Int(n)),
+        (Some(n), Mode::Float) => Ok(Number::Float(n as f64)),
+        (None, Mode::Float) => word.parse().map(Number::Float).map_err(|_| format!("{}: invalid number", word)),
+        (None, Mode::Integer) => Err(format!("{}: invalid number", word)),
+    }
+}
+
+fn unary(op: &str, value: Number) -> Number {
+    match (op, value) {
+        ("-", Number::Int(n)) => Number::Int(n.wrapping_neg()),
+        ("-", Number::Float(x)) => Number::Float(-x),
+        ("!", value) => Number::Int(!value.is_true() as i64),
+        ("~", value) => Number::Int(!value.as_int()),
+        _ => value,
+    }
+}
+
+fn binary(op: &str, lhs: Number, rhs: Number) -> Result<Number, String> {
+    let truth = |b: bool| Number::Int(b as i64);
+    Ok(match op {
+        "&&" => truth(lhs.is_true() && rhs.is_true()),
+        "||" => truth(lhs.is_true() || rhs.is_true()),
+        "==" => truth(lhs.as_float() == rhs.as_float()),
+        "!=" => truth(lhs.as_float() != rhs.as_float()),
+        "<" => truth(lhs.as_float() < rhs.as_float()),
+        "<=" => truth(lhs.as_float() <= rhs.as_float()),
+        ">" => truth(lhs.as_float() > rhs.as_float()),
+        ">=" => truth(lhs.as_float() >= rhs.as_float()),
+        "&" => Number::Int(lhs.as_int() & rhs.as_int()),
+        "|" => Number::Int(lhs.as_int() | rhs.as_int()),
+        "^" => Number::Int(lhs.as_int() ^ rhs.as_int()),
+        "<<" => Number::Int(lhs.as_int().wrapping_shl(rhs.as_int() as u32)),
+        ">>" => Number::Int(lhs.as_int().wrapping_shr(rhs.as_int() as u32)),
+        _ => match (lhs, rhs) {
+            (Number::Int(a), Number::Int(b)) => Number::Int(match op {
+                "+" => a.wrapping_add(b),
+                "-" => a.wrapping_sub(b),
+                "*" => a.wrapping_mul(b),
+                "/" | "%" if b == 0 => return Err(String::from("division by 0")),
+                "/" => a.wrapping_div(b),
+                "%" => a.wrapping_rem(b),
+                "**" if b < 0 => return Err(String::from("exponent less than 0")),
+                "**" => a.wrapping_pow(b.try_into().unwrap_or(u32::MAX)),
+                _ => unreachable!("unknown operator {}", op),
+            }),
+            (a, b) => {
+                let (a, b) = (a.as_float(), b.as_float());
+                Number::Float(match op {
+                    "+" => a + b,
+                    "-" => a - b,
+                    "*" => a * b,
+                    "/" | "%" if b == 0.0 => return Err(String::from("division by 0")),
+                    "/" => a / b,
+                    "%" => a % b,
+                    "**" => a.powf(b),
+                    _ => unreachable!("unknown operator {}", op),
+                })
+            }
+        },
+    })
+}
+
+/// The variables an expression reads, and those it has assigned so far.
+struct Scope<'a> {
+    mode: Mode,
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    assigned: BTreeMap<String, Number>,
+}
+
+impl Scope<'_> {
+    /// The value of `name`. Unset and empty variables count as 0; others
+    /// must hold a number.
+    fn get(&self, name: &str) -> Result<Number, String> {
+        if let Some(&n) = self.assigned.get(name) {
+            return Ok(n);
+        }
+        match (self.lookup)(name).filter(|value| !value.trim().is_empty()) {
+            Some(value) => parse_number(value.trim(), self.mode),
+            None => Ok(match self.mode {
+                Mode::Integer => Number::Int(0),
+                Mode::Float => Number::Float(0.0),
+            }),
+        }
+    }
+}
+
+/// Evaluate `expr` in `scope`. `&&`, `||` and `?:` only evaluate the
+/// operands they need, so that assignments in the others don't happen.
+fn evaluate(expr: &Expr, scope: &mut Scope) -> Result<Number, String> {
+    match expr {
+        Expr::Number(n) => Ok(*n),
+        Expr::Variable(name) => scope.get(name),
+        Expr::Unary(op, operand) => Ok(unary(op, evaluate(operand, scope)?)),
+        Expr::Binary(op @ ("&&" | "||"), lhs, rhs) => {
+            let lhs = evaluate(lhs, scope)?.is_true();
+            let value = match (*op, lhs) {
+                ("&&", false) => false,
+                ("||", true) => true,
+                _ => evaluate(rhs, scope)?.is_true(),
+            };
+            Ok(Number::Int(value as i64))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = evaluate(lhs, scope)?;
+            binary(op, lhs, evaluate(rhs, scope)?)
+        }
+        Expr::Conditional(condition, then, otherwise) => match evaluate(condition, scope)?.is_true() {
+            true => evaluate(then, scope),
+            false => evaluate(otherwise, scope),
+        },
+        Expr::Assign(name, op, value) => {
+            let value = evaluate(value, scope)?;
+            let value = match op {
+                Some(op) => binary(op, scope.get(name)?, value)?,
+                None => value,
+            };
+            scope.assigned.insert(name.clone(), value);
+            Ok(value)
+        }
+        Expr::Step(name, op, postfix) => {
+            let old = scope.get(name)?;
+            let one = match scope.mode {
+                Mode::Integer => Number::Int(1),
+                Mode::Float => Number::Float(1.0),
+            };
+            let new = binary(&op[..1], old, one)?;
+            scope.assigned.insert(name.clone(), new);
+            Ok(if *postfix { old } else { new })
+        }
+    }
+}
+
+/// Work out the arithmetic expression `expr`, with C's operators and
+/// precedence plus `**` for powers.
+pub fn eval(expr: &str, mode: Mode, lookup: &dyn Fn(&str) -> Option<String>) -> Result<Number, String> {
+    eval_assigning(expr, mode, lookup).map(|(value, _)| value)
+}
+
+/// Like `eval`, but also give back the variables that assignments such
+/// as `x = 1`, `x += 2` and `x++` in `expr` leave changed, with their new
+/// values, for the caller to store.
+pub fn eval_assigning(expr: &str, mode: Mode, lookup: &dyn Fn(&str) -> Option<String>)
+-> Result<(Number, BTreeMap<String, Number>), String> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser { tokens: &tokens, pos: 0, mode };
+    let parsed = parser.assignment()?;
+    if parser.pos < tokens.len() {
+        return Err(parser.error());
+    }
+    let mut scope = Scope { mode, lookup, assigned: BTreeMap::new() };
+    let value = evaluate(&parsed, &mut scope)?;
+    Ok((value, scope.assigned))
+}
@@ -0,0 +1,73 @@
+use std::ffi::CString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::Duration;
+
+use crate::datetime::{self, Zone};
+use crate::json::Value;
+use crate::Shell;
+
+/// Where `set -o audit` records command lines: the file named by
+/// `$RSH_AUDIT_LOG`, or syslog when it is `syslog`. Defaults to
+/// `$XDG_DATA_HOME/rsh/audit.jsonl`, or `~/.local/share/rsh/audit.jsonl`.
+enum Target {
+    File(PathBuf),
+    Syslog,
+}
+
+fn target(shell: &Shell) -> Option<Target> {
+    match shell.vars.get("RSH_AUDIT_LOG").filter(|log| !log.is_empty()) {
+        Some(log) if log == "syslog" => Some(Target::Syslog),
+        Some(log) => Some(Target::File(PathBuf::from(log))),
+        None => crate::data_home(shell).map(|data| Target::File(data.join("rsh/audit.jsonl"))),
+    }
+}
+
+/// Record that `command` ran in `cwd` for `duration`, up to now, and
+/// finished with `status`, as a line of JSON with when it started and who
+/// ran it where.
+pub fn record(shell: &Shell, command: &str, cwd: &Path, status: i32, duration: Duration) {
+    let Some(target) = target(shell) else {
+        return;
+    };
+    let start = shell.now().saturating_sub(duration);
+    let time = datetime::format(start.as_secs() as i64, start.subsec_nanos(), &Zone::Utc, "%Y-%m-%dT%H:%M:%S%.3fZ")
+        .unwrap_or_default();
+    let entry = Value::Object(vec![
+        (String::from("time"), Value::String(time)),
+        (String::from("user"), Value::String(whoami::username())),
+        (String::from("host"), Value::String(whoami::hostname())),
+        (String::from("pid"), Value::Number(std::process::id().to_string())),
+        (String::from("cwd"), Value::String(cwd.to_string_lossy().into_owned())),
+        (String::from("command"), Value::String(command.trim_end_matches('\n').to_owned())),
+        (String::from("status"), Value::Number(status.to_string())),
+        (String::from("duration"), Value::Number(format!("{:.6}", duration.as_secs_f64()))),
+    ]);
+    match target {
+        Target::File(path) => {
+            if let Err(e) = append(&path, &entry.to_string()) {
+                eprintln!("shell: {}: {}", path.display(), e);
+            }
+        }
+        Target::Syslog => syslog(&entry.to_string()),
+    }
+}
+
+fn append(path: &Path, line: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+/// Send `message` to syslog as `rsh[pid]`, at the user facility.
+fn syslog(message: &str) {
+    static OPEN: Once = Once::new();
+    OPEN.call_once(|| unsafe { libc::openlog(c"rsh".as_ptr(), libc::LOG_PID, libc::LOG_USER) });
+    // A NUL can only come from the command line; syslog stops at it.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    unsafe { libc::syslog(libc::LOG_INFO, c"%s".as_ptr(), message.as_ptr()) };
+}
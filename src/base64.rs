@@ -0,0 +1,48 @@
+/// The standard alphabet of RFC 4648.
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `data` with `=` padding.
+pub fn encode(data: &[u8]) -> String {
+    let mut res = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => res.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => res.push('='),
+            }
+        }
+    }
+    res
+}
+
+/// Decode `text`, ignoring whitespace. Padding is optional.
+pub fn decode(text: &str) -> Result<Vec<u8>, String> {
+    let mut res = Vec::with_capacity(text.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    let mut padding = false;
+    for ch in text.chars().filter(|ch| !ch.is_ascii_whitespace()) {
+        if ch == '=' {
+            padding = true;
+            continue;
+        }
+        let value = ALPHABET.iter().position(|&c| c as char == ch)
+            .filter(|_| !padding)
+            .ok_or_else(|| format!("invalid input near `{}'", ch))?;
+        bits = bits << 6 | value as u32;
+        count += 1;
+        if count == 4 {
+            res.extend_from_slice(&bits.to_be_bytes()[1..]);
+            bits = 0;
+            count = 0;
+        }
+    }
+    match count {
+        0 => {}
+        2 => res.push((bits >> 4) as u8),
+        3 => res.extend_from_slice(&((bits >> 2) as u16).to_be_bytes()),
+        _ => return Err(String::from("invalid input: truncated")),
+    }
+    Ok(res)
+}
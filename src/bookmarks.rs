@@ -0,0 +1,46 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+
+use crate::{home_dir, kv};
+
+/// The file bookmarks are kept in across sessions: `$RSH_BOOKMARKS_FILE`,
+/// or `~/.rsh_bookmarks`. It holds one `name<TAB>path` per line, as the
+/// file of `kv` does.
+pub fn path() -> Option<PathBuf> {
+    match std::env::var_os("RSH_BOOKMARKS_FILE") {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => home_dir().map(|home| home.join(".rsh_bookmarks")),
+    }
+}
+
+/// Whether `name` can be a bookmark: a name for `~name` and `:name`,
+/// which can also be kept in the file.
+pub fn is_name(name: &str) -> bool {
+    kv::is_key(name) && !name.contains(['/', ':', '~'])
+}
+
+/// The saved bookmarks; none when there is no file.
+pub fn load() -> io::Result<BTreeMap<String, String>> {
+    match path() {
+        Some(path) => kv::load(&path),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// Save `name` as a bookmark of `dir`, or forget it when there is no
+/// `dir`, leaving the other saved bookmarks as they are. Forgetting a
+/// bookmark that was never saved comes to `false`.
+pub fn store(name: &str, dir: Option<&str>) -> io::Result<bool> {
+    let path = path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME not set"))?;
+    let mut saved = kv::load(&path)?;
+    let changed = match dir {
+        Some(dir) => saved.insert(name.to_owned(), dir.to_owned()).as_deref() != Some(dir),
+        None if saved.remove(name).is_none() => return Ok(false),
+        None => true,
+    };
+    if changed {
+        kv::save(&path, &saved)?;
+    }
+    Ok(true)
+}
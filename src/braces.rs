@@ -0,0 +1,107 @@
+/// The byte positions of the unquoted `{`, `,` and `}` in `word` that
+/// brace expansion looks at. Quotes and backslashes make the characters
+/// after them literal, and `${` starts a parameter rather than a brace.
+fn structure(word: &str) -> Vec<(usize, u8)> {
+    let bytes = word.as_bytes();
+    let mut res = Vec::new();
+    let mut quote = None;
+    let mut i = 0;
+    while i < bytes.len() {
+        match (quote, bytes[i]) {
+            (None, b'\\') | (Some(b'"'), b'\\') => i += 1,
+            (None, q @ (b'\'' | b'"')) => quote = Some(q),
+            (Some(q), c) if c == q => quote = None,
+            (None, b'{') if i > 0 && bytes[i - 1] == b'$' => {
+                // Skip to the end of the parameter.
+                i = word[i..].find('}').map_or(bytes.len(), |end| i + end);
+            }
+            (None, c @ (b'{' | b',' | b'}')) => res.push((i, c)),
+            _ => {}
+        }
+        i += 1;
+    }
+    res
+}
+
+/// The words of the sequence `{start..end[..step]}`, given what is between
+/// the braces: integers, zero-padded to the widest end when either is, or
+/// single letters.
+fn sequence(inner: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = inner.split("..").collect();
+    let (start, end, step) = match parts.as_slice() {
+        [start, end] => (*start, *end, 1),
+        [start, end, step] => (*start, *end, step.parse::<i64>().ok()?.unsigned_abs().max(1) as i64),
+        _ => return None,
+    };
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |n: &str| n.trim_start_matches('-').starts_with('0') && n.trim_start_matches('-').len() > 1;
+        let width = match padded(start) || padded(end) {
+            true => start.len().max(end.len()),
+            false => 0,
+        };
+        let count = first.abs_diff(last) / step as u64 + 1;
+        let step = if first <= last { step } else { -step };
+        return Some((0..count as i64)
+            .map(|i| format!("{:0width$}", first + i * step, width = width))
+            .collect());
+    }
+    let (mut first, mut last) = (start.chars(), end.chars());
+    match (first.next(), first.next(), last.next(), last.next()) {
+        (Some(first), None, Some(last), None) if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() => {
+            let (first, last) = (first as u8 as i64, last as u8 as i64);
+            let count = first.abs_diff(last) / step as u64 + 1;
+            let step = if first <= last { step } else { -step };
+            Some((0..count as i64).map(|i| ((first + i * step) as u8 as char).to_string()).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Expand the braces in `word`: `a{b,c}d` becomes `abd acd` and `{1..3}`
+/// becomes `1 2 3`, with nesting and steps such as `{0..100..10}`. Braces
+/// that are quoted, or hold neither a comma nor a sequence, stay as they
+/// are.
+pub fn expand(word: &str) -> Vec<String> {
+    let marks = structure(word);
+    for (n, &(open, c)) in marks.iter().enumerate() {
+        if c != b'{' {
+            continue;
+        }
+        // Find the matching `}` and the commas directly inside.
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for &(i, c) in &marks[n + 1..] {
+            match c {
+                b'{' => depth += 1,
+                b'}' if depth == 0 => {
+                    close = Some(i);
+                    break;
+                }
+                b'}' => depth -= 1,
+                _ if depth == 0 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+        let alternatives: Vec<String> = match commas.is_empty() {
+            true => match sequence(&word[open + 1..close]) {
+                Some(words) => words,
+                None => continue,
+            },
+            false => {
+                let mut bounds = vec![open];
+                bounds.extend(&commas);
+                bounds.push(close);
+                bounds.windows(2).map(|pair| word[pair[0] + 1..pair[1]].to_owned()).collect()
+            }
+        };
+        let (prefix, suffix) = (&word[..open], &word[close + 1..]);
+        return alternatives.iter()
+            .flat_map(|alternative| expand(&format!("{}{}{}", prefix, alternative, suffix)))
+            .collect();
+    }
+    vec![word.to_owned()]
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::os::fd::OwnedFd;
+
+use crate::builtins::Builtin;
+use crate::plugins::{self, Plugin};
+use crate::Shell;
+
+/// Sets up a `Shell` for a program embedding the interpreter, with only
+/// what it asks for on top of the defaults:
+///
+/// ```no_run
+/// let mut shell = rust_shell::Shell::builder()
+///     .with_env("GREETING", "hello")
+///     .without_job_control()
+///     .build();
+/// let status = shell.eval("echo $GREETING");
+/// assert!(status.success());
+/// ```
+#[derive(Default)]
+pub struct ShellBuilder {
+    builtins: BTreeMap<String, Builtin>,
+    plugins: Vec<Box<dyn Plugin>>,
+    env: Vec<(String, String)>,
+    job_control: bool,
+    deterministic: bool,
+    stdio: [Option<OwnedFd>; 3],
+}
+
+impl ShellBuilder {
+    pub(crate) fn new() -> Self {
+        ShellBuilder { job_control: true, ..Default::default() }
+    }
+
+    /// Add the builtin `name`, replacing any builtin of the shell by that
+    /// name.
+    pub fn with_builtin(mut self, name: &str, builtin: Builtin) -> Self {
+        self.builtins.insert(name.to_owned(), builtin);
+        self
+    }
+
+    /// Add `plugin`, enabled.
+    pub fn with_plugin(mut self, plugin: Box<dyn Plugin>) -> Self {
+        self.plugins.push(plugin);
+        self
+    }
+
+    /// Export the variable `name` with `value` to the shell and the
+    /// commands it runs.
+    pub fn with_env(mut self, name: &str, value: &str) -> Self {
+        self.env.push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Run background commands without announcing them or reporting when
+    /// they finish. `jobs` and `wait` still know about them.
+    pub fn without_job_control(mut self) -> Self {
+        self.job_control = false;
+        self
+    }
+
+    /// Give the commands `fd` (a `File`, a pipe end, ...) as their stdin.
+    pub fn with_stdin(mut self, fd: impl Into<OwnedFd>) -> Self {
+        self.stdio[0] = Some(fd.into());
+        self
+    }
+
+    /// Send what the commands write to their stdout to `fd`.
+    pub fn with_stdout(mut self, fd: impl Into<OwnedFd>) -> Self {
+        self.stdio[1] = Some(fd.into());
+        self
+    }
+
+    /// Send what the commands write to their stderr, and the shell's own
+    /// errors, to `fd`.
+    pub fn with_stderr(mut self, fd: impl Into<OwnedFd>) -> Self {
+        self.stdio[2] = Some(fd.into());
+        self
+    }
+
+    /// Run in deterministic mode, as `Shell::make_deterministic` describes.
+    pub fn deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    pub fn build(self) -> Shell {
+        let mut shell = Shell { builtins: self.builtins, stdio: self.stdio, ..Default::default() };
+        for (name, value) in self.env {
+            shell.vars.export(&name, value);
+        }
+        crate::init_pwd(&mut shell);
+        if !self.job_control {
+            shell.jobs.silence();
+        }
+        if self.deterministic {
+            shell.make_deterministic();
+        }
+        for plugin in self.plugins {
+            plugins::add(&mut shell, plugin);
+        }
+        shell
+    }
+}
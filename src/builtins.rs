@@ -0,0 +1,3316 @@
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, PipeReader, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use nix::sys::signal::{self, Signal};
+use nix::sys::termios::{self, LocalFlags, SetArg};
+use nix::unistd::Pid;
+
+use crate::arith::{self, Mode, Number};
+use crate::completion::CompletionSpec;
+use crate::correct;
+use crate::base64;
+use crate::bookmarks;
+use crate::datetime::{self, Zone};
+use crate::error::ShellError;
+use crate::expand;
+use crate::frecency;
+use crate::history::History;
+use crate::hooks::{self, Event, Kind};
+use crate::jobs::Waited;
+use crate::json::{self as json_value, Value};
+use crate::keymap;
+use crate::kv;
+use crate::options::Options;
+use crate::lexer;
+use crate::parser::RESERVED;
+use crate::plugins;
+use crate::predicates;
+use crate::records;
+use crate::pty::{self as pseudo_terminal, Tee};
+use crate::runner;
+use crate::sha256::Sha256;
+use crate::traps::{self, Condition};
+use crate::trash;
+use crate::variables;
+use crate::exec::{open_redirect, status_code};
+use crate::{home_dir, Flow, PathMatcher, Shell};
+
+/// The streams standing in for a builtin's stdin, stdout and stderr,
+/// after the command's pipes and redirections have been applied.
+pub struct Io<'a> {
+    pub stdin: &'a mut dyn Read,
+    pub stdout: &'a mut dyn Write,
+    pub stderr: &'a mut dyn Write,
+}
+
+/// A command implemented inside the shell process. It receives the full
+/// argv (including its own name) and its output streams, and returns the
+/// exit status.
+pub type Builtin = fn(&mut Shell, &[String], &mut Io) -> i32;
+
+/// Every builtin, by name.
+pub const BUILTINS: &[(&str, Builtin)] = &[
+    (".", source),
+    (":", succeed),
+    ("[", test),
+    ("abbr", abbr),
+    ("alias", alias),
+    ("b64", b64),
+    ("bind", bind),
+    ("bookmark", bookmark),
+    ("break", break_),
+    ("cd", chdir),
+    ("complete", complete),
+    ("continue", continue_),
+    ("hash", hash),
+    ("datetime", datetime),
+    ("declare", declare),
+    ("dirs", dirs),
+    ("disown", disown),
+    ("eval", eval),
+    ("exit", exit),
+    ("export", export),
+    ("false", fail),
+    ("fc", fc),
+    #[cfg(feature = "fetch")]
+    ("fetch", fetch),
+    ("from-json", from_json),
+    ("getopts", getopts),
+    ("glob", glob),
+    ("history", history),
+    ("hook", hook),
+    ("j", jump),
+    ("jobs", jobs),
+    ("json", json),
+    ("keyword", keyword),
+    ("kill", kill),
+    ("kv", kv),
+    ("lastout", lastout),
+    ("let", let_),
+    ("logall", logall),
+    ("logout", logout),
+    ("math", math),
+    ("path", path),
+    ("plugin", plugin),
+    ("popd", popd),
+    ("printf", printf),
+    ("pty", pty),
+    ("pushd", pushd),
+    ("pwd", pwd),
+    ("random", random),
+    ("read", read),
+    ("readonly", readonly),
+    ("return", return_),
+    ("select", select),
+    ("set", set),
+    ("setopt", setopt),
+    ("sha256sum", sha256sum),
+    ("shellinfo", shellinfo),
+    ("shift", shift),
+    ("shopt", shopt),
+    ("sleep", sleep),
+    ("source", source),
+    ("string", string),
+    ("test", test),
+    ("to-table", to_table),
+    ("trap", trap),
+    ("trash", trash),
+    ("true", succeed),
+    ("type", type_of),
+    ("typeset", declare),
+    ("ulimit", ulimit),
+    ("umask", umask),
+    ("unalias", unalias),
+    ("unset", unset),
+    ("unsetopt", setopt),
+    ("uuid", uuid),
+    ("wait", wait),
+    ("where", where_),
+];
+
+/// Look up the builtin registered under `name`.
+pub fn find(name: &str) -> Option<Builtin> {
+    BUILTINS.iter().find(|(builtin, _)| *builtin == name).map(|&(_, builtin)| builtin)
+}
+
+/// Run `builtin` writing to the given redirection targets, or to the
+/// shell's own stdout/stderr where there are none.
+pub fn run(builtin: Builtin, shell: &mut Shell, argv: &[String],
+           stdin: Option<File>, stdout: Option<File>, stderr: Option<File>) -> i32 {
+    let mut stdin: Box<dyn Read> = match stdin {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stdin()),
+    };
+    let mut stdout: Box<dyn Write> = match stdout {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stdout()),
+    };
+    let mut stderr: Box<dyn Write> = match stderr {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stderr()),
+    };
+    let status = builtin(shell, argv, &mut Io { stdin: &mut stdin, stdout: &mut stdout, stderr: &mut stderr });
+    // Output without a final newline would otherwise wait in the buffer
+    // until after whatever runs next.
+    let _ = stdout.flush();
+    status
+}
+
+/// Run `builtin` with its stdout feeding a pipe, returning its status and
+/// the read end. The output is collected in memory first and written by a
+/// separate thread, so a reader that never drains the pipe cannot wedge
+/// the shell.
+pub fn run_into_pipe(builtin: Builtin, shell: &mut Shell, argv: &[String],
+                     stdin: Option<File>, stderr: Option<File>) -> (i32, io::Result<PipeReader>) {
+    let (status, output) = capture(builtin, shell, argv, stdin, stderr);
+    let reader = io::pipe().map(|(reader, mut writer)| {
+        thread::spawn(move || {
+            // The reader going away early is not an error worth reporting.
+            let _ = writer.write_all(&output);
+        });
+        reader
+    });
+    (status, reader)
+}
+
+/// Run `builtin` collecting its stdout in memory, returning its status
+/// and what it wrote.
+pub fn capture(builtin: Builtin, shell: &mut Shell, argv: &[String],
+               stdin: Option<File>, stderr: Option<File>) -> (i32, Vec<u8>) {
+    let mut output: Vec<u8> = Vec::new();
+    let mut stdin: Box<dyn Read> = match stdin {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stdin()),
+    };
+    let mut stderr: Box<dyn Write> = match stderr {
+        Some(file) => Box::new(file),
+        None => Box::new(io::stderr()),
+    };
+    let status = builtin(shell, argv, &mut Io { stdin: &mut stdin, stdout: &mut output, stderr: &mut stderr });
+    (status, output)
+}
+
+/// Map the outcome of writing a builtin's output to its exit status. A
+/// reader that went away is not reported, and the builtin fails as a
+/// program killed by `SIGPIPE` would.
+fn status(res: io::Result<()>) -> i32 {
+    match res {
+        Ok(()) => 0,
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => 128 + libc::SIGPIPE,
+        Err(e) => {
+            eprintln!("shell: write error: {}", e);
+            1
+        }
+    }
+}
+
+/// The directory `path` names under one of the directories of `$CDPATH`,
+/// when it is relative, doesn't start with `.` or `..` and isn't in the
+/// working directory.
+fn search_cdpath(shell: &Shell, path: &Path) -> Option<PathBuf> {
+    let explicit = matches!(path.components().next(), Some(Component::CurDir | Component::ParentDir));
+    if path.is_absolute() || explicit || path.is_dir() {
+        return None;
+    }
+    let cdpath = shell.vars.get("CDPATH")?;
+    cdpath.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join(path))
+        .find(|dir| dir.is_dir())
+}
+
+/// The logical working directory: `$PWD` while it is an absolute path,
+/// free of `.` and `..`, naming the working directory, as it keeps the
+/// symbolic links `cd` went through; otherwise the physical one.
+pub fn logical_cwd(shell: &Shell) -> Option<PathBuf> {
+    let physical = std::env::current_dir().ok()?;
+    let Some(pwd) = shell.vars.get("PWD").map(PathBuf::from) else {
+        return Some(physical);
+    };
+    let clean = pwd.is_absolute()
+        && pwd.components().all(|part| matches!(part, Component::RootDir | Component::Normal(_)));
+    let same = |a: &Path, b: &Path| match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    };
+    match clean && same(&pwd, &physical) {
+        true => Some(pwd),
+        false => Some(physical),
+    }
+}
+
+/// `cd [-L | -P] [dir]` changes to `dir`, or the home directory; `cd -`
+/// goes back to `$OLDPWD` and prints it. A relative `dir` missing from the
+/// working directory is looked for in the directories of `$CDPATH`, and
+/// the one found is printed. With `-L`, the default, `..` undoes the last
+/// component of the logical working directory, and `$PWD` keeps the
+/// symbolic links followed; `-P` resolves them.
+fn chdir(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if restricted(shell, "cd", io) {
+        return 1;
+    }
+    let mut physical = false;
+    let mut args = &argv[1..];
+    while let Some(flag @ ("-L" | "-P")) = args.first().map(String::as_str) {
+        physical = flag == "-P";
+        args = &args[1..];
+    }
+    if args.len() > 1 {
+        let _ = writeln!(io.stderr, "shell: cd: too many arguments");
+        return 1;
+    }
+    let path = if args.is_empty() {
+        match home_dir() {
+            Some(home) => home,
+            None => {
+                let _ = writeln!(io.stderr, "shell: cd: HOME not set");
+                return 1;
+            }
+        }
+    } else if args[0] == "-" {
+        match shell.vars.get("OLDPWD").filter(|dir| !dir.is_empty()) {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                let _ = writeln!(io.stderr, "shell: cd: OLDPWD not set");
+                return 1;
+            }
+        }
+    } else if let Some(dir) = bookmarked(shell, &args[0]) {
+        dir
+    } else {
+        PathBuf::from(&args[0])
+    };
+    let searched = search_cdpath(shell, &path);
+    let path = searched.clone().unwrap_or(path);
+    let old = logical_cwd(shell).unwrap_or_default();
+    let logical = PathBuf::from(normalize(&old.join(&path).to_string_lossy()));
+    // A logical path that doesn't lead anywhere, as when `..` leaves a
+    // symbolic link to a directory that was moved, falls back to the
+    // physical one.
+    let changed = match physical {
+        false => std::env::set_current_dir(&logical).map(|()| true)
+            .or_else(|_| std::env::set_current_dir(&path).map(|()| false)),
+        true => std::env::set_current_dir(&path).map(|()| false),
+    };
+    match changed {
+        Ok(followed) => {
+            let physical = std::env::current_dir().unwrap_or(path);
+            let new = if followed { logical } else { physical };
+            if searched.is_some() || args.first().is_some_and(|arg| arg == "-") {
+                let _ = writeln!(io.stdout, "{}", new.display());
+            }
+            hooks::emit(shell, Event::Chpwd { old: &old, new: &new });
+            0
+        }
+        Err(e) => {
+            if e.kind() == io::ErrorKind::NotFound && shell.options.correct {
+                if let Some(dir) = correct::directory(&path).filter(|dir| correct::confirm(&dir.to_string_lossy())) {
+                    let flag = String::from(if physical { "-P" } else { "-L" });
+                    return chdir(shell, &[String::from("cd"), flag, dir.to_string_lossy().into_owned()], io);
+                }
+            }
+            let _ = writeln!(io.stderr, "shell: cd: {}: {}", path.display(), e);
+            1
+        }
+    }
+}
+
+/// `pwd [-L | -P]` prints the logical working directory, or with `-P`
+/// the physical one, with symbolic links resolved.
+fn pwd(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let mut physical = false;
+    for arg in &argv[1..] {
+        match arg.as_str() {
+            "-L" => physical = false,
+            "-P" => physical = true,
+            _ => {
+                let _ = writeln!(io.stderr, "shell: pwd: usage: pwd [-L | -P]");
+                return 2;
+            }
+        }
+    }
+    let dir = match physical {
+        true => std::env::current_dir(),
+        false => logical_cwd(shell).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound)),
+    };
+    match dir {
+        Ok(dir) => status(writeln!(io.stdout, "{}", dir.display())),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: pwd: {}", e);
+            1
+        }
+    }
+}
+
+/// `source file` (or `. file`) runs the commands in `file` in the current
+/// shell, returning the status of the last one.
+fn source(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let Some(path) = argv.get(1) else {
+        let _ = writeln!(io.stderr, "shell: {}: usage: {} file", argv[0], argv[0]);
+        return 2;
+    };
+    if shell.restricted && path.contains('/') {
+        let _ = writeln!(io.stderr, "shell: {}: {}: restricted", argv[0], path);
+        return 1;
+    }
+    match crate::source(shell, Path::new(path)) {
+        Ok(status) => status,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: {}: {}", path, e);
+            1
+        }
+    }
+}
+
+/// `eval arg...` runs its arguments, joined with spaces, as a command line
+/// of the current shell, and returns its status.
+fn eval(shell: &mut Shell, argv: &[String], _io: &mut Io) -> i32 {
+    shell.run(&argv[1..].join(" "))
+}
+
+/// `declare [-aAirx] name[=value]...` gives variables attributes, then
+/// assigns the values given: `-a` and `-A` make indexed and associative
+/// arrays, `-i` integers, whose assigned values are worked out as
+/// arithmetic, `-r` makes them read-only and `-x` exports them.
+/// `declare -p [name...]`, or `declare` alone, prints variables as the
+/// commands that recreate them. `typeset` is the same builtin.
+fn declare(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let mut flags = String::new();
+    let mut args = argv[1..].iter().peekable();
+    while let Some(arg) = args.next_if(|arg| arg.len() > 1 && arg.starts_with('-')) {
+        flags.push_str(&arg[1..]);
+    }
+    let names: Vec<&String> = args.collect();
+    if flags.contains(|c| !"aAiprx".contains(c)) || (flags.contains('a') && flags.contains('A')) {
+        let _ = writeln!(io.stderr, "shell: {0}: usage: {0} [-a | -A] [-irx] name[=value]... | -p [name...]", argv[0]);
+        return 2;
+    }
+    if flags.contains('p') || names.is_empty() {
+        let all = shell.vars.names();
+        let names = match names.is_empty() {
+            true => all.iter().collect(),
+            false => names,
+        };
+        let mut status = 0;
+        for name in names {
+            match shell.vars.declaration(name) {
+                Some(declaration) => {
+                    let _ = writeln!(io.stdout, "{}", declaration);
+                }
+                None => {
+                    let _ = writeln!(io.stderr, "shell: {}: {}: not found", argv[0], name);
+                    status = 1;
+                }
+            }
+        }
+        return status;
+    }
+    let mut status = 0;
+    for arg in names {
+        let (name, value) = match variables::assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !variables::is_name(name) {
+            let _ = writeln!(io.stderr, "shell: {}: `{}': not a valid identifier", argv[0], arg);
+            status = 1;
+            continue;
+        }
+        if let Err(e) = declare_variable(shell, name, value, &flags) {
+            let _ = writeln!(io.stderr, "{}", e);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Give `name` the attributes of `flags` for `declare`, assigning it
+/// `value` once it is an array or an integer but before it is read-only.
+fn declare_variable(shell: &mut Shell, name: &str, value: Option<&str>, flags: &str) -> Result<(), ShellError> {
+    if value.is_some() || flags.contains(['a', 'A', 'i']) {
+        shell.vars.writable(name)?;
+    }
+    if flags.contains(['a', 'A']) {
+        shell.vars.declare(name, flags.contains('A'))?;
+    }
+    if flags.contains('i') {
+        shell.vars.set_integer(name);
+    }
+    if let Some(value) = value {
+        variables::assign(&mut shell.vars, &shell.options, name, expand::assigned(value))?;
+    }
+    if flags.contains('x') {
+        if let Some(value) = shell.vars.get(name) {
+            shell.vars.export(name, value);
+        }
+    }
+    if flags.contains('r') {
+        shell.vars.set_readonly(name);
+    }
+    Ok(())
+}
+
+/// `readonly name[=value]...` assigns each value given, then keeps the
+/// variables from being assigned or unset again. `readonly` alone, or
+/// `readonly -p`, lists the read-only variables.
+fn readonly(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if matches!(argv, [_] | [_, _]) && argv.get(1).is_none_or(|arg| arg == "-p") {
+        for name in shell.vars.readonly() {
+            let _ = match shell.vars.get(name) {
+                Some(value) => writeln!(io.stdout, "readonly {}={}", name, single_quote(&value)),
+                None => writeln!(io.stdout, "readonly {}", name),
+            };
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for arg in &argv[1..] {
+        let (name, value) = match variables::assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !variables::is_name(name) {
+            let _ = writeln!(io.stderr, "shell: readonly: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value {
+            if let Err(e) = variables::assign(&mut shell.vars, &shell.options, name, expand::assigned(value)) {
+                let _ = writeln!(io.stderr, "{}", e);
+                status = 1;
+                continue;
+            }
+        }
+        shell.vars.set_readonly(name);
+    }
+    status
+}
+
+/// `export [-n] name[=value]...` assigns each value given and exports the
+/// variables to children, or with `-n` stops exporting them. `export`
+/// alone, or `export -p`, lists the exported variables.
+fn export(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let mut args = &argv[1..];
+    let mut remove = false;
+    while let Some(flag @ ("-n" | "-p")) = args.first().map(String::as_str) {
+        remove |= flag == "-n";
+        args = &args[1..];
+    }
+    if args.is_empty() && !remove {
+        let mut exported: Vec<(String, String)> = std::env::vars().collect();
+        exported.sort();
+        for (name, value) in exported {
+            if status(writeln!(io.stdout, "export {}={}", name, single_quote(&value))) != 0 {
+                return 1;
+            }
+        }
+        return 0;
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match variables::assignment(arg) {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !variables::is_name(name) {
+            let _ = writeln!(io.stderr, "shell: export: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value {
+            if let Err(e) = variables::assign(&mut shell.vars, &shell.options, name, expand::assigned(value)) {
+                let _ = writeln!(io.stderr, "{}", e);
+                status = 1;
+                continue;
+            }
+        }
+        if remove {
+            shell.vars.unexport(name);
+        } else if shell.vars.is_array(name) {
+            let _ = writeln!(io.stderr, "shell: export: {}: cannot export an array", name);
+            status = 1;
+        } else if let Some(value) = shell.vars.get(name) {
+            shell.vars.export(name, value);
+        }
+    }
+    status
+}
+
+/// `exit [n]` leaves the shell with status `n`, or that of the last
+/// command, after running the `EXIT` trap.
+fn exit(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let status = match argv {
+        [_] => shell.vars.status,
+        [_, n] => match n.parse::<i64>() {
+            Ok(n) => (n & 0xff) as i32,
+            Err(_) => {
+                let _ = writeln!(io.stderr, "shell: exit: {}: numeric argument required", n);
+                2
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "shell: exit: too many arguments");
+            return 1;
+        }
+    };
+    if !crate::may_exit(shell) {
+        return 1;
+    }
+    let _ = io.stdout.flush();
+    crate::exit(shell, status)
+}
+
+/// `bind '"keys": function'` makes the keys run an editing function, and
+/// `bind '"keys": "text"'` makes them type the text, accepting the line
+/// when it ends in `\n`. Keys are written as in readline's inputrc:
+/// `\C-g` is Control-g and `\M-x` or `\ex` Meta-x. `bind` alone, or
+/// `bind -p`, lists the bindings; `bind -l` lists the functions and
+/// `bind -r keys` removes a binding.
+fn bind(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match argv.get(1).map(String::as_str) {
+        None | Some("-p") if argv.len() <= 2 => status(shell.keymap.iter().try_for_each(|(keys, action)| {
+            match action {
+                keymap::Action::Function(name) => writeln!(io.stdout, "\"{}\": {}", keymap::format_keys(keys), name),
+                keymap::Action::Macro(text) => {
+                    writeln!(io.stdout, "\"{}\": \"{}\"", keymap::format_keys(keys), keymap::format_macro(text))
+                }
+            }
+        })),
+        Some("-l") if argv.len() == 2 => status(keymap::functions().iter().try_for_each(|name| writeln!(io.stdout, "{}", name))),
+        Some("-r") if argv.len() > 2 => {
+            let mut result = 0;
+            for text in &argv[2..] {
+                match keymap::parse_sequence(text) {
+                    Ok(keys) if shell.keymap.unbind(&keys) => {}
+                    Ok(_) => {
+                        let _ = writeln!(io.stderr, "shell: bind: {}: not bound", text);
+                        result = 1;
+                    }
+                    Err(e) => {
+                        let _ = writeln!(io.stderr, "shell: bind: {}", e);
+                        result = 1;
+                    }
+                }
+            }
+            result
+        }
+        Some(flag) if flag.starts_with('-') => {
+            let _ = writeln!(io.stderr, "shell: bind: usage: bind [-lp] [-r keys] ['\"keys\": function' | '\"keys\": \"text\"' ...]");
+            2
+        }
+        _ => {
+            let mut result = 0;
+            for text in &argv[1..] {
+                match keymap::parse_binding(text) {
+                    Ok((keys, action)) => shell.keymap.bind(keys, action),
+                    Err(e) => {
+                        let _ = writeln!(io.stderr, "shell: bind: {}", e);
+                        result = 1;
+                    }
+                }
+            }
+            result
+        }
+    }
+}
+
+/// `break [n]` leaves the innermost loop, or `n` loops out from it.
+fn break_(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match loop_count(shell, argv, io) {
+        Ok(n) => {
+            shell.flow = Some(Flow::Break(n));
+            0
+        }
+        Err(status) => status,
+    }
+}
+
+/// `continue [n]` starts the next round of the innermost loop, or of the
+/// loop `n` out from it, leaving those inside that one.
+fn continue_(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match loop_count(shell, argv, io) {
+        Ok(n) => {
+            shell.flow = Some(Flow::Continue(n));
+            0
+        }
+        Err(status) => status,
+    }
+}
+
+/// The `n` of `break [n]` or `continue [n]`, at most the number of loops
+/// the command is in.
+fn loop_count(shell: &Shell, argv: &[String], io: &mut Io) -> Result<usize, i32> {
+    let n = match argv {
+        [_] => 1,
+        [_, n] => match n.parse::<usize>() {
+            Ok(n) if n > 0 => n,
+            _ => {
+                let _ = writeln!(io.stderr, "shell: {}: {}: loop count out of range", argv[0], n);
+                return Err(1);
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "shell: {}: usage: {} [n]", argv[0], argv[0]);
+            return Err(2);
+        }
+    };
+    if shell.loops == 0 {
+        let _ = writeln!(io.stderr, "shell: {}: only meaningful in a `for' or `select' loop", argv[0]);
+        return Err(1);
+    }
+    Ok(n.min(shell.loops))
+}
+
+/// `return [n]` leaves the file being sourced, making `n`, or the status
+/// of the last command, that of `source`.
+fn return_(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let status = match argv {
+        [_] => shell.vars.status,
+        [_, n] => match n.parse::<i64>() {
+            Ok(n) => (n & 0xff) as i32,
+            Err(_) => {
+                let _ = writeln!(io.stderr, "shell: return: {}: numeric argument required", n);
+                return 2;
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "shell: return: too many arguments");
+            return 1;
+        }
+    };
+    if shell.sourcing == 0 {
+        let _ = writeln!(io.stderr, "shell: return: can only `return' from a sourced script");
+        return 1;
+    }
+    shell.flow = Some(Flow::Return);
+    status
+}
+
+/// `logout [n]` leaves a login shell as `exit [n]` does.
+fn logout(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if !shell.login {
+        let _ = writeln!(io.stderr, "shell: logout: not login shell: use `exit'");
+        return 1;
+    }
+    exit(shell, argv, io)
+}
+
+/// `hook event command` runs `command` on each `event` (`startup`,
+/// `preprompt`, `preexec`, `postexec`, `chpwd` or `exit`); `hook - event`
+/// drops the commands of `event`; `hook` alone lists them all.
+fn hook(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match argv {
+        [_] => {
+            for (kind, command) in shell.hooks.commands() {
+                let _ = writeln!(io.stdout, "hook {} {}", kind.name(), single_quote(command));
+            }
+            0
+        }
+        [_, event, command] => {
+            let remove = event == "-";
+            let name = if remove { command } else { event };
+            let Some(kind) = Kind::parse(name) else {
+                let _ = writeln!(io.stderr, "shell: hook: {}: invalid event", name);
+                return 1;
+            };
+            if remove {
+                shell.hooks.remove_commands(kind);
+            } else {
+                shell.hooks.add_command(kind, command.clone());
+            }
+            0
+        }
+        _ => {
+            let _ = writeln!(io.stderr, "shell: hook: usage: hook [event command | - event]");
+            2
+        }
+    }
+}
+
+/// `complete [-f] [-d] [-W words] name...` sets how Tab completes the
+/// arguments of the commands named: with the words of `-W`, file names
+/// (`-f`) or directory names (`-d`). `complete -r [name...]` removes the
+/// specs of the names, or all of them. `complete -p [name...]`, or
+/// `complete` alone, prints the specs as `complete` commands, which can be
+/// saved to a file and sourced from `~/.rshrc` elsewhere.
+fn complete(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr, "shell: complete: usage: complete [-f] [-d] [-W words] name... | -p|-r [name...]");
+        2
+    };
+    let mut spec = CompletionSpec::default();
+    let (mut print, mut remove) = (argv.len() == 1, false);
+    let mut args = argv[1..].iter();
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => names.extend(args.by_ref()),
+            "-W" => match args.next() {
+                Some(words) => spec.words.extend(words.split_whitespace().map(str::to_owned)),
+                None => return usage(io),
+            },
+            flags if flags.starts_with('-') && flags.len() > 1 => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'f' => spec.files = true,
+                        'd' => spec.dirs = true,
+                        'p' => print = true,
+                        'r' => remove = true,
+                        _ => return usage(io),
+                    }
+                }
+            }
+            _ => names.push(arg),
+        }
+    }
+    if print || remove {
+        if print && remove || spec != CompletionSpec::default() {
+            return usage(io);
+        }
+        let mut res = 0;
+        for name in &names {
+            if !shell.completions.contains_key(*name) {
+                let _ = writeln!(io.stderr, "shell: complete: {}: no completion specification", name);
+                res = 1;
+            }
+        }
+        if remove {
+            match names.is_empty() {
+                true => shell.completions.clear(),
+                false => shell.completions.retain(|name, _| !names.contains(&name)),
+            }
+            return res;
+        }
+        let printed = shell.completions.iter()
+            .filter(|(name, _)| names.is_empty() || names.contains(name))
+            .try_for_each(|(name, spec)| {
+                let mut line = String::from("complete");
+                if spec.files {
+                    line.push_str(" -f");
+                }
+                if spec.dirs {
+                    line.push_str(" -d");
+                }
+                if !spec.words.is_empty() {
+                    line.push_str(" -W ");
+                    line.push_str(&single_quote(&spec.words.join(" ")));
+                }
+                writeln!(io.stdout, "{} {}", line, name)
+            });
+        return match status(printed) {
+            0 => res,
+            failed => failed,
+        };
+    }
+    if names.is_empty() {
+        return usage(io);
+    }
+    for name in names {
+        shell.completions.insert(name.clone(), spec.clone());
+    }
+    0
+}
+
+/// `datetime [-u | -z zone] [-d seconds] [+format]` prints the current
+/// time, or `seconds` after the epoch, in the local zone, UTC or `zone`
+/// (e.g. `Asia/Tokyo`). The format defaults to RFC 3339.
+fn datetime(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr, "shell: datetime: usage: datetime [-u | -z zone] [-d seconds] [+format]");
+        2
+    };
+    let mut zone = Zone::Local;
+    let mut time = None;
+    let mut format = "%Y-%m-%dT%H:%M:%S%:z";
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-u" => zone = Zone::Utc,
+            "-z" => match args.next() {
+                Some(name) => match Zone::named(name) {
+                    Ok(named) => zone = named,
+                    Err(e) => {
+                        let _ = writeln!(io.stderr, "shell: datetime: {}", e);
+                        return 1;
+                    }
+                },
+                None => return usage(io),
+            },
+            "-d" => match args.next().and_then(|seconds| seconds.parse::<f64>().ok()) {
+                Some(seconds) => time = Some(seconds),
+                None => return usage(io),
+            },
+            _ => match arg.strip_prefix('+') {
+                Some(spec) => format = spec,
+                None => return usage(io),
+            },
+        }
+    }
+    let (secs, nanos) = match time {
+        Some(seconds) => (seconds.floor() as i64, ((seconds - seconds.floor()) * 1e9) as u32),
+        None => {
+            let now = shell.now();
+            (now.as_secs() as i64, now.subsec_nanos())
+        }
+    };
+    match datetime::format(secs, nanos, &zone, format) {
+        Ok(text) => status(writeln!(io.stdout, "{}", text)),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: datetime: {}", e);
+            1
+        }
+    }
+}
+
+/// Entry `index` of the directory stack: the working directory for 0,
+/// then the directories `pushd` left, most recent first.
+pub fn dir_stack_entry(shell: &Shell, index: usize) -> Option<PathBuf> {
+    match index {
+        0 => logical_cwd(shell),
+        _ => shell.dir_stack.get(index - 1).cloned(),
+    }
+}
+
+/// `dir` with the home directory shown as `~`.
+fn abbreviate(dir: &Path) -> String {
+    match home_dir().and_then(|home| dir.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(rest) if rest.as_os_str().is_empty() => String::from("~"),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => dir.display().to_string(),
+    }
+}
+
+/// Print the directory stack on one line, as `dirs` does.
+fn print_dir_stack(shell: &Shell, io: &mut Io) -> i32 {
+    let dirs: Vec<String> = (0..=shell.dir_stack.len())
+        .filter_map(|index| dir_stack_entry(shell, index))
+        .map(|dir| abbreviate(&dir))
+        .collect();
+    status(writeln!(io.stdout, "{}", dirs.join(" ")))
+}
+
+/// `dirs` prints the directory stack, starting with the working
+/// directory; `dirs -v` prints one entry per line with the number to use
+/// in `~N`; `dirs -c` empties it.
+fn dirs(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match argv.get(1).map(String::as_str) {
+        None => print_dir_stack(shell, io),
+        Some("-v") => status((0..=shell.dir_stack.len())
+            .filter_map(|index| Some((index, dir_stack_entry(shell, index)?)))
+            .try_for_each(|(index, dir)| writeln!(io.stdout, "{:2}  {}", index, abbreviate(&dir)))),
+        Some("-c") => {
+            shell.dir_stack.clear();
+            0
+        }
+        Some(_) => {
+            let _ = writeln!(io.stderr, "shell: dirs: usage: dirs [-c | -v]");
+            2
+        }
+    }
+}
+
+/// Whether the shell is restricted, which keeps `builtin` from changing
+/// the working directory; reports it if so.
+fn restricted(shell: &Shell, builtin: &str, io: &mut Io) -> bool {
+    if shell.restricted {
+        let _ = writeln!(io.stderr, "shell: {}: restricted", builtin);
+    }
+    shell.restricted
+}
+
+/// `pushd dir` changes to `dir`, pushing the old working directory onto
+/// the stack; `pushd` alone swaps the working directory with the top of
+/// the stack. Prints the stack afterwards.
+fn pushd(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if restricted(shell, "pushd", io) {
+        return 1;
+    }
+    let old = match logical_cwd(shell) {
+        Some(dir) => dir,
+        None => {
+            let _ = writeln!(io.stderr, "shell: pushd: cannot determine the working directory");
+            return 1;
+        }
+    };
+    let (dir, swap) = match argv {
+        [_] => match shell.dir_stack.first() {
+            Some(dir) => (dir.to_string_lossy().into_owned(), true),
+            None => {
+                let _ = writeln!(io.stderr, "shell: pushd: no other directory");
+                return 1;
+            }
+        },
+        [_, dir] => (dir.clone(), false),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: pushd: usage: pushd [dir]");
+            return 2;
+        }
+    };
+    let status = chdir(shell, &[String::from("cd"), dir], io);
+    if status != 0 {
+        return status;
+    }
+    if swap {
+        shell.dir_stack[0] = old;
+    } else {
+        shell.dir_stack.insert(0, old);
+    }
+    print_dir_stack(shell, io)
+}
+
+/// `popd` changes to the directory on top of the stack and removes it,
+/// then prints the stack.
+fn popd(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if restricted(shell, "popd", io) {
+        return 1;
+    }
+    if argv.len() > 1 {
+        let _ = writeln!(io.stderr, "shell: popd: usage: popd");
+        return 2;
+    }
+    let Some(dir) = shell.dir_stack.first() else {
+        let _ = writeln!(io.stderr, "shell: popd: directory stack empty");
+        return 1;
+    };
+    let status = chdir(shell, &[String::from("cd"), dir.to_string_lossy().into_owned()], io);
+    if status != 0 {
+        return status;
+    }
+    shell.dir_stack.remove(0);
+    print_dir_stack(shell, io)
+}
+
+/// `fetch url [-o file]` downloads `url` over HTTP to stdout, or to
+/// `file`, which is only created once the server has answered.
+#[cfg(feature = "fetch")]
+fn fetch(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    let (url, file) = match args.as_slice() {
+        [url] => (*url, None),
+        [url, "-o", file] | ["-o", file, url] => (*url, Some(*file)),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: fetch: usage: fetch url [-o file]");
+            return 2;
+        }
+    };
+    let mut body = Vec::new();
+    if let Err(e) = crate::fetch::fetch(url, &mut body) {
+        let _ = writeln!(io.stderr, "shell: fetch: {}: {}", url, e);
+        return 1;
+    }
+    let written = match file {
+        Some(file) => fs::write(file, &body).map_err(|e| format!("{}: {}", file, e)),
+        None => io.stdout.write_all(&body).map_err(|e| format!("write error: {}", e)),
+    };
+    match written {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: fetch: {}", e);
+            1
+        }
+    }
+}
+
+/// `hash` lists the commands whose place in `$PATH` is remembered, with
+/// how often each ran; `hash name...` looks them up and remembers them,
+/// and `hash -r` forgets them all. `hash -d name=path` defines a named
+/// directory usable as `~name`; `hash -d` alone lists them.
+fn hash(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match argv.get(1).map(String::as_str) {
+        None => {
+            let mut entries = shell.command_hash.iter().peekable();
+            if entries.peek().is_none() {
+                return 0;
+            }
+            status(writeln!(io.stdout, "hits\tcommand").and_then(|()| {
+                entries.try_for_each(|(_, program, hits)| writeln!(io.stdout, "{:4}\t{}", hits, program.display()))
+            }))
+        }
+        Some("-r") if argv.len() == 2 => {
+            shell.command_hash.clear();
+            0
+        }
+        Some("-d") => named_dir(shell, &argv[2..], io),
+        Some(arg) if arg.starts_with('-') => {
+            let _ = writeln!(io.stderr, "shell: hash: usage: hash [-r] [name ...] or hash -d [name=path ...]");
+            2
+        }
+        Some(_) => {
+            let path = shell.vars.get("PATH").unwrap_or_default();
+            let mut res = 0;
+            for name in &argv[1..] {
+                if find(name).is_none() && shell.command_hash.remember(name, &path).is_none() {
+                    let _ = writeln!(io.stderr, "shell: hash: {}: not found", name);
+                    res = 1;
+                }
+            }
+            res
+        }
+    }
+}
+
+/// `hash -d` with `args`: define the named directories, or list them.
+fn named_dir(shell: &mut Shell, args: &[String], io: &mut Io) -> i32 {
+    if args.is_empty() {
+        return status(shell.named_dirs.iter()
+            .try_for_each(|(name, path)| writeln!(io.stdout, "{}={}", name, path)));
+    }
+    let mut status = 0;
+    for arg in args {
+        match arg.split_once('=') {
+            Some((name, path)) if !name.is_empty() && !name.contains('/') => {
+                let path = path.unfold(&shell.named_dirs);
+                shell.named_dirs.insert(name.to_owned(), path);
+            }
+            _ => {
+                let _ = writeln!(io.stderr, "shell: hash: {}: not a valid name=path pair", arg);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+/// The directory that `:name`, or `:name/rest` below it, names through
+/// the bookmark (or named directory) `name`.
+fn bookmarked(shell: &Shell, arg: &str) -> Option<PathBuf> {
+    let word = arg.strip_prefix(':')?;
+    let (name, rest) = word.split_once('/').unwrap_or((word, ""));
+    let dir = PathBuf::from(shell.named_dirs.get(name)?);
+    Some(if rest.is_empty() { dir } else { dir.join(rest) })
+}
+
+/// `bookmark name [dir]` names `dir`, the working directory by default,
+/// for `cd ~name` and `cd :name` in this session and later ones;
+/// `bookmark -d name` forgets it, and `bookmark` alone lists them.
+fn bookmark(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let (name, dir) = match &argv[1..] {
+        [] => {
+            let saved = match bookmarks::load() {
+                Ok(saved) => saved,
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "shell: bookmark: {}", e);
+                    return 1;
+                }
+            };
+            return status(saved.iter().try_for_each(|(name, dir)| writeln!(io.stdout, "{:<15}\t{}", name, dir)));
+        }
+        [flag, name] if flag == "-d" => (name, None),
+        [name] if !name.starts_with('-') => (name, logical_cwd(shell)),
+        [name, dir] if !name.starts_with('-') => {
+            let dir = dir.unfold(&shell.named_dirs);
+            let cwd = logical_cwd(shell).unwrap_or_default();
+            (name, Some(PathBuf::from(normalize(&cwd.join(dir).to_string_lossy()))))
+        }
+        _ => {
+            let _ = writeln!(io.stderr, "shell: bookmark: usage: bookmark [name [dir]] | bookmark -d name");
+            return 2;
+        }
+    };
+    if !bookmarks::is_name(name) {
+        let _ = writeln!(io.stderr, "shell: bookmark: {}: invalid name", name);
+        return 1;
+    }
+    let dir = match dir {
+        Some(dir) if !dir.is_dir() => {
+            let _ = writeln!(io.stderr, "shell: bookmark: {}: not a directory", dir.display());
+            return 1;
+        }
+        Some(dir) => Some(dir.to_string_lossy().into_owned()),
+        None => None,
+    };
+    match &dir {
+        Some(dir) => shell.named_dirs.insert(name.clone(), dir.clone()),
+        None => shell.named_dirs.remove(name.as_str()),
+    };
+    match bookmarks::store(name, dir.as_deref()) {
+        Ok(true) => 0,
+        Ok(false) => {
+            let _ = writeln!(io.stderr, "shell: bookmark: {}: no such bookmark", name);
+            1
+        }
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: bookmark: {}", e);
+            1
+        }
+    }
+}
+
+/// `getopts optstring name [arg...]` parses the next option of the
+/// arguments, or of the positional parameters, for a script: it sets
+/// `name` to the option letter and `$OPTARG` to its argument, for the
+/// letters followed by `:` in `optstring`, and moves `$OPTIND` to the
+/// argument to look at next. Grouped flags such as `-ab` are taken one
+/// call at a time. An unknown option or a missing argument sets `name` to
+/// `?` and is reported, unless `optstring` starts with `:`, in which case
+/// `$OPTARG` holds the letter and a missing argument sets `name` to `:`.
+/// Fails once the options are over, at `--` or the first argument not
+/// starting with `-`; setting `$OPTIND` to 1 starts again.
+fn getopts(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let [_, optstring, name, args @ ..] = argv else {
+        let _ = writeln!(io.stderr, "shell: getopts: usage: getopts optstring name [arg...]");
+        return 2;
+    };
+    if !variables::is_name(name) {
+        let _ = writeln!(io.stderr, "shell: getopts: `{}': not a valid identifier", name);
+        return 1;
+    }
+    if let Err(e) = shell.vars.writable(name).and(shell.vars.writable("OPTIND")).and(shell.vars.writable("OPTARG")) {
+        let _ = writeln!(io.stderr, "{}", e);
+        return 1;
+    }
+    let args = match args.is_empty() {
+        true => shell.vars.positional.clone(),
+        false => args.to_vec(),
+    };
+    let (silent, optstring) = match optstring.strip_prefix(':') {
+        Some(optstring) => (true, optstring),
+        None => (false, optstring.as_str()),
+    };
+    let mut optind = shell.vars.get("OPTIND").and_then(|n| n.parse().ok()).filter(|&n| n > 0).unwrap_or(1);
+    // Where in the argument at `$OPTIND` the next flag is, when `$OPTIND`
+    // is where the last call left it.
+    let arg: Vec<char> = args.get(optind - 1).map(|arg| arg.chars().collect()).unwrap_or_default();
+    let mut offset = match shell.getopts_position {
+        (last, offset) if last == optind && offset < arg.len() => offset,
+        _ => 1,
+    };
+    if offset == 1 && (arg.len() < 2 || arg[0] != '-' || arg == ['-', '-']) {
+        if arg == ['-', '-'] {
+            optind += 1;
+        }
+        shell.vars.set(name, String::from("?"));
+        shell.vars.unset("OPTARG");
+        shell.vars.set("OPTIND", optind.to_string());
+        shell.getopts_position = (optind, 1);
+        return 1;
+    }
+    let flag = arg[offset];
+    offset += 1;
+    let takes_argument = optstring.find(flag)
+        .filter(|_| flag != ':')
+        .map(|i| optstring[i + flag.len_utf8()..].starts_with(':'));
+    match takes_argument {
+        Some(true) if offset < arg.len() => {
+            shell.vars.set("OPTARG", arg[offset..].iter().collect());
+            shell.vars.set(name, flag.to_string());
+            offset = arg.len();
+        }
+        Some(true) => match args.get(optind) {
+            Some(value) => {
+                shell.vars.set("OPTARG", value.clone());
+                shell.vars.set(name, flag.to_string());
+                optind += 1;
+            }
+            None if silent => {
+                shell.vars.set("OPTARG", flag.to_string());
+                shell.vars.set(name, String::from(":"));
+            }
+            None => {
+                let _ = writeln!(io.stderr, "shell: getopts: option requires an argument -- {}", flag);
+                shell.vars.unset("OPTARG");
+                shell.vars.set(name, String::from("?"));
+            }
+        },
+        Some(false) => {
+            shell.vars.unset("OPTARG");
+            shell.vars.set(name, flag.to_string());
+        }
+        None if silent => {
+            shell.vars.set("OPTARG", flag.to_string());
+            shell.vars.set(name, String::from("?"));
+        }
+        None => {
+            let _ = writeln!(io.stderr, "shell: getopts: illegal option -- {}", flag);
+            shell.vars.unset("OPTARG");
+            shell.vars.set(name, String::from("?"));
+        }
+    }
+    if offset >= arg.len() {
+        optind += 1;
+        offset = 1;
+    }
+    shell.vars.set("OPTIND", optind.to_string());
+    shell.getopts_position = (optind, offset);
+    0
+}
+
+/// `glob [-0] pattern...` prints the paths matching each pattern, one
+/// per line or, with `-0`, each followed by a NUL byte, using the same
+/// matching as the shell's own expansion. A pattern matching nothing
+/// prints nothing; the status is 1 when no pattern matched.
+fn glob(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let nul = argv.get(1).is_some_and(|arg| arg == "-0");
+    let patterns = &argv[1 + nul as usize..];
+    if patterns.is_empty() {
+        let _ = writeln!(io.stderr, "shell: glob: usage: glob [-0] pattern...");
+        return 2;
+    }
+    let terminator = if nul { b'\0' } else { b'\n' };
+    let mut found = false;
+    for pattern in patterns {
+        let Some(paths) = shell.globber.glob(pattern, shell.options.glob()) else {
+            let _ = writeln!(io.stderr, "shell: glob: {}: invalid pattern", pattern);
+            return 2;
+        };
+        for path in paths {
+            found = true;
+            if let Err(e) = io.stdout.write_all(path.as_bytes()).and_then(|_| io.stdout.write_all(&[terminator])) {
+                return status(Err(e));
+            }
+        }
+    }
+    match found {
+        true => 0,
+        false => 1,
+    }
+}
+
+/// The index of the entry `spec` names among the oldest `count` of the
+/// history: by its number, counting back from the newest when negative,
+/// or as the newest that starts with it.
+fn fc_entry(history: &History, count: usize, spec: &str) -> Option<usize> {
+    match spec.parse::<i64>() {
+        Ok(n) if n < 0 => count.checked_sub(usize::try_from(n.unsigned_abs()).ok()?),
+        Ok(n) => usize::try_from(n).ok().filter(|&n| n >= 1 && n <= count).map(|n| n - 1),
+        Err(_) => (0..count).rev().find(|&i| history.get(i).is_some_and(|line| line.starts_with(spec))),
+    }
+}
+
+/// Show the commands `fc` is about to run, keep them in the history in
+/// place of the `fc` command, and run them.
+fn fc_run(shell: &mut Shell, commands: &str, io: &mut Io) -> i32 {
+    let commands = commands.trim_end();
+    let _ = writeln!(io.stdout, "{}", commands);
+    shell.history.lock().unwrap().push(commands, shell.now().as_secs());
+    shell.run(commands)
+}
+
+/// `fc [-e editor] [first [last]]` opens the entries from `first` to
+/// `last` (the last command by default) in `editor`, `$FCEDIT`, `$EDITOR`
+/// or `vi`, then runs what was saved. `fc -l [first [last]]` lists the
+/// entries, the last 16 by default, and `fc -s [old=new] [command]` runs
+/// the last command, or the newest starting with `command`, again with
+/// the first `old` in it replaced by `new`. Entries are given by number,
+/// negative to count back from the last, or by how the command starts.
+fn fc(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    const USAGE: &str = "shell: fc: usage: fc [-e editor] [first [last]] | fc -l [first [last]] | fc -s [old=new] [command]";
+    let mut editor = None;
+    let mut list = false;
+    let mut substitute = false;
+    let mut args = &argv[1..];
+    loop {
+        match args {
+            [flag, name, ..] if flag == "-e" => {
+                editor = Some(name.clone());
+                args = &args[2..];
+            }
+            [flag, ..] if flag == "-l" => {
+                list = true;
+                args = &args[1..];
+            }
+            [flag, ..] if flag == "-s" => {
+                substitute = true;
+                args = &args[1..];
+            }
+            _ => break,
+        }
+    }
+    let history = shell.history.lock().unwrap();
+    // The newest entry is normally this `fc` command itself.
+    let current = history.len().checked_sub(1).and_then(|i| history.get(i))
+        .is_some_and(|line| line.split_whitespace().next() == Some("fc"));
+    let count = history.len() - usize::from(current);
+    if substitute {
+        let (replace, args) = match args.split_first() {
+            Some((pair, rest)) if pair.contains('=') => (pair.split_once('='), rest),
+            _ => (None, args),
+        };
+        if args.len() > 1 {
+            let _ = writeln!(io.stderr, "{}", USAGE);
+            return 2;
+        }
+        let Some(index) = fc_entry(&history, count, args.first().map_or("-1", String::as_str)) else {
+            let _ = writeln!(io.stderr, "shell: fc: no command found");
+            return 1;
+        };
+        let mut command = history.get(index).unwrap_or_default().into_owned();
+        if let Some((old, new)) = replace.filter(|(old, _)| !old.is_empty()) {
+            command = command.replacen(old, new, 1);
+        }
+        drop(history);
+        return fc_run(shell, &command, io);
+    }
+    let (first, last) = match (args, list) {
+        ([], false) => ("-1", "-1"),
+        ([], true) => ("-16", "-1"),
+        ([first], false) => (first.as_str(), first.as_str()),
+        ([first], true) => (first.as_str(), "-1"),
+        ([first, last], _) => (first.as_str(), last.as_str()),
+        _ => {
+            let _ = writeln!(io.stderr, "{}", USAGE);
+            return 2;
+        }
+    };
+    // Listing from further back than the history goes starts at its
+    // beginning.
+    let first = match (fc_entry(&history, count, first), list) {
+        (None, true) if first.parse::<i64>().is_ok_and(|n| n < 0) => Some(0),
+        (first, _) => first,
+    };
+    let (Some(first), Some(last)) = (first, fc_entry(&history, count, last)) else {
+        let _ = writeln!(io.stderr, "shell: fc: history specification out of range");
+        return 1;
+    };
+    let indices: Vec<usize> = match first <= last {
+        true => (first..=last).collect(),
+        false => (last..=first).rev().collect(),
+    };
+    if list {
+        return status(indices.iter()
+            .try_for_each(|&i| writeln!(io.stdout, "{:5}  {}", i + 1, history.get(i).unwrap_or_default())));
+    }
+    let commands: String = indices.iter().map(|&i| format!("{}\n", history.get(i).unwrap_or_default())).collect();
+    drop(history);
+    let editor = editor
+        .or_else(|| shell.vars.get("FCEDIT").filter(|editor| !editor.trim().is_empty()))
+        .or_else(|| shell.vars.get("EDITOR").filter(|editor| !editor.trim().is_empty()))
+        .unwrap_or_else(|| String::from("vi"));
+    let path = std::env::temp_dir().join(format!("rsh-fc-{}", std::process::id()));
+    let written = File::options().mode(0o600).write(true).create_new(true).open(&path)
+        .and_then(|mut file| file.write_all(commands.as_bytes()));
+    if let Err(e) = written {
+        let _ = writeln!(io.stderr, "shell: fc: {}: {}", path.display(), e);
+        return 1;
+    }
+    let mut words = editor.split_whitespace();
+    let edited = Command::new(words.next().unwrap_or("vi")).args(words).arg(&path).status()
+        .map_err(|e| format!("{}: {}", editor, e))
+        .and_then(|status| match status.success() {
+            true => fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e)),
+            false => Err(format!("{}: {}", editor, status)),
+        });
+    let _ = fs::remove_file(&path);
+    match edited {
+        Ok(commands) if commands.trim().is_empty() => 0,
+        Ok(commands) => fc_run(shell, &commands, io),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: fc: {}", e);
+            1
+        }
+    }
+}
+
+/// `history` lists the entries, each after the time it was entered in
+/// the `strftime` format `$HISTTIMEFORMAT` when that is set; `history -n`
+/// picks up those other sessions have added since.
+fn history(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let format = shell.vars.get("HISTTIMEFORMAT");
+    let mut history = shell.history.lock().unwrap();
+    let time = |history: &History, n: usize| match (&format, history.time(n - 1)) {
+        (Some(format), Some(time)) => datetime::format(time as i64, 0, &Zone::Local, format).unwrap_or_default(),
+        _ => String::new(),
+    };
+    match argv.get(1).map(String::as_str) {
+        None => status(history.iter()
+            .try_for_each(|(n, line)| writeln!(io.stdout, "{:5}  {}{}", n, time(&history, n), line))),
+        Some("-n") if argv.len() == 2 => match history.store_mut().sync() {
+            Ok(()) => 0,
+            Err(e) => {
+                let _ = writeln!(io.stderr, "shell: history: {}", e);
+                1
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "shell: history: usage: history [-n]");
+            2
+        }
+    }
+}
+
+/// `disown [%job...]` stops tracking the jobs, the current one by default,
+/// so that they keep running when the shell exits; `disown -a` does so
+/// for every job.
+fn disown(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let jobs = match &argv[1..] {
+        [flag] if flag == "-a" => shell.jobs.running(),
+        [] => match shell.jobs.find("%+") {
+            Ok(job) => vec![job],
+            Err(_) => {
+                let _ = writeln!(io.stderr, "shell: disown: current: no such job");
+                return 1;
+            }
+        },
+        specs => {
+            let mut jobs = Vec::new();
+            for spec in specs {
+                match shell.jobs.find(spec) {
+                    Ok(job) => jobs.push(job),
+                    Err(e) => {
+                        let _ = writeln!(io.stderr, "shell: disown: {}", e);
+                        return 1;
+                    }
+                }
+            }
+            jobs
+        }
+    };
+    for job in jobs {
+        shell.jobs.disown(job.id);
+    }
+    0
+}
+
+/// `j pattern...` changes to the directory visited most often and most
+/// recently whose path holds the patterns in order, ignoring case; `j`
+/// alone lists the directories with their scores, best last.
+fn jump(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let Some(path) = frecency::path(shell) else {
+        let _ = writeln!(io.stderr, "shell: j: HOME not set");
+        return 1;
+    };
+    let mut dirs = match frecency::load(&path) {
+        Ok(dirs) => dirs,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: j: {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let now = shell.now().as_secs();
+    let cwd = logical_cwd(shell);
+    dirs.retain(|dir| dir.matches(&argv[1..]) && Some(&dir.path) != cwd.as_ref() && dir.path.is_dir());
+    dirs.sort_by(|a, b| a.score(now).total_cmp(&b.score(now)));
+    if argv.len() == 1 {
+        return status(dirs.iter().try_for_each(|dir| {
+            writeln!(io.stdout, "{:<10.1} {}", dir.score(now), dir.path.display())
+        }));
+    }
+    match dirs.last() {
+        Some(dir) => {
+            let dir = dir.path.to_string_lossy().into_owned();
+            chdir(shell, &[String::from("cd"), dir], io)
+        }
+        None => {
+            let _ = writeln!(io.stderr, "shell: j: {}: no matching directory", argv[1..].join(" "));
+            1
+        }
+    }
+}
+
+/// `jobs` lists the running background jobs; `jobs -l` adds their pids.
+fn jobs(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let long = match argv.get(1).map(String::as_str) {
+        None => false,
+        Some("-l") if argv.len() == 2 => true,
+        _ => {
+            let _ = writeln!(io.stderr, "shell: jobs: usage: jobs [-l]");
+            return 2;
+        }
+    };
+    status(shell.jobs.running().iter().try_for_each(|job| {
+        if long {
+            writeln!(io.stdout, "[{}]  {:<6} Running                 {}", job.id, job.pid, job.command)
+        } else {
+            writeln!(io.stdout, "[{}]  Running                 {}", job.id, job.command)
+        }
+    }))
+}
+
+/// `json get path` prints the value at `path`, such as `.items[0].name`,
+/// in the JSON document read from stdin: strings as they are, anything
+/// else as compact JSON. Fails when there is nothing at `path`.
+fn json(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let [_, command, path] = argv else {
+        let _ = writeln!(io.stderr, "shell: json: usage: json get path");
+        return 2;
+    };
+    if command != "get" {
+        let _ = writeln!(io.stderr, "shell: json: usage: json get path");
+        return 2;
+    }
+    let mut text = String::new();
+    if let Err(e) = io.stdin.read_to_string(&mut text) {
+        let _ = writeln!(io.stderr, "shell: json: {}", e);
+        return 1;
+    }
+    let found = json_value::parse(&text).and_then(|document| {
+        Ok(match json_value::get(&document, path)? {
+            Some(Value::String(s)) => Some(s.clone()),
+            Some(value) => Some(value.to_string()),
+            None => None,
+        })
+    });
+    match found {
+        Ok(Some(value)) => status(writeln!(io.stdout, "{}", value)),
+        Ok(None) => 1,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: json: {}", e);
+            1
+        }
+    }
+}
+
+/// Read stdin as text for the record builtin `name`.
+fn read_records(name: &str, io: &mut Io, parse: fn(&str) -> Result<Vec<Value>, String>) -> Option<Vec<Value>> {
+    let mut text = String::new();
+    if let Err(e) = io.stdin.read_to_string(&mut text) {
+        let _ = writeln!(io.stderr, "shell: {}: {}", name, e);
+        return None;
+    }
+    match parse(&text) {
+        Ok(found) => Some(found),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: {}: {}", name, e);
+            None
+        }
+    }
+}
+
+/// Write `records` one per line, as the record builtins pass them on.
+fn write_records<'a>(io: &mut Io, records: impl IntoIterator<Item = &'a Value>) -> i32 {
+    status(records.into_iter().try_for_each(|record| writeln!(io.stdout, "{}", record)))
+}
+
+/// `from-json` turns the JSON document on stdin into records, one per
+/// line: the items of an array, or the document itself. JSON lines pass
+/// through as they are.
+fn from_json(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() > 1 {
+        let _ = writeln!(io.stderr, "shell: from-json: usage: from-json");
+        return 2;
+    }
+    match read_records("from-json", io, records::from_json) {
+        Some(found) => write_records(io, &found),
+        None => 1,
+    }
+}
+
+/// `where field op value` passes on the records read from stdin whose
+/// `field` compares to `value` by `op`: `==`, `!=`, `<`, `<=`, `>`, `>=`
+/// (as numbers when both sides are) or `=~` (contains).
+fn where_(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let [_, field, op, value] = argv else {
+        let _ = writeln!(io.stderr, "shell: where: usage: where field op value");
+        return 2;
+    };
+    let Some(found) = read_records("where", io, records::read) else {
+        return 1;
+    };
+    let mut kept = Vec::new();
+    for record in &found {
+        match records::matches(record, field, op, value) {
+            Ok(true) => kept.push(record),
+            Ok(false) => {}
+            Err(e) => {
+                let _ = writeln!(io.stderr, "shell: where: {}", e);
+                return 2;
+            }
+        }
+    }
+    write_records(io, kept)
+}
+
+/// `select field...` keeps only the given fields of the records read from
+/// stdin, in that order.
+fn select(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() < 2 {
+        let _ = writeln!(io.stderr, "shell: select: usage: select field...");
+        return 2;
+    }
+    let Some(found) = read_records("select", io, records::read) else {
+        return 1;
+    };
+    let selected: Vec<Value> = found.iter().map(|record| records::select(record, &argv[1..])).collect();
+    write_records(io, &selected)
+}
+
+/// `to-table` shows the records read from stdin as a table, with a column
+/// for each field.
+fn to_table(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() > 1 {
+        let _ = writeln!(io.stderr, "shell: to-table: usage: to-table");
+        return 2;
+    }
+    match read_records("to-table", io, records::read) {
+        Some(found) => status(io.stdout.write_all(records::table(&found).as_bytes())),
+        None => 1,
+    }
+}
+
+/// `kv get key`, `kv set key value` and `kv del key` read and change
+/// values kept across sessions; `kv list` shows them all. `get` and `del`
+/// fail for a missing key.
+fn kv(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr, "shell: kv: usage: kv get key | kv set key value | kv del key | kv list");
+        2
+    };
+    let key = match args.as_slice() {
+        ["list"] => None,
+        ["get" | "del", key] | ["set", key, _] => Some(*key),
+        _ => return usage(io),
+    };
+    if let Some(key) = key.filter(|key| !kv::is_key(key)) {
+        let _ = writeln!(io.stderr, "shell: kv: {}: invalid key", key);
+        return 1;
+    }
+    let Some(path) = kv::path() else {
+        let _ = writeln!(io.stderr, "shell: kv: HOME not set");
+        return 1;
+    };
+    let mut values = match kv::load(&path) {
+        Ok(values) => values,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: kv: {}: {}", path.display(), e);
+            return 1;
+        }
+    };
+    let changed = match args.as_slice() {
+        ["get", key] => match values.get(*key) {
+            Some(value) => return status(writeln!(io.stdout, "{}", value)),
+            None => return 1,
+        },
+        ["list"] => {
+            return status(values.iter().try_for_each(|(key, value)| writeln!(io.stdout, "{:<15}\t{}", key, value)));
+        }
+        ["set", key, value] => values.insert(key.to_string(), value.to_string()).as_deref() != Some(*value),
+        ["del", key] => match values.remove(*key) {
+            Some(_) => true,
+            None => return 1,
+        },
+        _ => return usage(io),
+    };
+    if !changed {
+        return 0;
+    }
+    match kv::save(&path, &values) {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: kv: {}: {}", path.display(), e);
+            1
+        }
+    }
+}
+
+/// `kill [-signal | -s signal | -n number] target...` sends a signal,
+/// `TERM` unless another is named by name (with or without `SIG`) or
+/// number, to each target: a pid, or a job such as `%1`. `kill -l` lists
+/// the signals, and `kill -l signal` translates between a signal's name
+/// and number, or names the signal behind an exit status above 128.
+fn kill(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr,
+                         "shell: kill: usage: kill [-signal | -s signal | -n number] pid|%job... | kill -l [signal]");
+        2
+    };
+    let mut args = argv[1..].iter().map(String::as_str).peekable();
+    let spec = match args.peek().copied() {
+        Some("-l" | "-L") => {
+            args.next();
+            return list_signals(args.collect(), io);
+        }
+        Some("-s" | "-n") => {
+            args.next();
+            match args.next() {
+                Some(spec) => Some(spec),
+                None => return usage(io),
+            }
+        }
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 && arg != "--" => {
+            // `-9` alone would be a process group, but that needs `--`.
+            args.next();
+            Some(&arg[1..])
+        }
+        _ => None,
+    };
+    let signal = match spec.map(Condition::parse) {
+        None => Some(Signal::SIGTERM),
+        Some(Some(Condition::Signal(signal))) => Some(signal),
+        // Signal 0 only checks that the process exists.
+        Some(Some(Condition::Exit)) => None,
+        Some(None) => {
+            let _ = writeln!(io.stderr, "shell: kill: {}: invalid signal specification", spec.unwrap_or(""));
+            return 1;
+        }
+    };
+    if args.peek() == Some(&"--") {
+        args.next();
+    }
+    let targets: Vec<&str> = args.collect();
+    if targets.is_empty() {
+        return usage(io);
+    }
+    let mut res = 0;
+    for target in targets {
+        let pid = match target.starts_with('%') {
+            true => shell.jobs.find(target).map(|job| job.pid as i32),
+            false => target.parse::<i32>().map_err(|_| format!("{}: arguments must be process or job IDs", target)),
+        };
+        let sent = pid.and_then(|pid| {
+            signal::kill(Pid::from_raw(pid), signal).map_err(|e| format!("{}: {}", target, e.desc()))
+        });
+        if let Err(e) = sent {
+            let _ = writeln!(io.stderr, "shell: kill: {}", e);
+            res = 1;
+        }
+    }
+    res
+}
+
+/// `kill -l`: list every signal, or translate the names and numbers given.
+fn list_signals(specs: Vec<&str>, io: &mut Io) -> i32 {
+    if specs.is_empty() {
+        let names: Vec<String> = Signal::iterator()
+            .map(|signal| format!("{:2}) {}", signal as i32, signal.as_str()))
+            .collect();
+        return status(names.chunks(5).try_for_each(|row| writeln!(io.stdout, "{}", row.join("\t"))));
+    }
+    let mut res = 0;
+    for spec in specs {
+        let translated = match spec.parse::<i32>() {
+            // An exit status above 128 stands for death by signal.
+            Ok(n) => Signal::try_from(if n > 128 { n - 128 } else { n })
+                .map(|signal| signal.as_str().trim_start_matches("SIG").to_owned())
+                .ok(),
+            Err(_) => match Condition::parse(spec) {
+                Some(Condition::Signal(signal)) => Some((signal as i32).to_string()),
+                _ => None,
+            },
+        };
+        match translated {
+            Some(text) => {
+                if let Err(e) = writeln!(io.stdout, "{}", text) {
+                    return status(Err(e));
+                }
+            }
+            None => {
+                let _ = writeln!(io.stderr, "shell: kill: {}: invalid signal specification", spec);
+                res = 1;
+            }
+        }
+    }
+    res
+}
+
+/// `lastout` prints the tail of what the last command run under `set -o
+/// capture` wrote to stdout; `lastout -e` prints what it wrote to stderr.
+fn lastout(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let output = match argv.get(1).map(String::as_str) {
+        None if argv.len() == 1 => &shell.last_output.stdout,
+        Some("-e") if argv.len() == 2 => &shell.last_output.stderr,
+        _ => {
+            let _ = writeln!(io.stderr, "shell: lastout: usage: lastout [-e]");
+            return 2;
+        }
+    };
+    if !shell.options.capture && output.is_empty() {
+        let _ = writeln!(io.stderr, "shell: lastout: nothing captured; see `set -o capture'");
+        return 1;
+    }
+    status(io.stdout.write_all(output))
+}
+
+/// `logall on file` appends everything shown on the terminal from then on
+/// to `file`; `logall off` stops and `logall` alone reports the state.
+fn logall(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    match argv.get(1).map(String::as_str) {
+        None if argv.len() == 1 => {
+            let state = if shell.tee.is_some() { "on" } else { "off" };
+            status(writeln!(io.stdout, "logall {}", state))
+        }
+        Some("on") if argv.len() == 3 => {
+            if shell.tee.is_some() {
+                let _ = writeln!(io.stderr, "shell: logall: already on");
+                return 1;
+            }
+            if !io::stdin().is_terminal() {
+                let _ = writeln!(io.stderr, "shell: logall: stdin is not a terminal");
+                return 1;
+            }
+            let Some(log) = open_redirect(shell, &argv[2], true) else {
+                return 1;
+            };
+            match Tee::start(log) {
+                Ok(tee) => {
+                    shell.tee = Some(tee);
+                    0
+                }
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "shell: logall: {}", e);
+                    1
+                }
+            }
+        }
+        Some("off") if argv.len() == 2 => {
+            shell.tee = None;
+            0
+        }
+        _ => {
+            let _ = writeln!(io.stderr, "shell: logall: usage: logall [on file | off]");
+            2
+        }
+    }
+}
+
+/// `pty command [args...]` runs an external command on its own pty, so
+/// that it keeps its interactive behavior when the output is captured.
+fn pty(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() == 1 {
+        let _ = writeln!(io.stderr, "shell: pty: usage: pty command [args ...]");
+        return 2;
+    }
+    let mut command = Command::new(&argv[1]);
+    command.args(&argv[2..]);
+    match pseudo_terminal::run(command, io.stdout) {
+        Ok(status) => status_code(status),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: pty: {}: {}", argv[1], e);
+            if e.kind() == io::ErrorKind::NotFound { 127 } else { 126 }
+        }
+    }
+}
+
+/// `sha256sum [file...]` prints the SHA-256 digest of each file, or of
+/// stdin when there are none or for `-`, followed by its name.
+fn sha256sum(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let stdin = [String::from("-")];
+    let files = if argv.len() > 1 { &argv[1..] } else { &stdin[..] };
+    let mut res = 0;
+    for file in files {
+        let mut hasher = Sha256::default();
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut input: Box<dyn Read> = match file.as_str() {
+            "-" => Box::new(&mut *io.stdin),
+            _ => match File::open(file) {
+                Ok(f) => Box::new(f),
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "shell: sha256sum: {}: {}", file, e);
+                    res = 1;
+                    continue;
+                }
+            },
+        };
+        let read = loop {
+            match input.read(&mut buf) {
+                Ok(0) => break Ok(()),
+                Ok(n) => hasher.update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        drop(input);
+        if let Err(e) = read {
+            let _ = writeln!(io.stderr, "shell: sha256sum: {}: {}", file, e);
+            res = 1;
+            continue;
+        }
+        let hex: String = hasher.finish().iter().map(|b| format!("{:02x}", b)).collect();
+        if status(writeln!(io.stdout, "{}  {}", hex, file)) != 0 {
+            return 1;
+        }
+    }
+    res
+}
+
+/// Split `line`, a list of bytes and whether each was escaped with a
+/// backslash, into at most `count` fields at the unescaped bytes of
+/// `ifs`. Whitespace in `ifs` is trimmed around fields; other bytes each
+/// end one. The last field takes whatever is left.
+fn split_fields(line: &[(u8, bool)], ifs: &[u8], count: usize) -> Vec<String> {
+    let delimits = |&(byte, escaped): &(u8, bool)| !escaped && ifs.contains(&byte);
+    let blank = |entry: &(u8, bool)| delimits(entry) && entry.0.is_ascii_whitespace();
+    let text = |part: &[(u8, bool)]| {
+        String::from_utf8_lossy(&part.iter().map(|&(byte, _)| byte).collect::<Vec<_>>()).into_owned()
+    };
+    let mut fields = Vec::new();
+    let mut rest = &line[line.iter().take_while(|e| blank(e)).count()..];
+    while fields.len() + 1 < count && !rest.is_empty() {
+        let end = rest.iter().position(delimits).unwrap_or(rest.len());
+        fields.push(text(&rest[..end]));
+        rest = &rest[end..];
+        rest = &rest[rest.iter().take_while(|e| blank(e)).count()..];
+        if rest.first().is_some_and(|e| delimits(e) && !blank(e)) {
+            rest = &rest[1..];
+            rest = &rest[rest.iter().take_while(|e| blank(e)).count()..];
+        }
+    }
+    let end = rest.len() - rest.iter().rev().take_while(|e| blank(e)).count();
+    fields.push(text(&rest[..end]));
+    fields
+}
+
+/// `read [-r] [-s] [-p prompt] [name...]` reads a line from stdin, splits
+/// it at the characters of `$IFS` (blanks and newlines by default) and
+/// assigns the fields to the names in turn, the last name taking the rest
+/// of the line. Without names the whole line goes to `$REPLY`. A backslash
+/// escapes the character after it, and continues the line before a
+/// newline, unless `-r` is given. `-p` shows a prompt on stderr and `-s`
+/// keeps a terminal from echoing what is typed, for passwords. Fails at
+/// end of file.
+fn read(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr, "shell: read: usage: read [-r] [-s] [-p prompt] [name...]");
+        2
+    };
+    let (mut raw, mut silent, mut prompt) = (false, false, None);
+    let mut args = argv[1..].iter();
+    let mut names = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix('-').filter(|flags| !flags.is_empty() && names.is_empty()) {
+            Some("-") => {
+                names.extend(args.by_ref());
+            }
+            Some(flags) => {
+                for (i, flag) in flags.char_indices() {
+                    match flag {
+                        'r' => raw = true,
+                        's' => silent = true,
+                        'p' if i + 1 < flags.len() => {
+                            prompt = Some(flags[i + 1..].to_owned());
+                            break;
+                        }
+                        'p' => match args.next() {
+                            Some(text) => prompt = Some(text.clone()),
+                            None => return usage(io),
+                        },
+                        _ => return usage(io),
+                    }
+                }
+            }
+            None => names.push(arg),
+        }
+    }
+    if let Some(name) = names.iter().find(|name| !variables::is_name(name)) {
+        let _ = writeln!(io.stderr, "shell: read: `{}': not a valid identifier", name);
+        return 1;
+    }
+    let target = if names.is_empty() { "REPLY" } else { names[0].as_str() };
+    if let Err(e) = names.iter().try_for_each(|name| shell.vars.writable(name)).and(shell.vars.writable(target)) {
+        let _ = writeln!(io.stderr, "{}", e);
+        return 1;
+    }
+    let terminal = io::stdin().is_terminal();
+    if let Some(prompt) = prompt.filter(|_| terminal) {
+        let _ = write!(io.stderr, "{}", prompt);
+        let _ = io.stderr.flush();
+    }
+    let saved = match silent && terminal {
+        true => termios::tcgetattr(io::stdin()).ok(),
+        false => None,
+    };
+    if let Some(saved) = &saved {
+        let mut quiet = saved.clone();
+        quiet.local_flags.remove(LocalFlags::ECHO);
+        let _ = termios::tcsetattr(io::stdin(), SetArg::TCSADRAIN, &quiet);
+    }
+    // Read a byte at a time so as to leave what follows the line for the
+    // commands after this one.
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    let mut escaped = false;
+    let complete = loop {
+        match io.stdin.read(&mut byte) {
+            Ok(0) => break false,
+            Ok(_) if escaped => {
+                escaped = false;
+                if byte[0] != b'\n' {
+                    line.push((byte[0], true));
+                }
+            }
+            Ok(_) if byte[0] == b'\n' => break true,
+            Ok(_) if byte[0] == b'\\' && !raw => escaped = true,
+            Ok(_) => line.push((byte[0], false)),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                let _ = writeln!(io.stderr, "shell: read: {}", e);
+                break false;
+            }
+        }
+    };
+    if let Some(saved) = &saved {
+        let _ = termios::tcsetattr(io::stdin(), SetArg::TCSADRAIN, saved);
+    }
+    if names.is_empty() {
+        let bytes: Vec<u8> = line.iter().map(|&(b, _)| b).collect();
+        shell.vars.set("REPLY", String::from_utf8_lossy(&bytes).into_owned());
+    } else {
+        let ifs = shell.vars.get("IFS").unwrap_or_else(|| String::from(" \t\n"));
+        let mut fields = split_fields(&line, ifs.as_bytes(), names.len()).into_iter();
+        for name in names {
+            shell.vars.set(name, fields.next().unwrap_or_default());
+        }
+    }
+    match complete {
+        true => 0,
+        false => 1,
+    }
+}
+
+/// `set -o name` turns a shell option on and `set +o name` turns it off;
+/// `set -e`, `-u` and `-x` (combinable, as in `-eu`) are short for
+/// `errexit`, `nounset` and `xtrace`. `set -o` alone lists the options,
+/// `set +o` alone prints the commands that would restore them. The
+/// arguments after `--`, or from the first not starting with `-` or `+`,
+/// replace the positional parameters; `set --` alone clears them.
+fn set(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr, "shell: set: usage: set [-beux] [+beux] [-o | +o] [option ...] [--] [arg ...]");
+        2
+    };
+    if argv.len() == 1 {
+        return usage(io);
+    }
+    let mut status = 0;
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        let on = match arg.chars().next() {
+            _ if arg == "--" => {
+                shell.vars.positional = args.cloned().collect();
+                break;
+            }
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                shell.vars.positional = std::iter::once(arg).chain(args).cloned().collect();
+                break;
+            }
+        };
+        let names: Vec<&str> = if &arg[1..] == "o" {
+            match args.next() {
+                Some(name) => vec![name.as_str()],
+                None => {
+                    return self::status(shell.options.iter().try_for_each(|(name, value)| {
+                        if on {
+                            writeln!(io.stdout, "{:<15}\t{}", name, if value { "on" } else { "off" })
+                        } else {
+                            writeln!(io.stdout, "set {}o {}", if value { '-' } else { '+' }, name)
+                        }
+                    }));
+                }
+            }
+        } else {
+            match arg[1..].chars().map(Options::flag_name).collect::<Option<Vec<_>>>() {
+                Some(names) if !names.is_empty() => names,
+                _ => {
+                    let _ = writeln!(io.stderr, "shell: set: {}: invalid option", arg);
+                    return usage(io);
+                }
+            }
+        };
+        for name in names {
+            if !shell.options.set(name, on) {
+                let _ = writeln!(io.stderr, "shell: set: {}: invalid option name", name);
+                status = 1;
+            }
+        }
+    }
+    status
+}
+
+/// `setopt name...` turns shell options on and `unsetopt name...` turns
+/// them off. As in zsh, case and underscores in the names don't matter,
+/// so `INTERACTIVE_COMMENTS` is `interactive-comments`. With no names, `setopt` lists the options that
+/// are on and `unsetopt` those that are off.
+fn setopt(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let on = argv[0] == "setopt";
+    if argv.len() == 1 {
+        return status(shell.options.iter()
+            .filter(|&(_, value)| value == on)
+            .try_for_each(|(name, _)| writeln!(io.stdout, "{}", name)));
+    }
+    let mut result = 0;
+    let plain = |name: &str| name.to_ascii_lowercase().replace(['_', '-'], "");
+    for name in &argv[1..] {
+        match shell.options.iter().find(|(option, _)| plain(option) == plain(name)) {
+            Some((option, _)) => {
+                shell.options.set(option, on);
+            }
+            None => {
+                let _ = writeln!(io.stderr, "shell: {}: {}: no such option", argv[0], name);
+                result = 1;
+            }
+        }
+    }
+    result
+}
+
+/// `shopt [-pqsu] [name...]` is bash's way to the options `set -o` sets:
+/// `-s` turns the named ones on and `-u` off, `-q` only tells whether
+/// they are all on, and otherwise they are listed, as commands with `-p`.
+/// Without names, the listing covers every option, or with `-s` or `-u`
+/// those on or off.
+fn shopt(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let (mut set, mut quiet, mut commands) = (None, false, false);
+    let mut names = &argv[1..];
+    while let Some(flags) = names.first().and_then(|arg| arg.strip_prefix('-')).filter(|flags| !flags.is_empty()) {
+        names = &names[1..];
+        if flags == "-" {
+            break;
+        }
+        for flag in flags.chars() {
+            match flag {
+                's' => set = Some(true),
+                'u' => set = Some(false),
+                'q' => quiet = true,
+                'p' => commands = true,
+                _ => {
+                    let _ = writeln!(io.stderr, "shell: shopt: -{}: invalid option", flag);
+                    let _ = writeln!(io.stderr, "shell: shopt: usage: shopt [-pqsu] [name ...]");
+                    return 2;
+                }
+            }
+        }
+    }
+    let known: Vec<(&str, bool)> = shell.options.iter().collect();
+    let mut result = 0;
+    let mut chosen = Vec::new();
+    for name in names {
+        match known.iter().find(|(option, _)| option == name) {
+            Some(&option) => chosen.push(option),
+            None => {
+                let _ = writeln!(io.stderr, "shell: shopt: {}: invalid shell option name", name);
+                result = 1;
+            }
+        }
+    }
+    if let (Some(on), false) = (set, names.is_empty()) {
+        for (name, _) in chosen {
+            shell.options.set(name, on);
+        }
+        return result;
+    }
+    if names.is_empty() {
+        chosen = known.into_iter().filter(|&(_, value)| set.is_none_or(|on| value == on)).collect();
+    }
+    if chosen.iter().any(|&(_, value)| !value) {
+        result = 1;
+    }
+    if quiet {
+        return result;
+    }
+    let written = chosen.iter().try_for_each(|&(name, value)| match commands {
+        true => writeln!(io.stdout, "shopt -{} {}", if value { 's' } else { 'u' }, name),
+        false => writeln!(io.stdout, "{:<15}\t{}", name, if value { "on" } else { "off" }),
+    });
+    match status(written) {
+        0 => result,
+        error => error,
+    }
+}
+
+/// `shift [n]` drops the first `n` positional parameters, one by
+/// default, so that `$1` is what was `$n+1`. Fails, leaving them as they
+/// are, when there are fewer than `n`.
+fn shift(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let count = match argv.get(1..) {
+        Some([]) => 1,
+        Some([count]) => match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                let _ = writeln!(io.stderr, "shell: shift: {}: numeric argument required", count);
+                return 2;
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "shell: shift: usage: shift [n]");
+            return 2;
+        }
+    };
+    if count > shell.vars.positional.len() {
+        let _ = writeln!(io.stderr, "shell: shift: {}: shift count out of range", count);
+        return 1;
+    }
+    shell.vars.positional.drain(..count);
+    0
+}
+
+/// `let expr...` works out each arithmetic expression in turn, keeping
+/// the variables they assign, and succeeds when the last is not 0.
+fn let_(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() == 1 {
+        let _ = writeln!(io.stderr, "shell: let: usage: let expr...");
+        return 2;
+    }
+    let mut status = 1;
+    for expr in &argv[1..] {
+        status = match variables::arithmetic(&mut shell.vars, &shell.options, expr) {
+            Ok(value) if value.is_true() => 0,
+            Ok(_) => 1,
+            Err(e) => {
+                let _ = writeln!(io.stderr, "{}", e);
+                return 1;
+            }
+        };
+    }
+    status
+}
+
+/// `math [-i] [-s scale] expr...` works out the arithmetic expression
+/// made of its arguments and prints the result. Numbers are floating
+/// point, shown with at most `scale` (default 6) digits after the point,
+/// unless `-i` asks for integers as in C.
+fn math(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let mut mode = Mode::Float;
+    let mut scale = 6;
+    let mut words = &argv[1..];
+    loop {
+        match words.first().map(String::as_str) {
+            Some("-i") => {
+                mode = Mode::Integer;
+                words = &words[1..];
+            }
+            Some("-s") => match words.get(1).and_then(|scale| scale.parse::<usize>().ok()) {
+                Some(n) => {
+                    scale = n;
+                    words = &words[2..];
+                }
+                None => words = &[],
+            },
+            Some("--") => {
+                words = &words[1..];
+                break;
+            }
+            _ => break,
+        }
+    }
+    if words.is_empty() {
+        let _ = writeln!(io.stderr, "shell: math: usage: math [-i] [-s scale] expr...");
+        return 2;
+    }
+    let expr = words.join(" ");
+    let value = match arith::eval(&expr, mode, &|name| shell.vars.get(name)) {
+        Ok(value) if value.as_float().is_nan() => Err(String::from("result is not a number")),
+        res => res,
+    };
+    match value {
+        Ok(Number::Int(n)) => status(writeln!(io.stdout, "{}", n)),
+        Ok(value) => {
+            let mut text = format!("{:.*}", scale, value.as_float());
+            if text.contains('.') {
+                text = text.trim_end_matches('0').trim_end_matches('.').to_owned();
+            }
+            if text == "-0" {
+                text.remove(0);
+            }
+            status(writeln!(io.stdout, "{}", text))
+        }
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: math: {}: {}", expr, e);
+            1
+        }
+    }
+}
+
+/// Fill `buf` with random bytes from the kernel.
+fn random_bytes(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let rest = &mut buf[filled..];
+        let n = unsafe { libc::getrandom(rest.as_mut_ptr().cast(), rest.len(), 0) };
+        match n {
+            n if n >= 0 => filled += n as usize,
+            _ if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+            _ => return Err(io::Error::last_os_error()),
+        }
+    }
+    Ok(())
+}
+
+/// A random number from `start` to `end`, both included, drawn without
+/// favoring any of them.
+fn random_in(start: i64, end: i64) -> io::Result<i64> {
+    let span = end.abs_diff(start) as u128 + 1;
+    // The largest multiple of `span` that a u64 can hold; draws at or
+    // above it would skew the result.
+    let limit = (u64::MAX as u128 + 1) / span * span;
+    loop {
+        let mut buf = [0; 8];
+        random_bytes(&mut buf)?;
+        let n = u64::from_ne_bytes(buf) as u128;
+        if n < limit {
+            return Ok((start as i128 + (n % span) as i128) as i64);
+        }
+    }
+}
+
+/// `random [[start] end]` prints a random integer from `start` (default
+/// 0) to `end` (default 32767), both included.
+fn random(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let bounds: Option<Vec<i64>> = argv[1..].iter().map(|arg| arg.parse().ok()).collect();
+    let (start, end) = match bounds.as_deref() {
+        Some([]) => (0, 32767),
+        Some(&[end]) => (0, end),
+        Some(&[start, end]) => (start, end),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: random: usage: random [[start] end]");
+            return 2;
+        }
+    };
+    if start > end {
+        let _ = writeln!(io.stderr, "shell: random: {} is greater than {}", start, end);
+        return 1;
+    }
+    match random_in(start, end) {
+        Ok(n) => status(writeln!(io.stdout, "{}", n)),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: random: {}", e);
+            1
+        }
+    }
+}
+
+/// `wait` waits for every background job to finish; `wait target...`
+/// waits for each job (`%1`) or pid given and returns the status of the
+/// last. A trapped signal arriving meanwhile cuts it short with 128 plus
+/// the signal's number.
+fn wait(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let mut pids = Vec::new();
+    for target in &argv[1..] {
+        let pid = match target.starts_with('%') {
+            true => shell.jobs.find(target).map(|job| job.pid),
+            false => target.parse::<u32>().map_err(|_| format!("{}: not a pid or valid job spec", target)),
+        };
+        match pid {
+            Ok(pid) => pids.push(Some(pid)),
+            Err(e) => {
+                let _ = writeln!(io.stderr, "shell: wait: {}", e);
+                return 127;
+            }
+        }
+    }
+    if pids.is_empty() {
+        pids.push(None);
+    }
+    let mut res = 0;
+    for pid in pids {
+        res = loop {
+            match shell.jobs.wait_for(pid, Duration::from_millis(100)) {
+                Waited::Exited(status) => break status,
+                Waited::Unknown => {
+                    let _ = writeln!(io.stderr, "shell: wait: pid {} is not a child of this shell", pid.unwrap_or(0));
+                    break 127;
+                }
+                Waited::Running => {}
+            }
+            if let Some(signal) = traps::sleep(Duration::ZERO) {
+                return 128 + signal as i32;
+            }
+        };
+    }
+    res
+}
+
+/// `uuid` prints a random (version 4) UUID.
+fn uuid(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() > 1 {
+        let _ = writeln!(io.stderr, "shell: uuid: usage: uuid");
+        return 2;
+    }
+    let mut bytes = [0u8; 16];
+    if let Err(e) = random_bytes(&mut bytes) {
+        let _ = writeln!(io.stderr, "shell: uuid: {}", e);
+        return 1;
+    }
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    status(writeln!(io.stdout, "{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..]))
+}
+
+/// Parse a `sleep` interval: a decimal number of seconds, or of minutes,
+/// hours or days with an `m`, `h` or `d` suffix.
+fn parse_interval(arg: &str) -> Option<Duration> {
+    let (number, unit) = match arg.char_indices().last()? {
+        (i, 's') => (&arg[..i], 1.0),
+        (i, 'm') => (&arg[..i], 60.0),
+        (i, 'h') => (&arg[..i], 3600.0),
+        (i, 'd') => (&arg[..i], 86400.0),
+        _ => (arg, 1.0),
+    };
+    if !number.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    Duration::try_from_secs_f64(number.parse::<f64>().ok()? * unit).ok()
+}
+
+/// `sleep interval...` waits for the sum of the intervals, e.g. `sleep
+/// 0.2` or `sleep 1m 30`, without starting a process. A signal with a trap
+/// cuts it short, failing with 128 plus the signal number.
+fn sleep(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() < 2 {
+        let _ = writeln!(io.stderr, "shell: sleep: usage: sleep interval...");
+        return 2;
+    }
+    let mut total = Duration::ZERO;
+    for arg in &argv[1..] {
+        match parse_interval(arg) {
+            Some(interval) => total = total.saturating_add(interval),
+            None => {
+                let _ = writeln!(io.stderr, "shell: sleep: {}: invalid time interval", arg);
+                return 1;
+            }
+        }
+    }
+    match traps::sleep(total) {
+        Some(signal) => 128 + signal as i32,
+        None => 0,
+    }
+}
+
+/// `string split sep str...`, `string join sep str...`, `string match
+/// pattern str...` and `string replace [-a] old new str...` print their
+/// results one per line. `match` and `replace` fail when nothing matched.
+fn string(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    let (found, lines): (bool, Vec<String>) = match args.as_slice() {
+        ["split", sep, strings @ ..] if !sep.is_empty() => {
+            (true, strings.iter().flat_map(|s| s.split(sep)).map(str::to_owned).collect())
+        }
+        ["join", sep, strings @ ..] => (true, vec![strings.join(sep)]),
+        ["match", pattern, strings @ ..] => {
+            let matches: Vec<String> = strings.iter()
+                .filter(|s| shell.globber.matches(pattern, s).unwrap_or(false))
+                .map(|s| s.to_string())
+                .collect();
+            (!matches.is_empty(), matches)
+        }
+        ["replace", "-a", old, new, strings @ ..] | ["replace", old, new, strings @ ..] if !old.is_empty() => {
+            let all = args[1] == "-a";
+            let found = strings.iter().any(|s| s.contains(old));
+            (found, strings.iter()
+                .map(|s| if all { s.replace(old, new) } else { s.replacen(old, new, 1) })
+                .collect())
+        }
+        _ => {
+            let _ = writeln!(io.stderr, "shell: string: usage: string split sep str... | string join sep str...");
+            let _ = writeln!(io.stderr, "       string match pattern str... | string replace [-a] old new str...");
+            return 2;
+        }
+    };
+    match status(lines.iter().try_for_each(|line| writeln!(io.stdout, "{}", line))) {
+        0 if !found => 1,
+        code => code,
+    }
+}
+
+/// `path` without `.` components, repeated slashes and `..` after a
+/// named directory, worked out from the text alone.
+fn normalize(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." if parts.last().is_some_and(|last| *last != "..") => {
+                parts.pop();
+            }
+            // `..` stays at the root of an absolute path.
+            ".." if absolute => {}
+            _ => parts.push(part),
+        }
+    }
+    match (absolute, parts.is_empty()) {
+        (true, _) => format!("/{}", parts.join("/")),
+        (false, true) => String::from("."),
+        (false, false) => parts.join("/"),
+    }
+}
+
+/// `path basename path...`, `path dirname path...` and `path normalize
+/// path...` print each result on a line.
+fn path(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let paths = argv.get(2..).unwrap_or_default();
+    let transform: fn(&str) -> String = match argv.get(1).map(String::as_str) {
+        Some("basename") => |path| match Path::new(path).file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => normalize(path),
+        },
+        Some("dirname") => |path| match Path::new(path).parent() {
+            Some(parent) if parent.as_os_str().is_empty() => String::from("."),
+            Some(parent) => parent.to_string_lossy().into_owned(),
+            None => normalize(path),
+        },
+        Some("normalize") => normalize,
+        _ => {
+            let _ = writeln!(io.stderr, "shell: path: usage: path basename|dirname|normalize path...");
+            return 2;
+        }
+    };
+    status(paths.iter().try_for_each(|path| writeln!(io.stdout, "{}", transform(path))))
+}
+
+/// Optional cargo features this build was compiled with.
+const FEATURES: &[&str] = &[
+    #[cfg(feature = "fetch")]
+    "fetch",
+    #[cfg(feature = "sqlite")]
+    "sqlite",
+];
+
+/// Quote `text` as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut res = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+/// `shellinfo` describes the shell: its version, features, the files it
+/// uses and the options that are on. `shellinfo --json` prints the same
+/// as a JSON object, with every option and its state.
+fn shellinfo(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let json = match argv.get(1).map(String::as_str) {
+        None => false,
+        Some("--json") if argv.len() == 2 => true,
+        _ => {
+            let _ = writeln!(io.stderr, "shell: shellinfo: usage: shellinfo [--json]");
+            return 2;
+        }
+    };
+    let history = shell.history.lock().unwrap().store().location();
+    let crash_report = shell.vars.get("RSH_CRASH_REPORT").filter(|path| !path.is_empty());
+    if json {
+        let file = |path: &Option<String>| path.as_deref().map_or_else(|| String::from("null"), json_string);
+        let features: Vec<String> = FEATURES.iter().map(|feature| json_string(feature)).collect();
+        let options: Vec<String> = shell.options.iter()
+            .map(|(name, value)| format!("{}: {}", json_string(name), value))
+            .collect();
+        return status(writeln!(io.stdout,
+            "{{\"version\": {}, \"features\": [{}], \"files\": {{\"history\": {}, \"crash_report\": {}}}, \"options\": {{{}}}}}",
+            json_string(env!("CARGO_PKG_VERSION")), features.join(", "),
+            file(&history), file(&crash_report), options.join(", ")));
+    }
+    let on: Vec<&str> = shell.options.iter().filter(|&(_, value)| value).map(|(name, _)| name).collect();
+    let none = || String::from("none");
+    status((|| {
+        writeln!(io.stdout, "{:<15}\t{}", "version", env!("CARGO_PKG_VERSION"))?;
+        writeln!(io.stdout, "{:<15}\t{}", "features", if FEATURES.is_empty() { none() } else { FEATURES.join(" ") })?;
+        writeln!(io.stdout, "{:<15}\t{}", "history", history.unwrap_or_else(none))?;
+        writeln!(io.stdout, "{:<15}\t{}", "crash report", crash_report.unwrap_or_else(none))?;
+        writeln!(io.stdout, "{:<15}\t{}", "options", on.join(" "))
+    })())
+}
+
+/// `test expression` and `[ expression ]` succeed when the expression
+/// holds: file tests such as `-f file`, `-d dir` and `-x file`, string
+/// tests (`-z`, `-n`, `=`, `!=`) and integer comparisons (`-eq`, `-lt`
+/// and the like), combined with `!`, `-a`, `-o` and parentheses. A
+/// malformed expression makes the status 2.
+fn test(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let args = match argv[0].as_str() {
+        "[" => match argv[1..].split_last() {
+            Some((last, args)) if last == "]" => args,
+            _ => {
+                let _ = writeln!(io.stderr, "shell: [: missing `]'");
+                return 2;
+            }
+        },
+        _ => &argv[1..],
+    };
+    match predicates::evaluate(args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: {}: {}", argv[0], e);
+            2
+        }
+    }
+}
+
+/// `true` and `:` do nothing and succeed, run without starting a process;
+/// `:` is handy for expansions run only for their side effects.
+fn succeed(_shell: &mut Shell, _argv: &[String], _io: &mut Io) -> i32 {
+    0
+}
+
+/// `false` does nothing and fails.
+fn fail(_shell: &mut Shell, _argv: &[String], _io: &mut Io) -> i32 {
+    1
+}
+
+/// `trap action condition...` runs `action` when one of the signals
+/// arrives or, for `EXIT`, when the shell exits; an empty action ignores
+/// the signals. `trap - condition...` (or a lone `trap condition`) resets
+/// them and `trap` alone lists the traps.
+fn trap(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let args = match argv.get(1).map(String::as_str) {
+        Some("--") => &argv[2..],
+        _ => &argv[1..],
+    };
+    let (action, specs) = match args {
+        [] => {
+            return status(shell.traps.iter().try_for_each(|(condition, action)| {
+                writeln!(io.stdout, "trap -- {} {}", single_quote(action), condition.name())
+            }));
+        }
+        [spec] if Condition::parse(spec).is_some() => (None, args),
+        [action, specs @ ..] if !specs.is_empty() => ((action != "-").then_some(action), specs),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: trap: usage: trap [action condition ...]");
+            return 2;
+        }
+    };
+    let mut status = 0;
+    for spec in specs {
+        let Some(condition) = Condition::parse(spec) else {
+            let _ = writeln!(io.stderr, "shell: trap: {}: invalid signal specification", spec);
+            status = 1;
+            continue;
+        };
+        let res = match action {
+            Some(action) => shell.traps.set(condition, action.clone()),
+            None => shell.traps.reset(condition),
+        };
+        if let Err(e) = res {
+            let _ = writeln!(io.stderr, "shell: trap: {}: {}", spec, e.desc());
+            status = 1;
+        }
+    }
+    status
+}
+
+/// Quote `value` so that it reads back as a single word.
+pub fn single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// `abbr name text...` defines an abbreviation, `abbr name` prints it,
+/// `abbr -e name...` erases abbreviations and `abbr` alone lists every one.
+fn abbr(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let print = |io: &mut Io, name: &str, text: &str| writeln!(io.stdout, "abbr {} {}", name, single_quote(text));
+    match argv.get(1).map(String::as_str) {
+        None => status(shell.abbreviations.iter().try_for_each(|(name, text)| print(io, name, text))),
+        Some("-e") => {
+            let mut status = 0;
+            for name in &argv[2..] {
+                if shell.abbreviations.remove(name).is_none() {
+                    let _ = writeln!(io.stderr, "shell: abbr: {}: not found", name);
+                    status = 1;
+                }
+            }
+            status
+        }
+        Some(name) if argv.len() == 2 => match shell.abbreviations.get(name) {
+            Some(text) => status(print(io, name, text)),
+            None => {
+                let _ = writeln!(io.stderr, "shell: abbr: {}: not found", name);
+                1
+            }
+        },
+        Some(name) => {
+            shell.abbreviations.insert(name.to_owned(), argv[2..].join(" "));
+            0
+        }
+    }
+}
+
+/// `alias name=value` defines an alias, `alias name` prints it and `alias`
+/// alone lists every alias.
+fn alias(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() == 1 {
+        return status(shell.aliases.iter()
+            .try_for_each(|(name, value)| {
+                writeln!(io.stdout, "alias {}={}", name, single_quote(value))
+            }));
+    }
+    let mut status = 0;
+    for arg in &argv[1..] {
+        match arg.split_once('=') {
+            Some((name, value)) if !name.is_empty() => {
+                shell.aliases.insert(name.to_owned(), value.to_owned());
+            }
+            _ => match shell.aliases.get(arg.as_str()) {
+                Some(value) => {
+                    if writeln!(io.stdout, "alias {}={}", arg, single_quote(value)).is_err() {
+                        status = 1;
+                    }
+                }
+                None => {
+                    let _ = writeln!(io.stderr, "shell: alias: {}: not found", arg);
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+/// `keyword name=operator` makes the word `name` stand for an operator
+/// such as `&&` or `|` wherever it appears unquoted, `keyword name` prints
+/// it, `keyword -d name` removes it and `keyword` alone lists every one;
+/// a name already defined must be quoted. `set -o strict` turns them all
+/// off.
+fn keyword(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() == 1 {
+        return status(shell.keywords.iter()
+            .try_for_each(|(name, op)| writeln!(io.stdout, "keyword {}={}", name, single_quote(op))));
+    }
+    if argv[1] == "-d" {
+        let mut status = 0;
+        for name in &argv[2..] {
+            if shell.keywords.remove(name).is_none() {
+                let _ = writeln!(io.stderr, "shell: keyword: {}: not found", name);
+                status = 1;
+            }
+        }
+        return status;
+    }
+    let mut status = 0;
+    for arg in &argv[1..] {
+        match arg.split_once('=') {
+            Some((name, op)) if !name.is_empty() => match lexer::operator(op) {
+                Some(op) if name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') => {
+                    shell.keywords.insert(name.to_owned(), op);
+                }
+                Some(_) => {
+                    let _ = writeln!(io.stderr, "shell: keyword: {}: invalid keyword name", name);
+                    status = 1;
+                }
+                None => {
+                    let _ = writeln!(io.stderr, "shell: keyword: {}: not an operator", op);
+                    status = 1;
+                }
+            },
+            _ => match shell.keywords.get(arg.as_str()) {
+                Some(op) => {
+                    if writeln!(io.stdout, "keyword {}={}", arg, single_quote(op)).is_err() {
+                        status = 1;
+                    }
+                }
+                None => {
+                    let _ = writeln!(io.stderr, "shell: keyword: {}: not found", arg);
+                    status = 1;
+                }
+            },
+        }
+    }
+    status
+}
+
+/// Read all of `file`, or of stdin for `-`.
+fn read_input(file: &str, io: &mut Io) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    match file {
+        "-" => io.stdin.read_to_end(&mut data)?,
+        _ => File::open(file)?.read_to_end(&mut data)?,
+    };
+    Ok(data)
+}
+
+/// `b64 encode [file]` prints `file`, or stdin, in base64, in lines of 76
+/// characters; `b64 decode [file]` turns it back.
+fn b64(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    let (command, file) = match args.as_slice() {
+        [command @ ("encode" | "decode")] => (*command, "-"),
+        [command @ ("encode" | "decode"), file] => (*command, *file),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: b64: usage: b64 encode|decode [file]");
+            return 2;
+        }
+    };
+    let data = match read_input(file, io) {
+        Ok(data) => data,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: b64: {}: {}", file, e);
+            return 1;
+        }
+    };
+    if command == "encode" {
+        let encoded = base64::encode(&data);
+        return status(encoded.as_bytes().chunks(76).try_for_each(|line| {
+            io.stdout.write_all(line)?;
+            io.stdout.write_all(b"\n")
+        }));
+    }
+    match base64::decode(&String::from_utf8_lossy(&data)) {
+        Ok(decoded) => status(io.stdout.write_all(&decoded)),
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: b64: {}", e);
+            1
+        }
+    }
+}
+
+/// `plugin` lists the plugins, whether each is built into the program or
+/// a script from the plugins directory, and whether it is enabled;
+/// `plugin enable name` and `plugin disable name` turn one on or off, in
+/// this session and later ones.
+fn plugin(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let (name, on) = match &argv[1..] {
+        [] => {
+            return status(shell.plugins.iter().try_for_each(|(name, kind, enabled)| {
+                let state = if enabled { "enabled" } else { "disabled" };
+                writeln!(io.stdout, "{:<15}\t{:<7}\t{}", name, kind, state)
+            }));
+        }
+        [action, name] if action == "enable" => (name, true),
+        [action, name] if action == "disable" => (name, false),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: plugin: usage: plugin [enable name | disable name]");
+            return 2;
+        }
+    };
+    if let Err(e) = plugins::enable(shell, name, on) {
+        let _ = writeln!(io.stderr, "shell: plugin: {}", e);
+        return 1;
+    }
+    match plugins::save(shell) {
+        Ok(()) => 0,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: plugin: {}", e);
+            1
+        }
+    }
+}
+
+/// `printf format [arg...]` prints the arguments as `format` says, with
+/// `%s`, `%d`, `%f`, `%x` and the other C conversions, their flags, width
+/// and precision, and backslash escapes. The format is reused until every
+/// argument is used up.
+fn printf(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let Some(format) = argv.get(1) else {
+        let _ = writeln!(io.stderr, "shell: printf: usage: printf format [arg...]");
+        return 2;
+    };
+    match crate::printf::printf(format, &argv[2..]) {
+        Ok((out, errors)) => {
+            for error in &errors {
+                let _ = writeln!(io.stderr, "shell: printf: {}", error);
+            }
+            match status(io.stdout.write_all(&out)) {
+                0 if !errors.is_empty() => 1,
+                res => res,
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: printf: {}", e);
+            1
+        }
+    }
+}
+
+/// `trash [-rfdv] file...` moves files to the XDG trash rather than
+/// deleting them, taking the options of `rm` so that `set -o trash` can
+/// send `rm` here. `trash --list` shows what is in the trash, `trash
+/// --restore file...` puts files back, by their original path or their
+/// name in the trash, and `trash --empty` deletes everything for good.
+fn trash(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr,
+                         "shell: trash: usage: trash [-rfdv] file... | --list | --restore file... | --empty");
+        2
+    };
+    let dir = match trash::home(shell) {
+        Ok(dir) => dir,
+        Err(e) => {
+            let _ = writeln!(io.stderr, "shell: trash: {}", e);
+            return 1;
+        }
+    };
+    match argv.get(1).map(String::as_str) {
+        Some("--list") if argv.len() == 2 => {
+            return match trash::list(&dir) {
+                Ok(entries) => status(entries.iter().try_for_each(|entry| {
+                    writeln!(io.stdout, "{}\t{}", entry.deleted, entry.path.display())
+                })),
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "shell: trash: {}", e);
+                    1
+                }
+            };
+        }
+        Some("--empty") if argv.len() == 2 => {
+            return match trash::empty(&dir) {
+                Ok(()) => 0,
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "shell: trash: {}", e);
+                    1
+                }
+            };
+        }
+        Some("--restore") if argv.len() > 2 => {
+            let entries = match trash::list(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    let _ = writeln!(io.stderr, "shell: trash: {}", e);
+                    return 1;
+                }
+            };
+            let mut res = 0;
+            for file in &argv[2..] {
+                let path = std::path::absolute(file).unwrap_or_else(|_| PathBuf::from(file));
+                // The most recently trashed file of that path or name.
+                let entry = entries.iter().rev().find(|entry| entry.path == path || entry.name == *file);
+                let restored = match entry {
+                    Some(entry) => trash::restore(&dir, entry),
+                    None => Err(io::Error::new(io::ErrorKind::NotFound, "not in the trash")),
+                };
+                if let Err(e) = restored {
+                    let _ = writeln!(io.stderr, "shell: trash: {}: {}", file, e);
+                    res = 1;
+                }
+            }
+            return res;
+        }
+        Some(arg) if arg.starts_with("--") && arg.len() > 2 && !RM_OPTIONS.contains(&arg) => return usage(io),
+        _ => {}
+    }
+    let (mut recursive, mut force, mut dirs, mut verbose) = (false, false, false, false);
+    let mut files = Vec::new();
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => files.extend(args.by_ref()),
+            "--recursive" => recursive = true,
+            "--force" => force = true,
+            "--dir" => dirs = true,
+            "--verbose" => verbose = true,
+            "--interactive" => {}
+            flags if flags.starts_with('-') && flags.len() > 1 => {
+                for flag in flags[1..].chars() {
+                    match flag {
+                        'r' | 'R' => recursive = true,
+                        'f' => force = true,
+                        'd' => dirs = true,
+                        'v' => verbose = true,
+                        // Nothing is lost for good, so there is no need to ask.
+                        'i' | 'I' => {}
+                        _ => return usage(io),
+                    }
+                }
+            }
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        return if force { 0 } else { usage(io) };
+    }
+    let mut res = 0;
+    for file in files {
+        let path = Path::new(file);
+        let checked = match path.symlink_metadata() {
+            Err(e) if e.kind() == io::ErrorKind::NotFound && force => continue,
+            Err(e) => Err(e.to_string()),
+            Ok(meta) if meta.is_dir() && !recursive => match dirs {
+                true if fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none()) => Ok(()),
+                true => Err(String::from("Directory not empty")),
+                false => Err(String::from("Is a directory")),
+            },
+            Ok(_) => Ok(()),
+        };
+        match checked.and_then(|()| trash::put(&dir, path).map_err(|e| e.to_string())) {
+            Ok(()) if verbose => {
+                let _ = writeln!(io.stdout, "trashed {}", single_quote(file));
+            }
+            Ok(()) => {}
+            Err(e) => {
+                let _ = writeln!(io.stderr, "shell: trash: {}: {}", file, e);
+                res = 1;
+            }
+        }
+    }
+    res
+}
+
+/// The long options of `rm` that `trash` understands.
+const RM_OPTIONS: &[&str] = &["--recursive", "--force", "--dir", "--verbose", "--interactive"];
+
+/// `type name...` tells what running each name would do: expand an
+/// alias, start a compound command, run a builtin or run a program found
+/// in `$PATH`, in that order. `type -p name...` prints just the path of
+/// the program, as `which` does. Fails if any name is not found.
+fn type_of(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let path_only = argv.get(1).is_some_and(|arg| arg == "-p");
+    let names = &argv[1 + path_only as usize..];
+    if names.is_empty() {
+        let _ = writeln!(io.stderr, "shell: type: usage: type [-p] name...");
+        return 2;
+    }
+    let path = shell.vars.get("PATH").unwrap_or_default();
+    let mut res = 0;
+    for name in names {
+        let program = || runner::find_command(name, &path);
+        let written = if path_only {
+            match program() {
+                Some(program) => writeln!(io.stdout, "{}", program.display()),
+                None => {
+                    res = 1;
+                    continue;
+                }
+            }
+        } else if let Some(value) = shell.aliases.get(name.as_str()) {
+            writeln!(io.stdout, "{} is aliased to {}", name, single_quote(value))
+        } else if RESERVED.contains(&name.as_str()) {
+            writeln!(io.stdout, "{} is a shell keyword", name)
+        } else if find(name).is_some() {
+            writeln!(io.stdout, "{} is a shell builtin", name)
+        } else if let Some(program) = shell.command_hash.get(name) {
+            writeln!(io.stdout, "{} is hashed ({})", name, program.display())
+        } else if let Some(program) = program() {
+            writeln!(io.stdout, "{} is {}", name, program.display())
+        } else {
+            let _ = writeln!(io.stderr, "shell: type: {}: not found", name);
+            res = 1;
+            continue;
+        };
+        if status(written) != 0 {
+            return 1;
+        }
+    }
+    res
+}
+
+/// The limits `ulimit` knows: its flag, the resource, what it is called
+/// in `ulimit -a` and the size of the units it is given in.
+const LIMITS: &[(char, libc::__rlimit_resource_t, &str, u64)] = &[
+    ('c', libc::RLIMIT_CORE, "core file size (blocks)", 512),
+    ('d', libc::RLIMIT_DATA, "data seg size (kbytes)", 1024),
+    ('f', libc::RLIMIT_FSIZE, "file size (blocks)", 512),
+    ('l', libc::RLIMIT_MEMLOCK, "max locked memory (kbytes)", 1024),
+    ('m', libc::RLIMIT_RSS, "max memory size (kbytes)", 1024),
+    ('n', libc::RLIMIT_NOFILE, "open files", 1),
+    ('s', libc::RLIMIT_STACK, "stack size (kbytes)", 1024),
+    ('t', libc::RLIMIT_CPU, "cpu time (seconds)", 1),
+    ('u', libc::RLIMIT_NPROC, "max user processes", 1),
+    ('v', libc::RLIMIT_AS, "virtual memory (kbytes)", 1024),
+];
+
+/// `ulimit [-H|-S] [-a | -cdflmnstuv] [limit]` prints or sets the limits
+/// on the resources of the shell and the commands it runs: the soft limit
+/// by default, the hard one with `-H`. Setting without `-H` or `-S` sets
+/// both. Without a flag it is the file size limit.
+fn ulimit(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let usage = |io: &mut Io| {
+        let _ = writeln!(io.stderr, "shell: ulimit: usage: ulimit [-H|-S] [-a | -cdflmnstuv] [limit]");
+        2
+    };
+    let (mut hard, mut soft, mut all) = (false, false, false);
+    let mut flags = Vec::new();
+    let mut value = None;
+    for arg in &argv[1..] {
+        match arg.strip_prefix('-').filter(|flags| !flags.is_empty()) {
+            Some(letters) if value.is_none() => {
+                for c in letters.chars() {
+                    match c {
+                        'H' => hard = true,
+                        'S' => soft = true,
+                        'a' => all = true,
+                        _ => match LIMITS.iter().find(|limit| limit.0 == c) {
+                            Some(limit) => flags.push(limit),
+                            None => return usage(io),
+                        },
+                    }
+                }
+            }
+            _ if value.is_none() => value = Some(arg),
+            _ => return usage(io),
+        }
+    }
+    if all {
+        if value.is_some() || !flags.is_empty() {
+            return usage(io);
+        }
+        flags = LIMITS.iter().collect();
+    }
+    if flags.is_empty() {
+        flags.extend(LIMITS.iter().find(|limit| limit.0 == 'f'));
+    }
+    let show = |limit: libc::rlim_t, unit: u64| match limit {
+        libc::RLIM_INFINITY => String::from("unlimited"),
+        limit => (limit / unit).to_string(),
+    };
+    let Some(value) = value else {
+        for &&(flag, resource, name, unit) in &flags {
+            let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+            if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+                let _ = writeln!(io.stderr, "shell: ulimit: {}", io::Error::last_os_error());
+                return 1;
+            }
+            let current = show(if hard { limit.rlim_max } else { limit.rlim_cur }, unit);
+            let res = match flags.len() {
+                1 => writeln!(io.stdout, "{}", current),
+                _ => writeln!(io.stdout, "{:<28}(-{}) {}", name, flag, current),
+            };
+            if let Err(e) = res {
+                return status(Err(e));
+            }
+        }
+        return 0;
+    };
+    if flags.len() > 1 {
+        return usage(io);
+    }
+    let (flag, resource, _, unit) = *flags[0];
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(resource, &mut limit) } != 0 {
+        let _ = writeln!(io.stderr, "shell: ulimit: {}", io::Error::last_os_error());
+        return 1;
+    }
+    let new = match value.as_str() {
+        "unlimited" => libc::RLIM_INFINITY,
+        "hard" => limit.rlim_max,
+        "soft" => limit.rlim_cur,
+        _ => match value.parse::<u64>().ok().and_then(|n| n.checked_mul(unit)) {
+            Some(n) => n as libc::rlim_t,
+            None => {
+                let _ = writeln!(io.stderr, "shell: ulimit: {}: invalid number", value);
+                return 1;
+            }
+        },
+    };
+    if hard || !soft {
+        limit.rlim_max = new;
+    }
+    if soft || !hard {
+        limit.rlim_cur = new;
+    }
+    if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+        let _ = writeln!(io.stderr, "shell: ulimit: -{}: cannot modify limit: {}", flag, io::Error::last_os_error());
+        return 1;
+    }
+    0
+}
+
+/// The file-creation mask of the process.
+fn current_umask() -> u32 {
+    // Reading the mask means setting it, so it is put straight back.
+    let mask = unsafe { libc::umask(0) };
+    unsafe { libc::umask(mask) };
+    mask as u32 & 0o777
+}
+
+/// `perms` changed by the symbolic mode `spec` of `chmod`, such as
+/// `u=rwx,go=rx` or `a+r,g-w`.
+fn symbolic_mode(mut perms: u32, spec: &str) -> Option<u32> {
+    for clause in spec.split(',') {
+        let ops = clause.find(['+', '-', '='])?;
+        let mut who = 0;
+        for c in clause[..ops].chars() {
+            who |= match c {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return None,
+            };
+        }
+        if who == 0 {
+            who = 0o777;
+        }
+        let mut rest = &clause[ops..];
+        while let Some(op) = rest.chars().next() {
+            let end = rest[1..].find(['+', '-', '=']).map_or(rest.len(), |end| end + 1);
+            let mut bits = 0;
+            for c in rest[1..end].chars() {
+                bits |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    _ => return None,
+                };
+            }
+            match op {
+                '+' => perms |= bits & who,
+                '-' => perms &= !(bits & who),
+                _ => perms = (perms & !who) | (bits & who),
+            }
+            rest = &rest[end..];
+        }
+    }
+    Some(perms)
+}
+
+/// `umask` prints the file-creation mask, and `umask -S` the permissions it
+/// leaves, as `u=rwx,g=rx,o=rx`. `umask mode` sets it, for the shell and
+/// the commands it runs, to an octal mask or from symbolic permissions
+/// such as `u=rwx,go=rx` or `g-w`.
+fn umask(_shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let (symbolic, mode) = match &argv[1..] {
+        [] => (false, None),
+        [flag] if flag == "-S" => (true, None),
+        [mode] => (false, Some(mode)),
+        [flag, mode] if flag == "-S" => (true, Some(mode)),
+        _ => {
+            let _ = writeln!(io.stderr, "shell: umask: usage: umask [-S] [mode]");
+            return 2;
+        }
+    };
+    let mut mask = current_umask();
+    if let Some(mode) = mode {
+        let parsed = match mode.bytes().all(|b| b.is_ascii_digit()) {
+            true => u32::from_str_radix(mode, 8).ok().filter(|&mask| mask <= 0o777),
+            false => symbolic_mode(!mask & 0o777, mode).map(|perms| !perms & 0o777),
+        };
+        let Some(parsed) = parsed else {
+            let _ = writeln!(io.stderr, "shell: umask: {}: invalid mode", mode);
+            return 1;
+        };
+        unsafe { libc::umask(parsed as libc::mode_t) };
+        mask = parsed;
+        if !symbolic {
+            return 0;
+        }
+    }
+    if !symbolic {
+        return status(writeln!(io.stdout, "{:04o}", mask));
+    }
+    let perms = |shift: u32| {
+        let bits = (!mask >> shift) & 0o7;
+        [(0o4, 'r'), (0o2, 'w'), (0o1, 'x')].iter()
+            .filter(|&&(bit, _)| bits & bit != 0)
+            .map(|&(_, c)| c)
+            .collect::<String>()
+    };
+    status(writeln!(io.stdout, "u={},g={},o={}", perms(6), perms(3), perms(0)))
+}
+
+/// `unalias name...` removes aliases; `unalias -a` removes all of them.
+fn unalias(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    if argv.len() == 1 {
+        let _ = writeln!(io.stderr, "shell: unalias: usage: unalias [-a] name [name ...]");
+        return 2;
+    }
+    if argv[1] == "-a" {
+        shell.aliases.clear();
+        return 0;
+    }
+    let mut status = 0;
+    for name in &argv[1..] {
+        if shell.aliases.remove(name.as_str()).is_none() {
+            let _ = writeln!(io.stderr, "shell: unalias: {}: not found", name);
+            status = 1;
+        }
+    }
+    status
+}
+
+/// `unset [-v] name...` removes variables; `name[index]` removes one
+/// element of an array.
+fn unset(shell: &mut Shell, argv: &[String], io: &mut Io) -> i32 {
+    let names = match argv.get(1).map(String::as_str) {
+        Some("-v") => &argv[2..],
+        _ => &argv[1..],
+    };
+    let mut status = 0;
+    for name in names {
+        if let Err(e) = variables::unset(&mut shell.vars, &shell.options, name) {
+            let _ = writeln!(io.stderr, "{}", e);
+            status = 1;
+        }
+    }
+    status
+}
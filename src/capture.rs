@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::ChildStderr;
+use std::thread;
+
+use crate::{pager, Shell};
+
+/// How much of each stream is kept when `$RSH_CAPTURE_LIMIT` is unset.
+const DEFAULT_LIMIT: usize = 64 * 1024;
+
+/// The tail of what the last captured command wrote, shown by `lastout`.
+#[derive(Default)]
+pub struct LastOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Whether the output of a foreground command is recorded: under `set -o
+/// capture`, when stdout is a terminal.
+pub fn applies(shell: &Shell) -> bool {
+    shell.options.capture && io::stdout().is_terminal()
+}
+
+/// A reader keeping the last `limit` bytes that pass through it.
+struct Recorder<R> {
+    inner: R,
+    limit: usize,
+    tail: VecDeque<u8>,
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let kept = &buf[n.saturating_sub(self.limit)..n];
+        let excess = (self.tail.len() + kept.len()).saturating_sub(self.limit);
+        self.tail.drain(..excess);
+        self.tail.extend(kept);
+        Ok(n)
+    }
+}
+
+impl<R> Recorder<R> {
+    fn new(inner: R, limit: usize) -> Self {
+        Recorder { inner, limit, tail: VecDeque::new() }
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.tail.into()
+    }
+}
+
+/// Copy `from` to `to` a piece at a time, so that partial lines such as
+/// prompts show up as soon as they are written.
+fn relay(mut from: impl Read, mut to: impl Write) -> io::Result<()> {
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        to.write_all(&buf[..n])?;
+        to.flush()?;
+    }
+}
+
+/// Pass on the output of a foreground command whose stdout, and maybe
+/// stderr, the shell reads itself: through the pager when `paged`, and
+/// keeping the tail of each stream for `lastout` when `captured`.
+pub fn show(shell: &mut Shell, stdout: File, stderr: Option<ChildStderr>, paged: bool, captured: bool) {
+    let limit = match captured {
+        true => shell.vars.get("RSH_CAPTURE_LIMIT")
+            .and_then(|limit| crate::exec::parse_size(&limit))
+            .map_or(DEFAULT_LIMIT, |limit| limit as usize),
+        false => 0,
+    };
+    let errors = stderr.map(|stderr| thread::spawn(move || {
+        let mut recorder = Recorder::new(stderr, limit);
+        let _ = relay(&mut recorder, io::stderr());
+        recorder.finish()
+    }));
+    let mut recorder = Recorder::new(stdout, limit);
+    let res = match paged {
+        true => pager::show(shell, &mut recorder),
+        false => relay(&mut recorder, io::stdout()),
+    };
+    match res {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => eprintln!("shell: {}", e),
+        _ => {}
+    }
+    let stderr = errors.and_then(|errors| errors.join().ok()).unwrap_or_default();
+    if captured {
+        let stdout = recorder.finish();
+        shell.vars.set("LAST_STDOUT", String::from_utf8_lossy(&stdout).trim_end_matches('\n').to_owned());
+        shell.last_output = LastOutput { stdout, stderr };
+    }
+}
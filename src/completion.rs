@@ -0,0 +1,724 @@
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::{CmdKind, Highlighter};
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use crate::aliases::{Abbreviations, Aliases, Keywords};
+use crate::builtins::BUILTINS;
+use crate::expand::unquote;
+use crate::globbing::{GlobOptions, Globber};
+use crate::highlight;
+use crate::json::{self, Value};
+use crate::parser::{self, ParseError};
+use crate::profile;
+use crate::prompt;
+use crate::runner;
+use crate::workers::{Cancel, Pool, Task};
+use crate::{home_dir, NamedDirs, PathMatcher, Shell};
+
+/// How long a key press waits for completions computed in the background.
+/// Slower ones are abandoned; since scanned directories stay cached, the
+/// next attempt is usually quick.
+const BUDGET: Duration = Duration::from_millis(100);
+
+/// How to complete the arguments of a command, as set with the
+/// `complete` builtin or read from a spec file.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct CompletionSpec {
+    /// Offer file names (`complete -f`).
+    pub files: bool,
+    /// Offer directory names (`complete -d`).
+    pub dirs: bool,
+    /// Offer these words (`complete -W 'words'`).
+    pub words: Vec<String>,
+    /// Offer these for words starting with `-`.
+    pub flags: Vec<String>,
+    /// Offer these names, each completing the words after it by its own
+    /// spec.
+    pub subcommands: BTreeMap<String, CompletionSpec>,
+}
+
+impl CompletionSpec {
+    /// The spec described by the JSON `value`: an object with any of
+    /// `words` and `flags` (arrays of strings), `files` and `dirs`
+    /// (booleans) and `subcommands` (an object of specs by name).
+    pub fn from_json(value: &Value) -> Result<CompletionSpec, String> {
+        let Value::Object(members) = value else {
+            return Err(String::from("a spec must be an object"));
+        };
+        let strings = |name: &str, value: &Value| match value {
+            Value::Array(items) => items.iter()
+                .map(|item| match item {
+                    Value::String(text) => Ok(text.clone()),
+                    _ => Err(format!("{}: expected strings", name)),
+                })
+                .collect(),
+            _ => Err(format!("{}: expected an array", name)),
+        };
+        let mut spec = CompletionSpec::default();
+        for (name, value) in members {
+            match (name.as_str(), value) {
+                ("files", Value::Bool(files)) => spec.files = *files,
+                ("dirs", Value::Bool(dirs)) => spec.dirs = *dirs,
+                ("words", _) => spec.words = strings(name, value)?,
+                ("flags", _) => spec.flags = strings(name, value)?,
+                ("subcommands", Value::Object(subcommands)) => {
+                    for (name, value) in subcommands {
+                        let sub = CompletionSpec::from_json(value).map_err(|e| format!("{}: {}", name, e))?;
+                        spec.subcommands.insert(name.clone(), sub);
+                    }
+                }
+                _ => return Err(format!("{}: unexpected member", name)),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// The directory completion spec files are read from:
+/// `$XDG_CONFIG_HOME/rsh/completions`, or `~/.config/rsh/completions`.
+pub fn spec_dir(shell: &Shell) -> Option<PathBuf> {
+    crate::config_home(shell).map(|config| config.join("rsh/completions"))
+}
+
+/// The specs in the `name.json` files in `dir`, for the command `name`
+/// each, and the problems with the files that couldn't be used.
+pub fn load_specs(dir: &Path) -> (Vec<(String, CompletionSpec)>, Vec<String>) {
+    let (mut specs, mut errors) = (Vec::new(), Vec::new());
+    for path in fs::read_dir(dir).into_iter().flatten().flatten().map(|entry| entry.path()) {
+        let Some(name) = path.file_name().and_then(|name| name.to_str()?.strip_suffix(".json")) else {
+            continue;
+        };
+        let spec = fs::read_to_string(&path).map_err(|e| e.to_string())
+            .and_then(|text| json::parse(&text))
+            .and_then(|value| CompletionSpec::from_json(&value));
+        match spec {
+            Ok(spec) => specs.push((name.to_owned(), spec)),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (specs, errors)
+}
+
+/// The completion specs of commands, by command name.
+pub type CompletionSpecs = BTreeMap<String, CompletionSpec>;
+
+/// Shell state that completion providers draw on, refreshed before each
+/// prompt.
+pub struct Env {
+    pub named_dirs: NamedDirs,
+    pub globber: Arc<Globber>,
+    /// The value of `$PATH`.
+    pub path: String,
+    /// The names of aliases and of builtins added with
+    /// `ShellBuilder::with_builtin`, which highlighting shows as commands.
+    pub commands: BTreeSet<String>,
+    /// The specs set with the `complete` builtin.
+    pub specs: CompletionSpecs,
+    /// The directory spec files are read from.
+    pub spec_dir: Option<PathBuf>,
+    /// The specs in `spec_dir`, read when first needed.
+    spec_files: OnceCell<CompletionSpecs>,
+    /// Whether to report how long reading the spec files took, as under
+    /// `--profile-startup`.
+    pub profile: bool,
+    /// The abbreviations defined with `abbr`.
+    pub abbreviations: Abbreviations,
+    /// Whether Tab on a glob pattern replaces it with its matches, as set
+    /// by `RSH_GLOB_COMPLETE=expand`, rather than listing them.
+    pub glob_expand: bool,
+    /// How glob patterns match, from the shell's options.
+    pub glob_options: GlobOptions,
+    /// Threads for work too slow to do while a key press waits.
+    pub pool: Pool,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env {
+            named_dirs: NamedDirs::default(),
+            globber: Arc::default(),
+            path: String::new(),
+            commands: BTreeSet::new(),
+            specs: CompletionSpecs::new(),
+            spec_dir: None,
+            spec_files: OnceCell::new(),
+            profile: false,
+            abbreviations: Abbreviations::new(),
+            glob_expand: false,
+            glob_options: GlobOptions::default(),
+            pool: Pool::new(2),
+        }
+    }
+}
+
+impl Env {
+    /// Read spec files from `dir` from now on.
+    pub fn set_spec_dir(&mut self, dir: Option<PathBuf>) {
+        if dir != self.spec_dir {
+            self.spec_dir = dir;
+            self.spec_files = OnceCell::new();
+        }
+    }
+
+    /// The spec for the command `name`: the one set with `complete`, or
+    /// else the one in its spec file.
+    pub fn spec(&self, name: &str) -> Option<&CompletionSpec> {
+        self.specs.get(name).or_else(|| self.spec_files().get(name))
+    }
+
+    fn spec_files(&self) -> &CompletionSpecs {
+        self.spec_files.get_or_init(|| {
+            let Some(dir) = &self.spec_dir else {
+                return CompletionSpecs::new();
+            };
+            let start = Instant::now();
+            let (specs, errors) = load_specs(dir);
+            for e in errors {
+                eprintln!("shell: completions: {}", e);
+            }
+            if self.profile {
+                profile::print("completions", start.elapsed());
+            }
+            specs.into_iter().collect()
+        })
+    }
+}
+
+/// What is being completed.
+pub struct CompletionContext<'a> {
+    pub env: &'a Env,
+    /// The word under the cursor, as typed, including any quotes.
+    pub word: &'a str,
+    /// The words of the simple command before the word under the cursor;
+    /// empty when it is the command name.
+    pub words: &'a [&'a str],
+}
+
+/// A source of completions, such as file names or git branches.
+pub trait CompletionProvider {
+    /// Candidates for `ctx`, or `None` to leave it to other providers.
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>>;
+
+    /// Get ready for completions at the coming prompt, e.g. by starting
+    /// slow work in the background.
+    fn prepare(&self, _env: &Env) {}
+}
+
+/// Line editor helper providing completion for the interactive shell.
+pub struct ShellHelper {
+    pub env: Env,
+    /// Drawn at the right edge of the line the prompt ends on.
+    pub right_prompt: String,
+    /// Tried in order until one gives candidates.
+    providers: Vec<Box<dyn CompletionProvider>>,
+    /// Those of the enabled plugins, tried first.
+    pub plugin_providers: Vec<Box<dyn CompletionProvider>>,
+    /// The `Plugins::generation` the plugin providers were taken at.
+    pub plugins_generation: usize,
+    /// What the line is parsed with to tell whether it is complete: the
+    /// aliases, the keywords, and whether `#` starts a comment.
+    pub syntax: (Aliases, Keywords, bool),
+}
+
+impl Default for ShellHelper {
+    fn default() -> Self {
+        let mut helper = ShellHelper {
+            env: Env::default(),
+            right_prompt: String::new(),
+            providers: Vec::new(),
+            plugin_providers: Vec::new(),
+            plugins_generation: 0,
+            syntax: Default::default(),
+        };
+        helper.register(Box::new(Files));
+        helper.register(Box::new(Commands::default()));
+        helper.register(Box::new(SshHosts));
+        helper.register(Box::new(GitBranches));
+        helper.register(Box::new(Tilde));
+        helper.register(Box::new(Bookmarks));
+        helper.register(Box::new(Specs));
+        helper.register(Box::new(GlobMatches));
+        helper.register(Box::new(Abbreviation));
+        helper
+    }
+}
+
+impl ShellHelper {
+    /// Add `provider`, to be tried before those registered earlier.
+    pub fn register(&mut self, provider: Box<dyn CompletionProvider>) {
+        self.providers.insert(0, provider);
+    }
+
+    /// Let the providers get ready for the coming prompt.
+    pub fn prepare(&self) {
+        for provider in self.plugin_providers.iter().chain(&self.providers) {
+            provider.prepare(&self.env);
+        }
+    }
+}
+
+/// The word under the cursor and those before it in its simple command.
+struct Word<'a> {
+    /// Byte offset where the word starts in the line.
+    start: usize,
+    /// The word as typed, including any quotes.
+    text: &'a str,
+    /// The earlier words of the simple command.
+    words: Vec<&'a str>,
+}
+
+/// Locate the word that ends at `pos`, honoring quotes so that
+/// `"my fi<TAB>` completes a single word.
+fn current_word(line: &str, pos: usize) -> Word<'_> {
+    let line = &line[..pos];
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+    let mut words = Vec::new();
+    for (i, ch) in line.char_indices() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            _ => {}
+        }
+        if quote.is_some() {
+            continue;
+        }
+        if ch.is_whitespace() || "|;&<>".contains(ch) {
+            if i > start {
+                words.push(&line[start..i]);
+            }
+            if "|;&".contains(ch) {
+                words.clear();
+            }
+            start = i + ch.len_utf8();
+        }
+    }
+    Word { start, text: &line[start..], words }
+}
+
+/// Quote `text` when it contains characters the shell would split on. A
+/// leading `~` is expanded first since quoting would suppress it.
+fn quote(text: &str, named_dirs: &NamedDirs) -> String {
+    if text.chars().any(|c| c.is_whitespace() || "|;&<>".contains(c)) {
+        format!("\"{}\"", text.unfold(named_dirs))
+    } else {
+        text.to_owned()
+    }
+}
+
+/// Candidates for the names in `names` that start with `prefix`.
+fn matching<'a>(names: impl IntoIterator<Item = &'a str>, prefix: &str) -> Vec<Pair> {
+    let names: BTreeSet<&str> = names.into_iter().filter(|name| name.starts_with(prefix)).collect();
+    names.into_iter()
+        .map(|name| Pair { display: name.to_owned(), replacement: name.to_owned() })
+        .collect()
+}
+
+/// File names, for any word not claimed by another provider. The
+/// directory part is kept as typed (including a leading `~`) and only the
+/// last component is matched.
+struct Files;
+
+/// Complete `word` against the file system.
+fn complete_path(word: &str, named_dirs: &NamedDirs, globber: &Globber) -> Vec<Pair> {
+    let word = unquote(word);
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word.as_str()),
+    };
+    let pattern = format!("{}{}*", globber.escape(&dir.unfold(named_dirs)), globber.escape(prefix));
+    let mut candidates: Vec<Pair> = Vec::new();
+    for path in globber.glob(&pattern, GlobOptions::default()).unwrap_or_default() {
+        let name = path.rsplit('/').next().unwrap_or(&path);
+        if name.starts_with('.') && !prefix.starts_with('.') {
+            continue;
+        }
+        let display = if Path::new(&path).is_dir() { format!("{}/", name) } else { name.to_owned() };
+        candidates.push(Pair {
+            replacement: quote(&format!("{}{}", dir, display), named_dirs),
+            display,
+        });
+    }
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+    candidates
+}
+
+impl CompletionProvider for Files {
+    /// Scanning runs in the background, giving up after `BUDGET`.
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        let env = ctx.env;
+        let (word, named_dirs, globber) = (ctx.word.to_owned(), env.named_dirs.clone(), env.globber.clone());
+        let task = env.pool.submit(move |_| complete_path(&word, &named_dirs, &globber));
+        Some(task.wait(BUDGET).unwrap_or_default())
+    }
+}
+
+/// The arguments of commands given a spec with the `complete` builtin or
+/// a spec file: its subcommands and words, its flags for words starting
+/// with `-`, and file or directory names. After a subcommand, its own spec
+/// takes over. A command without a spec of its own uses that of its last
+/// path component, so that a spec for `make` covers `/usr/bin/make`.
+struct Specs;
+
+impl CompletionProvider for Specs {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        let env = ctx.env;
+        let command = ctx.words.first()?;
+        let mut spec = env.spec(command)
+            .or_else(|| env.spec(command.rsplit('/').next()?))?;
+        for word in &ctx.words[1..] {
+            if let Some(sub) = spec.subcommands.get(*word) {
+                spec = sub;
+            }
+        }
+        if ctx.word.starts_with('-') && !spec.flags.is_empty() {
+            return Some(matching(spec.flags.iter().map(String::as_str), ctx.word));
+        }
+        let names = spec.subcommands.keys().chain(&spec.words);
+        let mut candidates = matching(names.map(String::as_str), ctx.word);
+        if spec.files || spec.dirs {
+            let (word, named_dirs, globber) = (ctx.word.to_owned(), env.named_dirs.clone(), env.globber.clone());
+            let task = env.pool.submit(move |_| complete_path(&word, &named_dirs, &globber));
+            let paths = task.wait(BUDGET).unwrap_or_default();
+            candidates.extend(paths.into_iter().filter(|path| spec.files || path.display.ends_with('/')));
+        }
+        Some(candidates)
+    }
+}
+
+/// The paths a word with unquoted glob characters expands to, so that
+/// e.g. the targets of `rm` can be checked before running it. The matches
+/// are listed with the pattern kept as typed, or with
+/// `RSH_GLOB_COMPLETE=expand` put in its place.
+struct GlobMatches;
+
+impl CompletionProvider for GlobMatches {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        let word = ctx.word;
+        if !word.contains(['*', '?', '[']) || word.contains(['"', '\'', '\\']) {
+            return None;
+        }
+        let env = ctx.env;
+        let matches = env.globber.glob(&word.unfold(&env.named_dirs), env.glob_options)?;
+        let quoted: Vec<String> = matches.iter().map(|path| quote(path, &env.named_dirs)).collect();
+        if env.glob_expand {
+            let all = quoted.join(" ");
+            return Some(vec![Pair { display: all.clone(), replacement: all }]);
+        }
+        let mut candidates: Vec<Pair> = matches.iter().zip(quoted)
+            .map(|(path, replacement)| {
+                let display = if Path::new(path).is_dir() { format!("{}/", path) } else { path.clone() };
+                Pair { display, replacement }
+            })
+            .collect();
+        // With more than one match, the pattern itself is a candidate too,
+        // so that the text they have in common doesn't replace it.
+        if candidates.len() > 1 {
+            let display = format!("{}: {} matches", word, candidates.len());
+            candidates.insert(0, Pair { display, replacement: word.to_owned() });
+        }
+        Some(candidates)
+    }
+}
+
+/// The names of the commands found in `$PATH`, as of the modification
+/// times of its directories.
+type PathCommands = (String, Vec<Option<SystemTime>>, Arc<BTreeSet<String>>);
+
+/// The state of the scan for command names.
+#[derive(Default)]
+enum Scan {
+    #[default]
+    Unknown,
+    Scanning(String, Task<PathCommands>),
+    Scanned(PathCommands),
+}
+
+/// Builtins and the commands in `$PATH`, for the command name. `$PATH`
+/// is scanned in the background before each prompt, unless its
+/// directories haven't changed since the last scan.
+#[derive(Default)]
+struct Commands {
+    scan: RefCell<Scan>,
+}
+
+/// The modification time of each directory in `path`.
+fn mtimes(path: &str) -> Vec<Option<SystemTime>> {
+    path.split(':')
+        .map(|dir| fs::metadata(dir).and_then(|meta| meta.modified()).ok())
+        .collect()
+}
+
+/// List the executables in the directories of `path`, stopping early
+/// once the result is no longer wanted.
+fn scan_path(path: String, cancel: &Cancel) -> PathCommands {
+    let mtimes = mtimes(&path);
+    let mut names = BTreeSet::new();
+    for dir in path.split(':').filter(|dir| !dir.is_empty()) {
+        if cancel.is_cancelled() {
+            break;
+        }
+        names.extend(executables(dir));
+    }
+    (path, mtimes, Arc::new(names))
+}
+
+/// The names of the executable files in `dir`.
+pub fn executables(dir: &str) -> impl Iterator<Item = String> {
+    fs::read_dir(dir).into_iter().flatten().flatten()
+        .filter(|entry| entry.metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0))
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+}
+
+impl Commands {
+    /// The commands found by the last scan, waiting for it up to `BUDGET`.
+    fn path_commands(&self) -> Option<Arc<BTreeSet<String>>> {
+        let mut scan = self.scan.borrow_mut();
+        if let Scan::Scanning(_, task) = &*scan {
+            *scan = Scan::Scanned(task.wait(BUDGET)?);
+        }
+        match &*scan {
+            Scan::Scanned((_, _, names)) => Some(names.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl CompletionProvider for Commands {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        if !ctx.words.is_empty() || ctx.word.contains('/') {
+            return None;
+        }
+        let path_commands = self.path_commands().unwrap_or_default();
+        let builtins = BUILTINS.iter().map(|&(name, _)| name);
+        Some(matching(builtins.chain(path_commands.iter().map(String::as_str)), ctx.word))
+    }
+
+    fn prepare(&self, env: &Env) {
+        let mut scan = self.scan.borrow_mut();
+        let current = match &*scan {
+            Scan::Unknown => false,
+            Scan::Scanning(scanning, _) => *scanning == env.path,
+            Scan::Scanned((scanned, scanned_mtimes, _)) => {
+                *scanned == env.path && *scanned_mtimes == mtimes(&env.path)
+            }
+        };
+        if !current {
+            let path = env.path.clone();
+            let task = env.pool.submit(move |cancel| scan_path(path, cancel));
+            *scan = Scan::Scanning(env.path.clone(), task);
+        }
+    }
+}
+
+/// The home directory and the named directories, for words starting with
+/// `~` and no `/` yet.
+struct Tilde;
+
+impl CompletionProvider for Tilde {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        let prefix = ctx.word.strip_prefix('~').filter(|word| !word.contains('/'))?;
+        let names = std::iter::once("").chain(ctx.env.named_dirs.keys().map(String::as_str));
+        Some(names.filter(|name| name.starts_with(prefix))
+            .map(|name| {
+                let text = format!("~{}/", name);
+                Pair { display: text.clone(), replacement: text }
+            })
+            .collect())
+    }
+}
+
+/// Bookmarks written `:name`, for the argument of `cd` and `pushd`.
+struct Bookmarks;
+
+impl CompletionProvider for Bookmarks {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        if !matches!(ctx.words, ["cd" | "pushd", ..]) {
+            return None;
+        }
+        let prefix = ctx.word.strip_prefix(':').filter(|word| !word.contains('/'))?;
+        Some(ctx.env.named_dirs.keys()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| {
+                let text = format!(":{}/", name);
+                Pair { display: text.clone(), replacement: text }
+            })
+            .collect())
+    }
+}
+
+/// Branch names, for the arguments of the git subcommands that take one.
+struct GitBranches;
+
+/// The `.git` directory of the repository containing the working
+/// directory.
+fn git_dir() -> Option<PathBuf> {
+    let cwd = std::env::current_dir().ok()?;
+    cwd.ancestors().map(|dir| dir.join(".git")).find(|git| git.is_dir())
+}
+
+/// The branches of the repository whose git directory is `git`, both
+/// loose and packed.
+fn git_branches(git: &Path) -> BTreeSet<String> {
+    fn walk(dir: &Path, prefix: &str, names: &mut BTreeSet<String>) {
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.path().is_dir() {
+                walk(&entry.path(), &format!("{}/", name), names);
+            } else {
+                names.insert(name);
+            }
+        }
+    }
+    let mut names = BTreeSet::new();
+    walk(&git.join("refs/heads"), "", &mut names);
+    let packed = fs::read_to_string(git.join("packed-refs")).unwrap_or_default();
+    names.extend(packed.lines()
+        .filter_map(|line| line.split_once(' ')?.1.strip_prefix("refs/heads/"))
+        .map(str::to_owned));
+    names
+}
+
+impl CompletionProvider for GitBranches {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        let [git, subcommand, ..] = ctx.words else {
+            return None;
+        };
+        let takes_branch = matches!(*subcommand, "checkout" | "switch" | "merge" | "rebase" | "branch");
+        if *git != "git" || !takes_branch || ctx.word.starts_with('-') {
+            return None;
+        }
+        let branches = git_branches(&git_dir()?);
+        Some(matching(branches.iter().map(String::as_str), ctx.word))
+    }
+}
+
+/// The text of an abbreviation typed as the command name, followed by a
+/// space. Space after an abbreviation completes it this way too.
+struct Abbreviation;
+
+impl CompletionProvider for Abbreviation {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        if !ctx.words.is_empty() {
+            return None;
+        }
+        let text = ctx.env.abbreviations.get(ctx.word)?;
+        Some(vec![Pair { display: text.clone(), replacement: format!("{} ", text) }])
+    }
+}
+
+/// Host names from the ssh configuration and known hosts, for the
+/// arguments of `ssh` and `sftp`.
+struct SshHosts;
+
+/// The hosts named in `~/.ssh/config` and `~/.ssh/known_hosts`, leaving
+/// out patterns and hashed entries.
+fn ssh_hosts() -> BTreeSet<String> {
+    let Some(ssh) = home_dir().map(|home| home.join(".ssh")) else {
+        return BTreeSet::new();
+    };
+    let config = fs::read_to_string(ssh.join("config")).unwrap_or_default();
+    let configured = config.lines()
+        .filter_map(|line| {
+            let (keyword, hosts) = line.trim().split_once(char::is_whitespace)?;
+            keyword.eq_ignore_ascii_case("host").then_some(hosts)
+        })
+        .flat_map(str::split_whitespace);
+    let known_hosts = fs::read_to_string(ssh.join("known_hosts")).unwrap_or_default();
+    let known = known_hosts.lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .flat_map(|hosts| hosts.split(','))
+        .map(|host| host.strip_prefix('[').and_then(|host| host.split_once("]:")).map_or(host, |(host, _)| host));
+    configured.chain(known)
+        .filter(|host| !host.is_empty() && !host.starts_with('|') && !host.contains(['*', '?', '!']))
+        .map(str::to_owned)
+        .collect()
+}
+
+impl CompletionProvider for SshHosts {
+    fn complete(&self, ctx: &CompletionContext) -> Option<Vec<Pair>> {
+        let command = ctx.words.first()?;
+        if !matches!(*command, "ssh" | "sftp") || ctx.word.starts_with('-') {
+            return None;
+        }
+        let (user, host) = match ctx.word.split_once('@') {
+            Some((user, host)) => (format!("{}@", user), host),
+            None => (String::new(), ctx.word),
+        };
+        let hosts = ssh_hosts();
+        let mut candidates = matching(hosts.iter().map(String::as_str), host);
+        for candidate in &mut candidates {
+            candidate.replacement.insert_str(0, &user);
+        }
+        Some(candidates)
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>)
+    -> rustyline::Result<(usize, Vec<Pair>)> {
+        let word = current_word(line, pos);
+        let ctx = CompletionContext { env: &self.env, word: word.text, words: &word.words };
+        let candidates = self.plugin_providers.iter().chain(&self.providers)
+            .find_map(|provider| provider.complete(&ctx))
+            .unwrap_or_default();
+        Ok((word.start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        let env = &self.env;
+        Cow::Owned(highlight::highlight(line, |name| {
+            env.commands.contains(name)
+                || BUILTINS.iter().any(|&(builtin, _)| builtin == name)
+                || runner::is_runnable(name, &env.path)
+        }))
+    }
+
+    /// Every edit can change how the rest of the line is colored.
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, default: bool) -> Cow<'b, str> {
+        // Prompts of searches and the like leave out the right side.
+        match prompt::with_right(prompt, &self.right_prompt).filter(|_| default) {
+            Some(prompt) => Cow::Owned(prompt),
+            None => Cow::Borrowed(prompt),
+        }
+    }
+}
+
+impl Validator for ShellHelper {
+    /// A command left unfinished, such as an open quote, a `for` without
+    /// its `done` or a trailing `|`, goes on in the same buffer on the next
+    /// line, which Enter starts, so that all of it can still be edited.
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let (aliases, keywords, comments) = &self.syntax;
+        Ok(match parser::parse(ctx.input(), &[], aliases, keywords, *comments) {
+            Err(ParseError::Incomplete) => ValidationResult::Incomplete,
+            _ => ValidationResult::Valid(None),
+        })
+    }
+}
+
+impl Helper for ShellHelper {}
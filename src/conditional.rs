@@ -0,0 +1,152 @@
+use crate::error::ShellError;
+use crate::expand::{case_pattern, expand_literal, regex_pattern};
+use crate::predicates::{self, BINARY, UNARY};
+use crate::regex::Regex;
+use crate::variables::{self, Assigned};
+use crate::Shell;
+
+/// The expression of `[[ ]]`, evaluated by recursive descent over its
+/// words as typed, each expanded without splitting or globbing:
+///
+/// ```text
+/// or      := and ("||" and)*
+/// and     := not ("&&" not)*
+/// not     := "!" not | primary
+/// primary := "(" or ")" | unary word | word binary word | word
+/// ```
+///
+/// The right side of `==` and `!=` is a glob pattern, and that of `=~` an
+/// extended regular expression; the quoted parts of either match
+/// literally. The integer comparisons work out arithmetic expressions.
+struct Parser<'a> {
+    shell: &'a mut Shell,
+    words: &'a [String],
+    pos: usize,
+    /// Whether the result no longer depends on what is being parsed, as
+    /// after `false &&`, so that its words aren't expanded.
+    skip: bool,
+}
+
+fn error(message: impl std::fmt::Display) -> ShellError {
+    ShellError::expansion("[[", message)
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.words.get(self.pos + offset).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&'a str, ShellError> {
+        let word = self.peek(0).ok_or_else(|| error("argument expected"))?;
+        self.pos += 1;
+        Ok(word)
+    }
+
+    fn or(&mut self) -> Result<bool, ShellError> {
+        let mut res = self.and()?;
+        while self.peek(0) == Some("||") {
+            self.pos += 1;
+            let skip = self.skip;
+            self.skip |= res;
+            res |= self.and()?;
+            self.skip = skip;
+        }
+        Ok(res)
+    }
+
+    fn and(&mut self) -> Result<bool, ShellError> {
+        let mut res = self.not()?;
+        while self.peek(0) == Some("&&") {
+            self.pos += 1;
+            let skip = self.skip;
+            self.skip |= !res;
+            res &= self.not()?;
+            self.skip = skip;
+        }
+        Ok(res)
+    }
+
+    fn not(&mut self) -> Result<bool, ShellError> {
+        if self.peek(0) == Some("!") {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, ShellError> {
+        let first = self.next()?;
+        if let (Some(op), Some(_)) = (self.peek(0), self.peek(1)) {
+            if BINARY.contains(&op) || op == "=~" {
+                self.pos += 2;
+                return self.binary(first, op, self.words[self.pos - 1].as_str());
+            }
+        }
+        if first == "(" {
+            let res = self.or()?;
+            return match self.next() {
+                Ok(")") => Ok(res),
+                Ok(word) => Err(error(format!("`{}': expected `)'", word))),
+                Err(_) => Err(error("missing `)'")),
+            };
+        }
+        if UNARY.contains(&first) && self.peek(0).is_some() {
+            let word = self.next()?;
+            if self.skip {
+                return Ok(false);
+            }
+            let arg = expand_literal(self.shell, word)?;
+            return predicates::unary(first, &arg).map_err(error);
+        }
+        if self.skip {
+            return Ok(false);
+        }
+        Ok(!expand_literal(self.shell, first)?.is_empty())
+    }
+
+    fn binary(&mut self, left: &str, op: &str, right: &str) -> Result<bool, ShellError> {
+        if self.skip {
+            return Ok(false);
+        }
+        let shell = &mut *self.shell;
+        match op {
+            "=" | "==" | "!=" => {
+                let text = expand_literal(shell, left)?;
+                let matched = match shell.globber.matches(&case_pattern(shell, right)?, &text) {
+                    Some(matched) => matched,
+                    None => expand_literal(shell, right)? == text,
+                };
+                Ok(matched != (op == "!="))
+            }
+            "=~" => {
+                let text = expand_literal(shell, left)?;
+                let regex = Regex::new(&regex_pattern(shell, right)?).map_err(error)?;
+                let captures = regex.captures(&text);
+                let matched = captures.is_some();
+                let elements = captures.unwrap_or_default().into_iter().map(|capture| (None, capture)).collect();
+                variables::assign(&mut shell.vars, &shell.options, "BASH_REMATCH", Assigned::Array(elements))?;
+                Ok(matched)
+            }
+            "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+                let left = variables::arithmetic(&mut shell.vars, &shell.options, left)?;
+                let right = variables::arithmetic(&mut shell.vars, &shell.options, right)?;
+                predicates::binary(&left.to_string(), op, &right.to_string()).map_err(error)
+            }
+            _ => {
+                let left = expand_literal(shell, left)?;
+                predicates::binary(&left, op, &expand_literal(shell, right)?).map_err(error)
+            }
+        }
+    }
+}
+
+/// Evaluate the words of `[[ words ]]`. An error means the expression is
+/// malformed, or that one of its words can't be expanded.
+pub fn evaluate(shell: &mut Shell, words: &[String]) -> Result<bool, ShellError> {
+    let mut parser = Parser { shell, words, pos: 0, skip: false };
+    let res = parser.or()?;
+    match parser.peek(0) {
+        Some(word) => Err(error(format!("{}: unexpected argument", word))),
+        None => Ok(res),
+    }
+}
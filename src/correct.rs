@@ -0,0 +1,81 @@
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use crate::builtins::BUILTINS;
+use crate::completion::executables;
+use crate::Shell;
+
+/// The number of characters to insert, remove, replace or swap with the
+/// next one to turn `a` into `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Three rows of the table of distances between prefixes: the one
+    // before the previous, the previous, and the current.
+    let mut before = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// The candidate closest to `word`, if it is close enough to be what was
+/// meant: one edit away from a word of up to four characters, two from a
+/// longer one.
+fn closest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = if word.chars().count() <= 4 { 1 } else { 2 };
+    candidates.into_iter()
+        .filter(|candidate| *candidate != word)
+        .map(|candidate| (distance(word, candidate), candidate))
+        .filter(|&(distance, _)| distance <= limit)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The builtin or program in `$PATH` that the command `name`, which is
+/// neither, was likely meant to be.
+pub fn command(shell: &Shell, name: &str) -> Option<String> {
+    if name.contains('/') {
+        return None;
+    }
+    let path = shell.vars.get("PATH").unwrap_or_default();
+    let programs: Vec<String> = path.split(':').filter(|dir| !dir.is_empty()).flat_map(executables).collect();
+    let builtins = BUILTINS.iter().map(|&(name, _)| name).chain(shell.builtins.keys().map(String::as_str));
+    closest(name, builtins.chain(programs.iter().map(String::as_str))).map(String::from)
+}
+
+/// The directory that `dir`, which doesn't exist, was likely meant to be:
+/// one next to where it would be, with a name close to its own.
+pub fn directory(dir: &Path) -> Option<PathBuf> {
+    let name = dir.file_name()?.to_str()?;
+    let parent = dir.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let siblings: Vec<String> = fs::read_dir(parent).ok()?.flatten()
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    let sibling = closest(name, siblings.iter().map(String::as_str))?;
+    Some(dir.with_file_name(sibling))
+}
+
+/// Ask whether `suggestion` was meant. Only a terminal is asked; the
+/// answer is otherwise no.
+pub fn confirm(suggestion: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("shell: did you mean '{}'? [y/n] ", suggestion);
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).is_ok() && answer.trim().eq_ignore_ascii_case("y")
+}
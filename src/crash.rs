@@ -0,0 +1,70 @@
+use std::backtrace::Backtrace;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A panic in the shell itself, caught so that the session survives it.
+pub struct Crash {
+    message: String,
+    location: String,
+    backtrace: Backtrace,
+}
+
+/// The last panic on the main thread, left by the hook for `catch`.
+static LAST: Mutex<Option<Crash>> = Mutex::new(None);
+
+/// Record panics on the main thread instead of printing them, so that
+/// `catch` can report them. Other threads keep the default behavior.
+pub fn install_hook() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().name() != Some("main") {
+            return default(info);
+        }
+        let crash = Crash {
+            message: info.payload_as_str().unwrap_or("unknown cause").to_owned(),
+            location: info.location().map_or_else(String::new, ToString::to_string),
+            backtrace: Backtrace::force_capture(),
+        };
+        *LAST.lock().unwrap_or_else(|e| e.into_inner()) = Some(crash);
+    }));
+}
+
+/// Run `f`, turning a panic into an `Err`.
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Crash> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| {
+        LAST.lock().unwrap_or_else(|e| e.into_inner()).take().unwrap_or_else(|| Crash {
+            message: String::from("unknown cause"),
+            location: String::new(),
+            backtrace: Backtrace::disabled(),
+        })
+    })
+}
+
+impl Crash {
+    /// Append a report on the crash, with the command line that caused it,
+    /// to the file at `path`.
+    pub fn write_report(&self, path: &str, command: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        writeln!(file, "time: {}", time)?;
+        writeln!(file, "version: {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(file, "command: {}", command.trim_end())?;
+        writeln!(file, "error: {}", self)?;
+        writeln!(file, "{}\n", self.backtrace)
+    }
+}
+
+impl fmt::Display for Crash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.location.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{} at {}", self.message, self.location)
+        }
+    }
+}
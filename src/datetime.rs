@@ -0,0 +1,365 @@
+use std::ffi::CString;
+use std::path::Path;
+
+/// Where the compiled time zone files are.
+const ZONEINFO: &str = "/usr/share/zoneinfo";
+
+/// The time zone a time is shown in.
+pub enum Zone {
+    Local,
+    Utc,
+    /// A zone named as in `$TZ`, e.g. `Europe/Paris`, with its rules.
+    Named(Rules),
+}
+
+impl Zone {
+    /// The zone `name`, from its file under `/usr/share/zoneinfo`.
+    pub fn named(name: &str) -> Result<Zone, String> {
+        let unknown = || format!("{}: unknown time zone", name);
+        let path = Path::new(name);
+        if !path.components().all(|part| matches!(part, std::path::Component::Normal(_))) {
+            return Err(unknown());
+        }
+        let data = std::fs::read(Path::new(ZONEINFO).join(path)).map_err(|_| unknown())?;
+        Rules::parse(&data).map(Zone::Named).ok_or_else(unknown)
+    }
+}
+
+/// A UTC offset in seconds, whether it is daylight saving time, and the
+/// abbreviation shown for it.
+struct LocalType {
+    offset: i64,
+    dst: bool,
+    abbr: CString,
+}
+
+/// The rules of a zone, as a TZif file (RFC 8536) gives them: the times
+/// its offset changed, and a POSIX `TZ` rule for times after the last
+/// of them.
+pub struct Rules {
+    transitions: Vec<(i64, usize)>,
+    types: Vec<LocalType>,
+    rule: Option<PosixRule>,
+}
+
+impl Rules {
+    fn parse(data: &[u8]) -> Option<Rules> {
+        let counts = |data: &[u8]| -> Option<[usize; 6]> {
+            if !data.starts_with(b"TZif") {
+                return None;
+            }
+            let mut counts = [0; 6];
+            for (i, count) in counts.iter_mut().enumerate() {
+                *count = u32::from_be_bytes(data.get(20 + 4 * i..24 + 4 * i)?.try_into().ok()?) as usize;
+            }
+            Some(counts)
+        };
+        let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts(data)?;
+        // Version 2 and later repeat the data with 64-bit times, then give
+        // the rule for later times.
+        let (data, time_size) = match data.get(4) {
+            Some(b'2'..) => {
+                let len = 44 + timecnt * 5 + typecnt * 6 + charcnt + leapcnt * 8 + isstdcnt + isutcnt;
+                (data.get(len..)?, 8)
+            }
+            _ => (data, 4),
+        };
+        let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts(data)?;
+        let mut at = 44;
+        let mut take = |len: usize| {
+            let field = data.get(at..at + len);
+            at += len;
+            field
+        };
+        let times = take(timecnt * time_size)?;
+        let indices = take(timecnt)?;
+        let infos = take(typecnt * 6)?;
+        let chars = take(charcnt)?;
+        take(leapcnt * (time_size + 4) + isstdcnt + isutcnt)?;
+        let footer = data.get(at..);
+        let transitions = times.chunks(time_size).zip(indices)
+            .map(|(time, &index)| {
+                let time = match time_size {
+                    8 => i64::from_be_bytes(time.try_into().unwrap()),
+                    _ => i32::from_be_bytes(time.try_into().unwrap()) as i64,
+                };
+                (time, index as usize)
+            })
+            .collect::<Vec<_>>();
+        let types = infos.chunks(6)
+            .map(|info| {
+                let abbr = chars.get(info[5] as usize..)?;
+                let abbr = &abbr[..abbr.iter().position(|&b| b == 0)?];
+                Some(LocalType {
+                    offset: i32::from_be_bytes(info[..4].try_into().unwrap()) as i64,
+                    dst: info[4] != 0,
+                    abbr: CString::new(abbr).ok()?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if types.is_empty() || transitions.iter().any(|&(_, index)| index >= types.len()) {
+            return None;
+        }
+        let rule = footer
+            .and_then(|footer| footer.strip_prefix(b"\n"))
+            .and_then(|footer| std::str::from_utf8(&footer[..footer.iter().position(|&b| b == b'\n')?]).ok())
+            .and_then(PosixRule::parse);
+        Some(Rules { transitions, types, rule })
+    }
+
+    /// The offset, whether it is daylight saving time and the abbreviation
+    /// at `secs` since the epoch.
+    fn at(&self, secs: i64) -> (i64, bool, &CString) {
+        let later = self.transitions.last().is_none_or(|&(last, _)| secs >= last);
+        if let Some(rule) = self.rule.as_ref().filter(|_| later) {
+            return rule.at(secs);
+        }
+        let index = match self.transitions.partition_point(|&(time, _)| time <= secs) {
+            0 => 0,
+            n => self.transitions[n - 1].1,
+        };
+        let local = &self.types[index];
+        (local.offset, local.dst, &local.abbr)
+    }
+}
+
+/// When daylight saving time starts or ends in a year: a date and a time
+/// of day in seconds, local time.
+enum Change {
+    /// `Jn`: day `n` from 1 to 365, not counting February 29.
+    Julian(i64, i64),
+    /// `n`: day `n` from 0 to 365.
+    Day(i64, i64),
+    /// `Mm.w.d`: weekday `d` (0 is Sunday) of week `w` of month `m`, week
+    /// 5 being the last.
+    Weekday(i64, i64, i64, i64),
+}
+
+/// A POSIX `TZ` rule such as `CET-1CEST,M3.5.0,M10.5.0/3`: standard time,
+/// and maybe daylight saving time and when it starts and ends.
+struct PosixRule {
+    std: LocalType,
+    dst: Option<(LocalType, Change, Change)>,
+}
+
+impl PosixRule {
+    fn parse(rule: &str) -> Option<PosixRule> {
+        let mut rest = rule;
+        let std_abbr = abbreviation(&mut rest)?;
+        let std_offset = -offset(&mut rest)?;
+        let std = LocalType { offset: std_offset, dst: false, abbr: CString::new(std_abbr).ok()? };
+        if rest.is_empty() {
+            return Some(PosixRule { std, dst: None });
+        }
+        let dst_abbr = abbreviation(&mut rest)?;
+        let dst_offset = match rest.starts_with(',') || rest.is_empty() {
+            true => std_offset + 3600,
+            false => -offset(&mut rest)?,
+        };
+        let dst = LocalType { offset: dst_offset, dst: true, abbr: CString::new(dst_abbr).ok()? };
+        // Without dates, the rule is that of the United States.
+        let dates = match rest.strip_prefix(',') {
+            Some(dates) => dates,
+            None if rest.is_empty() => "M3.2.0,M11.1.0",
+            None => return None,
+        };
+        let (start, end) = dates.split_once(',')?;
+        Some(PosixRule { std, dst: Some((dst, change(start)?, change(end)?)) })
+    }
+
+    fn at(&self, secs: i64) -> (i64, bool, &CString) {
+        let std = (self.std.offset, false, &self.std.abbr);
+        let Some((dst, start, end)) = &self.dst else {
+            return std;
+        };
+        let year = civil_from_days((secs + self.std.offset).div_euclid(86400)).0;
+        let start = start.time(year) - self.std.offset;
+        let end = end.time(year) - dst.offset;
+        let in_dst = match start < end {
+            true => start <= secs && secs < end,
+            // Southern zones keep daylight saving time over the new year.
+            false => !(end <= secs && secs < start),
+        };
+        if in_dst { (dst.offset, true, &dst.abbr) } else { std }
+    }
+}
+
+impl Change {
+    /// Seconds since the epoch, local time, at which the change happens in
+    /// `year`.
+    fn time(&self, year: i64) -> i64 {
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let (day, time) = match *self {
+            Change::Julian(n, time) => (days_from_civil(year, 1, 1) + n - 1 + (leap && n >= 60) as i64, time),
+            Change::Day(n, time) => (days_from_civil(year, 1, 1) + n, time),
+            Change::Weekday(month, week, weekday, time) => {
+                let first = days_from_civil(year, month, 1);
+                let next_month = match month {
+                    12 => days_from_civil(year + 1, 1, 1),
+                    _ => days_from_civil(year, month + 1, 1),
+                };
+                // The epoch was a Thursday.
+                let mut day = first + (weekday - (first + 4).rem_euclid(7)).rem_euclid(7) + (week - 1) * 7;
+                while day >= next_month {
+                    day -= 7;
+                }
+                (day, time)
+            }
+        };
+        day * 86400 + time
+    }
+}
+
+/// Take a zone abbreviation, plain as `CET` or quoted as `<+03>`, off the
+/// front of `rest`.
+fn abbreviation<'a>(rest: &mut &'a str) -> Option<&'a str> {
+    let (abbr, len) = match rest.strip_prefix('<') {
+        Some(quoted) => {
+            let end = quoted.find('>')?;
+            (&quoted[..end], end + 2)
+        }
+        None => {
+            let end = rest.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len());
+            (&rest[..end], end)
+        }
+    };
+    *rest = &rest[len..];
+    (abbr.len() >= 3).then_some(abbr)
+}
+
+/// Take a time, `[+-]hh[:mm[:ss]]` in seconds, off the front of `rest`.
+fn offset(rest: &mut &str) -> Option<i64> {
+    let sign = match rest.as_bytes().first() {
+        Some(b'-') => -1,
+        _ => 1,
+    };
+    let unsigned = rest.trim_start_matches(['+', '-']);
+    let end = unsigned.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(unsigned.len());
+    let mut secs = 0;
+    for (i, part) in unsigned[..end].split(':').enumerate() {
+        if i > 2 {
+            return None;
+        }
+        secs += part.parse::<i64>().ok()? * [3600, 60, 1][i];
+    }
+    *rest = &unsigned[end..];
+    Some(sign * secs)
+}
+
+/// A `date[/time]` of a POSIX rule, the time defaulting to 02:00.
+fn change(spec: &str) -> Option<Change> {
+    let (date, time) = match spec.split_once('/') {
+        Some((date, mut time)) => (date, offset(&mut time).filter(|_| time.is_empty())?),
+        None => (spec, 7200),
+    };
+    if let Some(n) = date.strip_prefix('J') {
+        return Some(Change::Julian(n.parse().ok().filter(|n| (1..=365).contains(n))?, time));
+    }
+    if let Some(date) = date.strip_prefix('M') {
+        let mut parts = date.split('.').map(|part| part.parse::<i64>().ok());
+        let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+        let valid = (1..=12).contains(&month) && (1..=5).contains(&week) && (0..=6).contains(&weekday);
+        return (valid && parts.next().is_none()).then_some(Change::Weekday(month, week, weekday, time));
+    }
+    Some(Change::Day(date.parse().ok().filter(|n| (0..=365).contains(n))?, time))
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, month and day of `days` since the epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    (year_of_era + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// The broken-down time of `secs` since the epoch in `zone`. For a named
+/// zone, the name of its abbreviation points into `zone`.
+fn broken_down(secs: i64, zone: &Zone) -> Option<libc::tm> {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    let res = match zone {
+        Zone::Utc => unsafe { libc::gmtime_r(&(secs as libc::time_t), &mut tm) },
+        Zone::Local => unsafe { libc::localtime_r(&(secs as libc::time_t), &mut tm) },
+        Zone::Named(rules) => {
+            let (offset, dst, abbr) = rules.at(secs);
+            let res = unsafe { libc::gmtime_r(&(secs.checked_add(offset)? as libc::time_t), &mut tm) };
+            tm.tm_gmtoff = offset as _;
+            tm.tm_isdst = dst as _;
+            tm.tm_zone = abbr.as_ptr();
+            res
+        }
+    };
+    (!res.is_null()).then_some(tm)
+}
+
+/// Format `tm` with strftime(3).
+fn strftime(format: &str, tm: &libc::tm) -> Result<String, String> {
+    if format.is_empty() {
+        return Ok(String::new());
+    }
+    let c_format = CString::new(format).map_err(|_| String::from("format contains a NUL byte"))?;
+    let mut buf = vec![0u8; 256];
+    loop {
+        let len = unsafe { libc::strftime(buf.as_mut_ptr().cast(), buf.len(), c_format.as_ptr(), tm) };
+        // Zero means either an empty result or a buffer too small.
+        if len > 0 || buf.len() > 64 * format.len() + 256 {
+            buf.truncate(len);
+            return Ok(String::from_utf8_lossy(&buf).into_owned());
+        }
+        buf.resize(buf.len() * 2, 0);
+    }
+}
+
+/// Format the time `secs` seconds and `nanos` nanoseconds after the epoch
+/// in `zone`. The format is that of strftime(3) plus chrono's `%:z`
+/// (`+01:00`), `%f` (nanoseconds) and `%.3f`, `%.6f` and `%.9f` (a point
+/// and that many digits of the fraction of a second).
+pub fn format(secs: i64, nanos: u32, zone: &Zone, format: &str) -> Result<String, String> {
+    let tm = broken_down(secs, zone).ok_or_else(|| format!("{}: time out of range", secs))?;
+    let mut res = String::new();
+    let mut pending = String::new();
+    let mut rest = format;
+    while let Some(i) = rest.find('%') {
+        pending.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let offset = tm.tm_gmtoff;
+        let extension = [
+            ("%:z", format!("{}{:02}:{:02}", if offset < 0 { '-' } else { '+' }, offset.abs() / 3600, offset.abs() / 60 % 60)),
+            ("%f", format!("{:09}", nanos)),
+            ("%.3f", format!(".{:03}", nanos / 1_000_000)),
+            ("%.6f", format!(".{:06}", nanos / 1_000)),
+            ("%.9f", format!(".{:09}", nanos)),
+        ];
+        match extension.iter().find(|(spec, _)| rest.starts_with(spec)) {
+            Some((spec, value)) => {
+                res.push_str(&strftime(&pending, &tm)?);
+                pending.clear();
+                res.push_str(value);
+                rest = &rest[spec.len()..];
+            }
+            None => {
+                // Pass the conversion, or `%%`, through to strftime whole.
+                let len = rest[1..].chars().next().map_or(1, |c| 1 + c.len_utf8());
+                pending.push_str(&rest[..len]);
+                rest = &rest[len..];
+            }
+        }
+    }
+    pending.push_str(rest);
+    res.push_str(&strftime(&pending, &tm)?);
+    Ok(res)
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::parser::ParseError;
+
+/// Why a command line couldn't be read or a command couldn't be run,
+/// reported as `shell: <context>: <message>`.
+#[derive(Debug)]
+pub enum ShellError {
+    /// A command line that can't be parsed. Errors in scripts are placed
+    /// with the name of the script and the line they are on.
+    Syntax { at: Option<(String, usize)>, message: String },
+    /// A command that is neither a builtin nor a program in `PATH`.
+    CommandNotFound(String),
+    /// The file `target` of a redirection can't be used.
+    Redirect { target: String, message: String },
+    /// A word that can't be expanded, such as an unset variable under
+    /// `set -u` or a history reference to no event.
+    Expansion { context: String, message: String },
+    /// Reading the command line failed.
+    Input(String),
+    /// An assignment to, or `unset` of, a variable made read-only.
+    Readonly(String),
+}
+
+impl ShellError {
+    pub fn expansion(context: &str, message: impl fmt::Display) -> Self {
+        ShellError::Expansion { context: context.to_owned(), message: message.to_string() }
+    }
+
+    pub fn redirect(target: &str, message: impl fmt::Display) -> Self {
+        ShellError::Redirect { target: target.to_owned(), message: message.to_string() }
+    }
+
+    /// Print the error to stderr.
+    pub fn report(&self) {
+        eprintln!("{}", self);
+    }
+}
+
+impl From<ParseError> for ShellError {
+    /// A parse error of a whole command string, which has no script to
+    /// place it in.
+    fn from(error: ParseError) -> Self {
+        let message = match error {
+            ParseError::Syntax { message, .. } => message,
+            ParseError::Incomplete => String::from("syntax error: unexpected end of file"),
+        };
+        ShellError::Syntax { at: None, message }
+    }
+}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShellError::Syntax { at: Some((name, line)), message } => {
+                write!(f, "shell: {}: line {}: {}", name, line, message)
+            }
+            ShellError::Syntax { at: None, message } => write!(f, "shell: {}", message),
+            ShellError::CommandNotFound(name) => write!(f, "shell: {}: command not found", name),
+            ShellError::Redirect { target, message } => write!(f, "shell: {}: {}", target, message),
+            ShellError::Expansion { context, message } => write!(f, "shell: {}: {}", context, message),
+            ShellError::Input(message) => write!(f, "shell: {}", message),
+            ShellError::Readonly(name) => write!(f, "shell: {}: readonly variable", name),
+        }
+    }
+}
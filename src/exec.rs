@@ -0,0 +1,1084 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::{dup, dup2_stderr, dup2_stdin, dup2_stdout, fork, ForkResult, Pid};
+
+use crate::error::ShellError;
+use crate::expand::{self, case_pattern, expand_assignment, expand_command, expand_literal, expand_word, expand_words};
+use crate::parser::{self, AndOr, CaseClause, CompoundCommand, Connector, ForClause, IfClause, List, Pipeline, Redirect, RedirectOp};
+use crate::stats::{self, PipelineStats};
+use crate::variables::{self, Assigned};
+use crate::{builtins, capture, conditional, correct, crash, exit, heredoc, pager, priority, records, runner, Flow, Shell};
+
+/// Permission bits for files created by redirections, before the umask is
+/// applied. Defaults to 0666 and can be overridden with an octal value in
+/// `$RSH_REDIRECT_MODE`.
+fn redirect_mode(shell: &Shell) -> u32 {
+    shell.vars.get("RSH_REDIRECT_MODE")
+        .and_then(|mode| u32::from_str_radix(&mode, 8).ok())
+        .filter(|&mode| mode <= 0o7777)
+        .unwrap_or(0o666)
+}
+
+/// Open (creating if needed) the target of an output redirection.
+pub fn open_redirect(shell: &Shell, path: &str, append: bool) -> Option<File> {
+    File::options()
+        .mode(redirect_mode(shell))
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_or_else(
+            |e| {
+                ShellError::redirect(path, e).report();
+                None
+            },
+            |v| {
+                Some(v)
+            }
+        )
+}
+
+/// Report a word that couldn't be expanded. A script or `-c` command
+/// stops there, with status 1, as it does for an unset variable under
+/// `set -u`.
+fn expansion_failed(shell: &mut Shell, e: ShellError) {
+    let fatal = !shell.interactive && matches!(e, ShellError::Expansion { .. });
+    e.report();
+    if fatal {
+        exit(shell, 1);
+    }
+}
+
+fn parse_file_path(shell: &mut Shell, path: &str) -> Option<String> {
+    let mut res = match expand_word(shell, path) {
+        Ok(res) => res,
+        Err(e) => {
+            expansion_failed(shell, e);
+            return None;
+        }
+    };
+    if res.len() != 1 {
+        ShellError::redirect(path, "ambiguous redirect").report();
+        None
+    } else {
+        res.pop()
+    }
+}
+
+/// Streams a command's redirections resolve to.
+#[derive(Default)]
+struct Redirections {
+    stdin: Option<OwnedFd>,
+    stdout: Option<File>,
+    stderr: Option<File>,
+}
+
+/// A size such as `512`, `64k`, `10M` or `1G`, in bytes.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let (digits, unit) = match size.char_indices().last()? {
+        (i, 'k' | 'K') => (&size[..i], 1 << 10),
+        (i, 'm' | 'M') => (&size[..i], 1 << 20),
+        (i, 'g' | 'G') => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Whether `>` may truncate `path`. When `$RSH_CLOBBER_LIMIT` is set, a
+/// file at least that large is only overwritten after a warning, or with
+/// `RSH_CLOBBER_MODE=refuse` not at all, leaving `>|` to force it.
+fn may_overwrite(shell: &Shell, path: &str) -> bool {
+    let Some(limit) = shell.vars.get("RSH_CLOBBER_LIMIT").and_then(|limit| parse_size(&limit)) else {
+        return true;
+    };
+    let size = match std::fs::metadata(path) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 && meta.len() >= limit => meta.len(),
+        _ => return true,
+    };
+    if shell.vars.get("RSH_CLOBBER_MODE").is_some_and(|mode| mode == "refuse") {
+        let message = format!("not overwriting a file of {} bytes; use >| to force it", size);
+        ShellError::redirect(path, message).report();
+        return false;
+    }
+    eprintln!("shell: warning: {}: overwriting a file of {} bytes", path, size);
+    true
+}
+
+/// Resolve a command's redirections to the streams they name. Errors are
+/// reported and yield `None`, in which case the command must not run.
+fn open_redirections(shell: &mut Shell, redirects: &[Redirect]) -> Option<Redirections> {
+    let mut resources = Redirections::default();
+    for redirect in redirects {
+        if redirect.op == RedirectOp::HereDoc {
+            let stream = heredoc::stdin_for(&redirect.target).map_or_else(
+                |e| {
+                    ShellError::redirect("here-document", e).report();
+                    None
+                },
+                Some
+            )?;
+            resources.stdin = Some(stream);
+            continue;
+        }
+        if shell.restricted && redirect.op != RedirectOp::Read {
+            ShellError::redirect(&redirect.target, "restricted: cannot redirect output").report();
+            return None;
+        }
+        let path = parse_file_path(shell, &redirect.target)?;
+        if matches!(redirect.op, RedirectOp::Write | RedirectOp::WriteBoth) && !may_overwrite(shell, &path) {
+            return None;
+        }
+        let append = matches!(redirect.op, RedirectOp::Append | RedirectOp::AppendBoth);
+        match (redirect.op, redirect.fd) {
+            (RedirectOp::Read, _) => {
+                let file = File::open(&path).map_or_else(
+                    |e| {
+                        ShellError::redirect(&path, e).report();
+                        None
+                    },
+                    Some
+                )?;
+                resources.stdin = Some(file.into());
+            }
+            (RedirectOp::WriteBoth | RedirectOp::AppendBoth, _) => {
+                let file = open_redirect(shell, &path, append)?;
+                let copy = file.try_clone().map_or_else(
+                    |e| {
+                        ShellError::redirect(&path, e).report();
+                        None
+                    },
+                    Some
+                )?;
+                resources.stdout = Some(file);
+                resources.stderr = Some(copy);
+            }
+            (_, 2) => resources.stderr = Some(open_redirect(shell, &path, append)?),
+            _ => resources.stdout = Some(open_redirect(shell, &path, append)?),
+        }
+    }
+    Some(resources)
+}
+
+trait Wrapper {
+    fn apply_file_stream_filter(&mut self, resources: Redirections) -> &mut Self;
+    fn apply_pipe_stream_filter(&mut self, prev_stdout: &mut Option<OwnedFd>,
+                                wstream: bool) -> &mut Self;
+}
+
+impl Wrapper for Command {
+    fn apply_file_stream_filter(&mut self, resources: Redirections) -> &mut Self {
+        if let Some(stream) = resources.stdin {
+            self.stdin(Stdio::from(stream));
+        }
+        if let Some(stream) = resources.stdout {
+            self.stdout(stream);
+        }
+        if let Some(stream) = resources.stderr {
+            self.stderr(stream);
+        }
+        self
+    }
+
+    fn apply_pipe_stream_filter(mut self: &mut Self, 
+        prev_stdout: &mut Option<OwnedFd>, wstream: bool) 
+    -> &mut Self {
+        if wstream {
+            self = self.stdout(Stdio::piped());
+        }
+        if let Some(x) = prev_stdout.take() {
+            self = self.stdin(Stdio::from(x));
+        }
+        self
+    }
+}
+
+/// What starting a stage of a pipeline left behind: its process, still
+/// running, or its exit status when it ran inside the shell or failed.
+enum Spawned {
+    Child(Child),
+    /// A forked copy of the shell running a builtin or compound command.
+    Forked(Pid),
+    /// A process `PipelineStats` reaps, as its stage `n`.
+    Watched(usize),
+    Status(i32),
+}
+
+/// Convert a process exit status to the shell's numeric form, where death
+/// by signal N is reported as 128 + N.
+pub fn status_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0))
+}
+
+/// Wait for the forked process `pid` and return its status.
+pub fn wait_pid(pid: Pid) -> nix::Result<i32> {
+    loop {
+        match waitpid(pid, None) {
+            Ok(WaitStatus::Exited(_, status)) => return Ok(status),
+            Ok(WaitStatus::Signaled(_, signal, _)) => return Ok(128 + signal as i32),
+            Ok(_) | Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn wait_status(mut child: Child) -> i32 {
+    child.wait().map_or_else(
+        |e| {
+            eprintln!("{}", e);
+            1
+        },
+        status_code
+    )
+}
+
+/// Under `set -x`, print `argv` to stderr after `$PS4`, quoting words
+/// that wouldn't read back as themselves.
+fn trace(shell: &Shell, argv: &[String]) {
+    if !shell.options.xtrace {
+        return;
+    }
+    let quote = |word: &str| {
+        let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_^".contains(c);
+        match !word.is_empty() && word.chars().all(plain) {
+            true => word.to_owned(),
+            false => builtins::single_quote(word),
+        }
+    };
+    let words: Vec<String> = argv.iter()
+        .map(|word| match variables::assignment(word) {
+            Some((_, value)) if value.starts_with('(') && value.ends_with(')') => word.clone(),
+            Some((name, value)) => format!("{}={}", name, quote(value)),
+            None => quote(word),
+        })
+        .collect();
+    trace_text(shell, &words.join(" "));
+}
+
+/// Under `set -x`, print `text` to stderr after `$PS4`.
+fn trace_text(shell: &Shell, text: &str) {
+    if shell.options.xtrace {
+        let ps4 = shell.vars.get("PS4").unwrap_or_else(|| String::from("+ "));
+        eprintln!("{}{}", ps4, text);
+    }
+}
+
+/// Expand the values of the `name=value` words of a command made only of
+/// assignments, then make them, in order.
+fn assign(shell: &mut Shell, assignments: &[(&str, &str)]) -> Result<(), ShellError> {
+    let mut values = Vec::with_capacity(assignments.len());
+    for &(name, value) in assignments {
+        values.push((name, expand_assignment(shell, value)?));
+    }
+    trace(shell, &values.iter()
+        .map(|(name, value)| match value {
+            Assigned::Scalar(value) => format!("{}={}", name, value),
+            Assigned::Array(_) => expand::quote_assignment(name, value),
+        })
+        .collect::<Vec<_>>());
+    for (name, value) in values {
+        variables::assign(&mut shell.vars, &shell.options, name, value)?;
+    }
+    Ok(())
+}
+
+/// Expand the `name=value` words before a command's name into the
+/// variables they give that command's environment. `name+=value` appends
+/// to the shell's value.
+fn command_env(shell: &Shell, assignments: &[String]) -> Result<Vec<(String, String)>, ShellError> {
+    let mut env = Vec::with_capacity(assignments.len());
+    for word in assignments {
+        let Some((target, value)) = variables::assignment(word) else { continue };
+        let (name, append) = match target.strip_suffix('+') {
+            Some(name) => (name, true),
+            None => (target, false),
+        };
+        if !variables::is_name(name) {
+            return Err(ShellError::expansion(target, "not a valid environment variable name"));
+        }
+        shell.vars.writable(name)?;
+        let mut value = expand_literal(shell, value)?;
+        if append {
+            value.insert_str(0, &shell.vars.get(name).unwrap_or_default());
+        }
+        env.push((name.to_owned(), value));
+    }
+    Ok(env)
+}
+
+/// The value and environment entry a variable had before `lend_env` set
+/// it for a builtin.
+type Lent = (String, Option<String>, Option<OsString>);
+
+/// Export the variables of a builtin's `env` for as long as it runs,
+/// returning what `return_env` puts back afterwards.
+fn lend_env(shell: &mut Shell, env: Vec<(String, String)>) -> Vec<Lent> {
+    env.into_iter()
+        .map(|(name, value)| {
+            let lent = (name.clone(), shell.vars.get(&name), std::env::var_os(&name));
+            shell.vars.export(&name, value);
+            lent
+        })
+        .collect()
+}
+
+fn return_env(shell: &mut Shell, lent: Vec<Lent>) {
+    for (name, value, exported) in lent.into_iter().rev() {
+        match value {
+            Some(value) => shell.vars.set(&name, value),
+            None => shell.vars.unset(&name),
+        }
+        match exported {
+            Some(exported) => std::env::set_var(&name, exported),
+            None => std::env::remove_var(&name),
+        }
+    }
+}
+
+/// Under `set -o autocd`, turn a command that is only the name of a
+/// directory, and not of a builtin or a program, into `cd` to it.
+fn autocd(shell: &Shell, argv: Vec<String>) -> Vec<String> {
+    let is_directory = shell.options.autocd
+        && argv.len() == 1
+        && shell.builtin(&argv[0]).is_none()
+        && Path::new(&argv[0]).is_dir()
+        && !runner::is_runnable(&argv[0], &shell.vars.get("PATH").unwrap_or_default());
+    match is_directory {
+        true => vec![String::from("cd"), argv[0].clone()],
+        false => argv,
+    }
+}
+
+/// Under `set -o trash`, send `rm` to the `trash` builtin, so that what
+/// it removes can be restored.
+fn trash_rm(shell: &Shell, mut argv: Vec<String>) -> Vec<String> {
+    if shell.options.trash && argv[0] == "rm" {
+        argv[0] = String::from("trash");
+    }
+    argv
+}
+
+/// Start every stage of a pipeline, returning what each left behind. A
+/// stage that cannot run is reported once and the stage after it reads an
+/// empty stdin rather than the terminal.
+/// `background` pipelines never have their output paged. With `stats`,
+/// the stages are timed and the processes are left for it to reap.
+fn exec_command_with_pipes(shell: &mut Shell, commands: &[parser::Command], background: bool,
+                           mut stats: Option<&mut PipelineStats>) -> Vec<Spawned> {
+    let mut prev_stdout: Option<OwnedFd> = None;
+    let mut stages = Vec::with_capacity(commands.len());
+    let commands_nums = commands.len();
+    for (commands_count, command) in commands.iter().enumerate() {
+        let is_last = commands_count == commands_nums - 1;
+        if commands_count > 0 && prev_stdout.is_none() {
+            prev_stdout = File::open("/dev/null").ok().map(OwnedFd::from);
+        }
+        let simple = match command {
+            parser::Command::Simple(simple) => simple,
+            parser::Command::Compound(compound, redirects) => {
+                // A compound command in a pipeline runs in a copy of the
+                // shell, between the pipes of the stages around it.
+                let stdin = prev_stdout.take();
+                let (output, pipe) = match is_last {
+                    true => (None, None),
+                    false => match io::pipe() {
+                        Ok((reader, writer)) => (Some(OwnedFd::from(reader)), Some(OwnedFd::from(writer))),
+                        Err(e) => {
+                            eprintln!("shell: {}", e);
+                            (None, None)
+                        }
+                    },
+                };
+                let run = |shell: &mut Shell| with_redirections(shell, redirects, |shell| exec_compound(shell, compound));
+                prev_stdout = output;
+                stages.push(match fork_child(shell, stdin, pipe, run) {
+                    Some(pid) => match stats.as_deref_mut() {
+                        Some(stats) => Spawned::Watched(stats.process(compound.keyword(), pid.as_raw() as u32)),
+                        None => Spawned::Forked(pid),
+                    },
+                    None => Spawned::Status(1),
+                });
+                continue;
+            }
+        };
+        shell.vars.line = shell.lines.get(simple.line.wrapping_sub(1)).copied().unwrap_or(simple.line);
+        let resources = match open_redirections(shell, &simple.redirects) {
+            Some(resources) => resources,
+            None => {
+                prev_stdout = None;
+                stages.push(Spawned::Status(1));
+                continue;
+            }
+        };
+        // Assignments before the command's name are only for its
+        // environment; on their own they set shell variables.
+        let prefix = simple.words.iter().take_while(|word| variables::assignment(word).is_some()).count();
+        let (prefix, words) = simple.words.split_at(prefix);
+        if words.is_empty() && !prefix.is_empty() {
+            let assignments: Vec<_> = prefix.iter().filter_map(|word| variables::assignment(word)).collect();
+            prev_stdout = None;
+            match assign(shell, &assignments) {
+                Ok(()) => stages.push(Spawned::Status(0)),
+                Err(e) => {
+                    expansion_failed(shell, e);
+                    stages.push(Spawned::Status(1));
+                }
+            }
+            continue;
+        }
+        let expanded = command_env(shell, prefix).and_then(|env| Ok((env, expand_command(shell, words)?)));
+        let (env, argv) = match expanded {
+            Ok(expanded) => expanded,
+            Err(e) => {
+                expansion_failed(shell, e);
+                prev_stdout = None;
+                stages.push(Spawned::Status(1));
+                continue;
+            }
+        };
+        trace(shell, &env.iter().map(|(name, value)| format!("{}={}", name, value)).chain(argv.iter().cloned()).collect::<Vec<_>>());
+        if argv.is_empty() {
+            // With no command left after expansion, the assignments are
+            // made in the shell.
+            for (name, value) in env {
+                shell.vars.set(&name, value);
+            }
+            prev_stdout = None;
+            stages.push(Spawned::Status(0));
+            continue;
+        }
+        let argv = trash_rm(shell, autocd(shell, argv));
+        if shell.restricted && argv[0].contains('/') {
+            eprintln!("shell: {}: restricted: cannot specify `/' in command names", argv[0]);
+            prev_stdout = None;
+            stages.push(Spawned::Status(1));
+            continue;
+        }
+        let paged = !background && commands_nums == 1 && resources.stdout.is_none()
+            && pager::applies(shell, &argv[0]);
+        let captured = !background && is_last && resources.stdout.is_none() && capture::applies(shell);
+        if let Some(builtin) = shell.builtin(&argv[0]) {
+            let start = Instant::now();
+            let lent = lend_env(shell, env);
+            if background {
+                // Left running, a builtin gets a copy of the shell to
+                // itself, as a subshell does.
+                let stdin = resources.stdin.or(prev_stdout.take());
+                let (output, pipe) = match is_last || resources.stdout.is_some() {
+                    true => (None, None),
+                    false => match io::pipe() {
+                        Ok((reader, writer)) => (Some(OwnedFd::from(reader)), Some(OwnedFd::from(writer))),
+                        Err(e) => {
+                            eprintln!("shell: {}: {}", argv[0], e);
+                            (None, None)
+                        }
+                    },
+                };
+                let (stdout, stderr) = (resources.stdout, resources.stderr);
+                let pid = fork_child(shell, stdin, pipe, |shell| builtins::run(builtin, shell, &argv, None, stdout, stderr));
+                prev_stdout = output;
+                stages.push(pid.map_or(Spawned::Status(1), Spawned::Forked));
+                return_env(shell, lent);
+                continue;
+            }
+            let stdin = resources.stdin.or(prev_stdout.take()).map(File::from);
+            if is_last && resources.stdout.is_none() && records::shown_as_table(shell, &argv[0]) {
+                let (status, output) = builtins::capture(builtin, shell, &argv, stdin, resources.stderr);
+                let table = records::read(&String::from_utf8_lossy(&output)).map(|found| records::table(&found));
+                match table {
+                    Ok(table) => {
+                        let _ = io::stdout().write_all(table.as_bytes());
+                    }
+                    Err(e) => eprintln!("shell: {}: {}", argv[0], e),
+                }
+                stages.push(Spawned::Status(status));
+            } else if paged {
+                let (status, output) = builtins::capture(builtin, shell, &argv, stdin, resources.stderr);
+                if let Err(e) = pager::show(shell, output.as_slice()) {
+                    eprintln!("shell: {}", e);
+                }
+                stages.push(Spawned::Status(status));
+            } else if is_last || resources.stdout.is_some() {
+                stages.push(Spawned::Status(
+                    builtins::run(builtin, shell, &argv, stdin, resources.stdout, resources.stderr)));
+            } else {
+                let (status, output) = builtins::run_into_pipe(builtin, shell, &argv, stdin, resources.stderr);
+                prev_stdout = output.map_or_else(
+                                    |e| {
+                                        eprintln!("shell: {}: {}", argv[0], e);
+                                        None
+                                    },
+                                    |v| {
+                                        Some(v.into())
+                                    }
+                                );
+                stages.push(Spawned::Status(status));
+            }
+            return_env(shell, lent);
+            if let Some(stats) = stats.as_deref_mut() {
+                stats.builtin(&argv[0], start.elapsed());
+            }
+            continue;
+        }
+        let (priority, argv) = priority::strip_prefixes(&argv);
+        let wrapped = runner::wrap(argv, &shell.vars, &shell.globber);
+        let argv = wrapped.as_deref().unwrap_or(argv);
+        // A PATH given to the command alone is searched without touching
+        // the hash table.
+        let found = match env.iter().rfind(|(name, _)| name == "PATH") {
+            Some((_, path)) if !argv[0].contains('/') => runner::find_command(&argv[0], path),
+            _ => shell.command_hash.lookup(&argv[0], &shell.vars.get("PATH").unwrap_or_default()),
+        };
+        let Some(program) = found else {
+            prev_stdout = None;
+            stages.push(Spawned::Status(command_not_found(shell, argv)));
+            continue;
+        };
+        let mut command = Command::new(program);
+        command.arg0(&argv[0]).envs(env);
+        priority.apply(&mut command);
+        command.args(&argv[1..])
+               .apply_pipe_stream_filter(&mut prev_stdout, !is_last || paged || captured);
+        if captured && resources.stderr.is_none() {
+            command.stderr(Stdio::piped());
+        }
+        let child = command.apply_file_stream_filter(resources).spawn();
+        // Dropping the command closes the parent's copies of its pipe ends.
+        drop(command);
+        match child {
+            Ok(mut child) => {
+                prev_stdout = child.stdout.take().map(OwnedFd::from);
+                if let Some(output) = prev_stdout.take_if(|_| paged || captured) {
+                    capture::show(shell, File::from(output), child.stderr.take(), paged, captured);
+                }
+                stages.push(match stats.as_deref_mut() {
+                    Some(stats) => Spawned::Watched(stats.process(&argv[0], child.id())),
+                    None => Spawned::Child(child),
+                });
+            }
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    stages.push(Spawned::Status(command_not_found(shell, argv)));
+                } else {
+                    eprintln!("shell: {}: {}", argv[0], e);
+                    stages.push(Spawned::Status(126));
+                }
+            }
+        }
+    }
+    stages
+}
+
+/// Run `argv`, whose program can't be found, as the command `set -o
+/// correct` offers in its place, or else run `$COMMAND_NOT_FOUND` with the
+/// positional parameters set to `argv`, and return its status. Without
+/// either, the command is reported as not found, with status 127.
+fn command_not_found(shell: &mut Shell, argv: &[String]) -> i32 {
+    if shell.options.correct {
+        if let Some(name) = correct::command(shell, &argv[0]).filter(|name| correct::confirm(name)) {
+            let words: Vec<String> = std::iter::once(&name).chain(&argv[1..]).map(|word| builtins::single_quote(word)).collect();
+            return exec_text(shell, &words.join(" "));
+        }
+    }
+    let handler = shell.vars.get("COMMAND_NOT_FOUND").filter(|handler| !handler.trim().is_empty());
+    let Some(handler) = handler.filter(|_| !shell.handling_not_found) else {
+        ShellError::CommandNotFound(argv[0].clone()).report();
+        return 127;
+    };
+    let positional = std::mem::replace(&mut shell.vars.positional, argv.to_vec());
+    shell.handling_not_found = true;
+    let status = exec_text(shell, &handler);
+    shell.handling_not_found = false;
+    shell.vars.positional = positional;
+    status
+}
+
+/// Parse and run the commands in `text`, returning the status of the
+/// last, or 2 if they can't be parsed.
+fn exec_text(shell: &mut Shell, text: &str) -> i32 {
+    match parser::parse(text, &[], &shell.aliases, shell.keywords(), true) {
+        Ok(list) => exec_list(shell, &list),
+        Err(e) => {
+            ShellError::from(e).report();
+            2
+        }
+    }
+}
+
+fn exec_if(shell: &mut Shell, clause: &IfClause) -> i32 {
+    for (condition, body) in &clause.branches {
+        shell.conditions += 1;
+        let status = exec_list(shell, condition);
+        shell.conditions -= 1;
+        if status == 0 {
+            return exec_list(shell, body);
+        }
+    }
+    match &clause.else_body {
+        Some(body) => exec_list(shell, body),
+        None => 0,
+    }
+}
+
+fn exec_for(shell: &mut Shell, clause: &ForClause) -> i32 {
+    let words = match clause.words.as_ref().map(|words| expand_words(shell, words)) {
+        Some(Ok(words)) => words,
+        Some(Err(e)) => {
+            expansion_failed(shell, e);
+            return 1;
+        }
+        None => Vec::new(),
+    };
+    let mut status = 0;
+    shell.loops += 1;
+    for word in words {
+        if let Err(e) = shell.vars.writable(&clause.var) {
+            e.report();
+            status = 1;
+            break;
+        }
+        shell.vars.set(&clause.var, word);
+        status = exec_list(shell, &clause.body);
+        if !next_round(shell) {
+            break;
+        }
+    }
+    shell.loops -= 1;
+    status
+}
+
+/// Whether a loop goes on to its next round after its body ran, given
+/// the `break` or `continue` that may have ended the body early. One
+/// that reaches further out is passed on to the loop around.
+fn next_round(shell: &mut Shell) -> bool {
+    match shell.flow {
+        None => true,
+        Some(Flow::Return) => false,
+        Some(Flow::Break(1)) => {
+            shell.flow = None;
+            false
+        }
+        Some(Flow::Continue(1)) => {
+            shell.flow = None;
+            true
+        }
+        Some(Flow::Break(n)) => {
+            shell.flow = Some(Flow::Break(n - 1));
+            false
+        }
+        Some(Flow::Continue(n)) => {
+            shell.flow = Some(Flow::Continue(n - 1));
+            false
+        }
+    }
+}
+
+/// Show the words of `clause` (or the positional parameters) as a
+/// numbered menu on stderr, then read choices from stdin after `$PS3`,
+/// running the body with `$REPLY` set to each line read and the variable
+/// to the word it picks, or to nothing. An empty line shows the menu
+/// again; the loop ends at the end of the input.
+fn exec_select(shell: &mut Shell, clause: &ForClause) -> i32 {
+    let words = match clause.words.as_ref().map(|words| expand_words(shell, words)) {
+        Some(Ok(words)) => words,
+        Some(Err(e)) => {
+            expansion_failed(shell, e);
+            return 1;
+        }
+        None => shell.vars.positional.clone(),
+    };
+    if words.is_empty() {
+        return 0;
+    }
+    let width = words.len().to_string().len();
+    let mut status = 0;
+    let mut show_menu = true;
+    shell.loops += 1;
+    let status = loop {
+        if show_menu {
+            for (n, word) in words.iter().enumerate() {
+                eprintln!("{:>width$}) {}", n + 1, word);
+            }
+        }
+        eprint!("{}", shell.vars.get("PS3").unwrap_or_else(|| String::from("#? ")));
+        let Some(reply) = read_stdin_line() else {
+            eprintln!();
+            break status;
+        };
+        show_menu = reply.trim().is_empty();
+        if show_menu {
+            continue;
+        }
+        let choice = reply.trim().parse::<usize>().ok()
+            .and_then(|n| words.get(n.checked_sub(1)?))
+            .cloned()
+            .unwrap_or_default();
+        if let Err(e) = shell.vars.writable(&clause.var) {
+            e.report();
+            break 1;
+        }
+        shell.vars.set("REPLY", reply);
+        shell.vars.set(&clause.var, choice);
+        status = exec_list(shell, &clause.body);
+        if !next_round(shell) {
+            break status;
+        }
+    };
+    shell.loops -= 1;
+    status
+}
+
+/// Read a line from stdin a byte at a time, so as to leave what follows
+/// it for the commands after, without its newline. `None` at the end of
+/// the input.
+fn read_stdin_line() -> Option<String> {
+    let mut line = Vec::new();
+    let mut byte = [0u8];
+    loop {
+        match nix::unistd::read(io::stdin(), &mut byte) {
+            Ok(0) if line.is_empty() => return None,
+            Ok(0) => break,
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(Errno::EINTR) => continue,
+            Err(_) => return None,
+        }
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Run the first arm of `clause` with a pattern matching its word. An
+/// invalid pattern only matches its own text.
+fn exec_case(shell: &mut Shell, clause: &CaseClause) -> i32 {
+    let matches = |shell: &Shell, pattern: &str, word: &str| -> Result<bool, ShellError> {
+        Ok(match shell.globber.matches(&case_pattern(shell, pattern)?, word) {
+            Some(matched) => matched,
+            None => expand_literal(shell, pattern)? == word,
+        })
+    };
+    let word = match expand_literal(shell, &clause.word) {
+        Ok(word) => word,
+        Err(e) => {
+            expansion_failed(shell, e);
+            return 1;
+        }
+    };
+    for (patterns, body) in &clause.arms {
+        for pattern in patterns {
+            match matches(shell, pattern, &word) {
+                Ok(true) => return exec_list(shell, body),
+                Ok(false) => {}
+                Err(e) => {
+                    expansion_failed(shell, e);
+                    return 1;
+                }
+            }
+        }
+    }
+    0
+}
+
+/// Fork a copy of the shell that runs `run` with `stdin` and `stdout`, if
+/// given, in place of its own, and exits with the status `run` returns.
+/// Changes it makes to the working directory, variables and the like
+/// don't reach the parent.
+fn fork_child(shell: &mut Shell, stdin: Option<OwnedFd>, stdout: Option<OwnedFd>,
+              run: impl FnOnce(&mut Shell) -> i32) -> Option<Pid> {
+    let _ = io::stdout().flush();
+    match unsafe { fork() } {
+        Ok(ForkResult::Child) => {
+            if let Some(stdin) = stdin {
+                let _ = dup2_stdin(stdin);
+            }
+            if let Some(stdout) = stdout {
+                let _ = dup2_stdout(stdout);
+            }
+            shell.traps.reset_commands();
+            shell.hooks.clear_commands();
+            // Nor is it the session to hang up jobs or log out.
+            shell.interactive = false;
+            shell.login = false;
+            // The child must not fall back into the parent's prompt loop.
+            let status = crash::catch(|| run(shell)).unwrap_or_else(|crash| {
+                eprintln!("shell: internal error: {}", crash);
+                1
+            });
+            exit(shell, status);
+        }
+        Ok(ForkResult::Parent { child }) => Some(child),
+        Err(e) => {
+            eprintln!("shell: fork: {}", e);
+            None
+        }
+    }
+}
+
+/// Run `body` in a forked copy of the shell and wait for it.
+fn exec_subshell(shell: &mut Shell, body: &List) -> i32 {
+    let Some(child) = fork_child(shell, None, None, |shell| exec_list(shell, body)) else {
+        return 1;
+    };
+    wait_pid(child).unwrap_or_else(|e| {
+        eprintln!("shell: {}", e);
+        1
+    })
+}
+
+fn exec_compound(shell: &mut Shell, compound: &CompoundCommand) -> i32 {
+    match compound {
+        CompoundCommand::If(clause) => exec_if(shell, clause),
+        CompoundCommand::For(clause) => exec_for(shell, clause),
+        CompoundCommand::Select(clause) => exec_select(shell, clause),
+        CompoundCommand::Case(clause) => exec_case(shell, clause),
+        CompoundCommand::Subshell(body) => exec_subshell(shell, body),
+        CompoundCommand::Group(body) => exec_list(shell, body),
+        CompoundCommand::Arith(expr) => exec_arith(shell, expr),
+        CompoundCommand::Conditional(words) => exec_conditional(shell, words),
+    }
+}
+
+fn exec_conditional(shell: &mut Shell, words: &[String]) -> i32 {
+    trace_text(shell, &format!("[[ {} ]]", words.join(" ")));
+    match conditional::evaluate(shell, words) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            expansion_failed(shell, e);
+            2
+        }
+    }
+}
+
+fn exec_arith(shell: &mut Shell, expr: &str) -> i32 {
+    trace_text(shell, &format!("(({}))", expr));
+    match variables::arithmetic(&mut shell.vars, &shell.options, expr) {
+        Ok(value) if value.is_true() => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            expansion_failed(shell, e);
+            1
+        }
+    }
+}
+
+/// Point the shell's own stdin, stdout and stderr at `redirects` while
+/// `run` executes, so that builtins and children alike inherit them.
+fn with_redirections(shell: &mut Shell, redirects: &[Redirect],
+                     run: impl FnOnce(&mut Shell) -> i32) -> i32 {
+    if redirects.is_empty() {
+        return run(shell);
+    }
+    let Some(resources) = open_redirections(shell, redirects) else {
+        return 1;
+    };
+    let _ = io::stdout().flush();
+    let _saved = match (dup(io::stdin()), dup(io::stdout()), dup(io::stderr())) {
+        (Ok(stdin), Ok(stdout), Ok(stderr)) => SavedStreams([stdin, stdout, stderr]),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            eprintln!("shell: {}", e);
+            return 1;
+        }
+    };
+    let redirect = || -> nix::Result<()> {
+        if let Some(fd) = &resources.stdin {
+            dup2_stdin(fd)?;
+        }
+        if let Some(file) = &resources.stdout {
+            dup2_stdout(file)?;
+        }
+        if let Some(file) = &resources.stderr {
+            dup2_stderr(file)?;
+        }
+        Ok(())
+    };
+    match redirect() {
+        Ok(()) => run(shell),
+        Err(e) => {
+            eprintln!("shell: {}", e);
+            1
+        }
+    }
+}
+
+/// Copies of the shell's stdin, stdout and stderr, put back in place when
+/// dropped, even if running the command panicked.
+pub struct SavedStreams(pub [OwnedFd; 3]);
+
+impl Drop for SavedStreams {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        let _ = dup2_stdin(&self.0[0]);
+        let _ = dup2_stdout(&self.0[1]);
+        let _ = dup2_stderr(&self.0[2]);
+    }
+}
+
+/// The text shown for a pipeline in job notifications.
+fn describe(pipeline: &Pipeline) -> String {
+    pipeline.commands.iter()
+        .map(|command| match command {
+            parser::Command::Simple(simple) => simple.words.join(" "),
+            _ => String::from("..."),
+        })
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// Run a pipeline and return its exit status. A background pipeline is
+/// added to the job table and counts as successful.
+fn exec_pipeline(shell: &mut Shell, pipeline: &Pipeline, background: bool) -> i32 {
+    let timed = (pipeline.timed && !background).then(|| (Instant::now(), stats::cpu_time()));
+    // Timing a pipeline also reaps its earlier stages, so that what they
+    // used is counted.
+    let mut stats = ((shell.options.stats || timed.is_some()) && !background).then(PipelineStats::default);
+    let mut stages = match pipeline.commands.as_slice() {
+        // Left running, a compound command gets a copy of the shell to
+        // itself, as a subshell does.
+        [parser::Command::Compound(compound, redirects)] if background => {
+            let run = |shell: &mut Shell| with_redirections(shell, redirects, |shell| exec_compound(shell, compound));
+            vec![fork_child(shell, None, None, run).map_or(Spawned::Status(1), Spawned::Forked)]
+        }
+        [parser::Command::Compound(compound, redirects)] => vec![Spawned::Status(
+            with_redirections(shell, redirects, |shell| exec_compound(shell, compound)))],
+        commands => exec_command_with_pipes(shell, commands, background, stats.as_mut()),
+    };
+    if background {
+        let last = stages.pop_if(|last| matches!(last, Spawned::Child(_) | Spawned::Forked(_)));
+        let pid = match last {
+            Some(Spawned::Child(child)) => Some(child.id()),
+            Some(Spawned::Forked(pid)) => Some(pid.as_raw() as u32),
+            _ => None,
+        };
+        if let Some(pid) = pid {
+            // The job is the last process; the ones before it are reaped
+            // as they finish.
+            if stages.iter().any(|stage| matches!(stage, Spawned::Child(_) | Spawned::Forked(_))) {
+                std::thread::spawn(move || {
+                    for stage in stages {
+                        match stage {
+                            Spawned::Child(mut child) => drop(child.wait()),
+                            Spawned::Forked(pid) => drop(wait_pid(pid)),
+                            _ => {}
+                        }
+                    }
+                });
+            }
+            shell.vars.last_background = Some(pid);
+            shell.jobs.spawn(pid, describe(pipeline));
+            shell.vars.status = 0;
+            return 0;
+        }
+    }
+    let reaped = stats.take().map(|stats| stats.finish(shell)).unwrap_or_default();
+    let statuses: Vec<i32> = stages.into_iter()
+        .map(|stage| match stage {
+            Spawned::Child(child) => wait_status(child),
+            Spawned::Forked(pid) => wait_pid(pid).unwrap_or_else(|e| {
+                eprintln!("shell: {}", e);
+                1
+            }),
+            Spawned::Watched(n) => reaped.get(n).copied().flatten().unwrap_or(1),
+            Spawned::Status(status) => status,
+        })
+        .collect();
+    // Under `set -o pipefail`, a pipeline fails with its last failing
+    // stage.
+    let status = match shell.options.pipefail {
+        true => statuses.iter().rev().find(|&&status| status != 0),
+        false => statuses.last(),
+    };
+    let status = status.copied().unwrap_or(0);
+    shell.vars.pipe_status = statuses;
+    if let Some((start, (user, sys))) = timed {
+        let (user_now, sys_now) = stats::cpu_time();
+        let format = |time: Duration| format!("{}m{:.3}s", time.as_secs() / 60, time.as_secs_f64() % 60.0);
+        eprintln!("\nreal\t{}\nuser\t{}\nsys\t{}",
+                  format(start.elapsed()), format(user_now.saturating_sub(user)), format(sys_now.saturating_sub(sys)));
+    }
+    let status = if pipeline.negated {
+        (status == 0) as i32
+    } else {
+        status
+    };
+    shell.vars.status = status;
+    status
+}
+
+fn exec_and_or(shell: &mut Shell, and_or: &AndOr, background: bool) -> i32 {
+    // Only a lone pipeline can be left running; a list whose later parts
+    // depend on earlier statuses runs in the foreground.
+    let background = background && and_or.rest.is_empty();
+    let mut status = exec_pipeline(shell, &and_or.first, background);
+    let mut last = &and_or.first;
+    for (connector, pipeline) in &and_or.rest {
+        let run = match connector {
+            Connector::And => status == 0,
+            Connector::Or => status != 0,
+        };
+        if run && shell.flow.is_none() {
+            status = exec_pipeline(shell, pipeline, false);
+            last = pipeline;
+        }
+    }
+    if status != 0 && shell.options.errexit && shell.conditions == 0 && errexit_applies(and_or, last) {
+        exit(shell, status);
+    }
+    status
+}
+
+/// Whether `set -e` looks at the status of `last`, the pipeline of
+/// `and_or` that ran last. Pipelines before a `&&` or `||` and negated
+/// ones are tested rather than run for effect. Compound commands other
+/// than subshells, `((...))` and `[[...]]` have already had each of their
+/// parts checked.
+fn errexit_applies(and_or: &AndOr, last: &Pipeline) -> bool {
+    let final_pipeline = and_or.rest.last().map_or(&and_or.first, |(_, pipeline)| pipeline);
+    if !std::ptr::eq(last, final_pipeline) || last.negated {
+        return false;
+    }
+    !matches!(last.commands.as_slice(),
+        [parser::Command::Compound(compound, _)]
+            if !matches!(compound, CompoundCommand::Subshell(_) | CompoundCommand::Arith(_) | CompoundCommand::Conditional(_)))
+}
+
+/// Run the command string of a trap. `$?` is left as it was.
+pub fn run_trap(shell: &mut Shell, action: &str) {
+    let status = shell.vars.status;
+    match parser::parse(action, &[], &shell.aliases, shell.keywords(), true) {
+        Ok(list) => {
+            exec_list(shell, &list);
+        }
+        Err(e) => ShellError::from(e).report(),
+    }
+    shell.vars.status = status;
+}
+
+/// Run the traps of the signals that arrived since the last check.
+pub fn run_traps(shell: &mut Shell) {
+    for action in shell.traps.take_pending() {
+        run_trap(shell, &action);
+    }
+}
+
+/// Run every item of `list` in order, returning the status of the last.
+pub fn exec_list(shell: &mut Shell, list: &List) -> i32 {
+    let mut status = 0;
+    for item in list {
+        status = exec_and_or(shell, &item.and_or, item.background);
+        run_traps(shell);
+        if shell.flow.is_some() {
+            break;
+        }
+    }
+    status
+}
@@ -0,0 +1,273 @@
+use crate::error::ShellError;
+use crate::globbing::Globber;
+use crate::variables::Assigned;
+use crate::{braces, builtins, lexer, variables, PathMatcher, Shell};
+
+/// The builtins whose `name=value` arguments are expanded as assignments.
+const DECLARATIONS: &[&str] = &["declare", "readonly", "typeset"];
+
+/// Expand braces in, substitute variables in, tilde-expand and glob
+/// `word`. Only unquoted wildcards match file names, which are used as
+/// they are; other words have their quotes removed. A word made only of empty
+/// expansions disappears, as does a pattern matching nothing under `set
+/// -o nullglob`; under `set -o failglob` such a pattern is an error.
+pub fn expand_word(shell: &Shell, word: &str) -> Result<Vec<String>, ShellError> {
+    let mut fields = Vec::new();
+    for word in braces::expand(word) {
+        fields.extend(variables::substitute_fields(&word, &shell.vars, &shell.options)?);
+    }
+    let mut res = Vec::new();
+    for field in fields {
+        if field.is_empty() {
+            continue;
+        }
+        let unfolded = tilde_expand(shell, &field);
+        let (pattern, wild) = glob_pattern(&shell.globber, &unfolded);
+        let matches = match wild {
+            true => shell.globber.glob(&pattern, shell.options.glob()).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        if !matches.is_empty() {
+            res.extend(matches);
+            continue;
+        }
+        if wild && shell.options.failglob {
+            return Err(ShellError::expansion(&unquote(&unfolded), "no match"));
+        }
+        if !(wild && shell.options.nullglob) {
+            res.push(unquote(&unfolded));
+        }
+    }
+    Ok(res)
+}
+
+/// Tilde-expand `word`. `~N` stands for entry N of the directory stack as
+/// `dirs -v` numbers it; other forms are left to `PathMatcher::unfold`.
+fn tilde_expand(shell: &Shell, word: &str) -> String {
+    if let Some(rest) = word.strip_prefix('~') {
+        let (n, tail) = rest.split_once('/').map_or((rest, None), |(n, tail)| (n, Some(tail)));
+        let index = n.parse::<usize>().ok().filter(|_| n.bytes().all(|b| b.is_ascii_digit()));
+        if let Some(dir) = index.and_then(|index| builtins::dir_stack_entry(shell, index)) {
+            return match tail {
+                Some(tail) => dir.join(tail).to_string_lossy().into_owned(),
+                None => dir.to_string_lossy().into_owned(),
+            };
+        }
+    }
+    word.unfold(&shell.named_dirs)
+}
+
+/// Expand `word` into a single string without globbing, as done for
+/// assignment values and the subject of `case`.
+pub fn expand_literal(shell: &Shell, word: &str) -> Result<String, ShellError> {
+    let substituted = variables::substitute(word, &shell.vars, &shell.options)?;
+    Ok(unquote(&tilde_expand(shell, &substituted)))
+}
+
+/// Turn a `case` pattern into a glob pattern, with quoted and escaped
+/// characters matching literally.
+pub fn case_pattern(shell: &Shell, word: &str) -> Result<String, ShellError> {
+    let expanded = tilde_expand(shell, &variables::substitute(word, &shell.vars, &shell.options)?);
+    Ok(glob_pattern(&shell.globber, &expanded).0)
+}
+
+/// Turn a `[[ text =~ regex ]]` regular expression into one with quoted
+/// and escaped characters matching literally.
+pub fn regex_pattern(shell: &Shell, word: &str) -> Result<String, ShellError> {
+    let expanded = tilde_expand(shell, &variables::substitute(word, &shell.vars, &shell.options)?);
+    let escape = |c: char| match "\\.[]()*+?{}|^$".contains(c) {
+        true => format!("\\{}", c),
+        false => c.to_string(),
+    };
+    Ok(literal_pattern(&expanded, escape).0)
+}
+
+/// Turn the expanded `word` into a glob pattern with its quotes removed and
+/// the characters they quoted, or that are escaped, escaped for `globber`.
+/// Also tells whether any wildcard is left unquoted.
+fn glob_pattern(globber: &Globber, word: &str) -> (String, bool) {
+    literal_pattern(word, |c| globber.escape(&c.to_string()))
+}
+
+/// Remove the quotes of the expanded `word`, passing the characters they
+/// quoted, and escaped ones, through `escape`. Also tells whether any glob
+/// wildcard is left unquoted.
+fn literal_pattern(word: &str, escape: impl Fn(char) -> String) -> (String, bool) {
+    let mut quote: Option<char> = None;
+    let mut wild = false;
+    let mut res = String::with_capacity(word.len());
+    let mut chars = word.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let literal = match quote {
+            Some(q) if ch == q => {
+                quote = None;
+                continue;
+            }
+            Some('"') if ch == '\\' => match chars.peek() {
+                Some(&c) if "$`\"\\\n".contains(c) => chars.next().unwrap(),
+                _ => ch,
+            },
+            Some(_) => ch,
+            None if ch == '"' || ch == '\'' => {
+                quote = Some(ch);
+                continue;
+            }
+            None if ch == '\\' => match chars.next() {
+                Some(c) => c,
+                None => continue,
+            },
+            None => {
+                wild |= matches!(ch, '*' | '?' | '[');
+                res.push(ch);
+                continue;
+            }
+        };
+        res.push_str(&escape(literal));
+    }
+    (res, wild)
+}
+
+/// Expand the words of a list.
+pub fn expand_words(shell: &Shell, words: &[String]) -> Result<Vec<String>, ShellError> {
+    let mut res = Vec::new();
+    for word in words {
+        res.extend(expand_word(shell, word)?);
+    }
+    Ok(res)
+}
+
+/// Expand the words of a simple command into its argv. The arguments of
+/// `declare`, `typeset` and `readonly` that are assignments are expanded
+/// as assignments, without splitting or globbing their values, and passed
+/// on as `quote_assignment` writes them.
+pub fn expand_command(shell: &Shell, words: &[String]) -> Result<Vec<String>, ShellError> {
+    let Some((first, args)) = words.split_first().filter(|(first, _)| DECLARATIONS.contains(&first.as_str())) else {
+        return expand_words(shell, words);
+    };
+    let mut res = vec![first.clone()];
+    for word in args {
+        match variables::assignment(word) {
+            Some((name, value)) => res.push(quote_assignment(name, &expand_assignment(shell, value)?)),
+            None => res.extend(expand_word(shell, word)?),
+        }
+    }
+    Ok(res)
+}
+
+/// Expand the value of an assignment: a word expanded like `expand_literal`
+/// does, or an array's `(word ...)`, whose words are split and globbed
+/// like a command's. An element written `[subscript]=word` keeps its
+/// subscript for the variable to work out, and its word isn't split.
+pub fn expand_assignment(shell: &Shell, value: &str) -> Result<Assigned, ShellError> {
+    let Some(words) = lexer::array_elements(value) else {
+        return Ok(Assigned::Scalar(expand_literal(shell, value)?));
+    };
+    let mut elements = Vec::new();
+    for word in words {
+        match word.strip_prefix('[').and_then(|rest| rest.split_once("]=")) {
+            Some((index, value)) => elements.push((Some(index.to_owned()), expand_literal(shell, value)?)),
+            None => elements.extend(expand_word(shell, &word)?.into_iter().map(|value| (None, value))),
+        }
+    }
+    Ok(Assigned::Array(elements))
+}
+
+/// Write the assignment of `value` to `name` back as a word, with the
+/// value quoted, for `assigned` to read.
+pub fn quote_assignment(name: &str, value: &Assigned) -> String {
+    match value {
+        Assigned::Scalar(value) => format!("{}={}", name, builtins::single_quote(value)),
+        Assigned::Array(elements) => {
+            let elements: Vec<String> = elements.iter()
+                .map(|(index, value)| match index {
+                    Some(index) => format!("[{}]={}", index, builtins::single_quote(value)),
+                    None => builtins::single_quote(value),
+                })
+                .collect();
+            format!("{}=({})", name, elements.join(" "))
+        }
+    }
+}
+
+/// The value of an assignment written by `quote_assignment`.
+pub fn assigned(value: &str) -> Assigned {
+    let Some(words) = lexer::array_elements(value) else {
+        return Assigned::Scalar(unquote(value));
+    };
+    let elements = words.iter()
+        .map(|word| match word.strip_prefix('[').and_then(|rest| rest.split_once("]=")) {
+            Some((index, value)) => (Some(index.to_owned()), unquote(value)),
+            None => (None, unquote(word)),
+        })
+        .collect();
+    Assigned::Array(elements)
+}
+
+/// Remove the quote characters and escaping backslashes of `word`,
+/// keeping the quoted text.
+pub fn unquote(word: &str) -> String {
+    let mut quote: Option<char> = None;
+    let mut res = String::with_capacity(word.len());
+    let mut chars = word.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match quote {
+            Some(q) if ch == q => quote = None,
+            Some('"') if ch == '\\' => match chars.peek() {
+                Some(&c) if "$`\"\\\n".contains(c) => {
+                    res.push(c);
+                    chars.next();
+                }
+                _ => res.push(ch),
+            },
+            Some(_) => res.push(ch),
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None if ch == '\\' => {
+                if let Some(c) = chars.next() {
+                    res.push(c);
+                }
+            }
+            None => res.push(ch),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell() -> Shell {
+        let mut shell = Shell::new();
+        shell.set_var("x", "日本語🎉");
+        shell
+    }
+
+    #[test]
+    fn multibyte_values_next_to_text() {
+        let shell = shell();
+        assert_eq!(expand_word(&shell, "前${x}後").unwrap(), ["前日本語🎉後"]);
+        assert_eq!(expand_word(&shell, "\"é$x\"").unwrap(), ["é日本語🎉"]);
+    }
+
+    #[test]
+    fn lengths_count_characters() {
+        let mut shell = shell();
+        assert_eq!(expand_word(&shell, "${#x}").unwrap(), ["4"]);
+        shell.set_var("y", "e\u{301}tude");
+        assert_eq!(expand_word(&shell, "${#y}").unwrap(), ["6"]);
+    }
+
+    #[test]
+    fn multibyte_field_separators() {
+        let mut shell = shell();
+        shell.set_var("IFS", "・");
+        shell.set_var("v", "a・b・ü");
+        assert_eq!(expand_word(&shell, "$v").unwrap(), ["a", "b", "ü"]);
+    }
+
+    #[test]
+    fn unquoting_multibyte_words() {
+        assert_eq!(unquote("'日本'\"語\"🎉"), "日本語🎉");
+        assert_eq!(unquote("e\\\u{301}"), "e\u{301}");
+    }
+}
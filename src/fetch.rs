@@ -0,0 +1,140 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// How many redirects are followed before giving up.
+const MAX_REDIRECTS: usize = 5;
+
+/// How long connecting, or waiting for the server to send more, may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The parts of an `http://` URL.
+struct Url {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+fn parse_url(url: &str) -> Result<Url, String> {
+    let rest = match url.split_once("://") {
+        Some(("http", rest)) => rest,
+        Some((scheme, _)) => return Err(format!("{}: unsupported scheme", scheme)),
+        None => url,
+    };
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_owned()),
+        None => (rest, String::from("/")),
+    };
+    // The colons of a bracketed IPv6 address don't start a port.
+    let (host, port) = match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => {
+            let port = &authority[i + 1..];
+            (&authority[..i], port.parse().map_err(|_| format!("{}: invalid port", port))?)
+        }
+        _ => (authority, 80),
+    };
+    if host.is_empty() {
+        return Err(String::from("missing host"));
+    }
+    Ok(Url { host: host.to_owned(), port, path: path.replace(' ', "%20") })
+}
+
+/// A response whose headers have been read, with the body still to come.
+struct Response {
+    status: u16,
+    reason: String,
+    headers: Vec<(String, String)>,
+    body: BufReader<TcpStream>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Copy the body to `out`, undoing chunked transfer encoding.
+    fn copy_body(mut self, out: &mut dyn Write) -> io::Result<()> {
+        let chunked = self.header("Transfer-Encoding").is_some_and(|te| te.eq_ignore_ascii_case("chunked"));
+        if !chunked {
+            let limit = self.header("Content-Length").and_then(|len| len.trim().parse().ok()).unwrap_or(u64::MAX);
+            io::copy(&mut self.body.take(limit), out)?;
+            return Ok(());
+        }
+        loop {
+            let mut line = String::new();
+            self.body.read_line(&mut line)?;
+            let size = line.split(';').next().unwrap_or("").trim();
+            let size = u64::from_str_radix(size, 16)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+            if size == 0 {
+                return Ok(());
+            }
+            let copied = io::copy(&mut (&mut self.body).take(size), out)?;
+            if copied < size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            self.body.read_line(&mut line)?;
+        }
+    }
+}
+
+fn request(url: &Url) -> io::Result<Response> {
+    let addr = (url.host.trim_matches(['[', ']']), url.port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host not found"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let host = match url.port {
+        80 => url.host.clone(),
+        port => format!("{}:{}", url.host, port),
+    };
+    write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: rust-shell\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+           url.path, host)?;
+    let mut body = BufReader::new(stream);
+    let mut line = String::new();
+    body.read_line(&mut line)?;
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid response");
+    let mut parts = line.trim_end().splitn(3, ' ');
+    let status = parts.nth(1).and_then(|code| code.parse().ok()).ok_or_else(invalid)?;
+    let reason = parts.next().unwrap_or("").to_owned();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if body.read_line(&mut line)? == 0 {
+            return Err(invalid());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    Ok(Response { status, reason, headers, body })
+}
+
+/// Fetch `url` with a GET request, following redirects, and copy the body
+/// to `out`. Only plain `http://` is supported. A response other than a
+/// success is an error.
+pub fn fetch(url: &str, out: &mut dyn Write) -> Result<(), String> {
+    let mut url = parse_url(url)?;
+    for _ in 0..=MAX_REDIRECTS {
+        let response = request(&url).map_err(|e| format!("{}: {}", url.host, e))?;
+        match response.status {
+            200..=299 => return response.copy_body(out).map_err(|e| e.to_string()),
+            301 | 302 | 303 | 307 | 308 => {
+                let location = response.header("Location").ok_or("redirect without a location")?;
+                url = match location.starts_with('/') {
+                    true => Url { path: location.to_owned(), ..url },
+                    false => parse_url(location)?,
+                };
+            }
+            status => return Err(format!("{} {}", status, response.reason)),
+        }
+    }
+    Err(String::from("too many redirects"))
+}
@@ -0,0 +1,110 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::history::{decode, encode};
+use crate::Shell;
+
+/// Once the ranks add up to more than this, they are all scaled down, and
+/// the directories left with too little are forgotten.
+const MAX_TOTAL: f64 = 9000.0;
+
+/// A directory the shell has been in.
+pub struct Visited {
+    pub path: PathBuf,
+    /// How often it was visited, less for visits long ago.
+    pub rank: f64,
+    /// When it was last visited, in seconds since the epoch.
+    pub time: u64,
+}
+
+impl Visited {
+    /// How likely the directory is to be wanted `now`: its rank, weighed
+    /// up when it was visited in the last hour or day and down when not
+    /// in the last week, as `z` does.
+    pub fn score(&self, now: u64) -> f64 {
+        match now.saturating_sub(self.time) {
+            age if age < 3600 => self.rank * 4.0,
+            age if age < 86400 => self.rank * 2.0,
+            age if age < 604800 => self.rank / 2.0,
+            _ => self.rank / 4.0,
+        }
+    }
+
+    /// Whether each of `patterns` is in the path, in order and ignoring
+    /// case.
+    pub fn matches(&self, patterns: &[String]) -> bool {
+        let path = self.path.to_string_lossy().to_lowercase();
+        let mut rest = path.as_str();
+        patterns.iter().all(|pattern| {
+            let pattern = pattern.to_lowercase();
+            match rest.find(&pattern) {
+                Some(i) => {
+                    rest = &rest[i + pattern.len()..];
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+}
+
+/// The file the visited directories are kept in:
+/// `$XDG_DATA_HOME/rsh/dirs`, or `~/.local/share/rsh/dirs`. It holds one
+/// `rank<TAB>time<TAB>path` per line.
+pub fn path(shell: &Shell) -> Option<PathBuf> {
+    crate::data_home(shell).map(|data| data.join("rsh/dirs"))
+}
+
+/// The directories in the file at `path`; none when it is missing.
+pub fn load(path: &Path) -> io::Result<Vec<Visited>> {
+    let text = match fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text.split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, |&b| b == b'\t');
+            let rank = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+            let time = std::str::from_utf8(fields.next()?).ok()?.parse().ok()?;
+            let path = PathBuf::from(decode(fields.next()?).into_owned());
+            Some(Visited { path, rank, time })
+        })
+        .collect())
+}
+
+/// Replace the file at `path` with `dirs`, through a temporary file so
+/// that other sessions never read a partial one.
+fn save(path: &Path, dirs: &[Visited]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    for dir in dirs {
+        writeln!(file, "{}\t{}\t{}", dir.rank, dir.time, encode(&dir.path.to_string_lossy()))?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// Count a visit to `dir` at `now` in the file at `path`.
+pub fn visit(path: &Path, dir: &Path, now: u64) -> io::Result<()> {
+    let mut dirs = load(path)?;
+    match dirs.iter_mut().find(|visited| visited.path == dir) {
+        Some(visited) => {
+            visited.rank += 1.0;
+            visited.time = now;
+        }
+        None => dirs.push(Visited { path: dir.to_path_buf(), rank: 1.0, time: now }),
+    }
+    if dirs.iter().map(|visited| visited.rank).sum::<f64>() > MAX_TOTAL {
+        for visited in &mut dirs {
+            visited.rank *= 0.99;
+        }
+        dirs.retain(|visited| visited.rank >= 1.0);
+    }
+    save(path, &dirs)
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use glob::{MatchOptions, Pattern};
+
+/// How patterns match files, as set by the shell's glob options.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct GlobOptions {
+    /// Whether wildcards match a leading `.` (`set -o dotglob`).
+    pub dotglob: bool,
+    /// Whether a `**` component matches any number of directories (`set -o
+    /// globstar`); otherwise it is the same as `*`.
+    pub globstar: bool,
+    /// Whether letters match either case (`set -o nocaseglob`).
+    pub nocaseglob: bool,
+}
+
+impl GlobOptions {
+    fn match_options(self) -> MatchOptions {
+        MatchOptions {
+            case_sensitive: !self.nocaseglob,
+            require_literal_separator: false,
+            require_literal_leading_dot: !self.dotglob,
+        }
+    }
+}
+
+/// A pathname expansion engine. The shell uses the `glob` crate; another
+/// engine (e.g. `globset`) can be plugged in with `Globber::new`.
+pub trait GlobBackend: Send + Sync {
+    /// The paths matching `pattern`, or `None` if it is not a valid pattern.
+    fn glob(&self, pattern: &str, options: GlobOptions) -> Option<Vec<String>>;
+
+    /// Whether `text` matches `pattern`, or `None` if it is not a valid
+    /// pattern.
+    fn matches(&self, pattern: &str, text: &str) -> Option<bool>;
+
+    /// Escape `text` so that it matches only itself.
+    fn escape(&self, text: &str) -> String;
+}
+
+/// `pattern` with each run of `*` made a single one, except for `**`
+/// components under `globstar`, which the `glob` crate takes as recursive.
+fn single_stars(pattern: &str, globstar: bool) -> String {
+    let components: Vec<String> = pattern.split('/')
+        .map(|component| match globstar && component == "**" {
+            true => component.to_owned(),
+            false => {
+                let mut res = String::with_capacity(component.len());
+                for c in component.chars() {
+                    if !(c == '*' && res.ends_with('*')) {
+                        res.push(c);
+                    }
+                }
+                res
+            }
+        })
+        .collect();
+    components.join("/")
+}
+
+/// The `glob` crate.
+pub struct GlobCrate;
+
+impl GlobBackend for GlobCrate {
+    fn glob(&self, pattern: &str, options: GlobOptions) -> Option<Vec<String>> {
+        let pattern = single_stars(pattern, options.globstar);
+        let paths = glob::glob_with(&pattern, options.match_options()).ok()?;
+        Some(paths.flatten()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    fn matches(&self, pattern: &str, text: &str) -> Option<bool> {
+        Pattern::new(pattern).ok().map(|pattern| pattern.matches(text))
+    }
+
+    fn escape(&self, text: &str) -> String {
+        Pattern::escape(text)
+    }
+}
+
+/// How many pattern results are kept before the cache starts over.
+const CACHE_CAPACITY: usize = 256;
+
+/// A directory whose mtime is this recent may still change within the
+/// same timestamp tick, so its listing isn't cached yet.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+
+struct CacheEntry {
+    mtime: SystemTime,
+    paths: Vec<String>,
+}
+
+/// Pathname expansion through a `GlobBackend`. The results of patterns
+/// with wildcards only in their last component are remembered until the
+/// one directory they list is modified, so that repeated expansions and
+/// completions don't rescan it.
+pub struct Globber {
+    backend: Box<dyn GlobBackend>,
+    cache: Mutex<HashMap<(PathBuf, String, GlobOptions), CacheEntry>>,
+    /// Whether paths are sorted by their bytes rather than as the backend
+    /// orders them.
+    bytewise: bool,
+}
+
+impl Default for Globber {
+    fn default() -> Self {
+        Globber::new(Box::new(GlobCrate))
+    }
+}
+
+impl Globber {
+    pub fn new(backend: Box<dyn GlobBackend>) -> Self {
+        Globber { backend, cache: Mutex::default(), bytewise: false }
+    }
+
+    /// This globber with its results sorted by their bytes, whatever the
+    /// backend and locale.
+    pub fn bytewise(self) -> Self {
+        Globber { bytewise: true, ..self }
+    }
+
+    /// The paths matching `pattern`, or `None` if it is not a valid pattern.
+    pub fn glob(&self, pattern: &str, options: GlobOptions) -> Option<Vec<String>> {
+        let mut paths = self.cached_glob(pattern, options)?;
+        if self.bytewise {
+            paths.sort_unstable_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+        }
+        Some(paths)
+    }
+
+    fn cached_glob(&self, pattern: &str, options: GlobOptions) -> Option<Vec<String>> {
+        let Some(dir) = listed_dir(pattern) else {
+            return self.backend.glob(pattern, options);
+        };
+        let mtime = match fs::metadata(&dir).and_then(|meta| meta.modified()) {
+            Ok(mtime) => mtime,
+            Err(_) => return self.backend.glob(pattern, options),
+        };
+        let key = (dir, pattern.to_owned(), options);
+        if let Some(entry) = self.cache.lock().unwrap().get(&key) {
+            if entry.mtime == mtime {
+                return Some(entry.paths.clone());
+            }
+        }
+        let paths = self.backend.glob(pattern, options)?;
+        let settled = SystemTime::now().duration_since(mtime).is_ok_and(|age| age > SETTLE_TIME);
+        if settled {
+            let mut cache = self.cache.lock().unwrap();
+            if cache.len() >= CACHE_CAPACITY {
+                cache.clear();
+            }
+            cache.insert(key, CacheEntry { mtime, paths: paths.clone() });
+        }
+        Some(paths)
+    }
+
+    pub fn matches(&self, pattern: &str, text: &str) -> Option<bool> {
+        self.backend.matches(pattern, text)
+    }
+
+    pub fn escape(&self, text: &str) -> String {
+        self.backend.escape(text)
+    }
+}
+
+/// The absolute path of the only directory expanding `pattern` reads, if
+/// all of its wildcards are in the last component.
+fn listed_dir(pattern: &str) -> Option<PathBuf> {
+    let dir = match pattern.rsplit_once('/') {
+        Some(("", _)) => "/",
+        Some((dir, _)) => dir,
+        None => ".",
+    };
+    if dir.contains(['*', '?', '[']) {
+        return None;
+    }
+    std::env::current_dir().ok().map(|cwd| cwd.join(dir))
+}
@@ -0,0 +1,81 @@
+use std::fs::{self, File};
+use std::io::{self, Seek, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::fd::OwnedFd;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::lexer;
+use crate::LineReader;
+
+/// Bodies up to this size are fed through a pipe, which the kernel can
+/// buffer without anyone reading it yet. Larger bodies go to a temp file
+/// so that writing them can never block on a reader that hasn't started.
+const PIPE_THRESHOLD: usize = 16 * 1024;
+
+/// A here-document delimiter found on a command line.
+struct Delimiter {
+    word: String,
+    /// `<<-` strips leading tabs from body lines and the delimiter line.
+    strip_tabs: bool,
+}
+
+/// Find the `<<word` / `<<-word` operators of `line`, in order.
+fn delimiters(line: &str) -> Vec<Delimiter> {
+    lexer::here_doc_delimiters(line).into_iter()
+        .map(|(word, strip_tabs)| Delimiter { word, strip_tabs })
+        .collect()
+}
+
+/// Read the bodies of all here-documents opened on `line` from `reader`.
+pub fn read_bodies(reader: &mut LineReader, line: &str) -> io::Result<Vec<String>> {
+    let mut bodies = Vec::new();
+    for delimiter in delimiters(line) {
+        let mut body = String::new();
+        loop {
+            let mut buf = String::new();
+            if reader.read_line("", &mut buf)? == 0 {
+                eprintln!("shell: warning: here-document delimited by end-of-file (wanted '{}')",
+                          delimiter.word);
+                break;
+            }
+            let text = if delimiter.strip_tabs { buf.trim_start_matches('\t') } else { &buf };
+            if text.trim_end_matches('\n') == delimiter.word {
+                break;
+            }
+            body.push_str(text);
+        }
+        bodies.push(body);
+    }
+    Ok(bodies)
+}
+
+/// Create an already-unlinked temp file readable only by the user.
+fn anonymous_file() -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir()
+            .join(format!("rsh-heredoc-{}-{}", std::process::id(), n));
+        match File::options().mode(0o600).read(true).write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Turn a here-document body into a stream for a command's stdin.
+pub fn stdin_for(body: &str) -> io::Result<OwnedFd> {
+    if body.len() <= PIPE_THRESHOLD {
+        let (reader, mut writer) = io::pipe()?;
+        writer.write_all(body.as_bytes())?;
+        return Ok(reader.into());
+    }
+    let mut file = anonymous_file()?;
+    file.write_all(body.as_bytes())?;
+    file.rewind()?;
+    Ok(file.into())
+}
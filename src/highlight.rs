@@ -0,0 +1,157 @@
+use crate::parser::RESERVED;
+
+const COMMAND: &str = "\x1b[32m";
+const UNKNOWN: &str = "\x1b[31m";
+const STRING: &str = "\x1b[33m";
+const OPERATOR: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Reserved words after which a command name doesn't follow right away.
+const TAKES_WORDS: &[&str] = &["for", "case", "[["];
+
+/// Color `line` as it is typed: command names green when `is_command` knows
+/// them and red otherwise, quoted strings yellow, and operators and
+/// reserved words bold. An unfinished line is colored as far as it goes,
+/// with an unclosed quote running to its end.
+pub fn highlight(line: &str, is_command: impl Fn(&str) -> bool) -> String {
+    let mut out = String::with_capacity(line.len() * 2);
+    let mut command_position = true;
+    // Whether the next word is the target of a redirection.
+    let mut target = false;
+    let mut pos = 0;
+    while let Some(ch) = line[pos..].chars().next() {
+        if ch.is_whitespace() {
+            out.push(ch);
+            pos += ch.len_utf8();
+        } else if ch == '#' {
+            out.push_str(&line[pos..]);
+            break;
+        } else if is_operator(ch) {
+            let operator = operator(&line[pos..]);
+            paint(&mut out, OPERATOR, operator);
+            pos += operator.len();
+            target = operator.contains(['<', '>']);
+            command_position |= !target;
+        } else {
+            let word = &line[pos..word_end(line, pos)];
+            pos += word.len();
+            if target {
+                strings(&mut out, word);
+                target = false;
+            } else if command_position && RESERVED.contains(&word) {
+                paint(&mut out, OPERATOR, word);
+                command_position = !TAKES_WORDS.contains(&word);
+            } else if command_position && is_assignment(word) {
+                strings(&mut out, word);
+            } else if command_position {
+                match word.contains('$') {
+                    true => strings(&mut out, word),
+                    false if is_command(&unquote(word)) => paint(&mut out, COMMAND, word),
+                    false => paint(&mut out, UNKNOWN, word),
+                }
+                command_position = false;
+            } else {
+                strings(&mut out, word);
+            }
+        }
+    }
+    out
+}
+
+fn paint(out: &mut String, color: &str, text: &str) {
+    out.push_str(color);
+    out.push_str(text);
+    out.push_str(RESET);
+}
+
+fn is_operator(ch: char) -> bool {
+    "|&;<>()".contains(ch)
+}
+
+/// The operator `text` starts with.
+fn operator(text: &str) -> &str {
+    const OPERATORS: &[&str] = &["&>>", ">>", "&&", "||", ";;", "&>", ">&", "<<", "<&"];
+    OPERATORS.iter()
+        .find(|operator| text.starts_with(**operator))
+        .map_or(&text[..1], |operator| &text[..operator.len()])
+}
+
+/// Where the word starting at `start` ends: at whitespace or an operator
+/// outside quotes. The parentheses of `$(...)` and of an array assigned
+/// with `name=(...)` belong to the word.
+fn word_end(line: &str, start: usize) -> usize {
+    let mut quote = None;
+    let mut depth = 0;
+    let mut chars = line[start..].char_indices();
+    let mut prev = None;
+    while let Some((i, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (Some('"') | None, '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (None, '(') if depth > 0 || matches!(prev, Some('$' | '=')) => depth += 1,
+            (None, ')') if depth > 0 => depth -= 1,
+            (None, _) if depth == 0 && (ch.is_whitespace() || is_operator(ch)) => return start + i,
+            _ => {}
+        }
+        prev = Some(ch);
+    }
+    line.len()
+}
+
+/// Copy `word` to `out`, coloring its quoted parts.
+fn strings(out: &mut String, word: &str) {
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(ch) = chars.next() {
+        match quote {
+            Some(q) if ch == q => {
+                out.push(ch);
+                out.push_str(RESET);
+                quote = None;
+                continue;
+            }
+            None if ch == '\'' || ch == '"' => {
+                out.push_str(STRING);
+                quote = Some(ch);
+            }
+            _ => {}
+        }
+        out.push(ch);
+        if ch == '\\' && quote != Some('\'') {
+            out.extend(chars.next());
+        }
+    }
+    if quote.is_some() {
+        out.push_str(RESET);
+    }
+}
+
+/// Whether `word` assigns a variable, as in `name=value`.
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| {
+        let name = name.strip_suffix('+').unwrap_or(name);
+        !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// `word` with its quotes and backslashes taken out.
+fn unquote(word: &str) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some('"') | None, '\\') => out.extend(chars.next()),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
@@ -0,0 +1,752 @@
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::HashSet;
+use std::ffi::c_void;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Write};
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use nix::fcntl::{Flock, FlockArg};
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use rustyline::history::{SearchDirection, SearchResult};
+
+use crate::error::ShellError;
+use crate::lexer;
+use crate::{home_dir, Shell};
+
+/// A version of the history file: its inode, size and modification time.
+/// Rewriting the file replaces it with a new one, so that sessions that
+/// have the old one mapped can go on reading it.
+#[derive(Clone, Copy, PartialEq, Default)]
+struct Stamp {
+    ino: u64,
+    len: u64,
+    mtime: (i64, i64),
+}
+
+impl Stamp {
+    fn of(meta: &Metadata) -> Self {
+        Stamp { ino: meta.ino(), len: meta.len(), mtime: (meta.mtime(), meta.mtime_nsec()) }
+    }
+}
+
+/// Open the history file at `path` with `options` and lock it, making
+/// sure the file locked is still the one at `path`: a rewrite may have
+/// replaced it while waiting for the lock.
+fn lock(path: &Path, options: &OpenOptions, arg: FlockArg) -> io::Result<Flock<File>> {
+    loop {
+        let file = Flock::lock(options.open(path)?, arg).map_err(|(_, e)| io::Error::from(e))?;
+        if fs::metadata(path).map_or(true, |meta| meta.ino() == file.metadata().map_or(0, |locked| locked.ino())) {
+            return Ok(file);
+        }
+    }
+}
+
+/// A history file mapped into memory read-only.
+struct Mapped {
+    ptr: NonNull<c_void>,
+    len: usize,
+}
+
+// The mapping is private and never written through, so it can be read
+// from any thread.
+unsafe impl Send for Mapped {}
+
+impl Mapped {
+    /// Map the file at `path`, or return `None` if it is empty, along with
+    /// the version mapped. Its size is taken under a shared lock, so that a
+    /// line another session is appending is left out rather than cut
+    /// short.
+    fn open(path: &Path) -> io::Result<(Option<Mapped>, Stamp)> {
+        let file = lock(path, OpenOptions::new().read(true), FlockArg::LockShared)?;
+        let stamp = Stamp::of(&file.metadata()?);
+        let Some(len) = NonZeroUsize::new(stamp.len as usize) else {
+            return Ok((None, stamp));
+        };
+        let ptr = unsafe { mmap(None, len, ProtFlags::PROT_READ, MapFlags::MAP_PRIVATE, &*file, 0)? };
+        Ok((Some(Mapped { ptr, len: len.get() }), stamp))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr() as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mapped {
+    fn drop(&mut self) {
+        let _ = unsafe { munmap(self.ptr, self.len) };
+    }
+}
+
+/// Where command lines are kept. Entries are indexed from 0, oldest
+/// first.
+pub trait HistoryStore: Send {
+    /// Record `line` as the newest entry, entered at `time` in seconds
+    /// since the epoch.
+    fn append(&mut self, line: &str, time: u64) -> io::Result<()>;
+
+    fn len(&self) -> usize;
+
+    fn get(&self, index: usize) -> Option<Cow<'_, str>>;
+
+    /// When the entry at `index` was entered, if known.
+    fn time(&self, index: usize) -> Option<u64>;
+
+    /// Every entry, oldest first.
+    fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, str>> + '_> {
+        Box::new((0..self.len()).filter_map(|i| self.get(i)))
+    }
+
+    /// The nearest entry from `start` in direction `dir` that contains
+    /// `term`, or starts with it if `prefix` is set, as its index and the
+    /// position of the match in it.
+    fn search(&self, term: &str, start: usize, dir: SearchDirection, prefix: bool) -> Option<(usize, usize)> {
+        let indices: Box<dyn Iterator<Item = usize>> = match dir {
+            SearchDirection::Reverse => Box::new((0..=start.min(self.len().checked_sub(1)?)).rev()),
+            SearchDirection::Forward => Box::new(start..self.len()),
+        };
+        for index in indices {
+            let entry = self.get(index)?;
+            let pos = match prefix {
+                true => entry.starts_with(term).then_some(0),
+                false => entry.find(term),
+            };
+            if let Some(pos) = pos {
+                return Some((index, pos));
+            }
+        }
+        None
+    }
+
+    /// Remove the entries `keep` returns false for.
+    fn retain(&mut self, keep: &mut dyn FnMut(&str) -> bool) -> io::Result<()>;
+
+    /// Remove every entry that is `line` but the newest.
+    fn dedup(&mut self, line: &str) -> io::Result<()> {
+        let mut copies = self.iter().filter(|entry| entry == line).count();
+        self.retain(&mut |entry| {
+            if entry != line {
+                return true;
+            }
+            copies -= 1;
+            copies == 0
+        })
+    }
+
+    /// Remove the oldest entries, keeping the newest `size`.
+    fn trim(&mut self, size: usize) -> io::Result<()>;
+
+    /// Pick up entries added elsewhere, e.g. by other sessions, since the
+    /// store was opened or last synced.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Write out changes only made in memory so far.
+    fn save(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Where the entries are kept, for display.
+    fn location(&self) -> Option<String> {
+        None
+    }
+}
+
+/// History kept in memory only, for the current session, with the time
+/// of each entry.
+#[derive(Default)]
+pub struct MemoryStore(Vec<(String, u64)>);
+
+impl HistoryStore for MemoryStore {
+    fn append(&mut self, line: &str, time: u64) -> io::Result<()> {
+        self.0.push((line.to_owned(), time));
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.0.get(index).map(|(line, _)| Cow::Borrowed(line.as_str()))
+    }
+
+    fn time(&self, index: usize) -> Option<u64> {
+        self.0.get(index).map(|&(_, time)| time)
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&str) -> bool) -> io::Result<()> {
+        self.0.retain(|(line, _)| keep(line));
+        Ok(())
+    }
+
+    fn trim(&mut self, size: usize) -> io::Result<()> {
+        self.0.drain(..self.0.len().saturating_sub(size));
+        Ok(())
+    }
+}
+
+/// History kept in a file, one entry per line, each after a `#<epoch>`
+/// line with the time it was entered, as bash writes them.
+///
+/// Earlier sessions are read from the file, which is mapped rather than
+/// parsed when opened; where its entries start is only worked out the
+/// first time one is needed, and they are decoded one at a time. Lines
+/// entered in this session are appended to the file as they are added.
+/// The file is never truncated, only replaced, since other sessions may
+/// have it mapped. Duplicates dropped by `dedup` are only hidden until the
+/// file is next replaced, by `trim` or `save`.
+pub struct FileStore {
+    file: Option<Mapped>,
+    /// The version of the file mapped.
+    stamp: Stamp,
+    /// The file's entries, found on first use.
+    index: OnceCell<Vec<Saved>>,
+    /// The positions in `index` of the entries not hidden, when some are.
+    shown: Option<Vec<usize>>,
+    /// Lines of which only the newest copy is kept.
+    deduped: HashSet<String>,
+    /// Entries added since the file was mapped, with their times.
+    entries: Vec<(String, u64)>,
+    /// Unset after writing to the file fails.
+    path: Option<PathBuf>,
+}
+
+/// Where an entry is in the history file, and when it was entered if the
+/// file says.
+struct Saved {
+    line: Range<usize>,
+    time: Option<u64>,
+}
+
+/// Find the entries in `bytes`, the contents of a history file. A line of
+/// `#` and digits only is the time of the entry after it; the `#V2` line
+/// that files written by rustyline start with is skipped.
+fn parse(bytes: &[u8]) -> Vec<Saved> {
+    let mut saved = Vec::new();
+    let mut time = None;
+    let mut start = 0;
+    for end in bytes.iter().enumerate().filter(|&(_, &b)| b == b'\n').map(|(i, _)| i)
+        .chain((bytes.last() != Some(&b'\n')).then_some(bytes.len())) {
+        let line = &bytes[start..end];
+        let stamp = line.strip_prefix(b"#").filter(|digits| !digits.is_empty() && digits.iter().all(u8::is_ascii_digit));
+        if let Some(digits) = stamp {
+            time = std::str::from_utf8(digits).ok().and_then(|digits| digits.parse().ok());
+        } else if !line.is_empty() && (start > 0 || line != b"#V2") {
+            saved.push(Saved { line: start..end, time: time.take() });
+        }
+        start = end + 1;
+    }
+    saved
+}
+
+/// Make `line` fit on one line of the history file.
+pub fn encode(line: &str) -> String {
+    line.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Undo `encode`.
+pub fn decode(bytes: &[u8]) -> Cow<'_, str> {
+    let line = String::from_utf8_lossy(bytes);
+    if !line.contains('\\') {
+        return line;
+    }
+    let mut res = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => res.push('\n'),
+            ('\\', Some('\\')) => res.push('\\'),
+            _ => {
+                res.push(c);
+                continue;
+            }
+        }
+        chars.next();
+    }
+    Cow::Owned(res)
+}
+
+impl FileStore {
+    /// The history kept in the file at `path`, which need not exist yet.
+    pub fn open(path: PathBuf) -> Self {
+        let (file, stamp) = Mapped::open(&path).unwrap_or_default();
+        FileStore {
+            file,
+            stamp,
+            index: OnceCell::new(),
+            shown: None,
+            deduped: HashSet::new(),
+            entries: Vec::new(),
+            path: Some(path),
+        }
+    }
+
+    fn index(&self) -> &[Saved] {
+        self.index.get_or_init(|| self.file.as_ref().map_or_else(Vec::new, |file| parse(file.bytes())))
+    }
+
+    /// The file's entry shown at `index`.
+    fn saved(&self, index: usize) -> Option<&Saved> {
+        match &self.shown {
+            Some(shown) => shown.get(index).map(|&i| &self.index()[i]),
+            None => self.index().get(index),
+        }
+    }
+
+    fn saved_len(&self) -> usize {
+        self.shown.as_ref().map_or_else(|| self.index().len(), Vec::len)
+    }
+
+    /// Hide the copies in the file of each line in `deduped` but the
+    /// newest, which may be one of this session's entries instead.
+    fn hide_duplicates(&mut self) {
+        if self.deduped.is_empty() {
+            self.shown = None;
+            return;
+        }
+        let mut seen: HashSet<&str> = self.entries.iter().map(|(line, _)| line.as_str())
+            .filter(|line| self.deduped.contains(*line)).collect();
+        let bytes = self.file.as_ref().map_or(&[][..], Mapped::bytes);
+        let mut shown: Vec<usize> = self.index().iter().enumerate().rev()
+            .filter(|(_, entry)| {
+                let line = decode(&bytes[entry.line.clone()]);
+                match self.deduped.get(&*line) {
+                    Some(line) => seen.insert(line.as_str()),
+                    None => true,
+                }
+            })
+            .map(|(i, _)| i)
+            .collect();
+        shown.reverse();
+        self.shown = Some(shown);
+    }
+
+    /// Replace the file with one of the entries `select` picks from those
+    /// in its contents, under the lock appending takes, and map it again.
+    fn rewrite(&mut self, select: impl FnOnce(&[u8], Vec<Saved>) -> Vec<Saved>) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let file = lock(path, OpenOptions::new().read(true).append(true).create(true), FlockArg::LockExclusive)?;
+        let mut text = Vec::new();
+        (&*file).read_to_end(&mut text)?;
+        let mut saved = parse(&text);
+        if !self.deduped.is_empty() {
+            let mut seen = HashSet::new();
+            saved.reverse();
+            saved.retain(|entry| {
+                let line = decode(&text[entry.line.clone()]);
+                !self.deduped.contains(&*line) || seen.insert(line)
+            });
+            saved.reverse();
+        }
+        let mut kept = Vec::with_capacity(text.len());
+        for entry in select(&text, saved) {
+            if let Some(time) = entry.time {
+                kept.extend_from_slice(format!("#{}\n", time).as_bytes());
+            }
+            kept.extend_from_slice(&text[entry.line]);
+            kept.push(b'\n');
+        }
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(format!(".{}.tmp", std::process::id()));
+        let mode = file.metadata()?.mode();
+        let res = OpenOptions::new().write(true).create_new(true).mode(mode).open(&temp)
+            .and_then(|mut replacement| replacement.write_all(&kept))
+            .and_then(|_| fs::rename(&temp, path));
+        if res.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        res?;
+        // Appending sessions waiting for the lock find the file replaced.
+        drop(file);
+        (self.file, self.stamp) = Mapped::open(path)?;
+        self.index = OnceCell::new();
+        self.shown = None;
+        self.deduped.clear();
+        self.entries.clear();
+        Ok(())
+    }
+}
+
+impl HistoryStore for FileStore {
+    fn append(&mut self, line: &str, time: u64) -> io::Result<()> {
+        self.entries.push((line.to_owned(), time));
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Sessions sharing the file take turns, so that their lines don't
+        // interleave.
+        let res = lock(path, OpenOptions::new().create(true).append(true), FlockArg::LockExclusive)
+            .and_then(|mut file| file.write_all(format!("#{}\n{}\n", time, encode(line)).as_bytes()));
+        if let Err(e) = res {
+            let e = io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+            self.path = None;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.saved_len() + self.entries.len()
+    }
+
+    fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        match self.saved(index) {
+            Some(entry) => Some(decode(&self.file.as_ref()?.bytes()[entry.line.clone()])),
+            None => self.entries.get(index - self.saved_len()).map(|(line, _)| Cow::Borrowed(line.as_str())),
+        }
+    }
+
+    fn time(&self, index: usize) -> Option<u64> {
+        match self.saved(index) {
+            Some(entry) => entry.time,
+            None => self.entries.get(index - self.saved_len()).map(|&(_, time)| time),
+        }
+    }
+
+    /// Drop the entries `keep` returns false for from the file, or,
+    /// without a file to write, from those of this session.
+    fn retain(&mut self, keep: &mut dyn FnMut(&str) -> bool) -> io::Result<()> {
+        if self.path.is_none() {
+            self.entries.retain(|(line, _)| keep(line));
+        }
+        self.rewrite(|text, saved| saved.into_iter().filter(|entry| keep(&decode(&text[entry.line.clone()]))).collect())
+    }
+
+    /// Hide the earlier copies of `line`, leaving the file to be rewritten
+    /// without them later.
+    fn dedup(&mut self, line: &str) -> io::Result<()> {
+        if let Some(last) = self.entries.iter().rposition(|(entry, _)| entry == line) {
+            let mut i = 0;
+            self.entries.retain(|(entry, _)| {
+                i += 1;
+                entry != line || i - 1 == last
+            });
+        }
+        self.deduped.insert(line.to_owned());
+        self.hide_duplicates();
+        Ok(())
+    }
+
+    fn trim(&mut self, size: usize) -> io::Result<()> {
+        if self.path.is_none() {
+            self.entries.drain(..self.entries.len().saturating_sub(size));
+        }
+        self.rewrite(|_, mut saved| saved.split_off(saved.len().saturating_sub(size)))
+    }
+
+    /// Map the file again if it was appended to or replaced since. Lines
+    /// of this session were written to it, so they are found there along
+    /// with those of other sessions.
+    fn sync(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if fs::metadata(path).map_or(Stamp::default(), |meta| Stamp::of(&meta)) == self.stamp {
+            return Ok(());
+        }
+        (self.file, self.stamp) = Mapped::open(path)?;
+        self.index = OnceCell::new();
+        self.entries.clear();
+        self.hide_duplicates();
+        Ok(())
+    }
+
+    /// Rewrite the file without the duplicates hidden, if any.
+    fn save(&mut self) -> io::Result<()> {
+        if self.deduped.is_empty() {
+            return Ok(());
+        }
+        self.rewrite(|_, saved| saved)
+    }
+
+    fn location(&self) -> Option<String> {
+        self.path.as_ref().map(|path| path.display().to_string())
+    }
+}
+
+/// Command lines entered so far, oldest first, as kept by a
+/// `HistoryStore`. Entries are numbered from 1 as shown by the `history`
+/// builtin.
+pub struct History {
+    store: Box<dyn HistoryStore>,
+    /// How many of the newest entries of the store are in the history, as
+    /// set by `$HISTSIZE`; all of them when `None`.
+    size: Option<usize>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new(Box::new(MemoryStore::default()))
+    }
+}
+
+impl History {
+    pub fn new(store: Box<dyn HistoryStore>) -> Self {
+        History { store, size: None }
+    }
+
+    /// The history of an interactive session, kept by the backend named in
+    /// `$RSH_HISTORY_BACKEND`: `file` (the default) for the file named by
+    /// `$HISTFILE` or `~/.rsh_history`, `memory`, or, when built with the
+    /// `sqlite` feature, `sqlite` for the database `~/.rsh_history.db`.
+    pub fn configured(shell: &Shell) -> Self {
+        let backend = shell.vars.get("RSH_HISTORY_BACKEND").unwrap_or_default();
+        match backend.as_str() {
+            "memory" => History::default(),
+            #[cfg(feature = "sqlite")]
+            "sqlite" => {
+                let Some(path) = home_dir().map(|home| home.join(".rsh_history.db")) else {
+                    return History::default();
+                };
+                match crate::sqlite::SqliteStore::open(&path) {
+                    Ok(store) => History::new(Box::new(store)),
+                    Err(e) => {
+                        eprintln!("shell: {}: {}", path.display(), e);
+                        History::default()
+                    }
+                }
+            }
+            "" | "file" => {
+                let path = match shell.vars.get("HISTFILE").filter(|path| !path.is_empty()) {
+                    Some(path) => Some(PathBuf::from(path)),
+                    None => home_dir().map(|home| home.join(".rsh_history")),
+                };
+                match path {
+                    Some(path) => History::new(Box::new(FileStore::open(path))),
+                    None => History::default(),
+                }
+            }
+            _ => {
+                eprintln!("shell: {}: unknown history backend", backend);
+                History::default()
+            }
+        }
+    }
+
+    pub fn store(&self) -> &dyn HistoryStore {
+        &*self.store
+    }
+
+    pub fn store_mut(&mut self) -> &mut dyn HistoryStore {
+        &mut *self.store
+    }
+
+    /// Record `line`, entered at `time` in seconds since the epoch.
+    pub fn push(&mut self, line: &str, time: u64) {
+        if let Err(e) = self.store.append(line, time) {
+            eprintln!("shell: history: {}", e);
+        }
+    }
+
+    /// Remove every entry that is `line` but the newest.
+    pub fn dedup(&mut self, line: &str) {
+        if let Err(e) = self.store.dedup(line) {
+            eprintln!("shell: history: {}", e);
+        }
+    }
+
+    /// Write out what the store only changed in memory.
+    pub fn save(&mut self) {
+        if let Err(e) = self.store.save() {
+            eprintln!("shell: history: {}", e);
+        }
+    }
+
+    /// Drop the oldest entries of the store, keeping the newest `size`.
+    pub fn trim(&mut self, size: usize) {
+        if let Err(e) = self.store.trim(size) {
+            eprintln!("shell: history: {}", e);
+        }
+    }
+
+    /// Keep only the newest `size` entries of the store in the history,
+    /// or all of them.
+    pub fn set_size(&mut self, size: Option<usize>) {
+        self.size = size;
+    }
+
+    /// The index in the store of the oldest entry in the history.
+    fn first(&self) -> usize {
+        self.size.map_or(0, |size| self.store.len().saturating_sub(size))
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len() - self.first()
+    }
+
+    /// The entry at `index`, counting from 0.
+    pub fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        if index >= self.len() {
+            return None;
+        }
+        self.store.get(self.first() + index)
+    }
+
+    /// When the entry at `index` was entered, if known.
+    pub fn time(&self, index: usize) -> Option<u64> {
+        if index >= self.len() {
+            return None;
+        }
+        self.store.time(self.first() + index)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Cow<'_, str>)> {
+        self.store.iter().skip(self.first()).enumerate().map(|(i, line)| (i + 1, line))
+    }
+
+    /// The nearest entry from `start` in direction `dir` that contains
+    /// `term`, or starts with it if `prefix` is set, as its index and the
+    /// position of the match in it.
+    pub fn search(&self, term: &str, start: usize, dir: SearchDirection, prefix: bool) -> Option<(usize, usize)> {
+        let first = self.first();
+        let (index, pos) = self.store.search(term, first + start, dir, prefix)?;
+        Some((index.checked_sub(first)?, pos))
+    }
+
+    /// Resolve the event designator following a `!`, e.g. `!`, `12`, `-2`
+    /// or `gre`.
+    fn event(&self, designator: &str) -> Option<Cow<'_, str>> {
+        let len = self.len() as i64;
+        let index = if designator == "!" {
+            len - 1
+        } else if let Ok(n) = designator.parse::<i64>() {
+            if n < 0 { len + n } else { n - 1 }
+        } else {
+            let (index, _) = self.search(designator, self.len().checked_sub(1)?, SearchDirection::Reverse, true)?;
+            return self.get(index);
+        };
+        usize::try_from(index).ok().and_then(|i| self.get(i))
+    }
+
+    /// Perform `!!`, `!n`, `!-n` and `!prefix` expansion on `line`. A `!`
+    /// in single quotes or escaped, and those of `$!` and `${!name}`, are
+    /// left alone. Returns `Ok(None)` when the line contains no history
+    /// references.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, ShellError> {
+        let mut res = String::with_capacity(line.len());
+        let mut expanded = false;
+        let mut done = 0;
+        for i in lexer::history_marks(line) {
+            // Part of an event already substituted, as in `!!!`.
+            if i < done {
+                continue;
+            }
+            res.push_str(&line[done..i]);
+            let after = &line[i + 1..];
+            done = i + 1;
+            let len = if after.starts_with('!') {
+                1
+            } else if let Some(digits) = after.strip_prefix('-') {
+                let n = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+                if n == 0 { 0 } else { n + 1 }
+            } else {
+                after.find(|c: char| c.is_whitespace() || "=();|&<>\"".contains(c))
+                    .unwrap_or(after.len())
+            };
+            if len == 0 {
+                res.push('!');
+                continue;
+            }
+            let designator = &after[..len];
+            match self.event(designator) {
+                Some(event) => res.push_str(&event),
+                None => return Err(ShellError::expansion(&format!("!{}", designator), "event not found")),
+            }
+            expanded = true;
+            done += len;
+        }
+        res.push_str(&line[done..]);
+        Ok(if expanded { Some(res) } else { None })
+    }
+}
+
+/// The shell's history as seen by the line editor, for navigation and
+/// incremental search.
+pub struct EditorHistory(pub Arc<Mutex<History>>);
+
+impl EditorHistory {
+    fn search_match(&self, term: &str, start: usize, dir: SearchDirection, prefix: bool)
+    -> Option<SearchResult<'_>> {
+        let history = self.0.lock().unwrap();
+        if term.is_empty() || start >= history.len() {
+            return None;
+        }
+        let (idx, pos) = history.search(term, start, dir, prefix)?;
+        let entry = Cow::Owned(history.get(idx)?.into_owned());
+        Some(SearchResult { entry, idx, pos: if prefix { term.len() } else { pos } })
+    }
+}
+
+impl rustyline::history::History for EditorHistory {
+    fn get(&self, index: usize, _dir: SearchDirection) -> rustyline::Result<Option<SearchResult<'_>>> {
+        let history = self.0.lock().unwrap();
+        Ok(history.get(index).map(|entry| SearchResult { entry: Cow::Owned(entry.into_owned()), idx: index, pos: 0 }))
+    }
+
+    fn add(&mut self, line: &str) -> rustyline::Result<bool> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        self.0.lock().unwrap().push(line, time.as_secs());
+        Ok(true)
+    }
+
+    fn add_owned(&mut self, line: String) -> rustyline::Result<bool> {
+        self.add(&line)
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The shell decides what is recorded and where, so the editor's own
+    // settings and file operations don't apply.
+
+    fn set_max_len(&mut self, _len: usize) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn ignore_dups(&mut self, _yes: bool) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn ignore_space(&mut self, _yes: bool) {}
+
+    fn save(&mut self, _path: &Path) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn append(&mut self, _path: &Path) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn load(&mut self, _path: &Path) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> rustyline::Result<()> {
+        Ok(())
+    }
+
+    fn search(&self, term: &str, start: usize, dir: SearchDirection)
+    -> rustyline::Result<Option<SearchResult<'_>>> {
+        Ok(self.search_match(term, start, dir, false))
+    }
+
+    fn starts_with(&self, term: &str, start: usize, dir: SearchDirection)
+    -> rustyline::Result<Option<SearchResult<'_>>> {
+        Ok(self.search_match(term, start, dir, true))
+    }
+}
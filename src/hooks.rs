@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::audit;
+use crate::frecency;
+use crate::traps::Condition;
+use crate::exec::run_trap;
+use crate::Shell;
+
+/// Something that happens during a session, for hooks to react to.
+pub enum Event<'a> {
+    /// The shell is about to read its first command.
+    Startup,
+    /// A prompt is about to be shown to the user.
+    PrePrompt,
+    /// A command line is about to run.
+    PreExec { command: &'a str },
+    /// A command line finished.
+    PostExec { command: &'a str, status: i32, duration: Duration },
+    /// The working directory changed.
+    Chpwd { old: &'a Path, new: &'a Path },
+    /// The shell is exiting.
+    Exit,
+}
+
+/// The kinds of events, by which shell commands subscribe.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Startup,
+    PrePrompt,
+    PreExec,
+    PostExec,
+    Chpwd,
+    Exit,
+}
+
+const KINDS: &[(&str, Kind)] = &[
+    ("startup", Kind::Startup),
+    ("preprompt", Kind::PrePrompt),
+    ("preexec", Kind::PreExec),
+    ("postexec", Kind::PostExec),
+    ("chpwd", Kind::Chpwd),
+    ("exit", Kind::Exit),
+];
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Kind> {
+        KINDS.iter().find(|(kind, _)| *kind == name).map(|&(_, kind)| kind)
+    }
+
+    pub fn name(&self) -> &'static str {
+        KINDS.iter().find(|(_, kind)| kind == self).map_or("", |&(name, _)| name)
+    }
+}
+
+impl Event<'_> {
+    pub fn kind(&self) -> Kind {
+        match self {
+            Event::Startup => Kind::Startup,
+            Event::PrePrompt => Kind::PrePrompt,
+            Event::PreExec { .. } => Kind::PreExec,
+            Event::PostExec { .. } => Kind::PostExec,
+            Event::Chpwd { .. } => Kind::Chpwd,
+            Event::Exit => Kind::Exit,
+        }
+    }
+}
+
+/// Code run on every event.
+pub type Hook = Box<dyn FnMut(&mut Shell, &Event)>;
+
+/// Everything subscribed to events: hooks in the shell itself, and
+/// command strings added with the `hook` builtin.
+pub struct Hooks {
+    hooks: Vec<Hook>,
+    commands: BTreeMap<Kind, Vec<String>>,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        let mut hooks = Hooks { hooks: Vec::new(), commands: BTreeMap::new() };
+        hooks.subscribe(Box::new(forget_cwd));
+        hooks.subscribe(Box::new(update_pwd));
+        hooks.subscribe(Box::new(record_dir));
+        hooks.subscribe(Box::new(record_history));
+        hooks.subscribe(audit_commands());
+        hooks.subscribe(Box::new(share_history));
+        hooks.subscribe(Box::new(limit_history));
+        hooks.subscribe(Box::new(report_jobs));
+        hooks.subscribe(Box::new(set_title));
+        hooks.subscribe(Box::new(run_exit_trap));
+        hooks.subscribe(Box::new(run_prompt_command));
+        hooks
+    }
+}
+
+impl Hooks {
+    /// Run `hook` on every event from now on, after those subscribed
+    /// earlier.
+    pub fn subscribe(&mut self, hook: Hook) {
+        self.hooks.push(hook);
+    }
+
+    /// Run the command string `command` on events of `kind`.
+    pub fn add_command(&mut self, kind: Kind, command: String) {
+        self.commands.entry(kind).or_default().push(command);
+    }
+
+    /// Drop the command strings run on events of `kind`.
+    pub fn remove_commands(&mut self, kind: Kind) {
+        self.commands.remove(&kind);
+    }
+
+    /// Drop every command string, as a subshell starts without them.
+    pub fn clear_commands(&mut self) {
+        self.commands.clear();
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = (Kind, &str)> {
+        self.commands.iter()
+            .flat_map(|(&kind, commands)| commands.iter().map(move |command| (kind, command.as_str())))
+    }
+}
+
+/// Tell every subscriber about `event`: the shell's own hooks first, then
+/// the command strings, which see the status of a finished command line
+/// in `$?`. Commands for `exit` run only once.
+pub fn emit(shell: &mut Shell, event: Event) {
+    let mut hooks = std::mem::take(&mut shell.hooks.hooks);
+    for hook in &mut hooks {
+        hook(shell, &event);
+    }
+    // Hooks may have subscribed others meanwhile.
+    hooks.append(&mut shell.hooks.hooks);
+    shell.hooks.hooks = hooks;
+    let commands = match event.kind() {
+        Kind::Exit => shell.hooks.commands.remove(&Kind::Exit),
+        kind => shell.hooks.commands.get(&kind).cloned(),
+    };
+    for command in commands.unwrap_or_default() {
+        run_trap(shell, &command);
+    }
+}
+
+/// Look the working directory up again after it changes.
+fn forget_cwd(shell: &mut Shell, event: &Event) {
+    if let Event::Chpwd { .. } = event {
+        shell.cwd.take();
+    }
+}
+
+/// Export the new working directory as `$PWD` and the old one as
+/// `$OLDPWD`.
+fn update_pwd(shell: &mut Shell, event: &Event) {
+    if let Event::Chpwd { old, new } = event {
+        shell.vars.export("OLDPWD", old.to_string_lossy().into_owned());
+        shell.vars.export("PWD", new.to_string_lossy().into_owned());
+    }
+}
+
+/// Count each directory an interactive session changes to, for `j`.
+fn record_dir(shell: &mut Shell, event: &Event) {
+    if let Event::Chpwd { new, .. } = event {
+        let home = crate::home_dir();
+        if !shell.interactive || home.as_deref() == Some(*new) {
+            return;
+        }
+        if let Some(path) = frecency::path(shell) {
+            if let Err(e) = frecency::visit(&path, new, shell.now().as_secs()) {
+                eprintln!("shell: {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Keep each command line in the history, as `$HISTCONTROL` allows: a
+/// colon-separated list of `ignorespace` (leave out lines starting with a
+/// space), `ignoredups` (lines the same as the last entry), `ignoreboth`
+/// (both) and `erasedups` (remove earlier copies of each line, from the
+/// history file once the shell exits).
+fn record_history(shell: &mut Shell, event: &Event) {
+    if let Event::PreExec { command } = event {
+        let line = command.trim();
+        if line.is_empty() {
+            return;
+        }
+        let control = shell.vars.get("HISTCONTROL").unwrap_or_default();
+        let control: Vec<&str> = control.split(':').collect();
+        let ignore = |what| control.contains(&what) || control.contains(&"ignoreboth");
+        if ignore("ignorespace") && command.starts_with(' ') {
+            return;
+        }
+        let mut history = shell.history.lock().unwrap();
+        let last = history.len().checked_sub(1).and_then(|i| history.get(i));
+        if ignore("ignoredups") && last.is_some_and(|last| last == line) {
+            return;
+        }
+        history.push(line, shell.now().as_secs());
+        if control.contains(&"erasedups") {
+            history.dedup(line);
+        }
+    }
+}
+
+/// Under `set -o audit`, record each command line once it has run, with
+/// the directory it started in.
+fn audit_commands() -> Hook {
+    let mut cwd = PathBuf::new();
+    Box::new(move |shell, event| match event {
+        Event::PreExec { .. } => cwd = std::env::current_dir().unwrap_or_default(),
+        Event::PostExec { command, status, duration } if shell.options.audit && !command.trim().is_empty() => {
+            audit::record(shell, command, &cwd, *status, *duration);
+        }
+        _ => {}
+    })
+}
+
+/// Under `set -o sharehistory`, pick up the lines other sessions added to
+/// the history before each prompt.
+fn share_history(shell: &mut Shell, event: &Event) {
+    if let Event::PrePrompt = event {
+        if shell.options.sharehistory {
+            if let Err(e) = shell.history.lock().unwrap().store_mut().sync() {
+                eprintln!("shell: history: {}", e);
+            }
+        }
+    }
+}
+
+/// Show only the newest `$HISTSIZE` entries of the history, and on exit,
+/// keep only the newest `$HISTFILESIZE` in the history file and write out
+/// any other changes to it. Either is unlimited when unset or negative.
+fn limit_history(shell: &mut Shell, event: &Event) {
+    let limit = |name| shell.vars.get(name).and_then(|size| size.trim().parse::<usize>().ok());
+    match event {
+        Event::PrePrompt | Event::PreExec { .. } => shell.history.lock().unwrap().set_size(limit("HISTSIZE")),
+        // Not in forked copies of the shell, which share the file.
+        Event::Exit if shell.interactive => {
+            let mut history = shell.history.lock().unwrap();
+            match limit("HISTFILESIZE") {
+                Some(size) => history.trim(size),
+                None => history.save(),
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Report on the background jobs that finished since the last prompt,
+/// unless they are reported on right away under `set -o notify`.
+fn report_jobs(shell: &mut Shell, event: &Event) {
+    if let Event::PrePrompt = event {
+        shell.jobs.set_reporting(shell.options.notify);
+        shell.jobs.report();
+    }
+}
+
+/// Under `set -o title`, put `$RSH_TITLE` in the terminal's title bar
+/// before each prompt, and the command line while it runs under `set -o
+/// title-command`.
+fn set_title(shell: &mut Shell, event: &Event) {
+    if !shell.options.title || !io::stdout().is_terminal() {
+        return;
+    }
+    let title = match event {
+        Event::PrePrompt => shell.prompt.render_title(shell),
+        Event::PreExec { command } if shell.options.title_command => {
+            command.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+        }
+        _ => return,
+    };
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "\x1b]0;{}\x07", title.trim());
+    let _ = stdout.flush();
+}
+
+/// Run the `EXIT` trap, once.
+fn run_exit_trap(shell: &mut Shell, event: &Event) {
+    if let Event::Exit = event {
+        if let Some(action) = shell.traps.get(Condition::Exit).map(str::to_owned) {
+            let _ = shell.traps.reset(Condition::Exit);
+            run_trap(shell, &action);
+        }
+    }
+}
+
+/// Run `$PROMPT_COMMAND` before each prompt.
+fn run_prompt_command(shell: &mut Shell, event: &Event) {
+    if let Event::PrePrompt = event {
+        if let Some(command) = shell.vars.get("PROMPT_COMMAND").filter(|command| !command.trim().is_empty()) {
+            run_trap(shell, &command);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use rustyline::ExternalPrinter;
+
+use crate::exec::wait_pid;
+
+/// A pipeline left running in the background.
+#[derive(Clone)]
+pub struct Job {
+    pub id: usize,
+    pub pid: u32,
+    pub command: String,
+}
+
+/// The background jobs of the shell. Each job is waited for by its own
+/// thread, which reaps it as soon as it finishes and reports on it before
+/// the next prompt, or right away under `set -o notify`.
+#[derive(Default, Clone)]
+pub struct Jobs {
+    running: Arc<Mutex<Vec<Job>>>,
+    /// Prints notifications above the line being edited and redraws the
+    /// prompt and the user's input below them. Without it, notifications
+    /// go straight to stderr. It is only held while jobs are running: as
+    /// long as one exists, rustyline waits on its pipe with `select`, which
+    /// misses input already buffered, so typeahead would stall.
+    printer: Arc<Mutex<Option<Box<dyn ExternalPrinter + Send>>>>,
+    /// Whether jobs are reported on as soon as they finish.
+    immediate: Arc<AtomicBool>,
+    /// Whether there are prompts to report on jobs before. Until then,
+    /// finished jobs are forgotten.
+    prompting: Arc<AtomicBool>,
+    /// The finished jobs not yet reported on, with what they came to.
+    /// They keep their numbers until then.
+    pending: Arc<Mutex<Vec<(Job, String)>>>,
+    /// The statuses of finished jobs not yet collected by `wait`, by pid,
+    /// signalled as each is added.
+    finished: Arc<(Mutex<BTreeMap<u32, i32>>, Condvar)>,
+    /// Set when jobs are neither announced nor reported on.
+    quiet: bool,
+}
+
+/// What waiting for a job came to.
+pub enum Waited {
+    /// It finished with this status.
+    Exited(i32),
+    /// It is still running.
+    Running,
+    /// There is no such job.
+    Unknown,
+}
+
+impl Jobs {
+    /// Whether there are running jobs to report on right away but no
+    /// printer yet.
+    pub fn wants_printer(&self) -> bool {
+        self.immediate.load(Ordering::Relaxed)
+            && self.printer.lock().unwrap().is_none()
+            && !self.running.lock().unwrap().is_empty()
+    }
+
+    /// Report on jobs before each prompt from now on, or as soon as they
+    /// finish when `immediate`.
+    pub fn set_reporting(&self, immediate: bool) {
+        self.prompting.store(true, Ordering::Relaxed);
+        self.immediate.store(immediate, Ordering::Relaxed);
+        if !immediate {
+            self.printer.lock().unwrap().take();
+        }
+    }
+
+    /// Print the reports on the jobs that finished since the last call.
+    pub fn report(&self) {
+        let reports = self.reports();
+        if !self.quiet {
+            let _ = io::stderr().write_all(reports.as_bytes());
+        }
+    }
+
+    /// The lines reporting on the finished jobs, which are forgotten.
+    /// Among the jobs still known, `+` marks the most recent one and `-`
+    /// the one before it.
+    fn reports(&self) -> String {
+        let running = self.running.lock().unwrap();
+        let mut pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        pending.sort_by_key(|(job, _)| job.id);
+        let mut ids: Vec<usize> = running.iter().chain(pending.iter().map(|(job, _)| job)).map(|job| job.id).collect();
+        ids.sort_unstable_by(|a, b| b.cmp(a));
+        pending.iter()
+            .map(|(job, state)| {
+                let current = match ids.iter().position(|&id| id == job.id) {
+                    Some(0) => '+',
+                    Some(1) => '-',
+                    _ => ' ',
+                };
+                format!("[{}]{}  {:<24}{}\n", job.id, current, state, job.command)
+            })
+            .collect()
+    }
+
+    pub fn set_printer(&self, printer: Box<dyn ExternalPrinter + Send>) {
+        *self.printer.lock().unwrap() = Some(printer);
+    }
+
+    /// Stop announcing jobs and reporting their completion.
+    pub fn silence(&mut self) {
+        self.quiet = true;
+    }
+
+    /// The jobs still running, in the order they were started.
+    pub fn running(&self) -> Vec<Job> {
+        self.running.lock().unwrap().clone()
+    }
+
+    /// Stop tracking the job with `id`, so that it is neither reported on
+    /// nor hung up when the shell exits.
+    pub fn disown(&self, id: usize) {
+        self.running.lock().unwrap().retain(|job| job.id != id);
+    }
+
+    /// Send `SIGHUP` to every running job.
+    pub fn hang_up(&self) {
+        for job in self.running.lock().unwrap().iter() {
+            let _ = kill(Pid::from_raw(job.pid as i32), Signal::SIGHUP);
+        }
+    }
+
+    /// The running job `spec` names: `%n` by number, `%%` or `%+` the
+    /// most recent, `%-` the one before it, `%text` the one whose command
+    /// starts with `text` and `%?text` the one whose command contains it.
+    pub fn find(&self, spec: &str) -> Result<Job, String> {
+        let running = self.running.lock().unwrap();
+        let name = spec.strip_prefix('%').ok_or_else(|| format!("{}: not a job spec", spec))?;
+        let found: Vec<&Job> = match name {
+            "" | "%" | "+" => running.last().into_iter().collect(),
+            "-" => running.iter().rev().nth(1).into_iter().collect(),
+            _ if name.bytes().all(|b| b.is_ascii_digit()) => {
+                running.iter().filter(|job| job.id.to_string() == name).collect()
+            }
+            _ => match name.strip_prefix('?') {
+                Some(text) => running.iter().filter(|job| job.command.contains(text)).collect(),
+                None => running.iter().filter(|job| job.command.starts_with(name)).collect(),
+            },
+        };
+        match found.as_slice() {
+            [job] => Ok((*job).clone()),
+            [] => Err(format!("{}: no such job", spec)),
+            _ => Err(format!("{}: ambiguous job spec", spec)),
+        }
+    }
+
+    /// Track the child `pid` as a background job running `command`,
+    /// announcing its job number and pid.
+    pub fn spawn(&self, pid: u32, command: String) {
+        let id = {
+            let mut running = self.running.lock().unwrap();
+            let pending = self.pending.lock().unwrap();
+            let id = running.iter().chain(pending.iter().map(|(job, _)| job)).map(|job| job.id).max().unwrap_or(0) + 1;
+            drop(pending);
+            running.push(Job { id, pid, command });
+            id
+        };
+        if !self.quiet {
+            let _ = writeln!(io::stderr(), "[{}] {}", id, pid);
+        }
+        let jobs = self.clone();
+        thread::spawn(move || {
+            let status = wait_pid(Pid::from_raw(pid as i32));
+            let state = match &status {
+                Ok(0) => String::from("Done"),
+                Ok(status) => format!("Exit {}", status),
+                Err(e) => format!("Unknown ({})", e),
+            };
+            // The status is in place before the job stops counting as
+            // running, so that `wait` finds it in one place or the other.
+            let (finished, done) = &*jobs.finished;
+            finished.lock().unwrap().insert(pid, status.unwrap_or(127));
+            {
+                let mut running = jobs.running.lock().unwrap();
+                let job = running.iter().position(|job| job.id == id).map(|index| running.remove(index));
+                let reported = jobs.immediate.load(Ordering::Relaxed) || jobs.prompting.load(Ordering::Relaxed);
+                if let Some(job) = job.filter(|_| reported) {
+                    jobs.pending.lock().unwrap().push((job, state));
+                }
+            }
+            done.notify_all();
+            if jobs.immediate.load(Ordering::Relaxed) {
+                jobs.notify(jobs.reports());
+            }
+        });
+    }
+
+    /// Wait up to `timeout` for the job with `pid` to finish, or for every
+    /// job when there is no `pid`, collecting its status.
+    pub fn wait_for(&self, pid: Option<u32>, timeout: Duration) -> Waited {
+        let (finished, done) = &*self.finished;
+        let mut finished = finished.lock().unwrap();
+        for attempt in 0..2 {
+            let running = self.running.lock().unwrap();
+            match pid {
+                None if running.is_empty() => {
+                    finished.clear();
+                    return Waited::Exited(0);
+                }
+                None => {}
+                Some(pid) => {
+                    if let Some(status) = finished.remove(&pid) {
+                        return Waited::Exited(status);
+                    }
+                    if !running.iter().any(|job| job.pid == pid) {
+                        return Waited::Unknown;
+                    }
+                }
+            }
+            drop(running);
+            if attempt == 0 {
+                finished = done.wait_timeout(finished, timeout).unwrap().0;
+            }
+        }
+        Waited::Running
+    }
+
+    fn notify(&self, msg: String) {
+        let mut printer = self.printer.lock().unwrap();
+        let printed = self.quiet || printer.as_mut().is_some_and(|printer| printer.print(msg.clone()).is_ok());
+        if !printed {
+            let _ = io::stderr().write_all(msg.as_bytes());
+        }
+        if self.running.lock().unwrap().is_empty() {
+            printer.take();
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt::{self, Write};
+use std::str::CharIndices;
+
+/// A JSON value. Numbers keep their text, and objects the order of their
+/// members, so that values print back as they were written.
+#[derive(Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        format!("line {}: {}", line, message)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(ch) if ch == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(ch) => Err(self.error(&format!("expected `{}', found `{}'", expected, ch))),
+            None => Err(self.error(&format!("expected `{}', found end of input", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        for (word, value) in [("null", Value::Null), ("true", Value::Bool(true)), ("false", Value::Bool(false))] {
+            if rest.starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        match self.peek() {
+            Some('"') => self.string().map(Value::String),
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        return Ok(Value::Array(items));
+                    }
+                    self.eat(',')?;
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some('"') {
+                        return Err(self.error("expected a member name"));
+                    }
+                    let key = self.string()?;
+                    self.eat(':')?;
+                    members.push((key, self.value()?));
+                    self.skip_whitespace();
+                    if self.peek() == Some('}') {
+                        self.pos += 1;
+                        return Ok(Value::Object(members));
+                    }
+                    self.eat(',')?;
+                }
+            }
+            Some(ch) if ch == '-' || ch.is_ascii_digit() => {
+                let len = rest.find(|c: char| !c.is_ascii_digit() && !"+-.eE".contains(c)).unwrap_or(rest.len());
+                let number = &rest[..len];
+                if number.parse::<f64>().is_err() {
+                    return Err(self.error(&format!("invalid number `{}'", number)));
+                }
+                self.pos += len;
+                Ok(Value::Number(number.to_owned()))
+            }
+            Some(ch) => Err(self.error(&format!("unexpected `{}'", ch))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    /// A string literal, starting at its opening quote.
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut value = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(value);
+                }
+                '\\' => match chars.next().map(|(_, ch)| ch) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('b') => value.push('\x08'),
+                    Some('f') => value.push('\x0c'),
+                    Some('u') => {
+                        let mut code = hex4(&mut chars).ok_or_else(|| self.error("invalid \\u escape"))?;
+                        // A UTF-16 surrogate pair spells out one character.
+                        if (0xd800..0xdc00).contains(&code) && chars.as_str().starts_with("\\u") {
+                            chars.nth(1);
+                            let low = hex4(&mut chars).ok_or_else(|| self.error("invalid \\u escape"))?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    Some(other) => value.push(other),
+                    None => break,
+                },
+                _ => value.push(ch),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+}
+
+/// The four hex digits of a `\u` escape.
+fn hex4(chars: &mut CharIndices) -> Option<u32> {
+    let hex: String = chars.take(4).map(|(_, ch)| ch).collect();
+    u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4)
+}
+
+/// Parse the JSON document `text`.
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = Parser { text, pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.peek() {
+        Some(_) => Err(parser.error("unexpected text after the value")),
+        None => Ok(value),
+    }
+}
+
+/// A step of a path: a member name or an array index.
+enum Step {
+    Member(String),
+    Index(i64),
+}
+
+/// Parse a path such as `.items[0].name`, `.["odd key"]` or `.[-1]`; `.`
+/// alone is the whole document. A negative index counts from the end.
+fn parse_path(path: &str) -> Result<Vec<Step>, String> {
+    let invalid = || format!("{}: invalid path", path);
+    let mut steps = Vec::new();
+    let mut rest = path.strip_prefix('.').ok_or_else(invalid)?;
+    while !rest.is_empty() {
+        if let Some(inner) = rest.strip_prefix('[') {
+            let end = inner.find(']').ok_or_else(invalid)?;
+            let key = &inner[..end];
+            steps.push(match key.strip_prefix('"').and_then(|key| key.strip_suffix('"')) {
+                Some(key) => Step::Member(key.to_owned()),
+                None => Step::Index(key.trim().parse().map_err(|_| invalid())?),
+            });
+            rest = &inner[end + 1..];
+        } else {
+            rest = rest.strip_prefix('.').unwrap_or(rest);
+            let len = rest.find(['.', '[']).unwrap_or(rest.len());
+            if len == 0 {
+                return Err(invalid());
+            }
+            steps.push(Step::Member(rest[..len].to_owned()));
+            rest = &rest[len..];
+        }
+    }
+    Ok(steps)
+}
+
+/// The value at `path` inside `value`, or `None` if there is nothing
+/// there.
+pub fn get<'a>(value: &'a Value, path: &str) -> Result<Option<&'a Value>, String> {
+    let mut value = value;
+    for step in parse_path(path)? {
+        let next = match (step, value) {
+            (Step::Member(name), Value::Object(members)) => members.iter().rev()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value),
+            (Step::Index(index), Value::Array(items)) => {
+                let index = if index < 0 { items.len() as i64 + index } else { index };
+                usize::try_from(index).ok().and_then(|index| items.get(index))
+            }
+            _ => None,
+        };
+        match next {
+            Some(next) => value = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(value))
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in s.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\t' => f.write_str("\\t")?,
+            '\r' => f.write_str("\\r")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Compact JSON, on one line.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => f.write_str(n),
+            Value::String(s) => write_string(f, s),
+            Value::Array(items) => {
+                f.write_char('[')?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_char(']')
+            }
+            Value::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
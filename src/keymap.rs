@@ -0,0 +1,256 @@
+use rustyline::{Anchor, At, Cmd, KeyCode, KeyEvent, Modifiers, Movement, Word};
+
+/// Makes the line editor command of an editing function.
+type MakeCmd = fn() -> Cmd;
+
+/// The editing functions that are commands of the line editor, by their
+/// readline names.
+const COMMANDS: &[(&str, MakeCmd)] = &[
+    ("abort", || Cmd::Abort),
+    ("accept-line", || Cmd::AcceptLine),
+    ("backward-char", || Cmd::Move(Movement::BackwardChar(1))),
+    ("backward-delete-char", || Cmd::Kill(Movement::BackwardChar(1))),
+    ("backward-kill-line", || Cmd::Kill(Movement::BeginningOfLine)),
+    ("backward-kill-word", || Cmd::Kill(Movement::BackwardWord(1, Word::Emacs))),
+    ("backward-word", || Cmd::Move(Movement::BackwardWord(1, Word::Emacs))),
+    ("beginning-of-history", || Cmd::BeginningOfHistory),
+    ("beginning-of-line", || Cmd::Move(Movement::BeginningOfLine)),
+    ("capitalize-word", || Cmd::CapitalizeWord),
+    ("clear-screen", || Cmd::ClearScreen),
+    ("complete", || Cmd::Complete),
+    ("delete-char", || Cmd::Kill(Movement::ForwardChar(1))),
+    ("downcase-word", || Cmd::DowncaseWord),
+    ("end-of-history", || Cmd::EndOfHistory),
+    ("end-of-line", || Cmd::Move(Movement::EndOfLine)),
+    ("forward-char", || Cmd::Move(Movement::ForwardChar(1))),
+    ("forward-search-history", || Cmd::ForwardSearchHistory),
+    ("forward-word", || Cmd::Move(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs))),
+    ("history-search-backward", || Cmd::HistorySearchBackward),
+    ("history-search-forward", || Cmd::HistorySearchForward),
+    ("kill-line", || Cmd::Kill(Movement::EndOfLine)),
+    ("kill-whole-line", || Cmd::Kill(Movement::WholeLine)),
+    ("kill-word", || Cmd::Kill(Movement::ForwardWord(1, At::AfterEnd, Word::Emacs))),
+    ("next-history", || Cmd::NextHistory),
+    ("previous-history", || Cmd::PreviousHistory),
+    ("quoted-insert", || Cmd::QuotedInsert),
+    ("reverse-search-history", || Cmd::ReverseSearchHistory),
+    ("transpose-chars", || Cmd::TransposeChars),
+    ("transpose-words", || Cmd::TransposeWords(1)),
+    ("undo", || Cmd::Undo(1)),
+    ("unix-line-discard", || Cmd::Kill(Movement::BeginningOfLine)),
+    ("unix-word-rubout", || Cmd::Kill(Movement::BackwardWord(1, Word::Big))),
+    ("upcase-word", || Cmd::UpcaseWord),
+    ("yank", || Cmd::Yank(1, Anchor::Before)),
+    ("yank-pop", || Cmd::YankPop),
+];
+
+/// The editing functions the shell adds, which work from its state.
+pub const SHELL_FUNCTIONS: &[&str] = &["shell-expand-line"];
+
+/// The line editor command of the editing function `name`, if it is one
+/// of the editor's rather than the shell's.
+pub fn command(name: &str) -> Option<Cmd> {
+    COMMANDS.iter().find(|(function, _)| *function == name).map(|(_, cmd)| cmd())
+}
+
+/// The names of all the editing functions, sorted.
+pub fn functions() -> Vec<&'static str> {
+    let mut names: Vec<&str> = COMMANDS.iter().map(|(name, _)| *name).chain(SHELL_FUNCTIONS.iter().copied()).collect();
+    names.sort_unstable();
+    names
+}
+
+/// What pressing a key sequence does.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Action {
+    /// Run an editing function.
+    Function(&'static str),
+    /// Type the text as if by hand. A trailing newline accepts the line.
+    Macro(String),
+}
+
+/// The key bindings the shell sets up in the line editor, on top of the
+/// editor's own: its defaults and those made with `bind`.
+pub struct Keymap {
+    bindings: Vec<(Vec<KeyEvent>, Action)>,
+    /// Counts the changes, so that the line editor knows when to take the
+    /// bindings again.
+    generation: usize,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            bindings: vec![(vec![KeyEvent(KeyCode::Char('E'), Modifiers::CTRL_ALT)], Action::Function("shell-expand-line"))],
+            generation: 0,
+        }
+    }
+}
+
+impl Keymap {
+    /// Make `keys` do `action`, replacing what they did before.
+    pub fn bind(&mut self, keys: Vec<KeyEvent>, action: Action) {
+        match self.bindings.iter_mut().find(|(bound, _)| *bound == keys) {
+            Some(binding) => binding.1 = action,
+            None => self.bindings.push((keys, action)),
+        }
+        self.generation += 1;
+    }
+
+    /// Undo the binding of `keys`, returning whether there was one.
+    pub fn unbind(&mut self, keys: &[KeyEvent]) -> bool {
+        let before = self.bindings.len();
+        self.bindings.retain(|(bound, _)| bound != keys);
+        self.generation += 1;
+        self.bindings.len() < before
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Vec<KeyEvent>, Action)> {
+        self.bindings.iter()
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+}
+
+/// Parse a binding in the form of readline's inputrc, as `bind` takes it:
+/// `"keys": function-name` or `"keys": "text"`.
+pub fn parse_binding(text: &str) -> Result<(Vec<KeyEvent>, Action), String> {
+    let (keys, rest) = quoted(text.trim_start()).ok_or_else(|| format!("{}: key sequence must be quoted", text))?;
+    let keys = parse_keys(&keys)?;
+    let rest = rest.trim_start().strip_prefix(':').ok_or_else(|| format!("{}: missing `:'", text))?.trim();
+    let action = match quoted(rest) {
+        Some((macro_text, after)) if after.trim().is_empty() => Action::Macro(macro_text),
+        Some(_) => return Err(format!("{}: text after the macro", text)),
+        None => match functions().into_iter().find(|name| *name == rest) {
+            Some(name) => Action::Function(name),
+            None => return Err(format!("{}: unknown function name", rest)),
+        },
+    };
+    Ok((keys, action))
+}
+
+/// The keys of a sequence as `bind -r` takes it, in double quotes or not.
+pub fn parse_sequence(text: &str) -> Result<Vec<KeyEvent>, String> {
+    let (keys, rest) = match text.starts_with('"') {
+        true => quoted(text),
+        false => quoted(&format!("\"{}\"", text)).map(|(keys, _)| (keys, "")),
+    }
+    .ok_or_else(|| format!("{}: unterminated key sequence", text))?;
+    if !rest.trim().is_empty() {
+        return Err(format!("{}: text after the key sequence", text));
+    }
+    parse_keys(&keys)
+}
+
+/// The text between the double quotes `text` starts with, with its
+/// escapes undone, and what follows the closing quote.
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let body = text.strip_prefix('"')?;
+    let mut out = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '"' => return Some((out, &body[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'a' => out.push('\x07'),
+                'e' => out.push('\x1b'),
+                // Key names such as `\C-g` are left for `parse_keys`.
+                c @ ('C' | 'M') => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                c => out.push(c),
+            },
+            _ => out.push(ch),
+        }
+    }
+    None
+}
+
+/// The keys of a sequence as written between the quotes of a binding,
+/// once `quoted` has undone the escapes: `\C-x` is Control-x, and `\M-x`
+/// or Escape followed by `x` is Meta-x.
+pub fn parse_keys(text: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut keys = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(mut ch) = chars.next() {
+        let mut mods = Modifiers::NONE;
+        loop {
+            let prefix = match (ch, chars.peek()) {
+                ('\\', Some(&prefix @ ('C' | 'M'))) => {
+                    chars.next();
+                    if chars.next() != Some('-') {
+                        return Err(format!("\"{}\": expected `-' after `\\{}'", text, prefix));
+                    }
+                    prefix
+                }
+                ('\x1b', Some(_)) if !mods.contains(Modifiers::ALT) => 'M',
+                _ => break,
+            };
+            mods |= if prefix == 'C' { Modifiers::CTRL } else { Modifiers::ALT };
+            ch = chars.next().ok_or_else(|| format!("\"{}\": missing key", text))?;
+        }
+        let key = match ch {
+            '?' if mods.contains(Modifiers::CTRL) => KeyEvent(KeyCode::Backspace, mods - Modifiers::CTRL),
+            '\n' | '\r' => KeyEvent(KeyCode::Enter, mods),
+            _ => KeyEvent::normalize(KeyEvent::new(ch, mods)),
+        };
+        keys.push(key);
+    }
+    match keys.is_empty() {
+        true => Err(String::from("\"\": empty key sequence")),
+        false => Ok(keys),
+    }
+}
+
+/// `keys` written as `parse_keys` reads them.
+pub fn format_keys(keys: &[KeyEvent]) -> String {
+    let mut out = String::new();
+    for &KeyEvent(code, mods) in keys {
+        if mods.contains(Modifiers::ALT) {
+            out.push_str("\\e");
+        }
+        if mods.contains(Modifiers::CTRL) {
+            out.push_str("\\C-");
+        }
+        match code {
+            KeyCode::Char(c) if mods.contains(Modifiers::CTRL) => out.push(c.to_ascii_lowercase()),
+            KeyCode::Char(c @ ('"' | '\\')) => {
+                out.push('\\');
+                out.push(c);
+            }
+            KeyCode::Char(c) => out.push(c),
+            KeyCode::Tab => out.push_str("\\t"),
+            KeyCode::Enter => out.push_str("\\r"),
+            KeyCode::Esc => out.push_str("\\e"),
+            KeyCode::Backspace => out.push_str("\\C-?"),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
+
+/// `text` escaped to go between the quotes of a macro.
+pub fn format_macro(text: &str) -> String {
+    let mut out = String::new();
+    for ch in text.chars() {
+        match ch {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\x07' => out.push_str("\\a"),
+            '\x1b' => out.push_str("\\e"),
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::history::{decode, encode};
+use crate::home_dir;
+
+/// The file the `kv` builtin keeps its values in: `$RSH_KV_FILE`, or
+/// `~/.rsh_kv`.
+pub fn path() -> Option<PathBuf> {
+    match std::env::var_os("RSH_KV_FILE") {
+        Some(path) if !path.is_empty() => Some(PathBuf::from(path)),
+        _ => home_dir().map(|home| home.join(".rsh_kv")),
+    }
+}
+
+/// Whether `key` can be stored: it must be non-empty and free of
+/// whitespace, which separates it from the value in the file.
+pub fn is_key(key: &str) -> bool {
+    !key.is_empty() && !key.contains(char::is_whitespace)
+}
+
+/// The values stored in the file at `path`, one `key<TAB>value` per line.
+/// A missing file holds nothing.
+pub fn load(path: &Path) -> io::Result<BTreeMap<String, String>> {
+    let text = match fs::read(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(text.split(|&b| b == b'\n')
+        .filter_map(|line| {
+            let tab = line.iter().position(|&b| b == b'\t')?;
+            let key = String::from_utf8_lossy(&line[..tab]).into_owned();
+            Some((key, decode(&line[tab + 1..]).into_owned()))
+        })
+        .collect())
+}
+
+/// Replace the file at `path` with `values`. The new contents are written
+/// to a temporary file first, so readers never see a partial file.
+pub fn save(path: &Path, values: &BTreeMap<String, String>) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", std::process::id()));
+    let mut file = File::create(&tmp)?;
+    for (key, value) in values {
+        writeln!(file, "{}\t{}", key, encode(value))?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
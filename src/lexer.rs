@@ -0,0 +1,292 @@
+use crate::aliases::Keywords;
+use crate::expand::unquote;
+use crate::parser::ParseError;
+use crate::variables::is_name;
+
+/// A piece of a command line as the parser sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A word as typed, quotes included; quote removal happens during
+    /// expansion.
+    Word(String),
+    Op(&'static str),
+    Newline,
+}
+
+/// Operators, longest first so that `&&` wins over `&`.
+const OPERATORS: &[&str] = &[
+    "<<-", "2>>", "2>|", "&>>", "&&", "&>", "||", ";;", "<<", ">>", ">|", "2>", ";", "&", "|", "(", ")", "<", ">",
+];
+
+/// The operator written `text`, if it is one.
+pub fn operator(text: &str) -> Option<&'static str> {
+    OPERATORS.iter().find(|&&op| op == text).copied()
+}
+
+/// Turn the words of `tokens` that are keyword macros into their operators.
+pub fn expand_keywords(tokens: Vec<(Token, usize)>, keywords: &Keywords) -> Vec<(Token, usize)> {
+    tokens.into_iter()
+        .map(|(token, line)| match &token {
+            Token::Word(word) => (keywords.get(word).map_or(token, |&op| Token::Op(op)), line),
+            _ => (token, line),
+        })
+        .collect()
+}
+
+/// Split `text` into words and operators, each with the line it starts
+/// on. With `comments`, a `#` starting a word starts a comment running to
+/// the end of the line.
+pub fn tokenize(text: &str, comments: bool) -> Result<Vec<(Token, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut word_line = 1;
+    // The line of the byte offset given, counted on from the last one.
+    let mut counted = (0, 1);
+    let mut line_at = |i: usize| {
+        counted = (i, counted.1 + text[counted.0..i].matches('\n').count());
+        counted.1
+    };
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if word.is_empty() {
+            word_line = line_at(i);
+        }
+        match ch {
+            '\'' | '"' => {
+                word.push(ch);
+                loop {
+                    match chars.next() {
+                        Some((_, c)) => {
+                            if c == '\\' && ch == '"' && chars.next_if(|&(_, c)| c == '\n').is_some() {
+                                continue;
+                            }
+                            word.push(c);
+                            if c == ch {
+                                break;
+                            }
+                            if c == '\\' && ch == '"' {
+                                if let Some((_, c)) = chars.next() {
+                                    word.push(c);
+                                }
+                            }
+                        }
+                        None => return Err(ParseError::Incomplete),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // A backslash-newline joins the line with the next one.
+                Some((_, '\n')) if chars.peek().is_none() => return Err(ParseError::Incomplete),
+                Some((_, '\n')) => {}
+                Some((_, c)) => {
+                    word.push(ch);
+                    word.push(c);
+                }
+                None => word.push(ch),
+            },
+            // The list of an array assignment, `$((...))` and a `((...))`
+            // arithmetic command are each part of a word.
+            '(' if opens_array(&word)
+                || (word.ends_with('$') && !word.ends_with("\\$"))
+                || (word.is_empty() && text[i..].starts_with("((")) =>
+            {
+                word.push(ch);
+                let mut depth = 1;
+                let mut quote: Option<char> = None;
+                while depth > 0 {
+                    let Some((_, c)) = chars.next() else {
+                        return Err(ParseError::Incomplete);
+                    };
+                    word.push(c);
+                    match (quote, c) {
+                        (Some(q), _) if c == q => quote = None,
+                        (Some('\''), _) => {}
+                        (_, '\\') => {
+                            if let Some((_, c)) = chars.next() {
+                                word.push(c);
+                            }
+                        }
+                        (None, '\'' | '"') => quote = Some(c),
+                        (None, '(') => depth += 1,
+                        (None, ')') => depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            '#' if comments && word.is_empty() => {
+                while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            }
+            '\n' => {
+                if !word.is_empty() {
+                    tokens.push((Token::Word(std::mem::take(&mut word)), word_line));
+                }
+                tokens.push((Token::Newline, line_at(i)));
+            }
+            c if c.is_whitespace() => {
+                if !word.is_empty() {
+                    tokens.push((Token::Word(std::mem::take(&mut word)), word_line));
+                }
+            }
+            _ => {
+                let rest = &text[i..];
+                // `2>` is only an operator at the start of a word.
+                let op = OPERATORS.iter()
+                    .find(|op| rest.starts_with(**op) && (word.is_empty() || !op.starts_with('2')));
+                match op {
+                    Some(op) => {
+                        if !word.is_empty() {
+                            tokens.push((Token::Word(std::mem::take(&mut word)), word_line));
+                        }
+                        tokens.push((Token::Op(op), line_at(i)));
+                        for _ in 1..op.len() {
+                            chars.next();
+                        }
+                    }
+                    None => word.push(ch),
+                }
+            }
+        }
+    }
+    if !word.is_empty() {
+        tokens.push((Token::Word(word), word_line));
+    }
+    Ok(tokens)
+}
+
+/// Whether `word` is the start of an array assignment, `name=` or
+/// `name+=`, so that a `(` after it opens the list of elements.
+fn opens_array(word: &str) -> bool {
+    word.strip_suffix('=')
+        .map(|name| name.strip_suffix('+').unwrap_or(name))
+        .is_some_and(is_name)
+}
+
+/// The words of the list in `value`, if it is written `(word ...)` as the
+/// value of an array assignment.
+pub fn array_elements(value: &str) -> Option<Vec<String>> {
+    let list = value.strip_prefix('(')?.strip_suffix(')')?;
+    let tokens = tokenize(list, true).ok()?;
+    Some(tokens.into_iter()
+        .filter_map(|(token, _)| match token {
+            Token::Word(word) => Some(word),
+            _ => None,
+        })
+        .collect())
+}
+
+/// The here-document delimiters opened by `<<word` and `<<-word` on the
+/// complete line `line`, in order, with their quotes removed and whether
+/// they strip leading tabs.
+pub fn here_doc_delimiters(line: &str) -> Vec<(String, bool)> {
+    let Ok(tokens) = tokenize(line, false) else {
+        return Vec::new();
+    };
+    tokens.windows(2)
+        .filter_map(|pair| match pair {
+            [(Token::Op(op @ ("<<" | "<<-")), _), (Token::Word(word), _)] => Some((unquote(word), *op == "<<-")),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The byte offsets of the `!`s on `line` that may start a history
+/// reference: those outside single quotes that aren't escaped with a
+/// backslash and aren't part of `$!` or `${!name}`.
+pub fn history_marks(line: &str) -> Vec<usize> {
+    let mut marks = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev = (' ', ' ');
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            prev = (prev.1, ' ');
+            continue;
+        }
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (_, '\\') => escaped = true,
+            (_, '!') if prev.1 != '$' && prev != ('$', '{') => marks.push(i),
+            _ => {}
+        }
+        prev = (prev.1, c);
+    }
+    marks
+}
+
+/// The byte offset of the `#` that starts a comment on `line`, if any:
+/// the first unquoted, unescaped `#` at the start of a word.
+pub fn comment_start(line: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut word_start = true;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            word_start = false;
+            continue;
+        }
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some('"') if c == '\\' => escaped = true,
+            Some(_) => {}
+            None => match c {
+                '#' if word_start => return Some(i),
+                '\\' => escaped = true,
+                '\'' | '"' => quote = Some(c),
+                _ => {}
+            },
+        }
+        word_start = quote.is_none() && (c.is_whitespace() || ";&|()<>".contains(c));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        tokenize(text, true).unwrap().into_iter().map(|(token, _)| token).collect()
+    }
+
+    fn word(text: &str) -> Token {
+        Token::Word(text.to_owned())
+    }
+
+    #[test]
+    fn accented_words() {
+        assert_eq!(tokens("echo héllo | grep é"), [word("echo"), word("héllo"), Token::Op("|"), word("grep"), word("é")]);
+    }
+
+    #[test]
+    fn cjk_next_to_operators() {
+        assert_eq!(tokens("echo 你好>文件;cat<文件"), [
+            word("echo"), word("你好"), Token::Op(">"), word("文件"), Token::Op(";"),
+            word("cat"), Token::Op("<"), word("文件"),
+        ]);
+    }
+
+    #[test]
+    fn emoji_in_quotes() {
+        assert_eq!(tokens("echo \"👍🏽 ok\"&&echo '🎉'"), [
+            word("echo"), word("\"👍🏽 ok\""), Token::Op("&&"), word("echo"), word("'🎉'"),
+        ]);
+    }
+
+    #[test]
+    fn combining_characters() {
+        // An e followed by a combining acute accent.
+        assert_eq!(tokens("echo e\u{301}tude|wc"), [word("echo"), word("e\u{301}tude"), Token::Op("|"), word("wc")]);
+    }
+
+    #[test]
+    fn multibyte_comments_and_history_marks() {
+        assert_eq!(tokens("echo 日本 # コメント"), [word("echo"), word("日本")]);
+        assert_eq!(comment_start("echo ü # c"), Some("echo ü ".len()));
+        assert_eq!(history_marks("echo 日本!x '語!'"), ["echo 日本".len()]);
+    }
+}
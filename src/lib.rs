@@ -0,0 +1,996 @@
+// Job control, redirections and subshells are built on fork, process
+// groups and POSIX signals, which Windows doesn't have.
+#[cfg(not(unix))]
+compile_error!("the shell only runs on Unix-like systems");
+
+mod aliases;
+mod arith;
+mod audit;
+mod base64;
+mod bookmarks;
+mod braces;
+mod builder;
+mod builtins;
+mod capture;
+mod completion;
+mod conditional;
+mod correct;
+mod crash;
+mod datetime;
+mod error;
+mod exec;
+mod expand;
+#[cfg(feature = "fetch")]
+mod fetch;
+mod frecency;
+mod globbing;
+mod heredoc;
+mod highlight;
+mod hooks;
+mod history;
+mod jobs;
+mod json;
+mod keymap;
+mod kv;
+mod lexer;
+mod options;
+mod pager;
+mod parser;
+mod plugins;
+mod predicates;
+mod printf;
+mod priority;
+mod profile;
+mod project;
+mod prompt;
+mod pty;
+mod records;
+mod regex;
+mod runner;
+mod sha256;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod traps;
+mod trash;
+mod variables;
+mod widgets;
+mod workers;
+
+use std::collections::{BTreeMap, VecDeque};
+
+use std::path::{Component, Path, PathBuf};
+use std::cell::OnceCell;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::fs::File;
+use std::os::fd::OwnedFd;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::io::Write;
+use nix::unistd::{dup, dup2_stderr, dup2_stdin, dup2_stdout};
+use rustyline::config::{CompletionType, Config, Configurer, EditMode};
+use rustyline::error::ReadlineError;
+use rustyline::{Cmd, Editor, EventHandler, ExternalPrinter, KeyCode, KeyEvent, Modifiers};
+use users::os::unix::UserExt;
+use completion::{CompletionSpecs, ShellHelper};
+use crash::Crash;
+use error::ShellError;
+use exec::{exec_list, run_traps, SavedStreams};
+use aliases::{Abbreviations, Aliases, Keywords};
+use globbing::{GlobOptions, Globber};
+use history::{EditorHistory, History};
+use hooks::{Event, Hooks};
+use jobs::Jobs;
+use keymap::{Action, Keymap};
+use options::Options;
+use profile::StartupProfile;
+use prompt::Prompt;
+use runner::CommandHash;
+use traps::Traps;
+use variables::Variables;
+use widgets::{ExpandAbbreviation, ExpandLine, Macro, Snapshot};
+
+pub use builder::ShellBuilder;
+pub use builtins::{Builtin, Io};
+pub use completion::{CompletionContext, CompletionProvider, Env};
+pub use plugins::Plugin;
+pub use prompt::{CachePolicy, PromptSegment};
+use parser::{List, ParseError};
+
+/// Source of command lines: a script, a line editor when attached to a
+/// terminal, or plain buffered stdin otherwise.
+struct LineReader {
+    editor: Option<Editor<ShellHelper, EditorHistory>>,
+    /// The script or `-c` command being run, read without prompting,
+    /// with the name syntax errors in it are reported under.
+    script: Option<(Box<dyn BufRead>, String)>,
+    /// How many lines of the script have been read.
+    line: usize,
+    /// The shell state key bindings work from.
+    snapshot: Arc<Mutex<Snapshot>>,
+    /// The generation of the shell's keymap last bound in the editor.
+    keymap_generation: Option<usize>,
+    /// The key sequences bound from the keymap.
+    bound: Vec<rustyline::Event>,
+    /// The text of a macro that accepted the line, and where in the line
+    /// it goes.
+    pending_macro: Arc<Mutex<Option<(usize, String)>>>,
+    /// The lines after the first of a command edited over several, handed
+    /// out one at a time as a script's lines are.
+    queued: VecDeque<String>,
+}
+
+impl LineReader {
+    fn new(history: &Arc<Mutex<History>>) -> Self {
+        let snapshot: Arc<Mutex<Snapshot>> = Arc::default();
+        let editor = if io::stdin().is_terminal() {
+            let config = Config::builder()
+                .completion_type(CompletionType::List)
+                .build();
+            Editor::with_history(config, EditorHistory(history.clone())).ok().map(|mut editor| {
+                editor.set_helper(Some(ShellHelper::default()));
+                editor
+            })
+        } else {
+            None
+        };
+        LineReader {
+            editor,
+            script: None,
+            line: 0,
+            snapshot,
+            keymap_generation: None,
+            bound: Vec::new(),
+            pending_macro: Arc::default(),
+            queued: VecDeque::new(),
+        }
+    }
+
+    fn from_script(script: Box<dyn BufRead>, name: &str) -> Self {
+        LineReader {
+            editor: None,
+            script: Some((script, name.to_owned())),
+            line: 0,
+            snapshot: Arc::default(),
+            keymap_generation: None,
+            bound: Vec::new(),
+            pending_macro: Arc::default(),
+            queued: VecDeque::new(),
+        }
+    }
+
+    /// Set up the key bindings of `keymap` in the editor, in place of
+    /// those set up before. Space expands abbreviations unless bound to
+    /// something else.
+    fn bind_keys(&mut self, keymap: &Keymap) {
+        let Some(editor) = self.editor.as_mut() else {
+            return;
+        };
+        for event in self.bound.drain(..) {
+            editor.unbind_sequence(event);
+        }
+        editor.bind_sequence(KeyEvent(KeyCode::Char(' '), Modifiers::NONE),
+                             EventHandler::Conditional(Box::new(ExpandAbbreviation(self.snapshot.clone()))));
+        for (keys, action) in keymap.iter() {
+            let handler = match action {
+                Action::Function("shell-expand-line") => {
+                    EventHandler::Conditional(Box::new(ExpandLine(self.snapshot.clone())))
+                }
+                Action::Function(name) => EventHandler::Simple(keymap::command(name).unwrap_or(Cmd::Noop)),
+                Action::Macro(text) => EventHandler::Conditional(Box::new(Macro {
+                    text: text.clone(),
+                    pending: self.pending_macro.clone(),
+                })),
+            };
+            let event = rustyline::Event::KeySeq(keys.clone());
+            editor.bind_sequence(event.clone(), handler);
+            self.bound.push(event);
+        }
+        self.keymap_generation = Some(keymap.generation());
+    }
+
+    /// Refresh the editor's view of shell state used for completion, and
+    /// give running jobs a way to report on themselves.
+    fn sync(&mut self, shell: &Shell) {
+        if shell.jobs.wants_printer() {
+            if let Some(printer) = self.external_printer() {
+                shell.jobs.set_printer(printer);
+            }
+        }
+        if let Some(editor) = self.editor.as_mut() {
+            editor.set_edit_mode(if shell.options.vi { EditMode::Vi } else { EditMode::Emacs });
+        }
+        if self.keymap_generation != Some(shell.keymap.generation()) {
+            self.bind_keys(&shell.keymap);
+        }
+        if let Some(helper) = self.editor.as_mut().and_then(|e| e.helper_mut()) {
+            helper.env.named_dirs = shell.named_dirs.clone();
+            helper.env.globber = shell.globber.clone();
+            helper.env.path = shell.vars.get("PATH").unwrap_or_default();
+            helper.env.commands = shell.aliases.keys().chain(shell.builtins.keys()).cloned().collect();
+            helper.env.specs = shell.completions.clone();
+            helper.env.set_spec_dir(completion::spec_dir(shell));
+            helper.env.abbreviations = shell.abbreviations.clone();
+            helper.env.glob_expand = shell.vars.get("RSH_GLOB_COMPLETE").is_some_and(|mode| mode == "expand");
+            helper.env.glob_options = shell.options.glob();
+            if helper.plugins_generation != shell.plugins.generation() {
+                helper.plugins_generation = shell.plugins.generation();
+                helper.plugin_providers = shell.plugins.completion_providers();
+            }
+            helper.prepare();
+            helper.right_prompt = shell.prompt.render_right(shell);
+            helper.syntax = (shell.aliases.clone(), shell.keywords().clone(), shell.options.interactive_comments);
+            *self.snapshot.lock().unwrap() = Snapshot::take(shell);
+        }
+    }
+
+    /// Report how long completion reads its spec files, which it does
+    /// when first asked, as `--profile-startup` does for startup.
+    fn set_profile(&mut self, profile: bool) {
+        if let Some(helper) = self.editor.as_mut().and_then(|e| e.helper_mut()) {
+            helper.env.profile = profile;
+        }
+    }
+
+    /// Whether input comes from a user at a terminal.
+    fn is_interactive(&self) -> bool {
+        self.editor.is_some()
+    }
+
+    /// A handle other threads can print through without corrupting the
+    /// line being edited.
+    fn external_printer(&mut self) -> Option<Box<dyn ExternalPrinter + Send>> {
+        let printer = self.editor.as_mut()?.create_external_printer().ok()?;
+        Some(Box::new(printer))
+    }
+
+    /// Append the next line (with its newline) to `buf`, returning the
+    /// number of bytes read; 0 means end of input. Interrupting the line
+    /// editor is reported as `ErrorKind::Interrupted`.
+    fn read_line(&mut self, prompt: &str, buf: &mut String) -> io::Result<usize> {
+        self.line += 1;
+        let editor = match self.editor.as_mut() {
+            Some(editor) => editor,
+            None => {
+                if let Some((script, _)) = self.script.as_mut() {
+                    return script.read_line(buf);
+                }
+                // A prompt nobody reads is no reason to stop reading.
+                let _ = write!(io::stdout(), "{}", prompt).and_then(|_| io::stdout().flush());
+                return io::stdin().read_line(buf);
+            }
+        };
+        if let Some(line) = self.queued.pop_front() {
+            buf.push_str(&line);
+            buf.push('\n');
+            return Ok(line.len() + 1);
+        }
+        let read = editor.readline(prompt);
+        let pending = self.pending_macro.lock().unwrap().take();
+        match read {
+            Ok(mut line) => {
+                if let Some((pos, text)) = pending {
+                    line.insert_str(pos.min(line.len()), &text);
+                    let _ = writeln!(io::stdout(), "{}", line);
+                }
+                if let Some((first, rest)) = line.split_once('\n') {
+                    self.queued.extend(rest.split('\n').map(str::to_owned));
+                    line.truncate(first.len());
+                }
+                buf.push_str(&line);
+                buf.push('\n');
+                Ok(line.len() + 1)
+            }
+            Err(ReadlineError::Interrupted) => Err(io::ErrorKind::Interrupted.into()),
+            Err(ReadlineError::Eof) => Ok(0),
+            Err(ReadlineError::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+}
+
+/// Read lines until they form a complete command, returning its text and
+/// syntax tree, or `Ok(None)` at end of input. History references are
+/// expanded and here-document bodies collected as each line is read.
+/// Where each line came from is left in `shell.lines`.
+fn load_command_line(shell: &mut Shell, reader: &mut LineReader, prompt: &str)
+-> Result<Option<(String, List)>, ShellError> {
+    let mut buf = String::new();
+    let mut heredocs: Vec<String> = Vec::new();
+    // The line of the script each line of `buf` was read from.
+    let mut lines = Vec::new();
+    let mut prompt = prompt;
+    let continuation = shell.prompt.render_continuation(shell);
+    let comments = shell.options.interactive_comments || !reader.is_interactive();
+    // The part of a line before any comment, which alone is subject to
+    // history expansion and can open here-documents.
+    let code_len = |line: &str| match comments {
+        true => lexer::comment_start(line).unwrap_or(line.len()),
+        false => line.len(),
+    };
+    loop {
+        let mut line = String::new();
+        match reader.read_line(prompt, &mut line) {
+            Ok(0) if buf.trim().is_empty() => return Ok(None),
+            Ok(0) => return Err(ShellError::from(ParseError::Incomplete)),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                return Ok(Some((String::new(), List::new())));
+            }
+            Err(e) => return Err(ShellError::Input(e.to_string())),
+        }
+        let text = line.trim_end_matches('\n');
+        // An abbreviation still being typed when Enter is pressed.
+        if let Some((start, value)) = widgets::abbreviation(&shell.abbreviations, text, text.len())
+            .filter(|_| reader.is_interactive())
+        {
+            line = format!("{}{}\n", &text[..start], value);
+            let _ = writeln!(io::stdout(), "{}", line.trim_end_matches('\n'));
+        }
+        let text = line.trim_end_matches('\n');
+        let code = &text[..code_len(text)];
+        // History references are only for lines typed at the prompt.
+        let expanded = match reader.is_interactive() {
+            true => shell.history.lock().unwrap().expand(code)?,
+            false => None,
+        };
+        if let Some(expanded) = expanded {
+            line = format!("{}{}\n", expanded, &text[code.len()..]);
+            let _ = writeln!(io::stdout(), "{}", line.trim_end_matches('\n'));
+        }
+        let code = &line[..code_len(&line)];
+        lines.push(reader.line);
+        heredocs.extend(heredoc::read_bodies(reader, code).map_err(|e| ShellError::Input(e.to_string()))?);
+        buf.push_str(&line);
+        match parser::parse(&buf, &heredocs, &shell.aliases, shell.keywords(), comments) {
+            Ok(list) => {
+                shell.lines = lines;
+                return Ok(Some((buf, list)));
+            }
+            Err(ParseError::Incomplete) => prompt = &continuation,
+            // Scripts say where the error is, as lines of the file.
+            Err(ParseError::Syntax { line, message }) => {
+                let at = reader.script.as_ref().map(|(_, name)| {
+                    (name.clone(), lines.get(line - 1).or(lines.last()).copied().unwrap_or(line))
+                });
+                return Err(ShellError::Syntax { at, message });
+            }
+        }
+    }
+}
+
+/// A way out of the commands being run other than finishing them, set by
+/// `break`, `continue` and `return` and followed by the loops and sourced
+/// files around them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Flow {
+    /// Leave this many loops.
+    Break(usize),
+    /// Leave this many loops less one, and start the next round of the
+    /// loop reached.
+    Continue(usize),
+    /// Leave the file being sourced.
+    Return,
+}
+
+/// Named directories defined with `hash -d`, expanded from `~name`.
+pub type NamedDirs = BTreeMap<String, String>;
+
+/// State shared by everything the shell executes.
+#[derive(Default)]
+pub struct Shell {
+    pub(crate) named_dirs: NamedDirs,
+    /// The directories `pushd` left, most recent first.
+    pub(crate) dir_stack: Vec<PathBuf>,
+    pub(crate) history: Arc<Mutex<History>>,
+    pub(crate) aliases: Aliases,
+    pub(crate) abbreviations: Abbreviations,
+    pub(crate) keywords: Keywords,
+    pub(crate) vars: Variables,
+    pub(crate) jobs: Jobs,
+    pub(crate) options: Options,
+    pub(crate) globber: Arc<Globber>,
+    /// How to complete the arguments of commands, set with `complete`.
+    pub(crate) completions: CompletionSpecs,
+    /// Where external commands were found in `$PATH`.
+    pub(crate) command_hash: CommandHash,
+    /// Set while `logall` is recording the session.
+    pub(crate) tee: Option<pty::Tee>,
+    /// What the last command run under `set -o capture` wrote.
+    pub(crate) last_output: capture::LastOutput,
+    /// The working directory, looked up once and forgotten by `cd`.
+    pub(crate) cwd: OnceCell<PathBuf>,
+    /// The line of the input each line of the command being run was read
+    /// from, for `$LINENO`.
+    pub(crate) lines: Vec<usize>,
+    /// How many loops the running command is in, for `break` and
+    /// `continue`.
+    pub(crate) loops: usize,
+    /// How many files are being sourced, for `return`.
+    pub(crate) sourcing: usize,
+    /// Set while the commands being run are left early.
+    pub(crate) flow: Option<Flow>,
+    /// How many `if` conditions are being evaluated. Failures there don't
+    /// trigger `set -e`.
+    pub(crate) conditions: usize,
+    /// Set while `$COMMAND_NOT_FOUND` runs, so that a command it can't
+    /// find is reported rather than handled again.
+    pub(crate) handling_not_found: bool,
+    pub(crate) traps: Traps,
+    pub(crate) prompt: Prompt,
+    pub(crate) hooks: Hooks,
+    pub(crate) plugins: plugins::Plugins,
+    /// The key bindings of the line editor, changed with `bind`.
+    pub(crate) keymap: Keymap,
+    /// Builtins added by `ShellBuilder::with_builtin`, which take the
+    /// place of the shell's own.
+    pub(crate) builtins: BTreeMap<String, Builtin>,
+    /// Set in deterministic mode; see `make_deterministic`.
+    pub(crate) deterministic: bool,
+    /// Set for a session that reads commands from a terminal.
+    pub(crate) interactive: bool,
+    /// Set for a login shell, which runs `~/.rsh_logout` as it exits.
+    pub(crate) login: bool,
+    /// Set in restricted mode, which keeps the working directory and
+    /// `$PATH` as they are, and forbids redirecting output and running
+    /// commands named with a `/`.
+    pub(crate) restricted: bool,
+    /// Set once exiting was put off to warn about running jobs.
+    pub(crate) warned_jobs: bool,
+    /// How many times in a row end of input was ignored under `set -o
+    /// ignoreeof`.
+    pub(crate) ignored_eofs: usize,
+    /// The `$OPTIND` that `getopts` left, and where in that argument the
+    /// flag after the last it parsed is, for grouped flags such as `-ab`.
+    pub(crate) getopts_position: (usize, usize),
+    /// The stdin, stdout and stderr given to `ShellBuilder`, which `run`
+    /// points the process's own at.
+    pub(crate) stdio: [Option<OwnedFd>; 3],
+}
+
+impl Shell {
+    /// A shell set up as `Shell::builder().build()` would.
+    pub fn new() -> Self {
+        ShellBuilder::new().build()
+    }
+
+    pub fn builder() -> ShellBuilder {
+        ShellBuilder::new()
+    }
+
+    /// Make script runs reproducible: seed `$RANDOM` the same way every
+    /// time, stop `$SECONDS` and the clock of `datetime` (at
+    /// `$SOURCE_DATE_EPOCH`, or the epoch) and sort glob results by their
+    /// bytes.
+    pub fn make_deterministic(&mut self) {
+        self.deterministic = true;
+        self.vars.freeze();
+        self.globber = Arc::new(Globber::default().bytewise());
+    }
+
+    /// The time since the epoch, as far as the shell is concerned.
+    pub fn now(&self) -> Duration {
+        match self.deterministic {
+            true => Duration::from_secs(self.vars.get("SOURCE_DATE_EPOCH")
+                .and_then(|epoch| epoch.trim().parse().ok())
+                .unwrap_or(0)),
+            false => SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default(),
+        }
+    }
+
+    /// The keyword macros in effect: none under `set -o strict`.
+    pub(crate) fn keywords(&self) -> &Keywords {
+        static NONE: Keywords = Keywords::new();
+        match self.options.strict {
+            true => &NONE,
+            false => &self.keywords,
+        }
+    }
+
+    /// The builtin run for the command `name`, if any.
+    pub fn builtin(&self, name: &str) -> Option<Builtin> {
+        self.builtins.get(name).copied().or_else(|| builtins::find(name))
+    }
+
+    /// The value of the variable `name`, if it is set.
+    pub fn var(&self, name: &str) -> Option<String> {
+        self.vars.get(name)
+    }
+
+    /// Set the variable `name` to `value`.
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        self.vars.set(name, value.to_owned());
+    }
+
+    /// Parse and run the commands in `command`, returning the exit status
+    /// of the last, or 2 after a syntax error.
+    pub fn eval(&mut self, command: &str) -> ExitStatus {
+        ExitStatus::from_raw((self.run(command) & 0xff) << 8)
+    }
+
+    /// Parse and run the commands in `command`, returning the status of
+    /// the last, or 2 after a syntax error.
+    pub(crate) fn run(&mut self, command: &str) -> i32 {
+        let _saved = match self.redirect_stdio() {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("shell: {}", e);
+                return 1;
+            }
+        };
+        let status = match parser::parse(command, &[], &self.aliases, self.keywords(), true) {
+            Ok(list) => exec_list(self, &list),
+            Err(e) => {
+                ShellError::from(e).report();
+                2
+            }
+        };
+        self.vars.status = status;
+        status
+    }
+
+    /// Point stdin, stdout and stderr at the streams in `stdio`, returning
+    /// copies of the ones they replace to put back.
+    fn redirect_stdio(&self) -> nix::Result<Option<SavedStreams>> {
+        if self.stdio.iter().all(Option::is_none) {
+            return Ok(None);
+        }
+        let _ = io::stdout().flush();
+        let saved = SavedStreams([dup(io::stdin())?, dup(io::stdout())?, dup(io::stderr())?]);
+        if let Some(fd) = &self.stdio[0] {
+            dup2_stdin(fd)?;
+        }
+        if let Some(fd) = &self.stdio[1] {
+            dup2_stdout(fd)?;
+        }
+        if let Some(fd) = &self.stdio[2] {
+            dup2_stderr(fd)?;
+        }
+        Ok(Some(saved))
+    }
+}
+
+pub trait PathMatcher {
+    fn match_wild_card(&self, globber: &Globber, options: GlobOptions) -> Vec<String>;
+    fn unfold(&self, named_dirs: &NamedDirs) -> String;
+}
+
+impl PathMatcher for str {
+    /// The paths matching `self`, or `self` itself when there are none or
+    /// it is not a valid pattern.
+    fn match_wild_card(&self, globber: &Globber, options: GlobOptions) -> Vec<String> {
+        let mut res = globber.glob(self, options).unwrap_or_default();
+        if res.is_empty() {
+            res.push(self.to_owned());
+        }
+        res
+    }
+    fn unfold(&self, named_dirs: &NamedDirs) -> String {
+        // Without a home directory, `~` stays as it is.
+        if self == "~" {
+            home_dir().map_or_else(|| self.to_owned(), |home| home.to_string_lossy().into_owned())
+        } else if let Some(rest) = self.strip_prefix("~/") {
+            home_dir().map_or_else(|| self.to_owned(), |home| home.join(rest).to_string_lossy().into_owned())
+        } else if let Some(word) = self.strip_prefix('~') {
+            let (name, rest) = match word.split_once('/') {
+                Some((name, rest)) => (name, Some(rest)),
+                None => (word, None),
+            };
+            // `~+` and `~-` are the working and previous directories,
+            // and other names are named directories or users.
+            let dir = match name {
+                "+" => std::env::var_os("PWD").map(PathBuf::from),
+                "-" => std::env::var_os("OLDPWD").map(PathBuf::from),
+                _ => named_dirs.get(name).map(PathBuf::from).or_else(|| {
+                    users::get_user_by_name(name).map(|user| user.home_dir().to_path_buf())
+                }),
+            };
+            match (dir, rest) {
+                (Some(dir), Some(rest)) => dir.join(rest).to_string_lossy().into_owned(),
+                (Some(dir), None) => dir.to_string_lossy().into_owned(),
+                (None, _) => self.to_owned(),
+            }
+        } else {
+            self.to_owned()
+        }
+    }
+}
+
+/// The user's home directory, taken from `$HOME` when it is set and
+/// falling back to the passwd database otherwise.
+pub fn home_dir() -> Option<PathBuf> {
+    match std::env::var_os("HOME") {
+        Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => dirs::home_dir(),
+    }
+}
+
+/// Export `$PWD` as the working directory, unless it already names it as
+/// a path through symbolic links may.
+fn init_pwd(shell: &mut Shell) {
+    let Ok(cwd) = std::env::current_dir() else {
+        return;
+    };
+    let same = |pwd: &str| {
+        let pwd = Path::new(pwd);
+        pwd.is_absolute() && !pwd.components().any(|c| matches!(c, Component::CurDir | Component::ParentDir))
+            && std::fs::metadata(pwd).ok().zip(std::fs::metadata(&cwd).ok())
+                .is_some_and(|(a, b)| a.dev() == b.dev() && a.ino() == b.ino())
+    };
+    if !shell.vars.get("PWD").is_some_and(|pwd| same(&pwd)) {
+        shell.vars.export("PWD", cwd.to_string_lossy().into_owned());
+    }
+}
+
+/// Where user configuration is kept: `$XDG_CONFIG_HOME`, or `~/.config`.
+pub fn config_home(shell: &Shell) -> Option<PathBuf> {
+    shell.vars.get("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(".config")))
+}
+
+/// Where user data is kept: `$XDG_DATA_HOME`, or `~/.local/share`.
+pub fn data_home(shell: &Shell) -> Option<PathBuf> {
+    shell.vars.get("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(".local/share")))
+}
+
+/// Whether end of input is ignored at an interactive prompt under `set
+/// -o ignoreeof`: it is, with a reminder of how to leave, until it comes
+/// `$IGNOREEOF` times in a row (10 when unset or not a number).
+fn ignore_eof(shell: &mut Shell) -> bool {
+    if !shell.interactive || !shell.options.ignoreeof {
+        return false;
+    }
+    let limit = shell.vars.get("IGNOREEOF").and_then(|limit| limit.trim().parse().ok()).unwrap_or(10);
+    if shell.ignored_eofs >= limit {
+        return false;
+    }
+    shell.ignored_eofs += 1;
+    eprintln!("Use \"{}\" to leave the shell.", if shell.login { "logout" } else { "exit" });
+    true
+}
+
+/// Whether the shell may exit now. An interactive shell with running jobs
+/// warns about them and stays the first time, unless under `set -o
+/// huponexit`; it hangs them up once it does exit.
+fn may_exit(shell: &mut Shell) -> bool {
+    if !shell.interactive || shell.warned_jobs || shell.options.huponexit || shell.jobs.running().is_empty() {
+        return true;
+    }
+    eprintln!("shell: you have running jobs");
+    shell.warned_jobs = true;
+    false
+}
+
+/// Leave the shell with `status` after running the `EXIT` trap, and
+/// `~/.rsh_logout` for a login shell, putting the terminal back first. An
+/// interactive shell hangs up the jobs it leaves running.
+fn exit(shell: &mut Shell, status: i32) -> ! {
+    hooks::emit(shell, Event::Exit);
+    if shell.login {
+        shell.login = false;
+        if let Some(home) = home_dir() {
+            source_if_present(shell, &home.join(".rsh_logout"));
+        }
+    }
+    if shell.interactive {
+        shell.jobs.hang_up();
+    }
+    shell.tee.take();
+    let _ = io::stdout().flush();
+    std::process::exit(status);
+}
+
+/// How the shell was started:
+/// `shell [-lr] [--profile-startup] [--norc] [--deterministic] [--stats] [script [arg ...]]`
+/// or `shell [-lr] [--profile-startup] [--norc] [--deterministic] [--stats] -c command [name [arg ...]]`.
+/// A name starting with `-`, as `login` gives it, also makes a login
+/// shell.
+#[derive(Default)]
+struct Invocation {
+    /// Run as a login shell, reading the profile first.
+    login: bool,
+    /// Run in restricted mode once the startup files are read.
+    restricted: bool,
+    profile_startup: bool,
+    /// Run in deterministic mode.
+    deterministic: bool,
+    /// Start with `set -o stats`.
+    stats: bool,
+    /// Skip `~/.rshrc` in an interactive session.
+    norc: bool,
+    /// The script to run instead of reading commands from stdin.
+    script: Option<String>,
+    /// The command string given with `-c`.
+    command: Option<String>,
+    /// The positional parameters.
+    args: Vec<String>,
+}
+
+impl Invocation {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Invocation, String> {
+        let mut invocation = Invocation::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-l" | "--login" => invocation.login = true,
+                "-r" | "--restricted" => invocation.restricted = true,
+                "--profile-startup" => invocation.profile_startup = true,
+                "--norc" => invocation.norc = true,
+                "--deterministic" => invocation.deterministic = true,
+                "--stats" => invocation.stats = true,
+                "-c" => {
+                    invocation.command = Some(args.next().ok_or("shell: -c: option requires an argument")?);
+                    break;
+                }
+                "--" => {
+                    invocation.script = args.next();
+                    break;
+                }
+                _ if arg.starts_with('-') && arg.len() > 1 => {
+                    return Err(format!("shell: {}: invalid option", arg));
+                }
+                _ => {
+                    invocation.script = Some(arg);
+                    break;
+                }
+            }
+        }
+        invocation.args = args.collect();
+        Ok(invocation)
+    }
+
+    /// Whether commands come from a script or `-c` rather than stdin.
+    fn runs_script(&self) -> bool {
+        self.script.is_some() || self.command.is_some()
+    }
+}
+
+/// Run the shell with the arguments it was started with, as the
+/// `rust_shell` binary does.
+pub fn main() {
+    main_with_plugins(Vec::new())
+}
+
+/// Run the shell as `main` does, with `plugins` added.
+pub fn main_with_plugins(plugins: Vec<Box<dyn Plugin>>) {
+    let mut args = std::env::args();
+    let dashed = args.next().is_some_and(|name| name.starts_with('-'));
+    let mut invocation = match Invocation::parse(args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("usage: shell [-lr] [--profile-startup] [--norc] [--deterministic] [--stats] [script [arg ...]]");
+            eprintln!("       shell [-lr] [--profile-startup] [--norc] [--deterministic] [--stats] -c command [name [arg ...]]");
+            std::process::exit(2);
+        }
+    };
+    invocation.login |= dashed;
+    let mut profile = StartupProfile::new(invocation.profile_startup);
+    let mut shell = profile.time("shell state", Shell::default);
+    init_pwd(&mut shell);
+    if invocation.deterministic {
+        shell.make_deterministic();
+    }
+    shell.options.stats = invocation.stats;
+    for plugin in plugins {
+        plugins::add(&mut shell, plugin);
+    }
+    let runs_script = invocation.runs_script();
+    shell.vars.positional = invocation.args;
+    let mut reader = match (&invocation.command, &invocation.script) {
+        (Some(command), _) => {
+            // As with `sh -c`, the first argument after the command is `$0`.
+            if !shell.vars.positional.is_empty() {
+                shell.vars.name = shell.vars.positional.remove(0);
+            }
+            LineReader::from_script(Box::new(io::Cursor::new(format!("{}\n", command))), "-c")
+        }
+        (None, Some(script)) => match File::open(script) {
+            Ok(file) => {
+                shell.vars.name = script.clone();
+                LineReader::from_script(Box::new(BufReader::new(file)), script)
+            }
+            Err(e) => {
+                eprintln!("shell: {}: {}", script, e);
+                std::process::exit(127);
+            }
+        },
+        (None, None) => profile.time("line editor", || LineReader::new(&shell.history)),
+    };
+    if reader.is_interactive() {
+        shell.interactive = true;
+        reader.set_profile(invocation.profile_startup);
+        match bookmarks::load() {
+            Ok(saved) => shell.named_dirs.extend(saved),
+            Err(e) => eprintln!("shell: bookmarks: {}", e),
+        }
+        shell.hooks.subscribe(project::overlays());
+    } else {
+        // Scripts and `-c` commands don't announce the jobs they start.
+        shell.jobs.silence();
+    }
+    crash::install_hook();
+    if invocation.login {
+        shell.login = true;
+        if let Err(crash) = crash::catch(|| profile.time("profile", || run_profile(&mut shell))) {
+            recover(&mut shell, &crash, "");
+        }
+    }
+    if reader.is_interactive() && !invocation.norc {
+        if let Err(crash) = crash::catch(|| profile.time("plugins", || plugins::load(&mut shell))) {
+            recover(&mut shell, &crash, "");
+        }
+        if let Err(crash) = crash::catch(|| profile.time("rc file", || run_rc_file(&mut shell))) {
+            recover(&mut shell, &crash, "");
+        }
+    }
+    // After the rc file, which may set `$HISTFILE` or pick another backend.
+    if reader.is_interactive() {
+        *shell.history.lock().unwrap() = profile.time("history", || History::configured(&shell));
+    }
+    if invocation.restricted {
+        restrict(&mut shell);
+    }
+    hooks::emit(&mut shell, Event::Startup);
+    let mut command = String::new();
+    loop {
+        let res = crash::catch(|| {
+            command.clear();
+            run_traps(&mut shell);
+            if reader.is_interactive() {
+                hooks::emit(&mut shell, Event::PrePrompt);
+            }
+            reader.sync(&shell);
+            let prompt = profile.time("prompt", || shell.prompt.render(&shell));
+            profile.report();
+            match load_command_line(&mut shell, &mut reader, &prompt) {
+                // The shell's status is that of its last command.
+                Ok(None) if ignore_eof(&mut shell) || !may_exit(&mut shell) => {}
+                Ok(None) => {
+                    let status = shell.vars.status;
+                    exit(&mut shell, status)
+                }
+                Ok(Some((text, list))) => {
+                    shell.ignored_eofs = 0;
+                    command = text;
+                    hooks::emit(&mut shell, Event::PreExec { command: &command });
+                    let start = Instant::now();
+                    let status = exec_list(&mut shell, &list);
+                    let duration = start.elapsed();
+                    hooks::emit(&mut shell, Event::PostExec { command: &command, status, duration });
+                }
+                Err(e) => {
+                    e.report();
+                    shell.vars.status = 2;
+                    // There is no telling where a broken script resumes.
+                    if runs_script {
+                        exit(&mut shell, 2);
+                    }
+                }
+            }
+        });
+        if let Err(crash) = res {
+            recover(&mut shell, &crash, &command);
+        }
+    }
+}
+
+/// Run the commands in the file at `path` in the current shell, as
+/// `source` does. Returns the status of the last command, or 2 after a
+/// syntax error, which ends the file.
+pub fn source(shell: &mut Shell, path: &Path) -> io::Result<i32> {
+    let file = File::open(path)?;
+    let mut reader = LineReader::from_script(Box::new(BufReader::new(file)), &path.to_string_lossy());
+    shell.vars.status = 0;
+    let lines = std::mem::take(&mut shell.lines);
+    // Loops around the `source` command are not the file's to leave.
+    let loops = std::mem::take(&mut shell.loops);
+    shell.sourcing += 1;
+    let status = loop {
+        match load_command_line(shell, &mut reader, "") {
+            Ok(None) => break shell.vars.status,
+            Ok(Some((_, list))) => {
+                exec_list(shell, &list);
+                if shell.flow.take().is_some() {
+                    break shell.vars.status;
+                }
+            }
+            Err(e) => {
+                e.report();
+                shell.vars.status = 2;
+                break 2;
+            }
+        }
+    };
+    shell.sourcing -= 1;
+    shell.loops = loops;
+    shell.lines = lines;
+    Ok(status)
+}
+
+/// Run `~/.rshrc`, if there is one, for an interactive session.
+fn run_rc_file(shell: &mut Shell) {
+    if let Some(home) = home_dir() {
+        source_if_present(shell, &home.join(".rshrc"));
+    }
+}
+
+/// Put the shell in restricted mode. `$PATH`, and the variables naming
+/// what the shell runs, become read-only.
+fn restrict(shell: &mut Shell) {
+    shell.restricted = true;
+    for name in ["PATH", "SHELL", "RSH_RUNNER", "RSH_RUNNER_COMMANDS", "COMMAND_NOT_FOUND"] {
+        shell.vars.set_readonly(name);
+    }
+}
+
+/// Run the profile of a login shell: `/etc/profile`, then the first of
+/// `~/.rsh_profile` and `~/.profile` there is.
+fn run_profile(shell: &mut Shell) {
+    source_if_present(shell, Path::new("/etc/profile"));
+    let Some(home) = home_dir() else {
+        return;
+    };
+    if let Some(path) = [".rsh_profile", ".profile"].iter().map(|name| home.join(name)).find(|path| path.exists()) {
+        source_if_present(shell, &path);
+    }
+}
+
+/// Run the commands in the file at `path`, if there is one, reporting a
+/// file that can't be read.
+fn source_if_present(shell: &mut Shell, path: &Path) {
+    match source(shell, path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("shell: {}: {}", path.display(), e),
+    }
+}
+
+/// Report `crash`, which interrupted `command`, and get the shell back
+/// into a usable state. A report with a backtrace is appended to
+/// `$RSH_CRASH_REPORT` when it is set.
+fn recover(shell: &mut Shell, crash: &Crash, command: &str) {
+    eprintln!("shell: internal error: {}", crash);
+    if let Some(path) = shell.vars.get("RSH_CRASH_REPORT").filter(|path| !path.is_empty()) {
+        match crash.write_report(&path, command) {
+            Ok(()) => eprintln!("shell: crash report written to {}", path),
+            Err(e) => eprintln!("shell: {}: {}", path, e),
+        }
+    }
+    shell.conditions = 0;
+    shell.loops = 0;
+    shell.sourcing = 0;
+    shell.flow = None;
+    shell.history.clear_poison();
+    shell.vars.status = 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_lines_do_nothing() {
+        let mut shell = Shell::new();
+        for line in ["", "   ", "\n\n", "\t\n  \n"] {
+            assert_eq!(shell.run(line), 0, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn lone_operators_are_syntax_errors() {
+        let mut shell = Shell::new();
+        for line in [";", ";;", "|", "&", "&&", "||", ">", ")", "}", "a ;; b", "| a", "a | | b"] {
+            assert_eq!(shell.run(line), 2, "{:?}", line);
+        }
+        // The shell is still usable afterwards.
+        assert_eq!(shell.run("true"), 0);
+    }
+}
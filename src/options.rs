@@ -0,0 +1,220 @@
+use crate::globbing::GlobOptions;
+
+/// Shell options, turned on with `set -o name` and off with `set +o name`.
+#[derive(Clone)]
+pub struct Options {
+    /// Record every command line run, with when, where and by whom, its
+    /// status and how long it took, in `$RSH_AUDIT_LOG` (`set -o audit`).
+    pub audit: bool,
+    /// Run a command line that is just the name of a directory, rather
+    /// than of a command, as `cd` to it (`set -o autocd`).
+    pub autocd: bool,
+    /// Keep the tail of what foreground programs write for `lastout`
+    /// (`set -o capture`). Their output then goes through the shell, so
+    /// they no longer see a terminal.
+    pub capture: bool,
+    /// Offer to correct the name of a command that can't be found, or of
+    /// a directory `cd` can't find, to a close one (`set -o correct`).
+    pub correct: bool,
+    /// Edit command lines with emacs-style key bindings (`set -o emacs`,
+    /// the default).
+    pub emacs: bool,
+    /// Let patterns match names starting with `.` without a literal `.`
+    /// (`set -o dotglob`).
+    pub dotglob: bool,
+    /// Make a pattern that matches no file an error, so that the command
+    /// isn't run (`set -o failglob`).
+    pub failglob: bool,
+    /// Let `**` match files in all subdirectories (`set -o globstar`).
+    pub globstar: bool,
+    /// Hang up running jobs when an interactive shell exits without first
+    /// warning about them (`set -o huponexit`).
+    pub huponexit: bool,
+    /// Keep an interactive shell running when Ctrl-D is pressed on an
+    /// empty line, until `$IGNOREEOF` of them in a row (`set -o
+    /// ignoreeof`).
+    pub ignoreeof: bool,
+    /// Whether `#` starts a comment in interactive input. Scripts always
+    /// allow comments.
+    pub interactive_comments: bool,
+    /// Exit when a command fails (`set -e`).
+    pub errexit: bool,
+    /// Match file names regardless of case (`set -o nocaseglob`).
+    pub nocaseglob: bool,
+    /// Report on background jobs as soon as they finish rather than before
+    /// the next prompt (`set -b`).
+    pub notify: bool,
+    /// Drop a pattern that matches no file from the command, rather than
+    /// passing it on as it is (`set -o nullglob`).
+    pub nullglob: bool,
+    /// Treat expanding an unset variable as an error (`set -u`), which
+    /// ends a script or `-c` command.
+    pub nounset: bool,
+    /// Make a pipeline fail with the status of its last failing stage,
+    /// rather than that of its last stage (`set -o pipefail`).
+    pub pipefail: bool,
+    /// Send foreground output longer than the terminal through `$PAGER`
+    /// (`set -o paging`).
+    pub paging: bool,
+    /// Pick up the lines other sessions add to the history file before
+    /// each prompt (`set -o sharehistory`).
+    pub sharehistory: bool,
+    /// Report the wall time, CPU time and peak memory of each stage of
+    /// foreground pipelines after they finish (`set -o stats`).
+    pub stats: bool,
+    /// Edit command lines with vi-style key bindings, in insert mode
+    /// until Escape switches to command mode (`set -o vi`).
+    pub vi: bool,
+    /// Leave words defined with `keyword` alone, for scripts written for
+    /// other shells (`set -o strict`).
+    pub strict: bool,
+    /// Show `$RSH_TITLE` in the terminal's title bar at each prompt
+    /// (`set -o title`).
+    pub title: bool,
+    /// Under `set -o title`, show each command line in the title bar while
+    /// it runs (`set -o title-command`).
+    pub title_command: bool,
+    /// Show the records that `from-json`, `where` and `select` write at
+    /// the end of a pipeline as a table (`set -o structured`).
+    pub structured: bool,
+    /// Move what `rm` removes to the trash with the `trash` builtin
+    /// instead (`set -o trash`).
+    pub trash: bool,
+    /// Print each command to stderr before running it (`set -x`).
+    pub xtrace: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            audit: false,
+            autocd: false,
+            capture: false,
+            correct: false,
+            dotglob: false,
+            emacs: true,
+            failglob: false,
+            globstar: false,
+            huponexit: false,
+            ignoreeof: false,
+            interactive_comments: true,
+            errexit: false,
+            nocaseglob: false,
+            notify: false,
+            nounset: false,
+            nullglob: false,
+            paging: false,
+            pipefail: false,
+            sharehistory: false,
+            stats: false,
+            strict: false,
+            title: false,
+            title_command: false,
+            structured: false,
+            trash: false,
+            vi: false,
+            xtrace: false,
+        }
+    }
+}
+
+impl Options {
+    /// The options that change how patterns match files.
+    pub fn glob(&self) -> GlobOptions {
+        GlobOptions { dotglob: self.dotglob, globstar: self.globstar, nocaseglob: self.nocaseglob }
+    }
+
+    /// The option called `name` in `set -o`.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "audit" => Some(&mut self.audit),
+            "autocd" => Some(&mut self.autocd),
+            "capture" => Some(&mut self.capture),
+            "correct" => Some(&mut self.correct),
+            "dotglob" => Some(&mut self.dotglob),
+            "emacs" => Some(&mut self.emacs),
+            "errexit" => Some(&mut self.errexit),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "huponexit" => Some(&mut self.huponexit),
+            "ignoreeof" => Some(&mut self.ignoreeof),
+            "interactive-comments" => Some(&mut self.interactive_comments),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "notify" => Some(&mut self.notify),
+            "nounset" => Some(&mut self.nounset),
+            "nullglob" => Some(&mut self.nullglob),
+            "paging" => Some(&mut self.paging),
+            "pipefail" => Some(&mut self.pipefail),
+            "sharehistory" => Some(&mut self.sharehistory),
+            "stats" => Some(&mut self.stats),
+            "strict" => Some(&mut self.strict),
+            "title" => Some(&mut self.title),
+            "title-command" => Some(&mut self.title_command),
+            "structured" => Some(&mut self.structured),
+            "trash" => Some(&mut self.trash),
+            "vi" => Some(&mut self.vi),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    /// Turn the option called `name` on or off, returning false when there
+    /// is none. `emacs` and `vi` choose between key bindings, so turning
+    /// one on turns the other off.
+    pub fn set(&mut self, name: &str, on: bool) -> bool {
+        let Some(option) = self.get_mut(name) else {
+            return false;
+        };
+        *option = on;
+        match name {
+            "emacs" if on => self.vi = false,
+            "vi" if on => self.emacs = false,
+            _ => {}
+        }
+        true
+    }
+
+    /// The name of the option turned on by `set -<flag>`.
+    pub fn flag_name(flag: char) -> Option<&'static str> {
+        match flag {
+            'b' => Some("notify"),
+            'e' => Some("errexit"),
+            'u' => Some("nounset"),
+            'x' => Some("xtrace"),
+            _ => None,
+        }
+    }
+
+    /// Every option with its state, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, bool)> {
+        [
+            ("audit", self.audit),
+            ("autocd", self.autocd),
+            ("capture", self.capture),
+            ("correct", self.correct),
+            ("dotglob", self.dotglob),
+            ("emacs", self.emacs),
+            ("errexit", self.errexit),
+            ("failglob", self.failglob),
+            ("globstar", self.globstar),
+            ("huponexit", self.huponexit),
+            ("ignoreeof", self.ignoreeof),
+            ("interactive-comments", self.interactive_comments),
+            ("nocaseglob", self.nocaseglob),
+            ("notify", self.notify),
+            ("nounset", self.nounset),
+            ("nullglob", self.nullglob),
+            ("paging", self.paging),
+            ("pipefail", self.pipefail),
+            ("sharehistory", self.sharehistory),
+            ("stats", self.stats),
+            ("strict", self.strict),
+            ("title", self.title),
+            ("title-command", self.title_command),
+            ("structured", self.structured),
+            ("trash", self.trash),
+            ("vi", self.vi),
+            ("xtrace", self.xtrace),
+        ].into_iter()
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::fd::AsFd;
+use std::process::{Command, Stdio};
+
+use crate::Shell;
+
+/// Programs that need the terminal for themselves, whose output is never
+/// paged while `$RSH_NOPAGE` is unset.
+const NOPAGE: &str = "less:more:most:man:vi:vim:nvim:nano:emacs:top:htop:ssh:tmux:screen";
+
+/// Whether the output of `name`, run in the foreground outside a pipeline
+/// and without redirecting its stdout, goes through the pager: under `set
+/// -o paging`, when stdout is a terminal and `name` isn't one of the
+/// colon-separated commands of `$RSH_NOPAGE`.
+pub fn applies(shell: &Shell, name: &str) -> bool {
+    let nopage = shell.vars.get("RSH_NOPAGE").unwrap_or_else(|| NOPAGE.to_owned());
+    shell.options.paging && io::stdout().is_terminal() && !nopage.split(':').any(|excluded| excluded == name)
+}
+
+/// Copy `output` to stdout, or to `$PAGER` (`less` by default) once it
+/// turns out longer than the terminal. Stops reading early when the pager
+/// quits, so that the writer sees a broken pipe.
+pub fn show(shell: &Shell, mut output: impl Read) -> io::Result<()> {
+    let rows = crate::pty::window_size(io::stdout().as_fd())
+        .map(|size| size.ws_row as usize)
+        .filter(|&rows| rows > 0)
+        .unwrap_or(24);
+    let mut seen = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match output.read(&mut buf) {
+            Ok(0) => return io::stdout().write_all(&seen),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        seen.extend_from_slice(&buf[..n]);
+        // One row is kept for the prompt that follows.
+        if seen.iter().filter(|&&b| b == b'\n').count() >= rows.saturating_sub(1) {
+            break;
+        }
+    }
+    let pager = shell.vars.get("PAGER")
+        .filter(|pager| !pager.trim().is_empty())
+        .unwrap_or_else(|| String::from("less"));
+    let mut words = pager.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("less"));
+    command.args(words).stdin(Stdio::piped());
+    if shell.vars.get("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            eprintln!("shell: {}: {}", pager, e);
+            io::stdout().write_all(&seen)?;
+            io::copy(&mut output, &mut io::stdout())?;
+            return Ok(());
+        }
+    };
+    let mut stdin = child.stdin.take().unwrap();
+    let copied = stdin.write_all(&seen).and_then(|_| io::copy(&mut output, &mut stdin).map(drop));
+    drop(stdin);
+    child.wait()?;
+    match copied {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        res => res,
+    }
+}
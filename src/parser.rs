@@ -0,0 +1,729 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::aliases::{Aliases, Keywords};
+use crate::lexer::{expand_keywords, tokenize, Token};
+use crate::variables::is_name;
+
+/// Why a command line could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// The input ended inside a construct (open quote, `if` without `fi`,
+    /// trailing `|`, ...); reading more lines may complete it.
+    Incomplete,
+    /// The input can never become valid. `line` counts from 1 for the
+    /// first line of the input.
+    Syntax { line: usize, message: String },
+}
+
+impl ParseError {
+    fn unexpected(token: &Token) -> Self {
+        let text = match token {
+            Token::Newline => "newline",
+            Token::Word(word) => word,
+            Token::Op(op) => op,
+        };
+        ParseError::Syntax { line: 1, message: format!("syntax error near unexpected token '{}'", text) }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectOp {
+    /// `<`
+    Read,
+    /// `>`
+    Write,
+    /// `>>`
+    Append,
+    /// `>|`, which overwrites a file even when `>` would refuse to.
+    Clobber,
+    /// `&>`, which sends both stdout and stderr to the file.
+    WriteBoth,
+    /// `&>>`, which appends both stdout and stderr to the file.
+    AppendBoth,
+    /// `<<` / `<<-`; the target holds the body.
+    HereDoc,
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// The file descriptor being redirected.
+    pub fd: u32,
+    pub op: RedirectOp,
+    /// The file name as typed, or the here-document body.
+    pub target: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimpleCommand {
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+    /// The line it starts on, counting from 1 for the first line of the
+    /// input.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct IfClause {
+    /// `if`/`elif` conditions, each with the body run when it succeeds.
+    pub branches: Vec<(List, List)>,
+    pub else_body: Option<List>,
+}
+
+/// A `for` loop, or a `select` loop, which has the same parts.
+#[derive(Debug, Clone)]
+pub struct ForClause {
+    pub var: String,
+    /// The words after `in`, unexpanded; `None` when there is no `in`.
+    pub words: Option<Vec<String>>,
+    pub body: List,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseClause {
+    pub word: String,
+    /// Each arm's `|`-separated patterns and the list run when one matches.
+    pub arms: Vec<(Vec<String>, List)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CompoundCommand {
+    If(IfClause),
+    For(ForClause),
+    /// `select name in word...; do list; done`, which runs `list` with
+    /// `name` set to each word chosen from a numbered menu.
+    Select(ForClause),
+    Case(CaseClause),
+    /// `( list )`, run in a child copy of the shell.
+    Subshell(List),
+    /// `{ list; }`, run in the current shell.
+    Group(List),
+    /// `(( expression ))`, which succeeds when the arithmetic expression
+    /// works out to anything but 0.
+    Arith(String),
+    /// `[[ expression ]]`: its words and operators as typed, with the
+    /// regular expression after a `=~` joined into one word.
+    Conditional(Vec<String>),
+}
+
+impl CompoundCommand {
+    /// The word or operator the command opens with.
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            CompoundCommand::If(_) => "if",
+            CompoundCommand::For(_) => "for",
+            CompoundCommand::Select(_) => "select",
+            CompoundCommand::Case(_) => "case",
+            CompoundCommand::Subshell(_) => "(",
+            CompoundCommand::Group(_) => "{",
+            CompoundCommand::Arith(_) => "((",
+            CompoundCommand::Conditional(_) => "[[",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// A compound command with the redirections following it, which apply
+    /// to everything it runs.
+    Compound(CompoundCommand, Vec<Redirect>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+    /// Whether the pipeline was prefixed with `!`.
+    pub negated: bool,
+    /// Whether the pipeline was prefixed with `time`.
+    pub timed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    And,
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`.
+#[derive(Debug, Clone)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub and_or: AndOr,
+    /// Whether the item was terminated by `&`.
+    pub background: bool,
+}
+
+/// And-or lists separated by `;`, `&` or newlines. A `;;` ends the list of
+/// a `case` arm and `)` that of a subshell.
+pub type List = Vec<ListItem>;
+
+/// Words that start or end compound commands when in command position.
+pub const RESERVED: &[&str] = &[
+    "if", "then", "elif", "else", "fi", "for", "do", "done", "case", "esac", "{", "}", "!",
+    "time", "[[", "]]",
+];
+
+struct Parser<'a> {
+    /// Tokens, each with the aliases that must not be expanded again in it
+    /// and the line it is on.
+    tokens: Vec<(Token, Rc<Vec<String>>, usize)>,
+    pos: usize,
+    /// The furthest token looked at, which is where a syntax error is.
+    seen: Cell<usize>,
+    heredocs: std::slice::Iter<'a, String>,
+    aliases: &'a Aliases,
+    keywords: &'a Keywords,
+    comments: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.seen.set(self.seen.get().max(self.pos));
+        self.tokens.get(self.pos).map(|(token, _, _)| token)
+    }
+
+    /// The line the next token is on.
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or(self.tokens.last()).map_or(1, |&(_, _, line)| line)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.seen.set(self.seen.get().max(self.pos));
+        let token = self.tokens.get(self.pos).map(|(token, _, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    /// `error` placed on the line of the token it is about.
+    fn locate(&self, error: ParseError) -> ParseError {
+        match error {
+            ParseError::Syntax { message, .. } => {
+                let line = self.tokens.get(self.seen.get()).or(self.tokens.last()).map_or(1, |&(_, _, line)| line);
+                ParseError::Syntax { line, message }
+            }
+            error => error,
+        }
+    }
+
+    /// Whether the next token is the unquoted reserved word `word`.
+    fn at_reserved(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w == word)
+    }
+
+    fn expect_reserved(&mut self, word: &str) -> Result<(), ParseError> {
+        match self.next() {
+            Some(Token::Word(w)) if w == word => Ok(()),
+            Some(token) => Err(ParseError::unexpected(&token)),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    /// Replace an alias in command position by its value. Tokens coming
+    /// from an alias remember it so that recursive expansion terminates.
+    fn expand_alias(&mut self) -> Result<(), ParseError> {
+        loop {
+            let (word, disabled, line) = match self.tokens.get(self.pos) {
+                Some((Token::Word(word), disabled, line)) => (word.clone(), disabled.clone(), *line),
+                _ => return Ok(()),
+            };
+            let value = match self.aliases.get(&word) {
+                Some(value) if !disabled.contains(&word) => value,
+                _ => return Ok(()),
+            };
+            let mut chain = (*disabled).clone();
+            chain.push(word);
+            let chain = Rc::new(chain);
+            let expansion = expand_keywords(tokenize(value, self.comments)?, self.keywords)
+                .into_iter()
+                .map(|(token, _)| (token, chain.clone(), line));
+            self.tokens.splice(self.pos..self.pos + 1, expansion);
+        }
+    }
+
+    /// Parse a list up to the end of input or one of `terminators`.
+    fn list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let mut list = List::new();
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None | Some(Token::Op(";;" | ")")) => return Ok(list),
+                Some(Token::Word(w)) if terminators.contains(&w.as_str()) => return Ok(list),
+                _ => {}
+            }
+            let and_or = self.and_or()?;
+            let background = match self.peek() {
+                Some(Token::Op(";")) => {
+                    self.pos += 1;
+                    false
+                }
+                Some(Token::Op("&")) => {
+                    self.pos += 1;
+                    true
+                }
+                Some(Token::Newline) | Some(Token::Op(";;" | ")")) | None => false,
+                Some(Token::Word(w)) if terminators.contains(&w.as_str()) => false,
+                Some(token) => return Err(ParseError::unexpected(token)),
+            };
+            list.push(ListItem { and_or, background });
+        }
+    }
+
+    /// Parse a non-empty list terminated by one of `terminators`.
+    fn compound_list(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let list = self.list(terminators)?;
+        match self.peek() {
+            None => Err(ParseError::Incomplete),
+            Some(token) if list.is_empty() => Err(ParseError::unexpected(token)),
+            Some(_) => Ok(list),
+        }
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = match self.peek() {
+                Some(Token::Op("&&")) => Connector::And,
+                Some(Token::Op("||")) => Connector::Or,
+                _ => return Ok(AndOr { first, rest }),
+            };
+            self.pos += 1;
+            self.skip_newlines();
+            rest.push((connector, self.pipeline()?));
+        }
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let timed = self.at_reserved("time");
+        if timed {
+            self.pos += 1;
+        }
+        let negated = self.at_reserved("!");
+        if negated {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.command()?];
+        while self.peek() == Some(&Token::Op("|")) {
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { commands, negated, timed })
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        self.expand_alias()?;
+        let compound = match self.peek() {
+            None => return Err(ParseError::Incomplete),
+            Some(Token::Word(w)) if w == "if" => self.if_clause()?,
+            Some(Token::Word(w)) if w == "for" => CompoundCommand::For(self.for_clause("for")?),
+            Some(Token::Word(w)) if w == "select" && self.at_select_loop() => {
+                CompoundCommand::Select(self.for_clause("select")?)
+            }
+            Some(Token::Word(w)) if w == "case" => self.case_clause()?,
+            Some(Token::Word(w)) if w == "{" => self.group()?,
+            Some(Token::Word(w)) if w == "[[" => self.conditional()?,
+            Some(Token::Op("(")) => self.subshell()?,
+            Some(Token::Word(w)) if w.len() >= 4 && w.starts_with("((") && w.ends_with("))") => {
+                let expr = w[2..w.len() - 2].to_owned();
+                self.pos += 1;
+                CompoundCommand::Arith(expr)
+            }
+            Some(Token::Word(w)) if RESERVED.contains(&w.as_str()) => {
+                return Err(ParseError::unexpected(&Token::Word(w.clone())));
+            }
+            _ => return self.simple_command(),
+        };
+        let mut redirects = Vec::new();
+        while let Some(Token::Op(op)) = self.peek() {
+            if !(op.contains('<') || op.contains('>')) {
+                break;
+            }
+            let op = *op;
+            self.pos += 1;
+            redirects.push(self.redirect(op)?);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    fn if_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("if")?;
+        let mut branches = Vec::new();
+        let mut else_body = None;
+        loop {
+            let condition = self.compound_list(&["then"])?;
+            self.expect_reserved("then")?;
+            let body = self.compound_list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+            match self.next() {
+                Some(Token::Word(w)) if w == "elif" => continue,
+                Some(Token::Word(w)) if w == "else" => {
+                    else_body = Some(self.compound_list(&["fi"])?);
+                    self.expect_reserved("fi")?;
+                    break;
+                }
+                _ => break,
+            }
+        }
+        Ok(CompoundCommand::If(IfClause { branches, else_body }))
+    }
+
+    /// Whether the `select` here starts a `select name in ...` or `select
+    /// name; do` loop, rather than running the `select` builtin.
+    fn at_select_loop(&self) -> bool {
+        let mut tokens = self.tokens[self.pos + 1..].iter().map(|(token, _, _)| token);
+        match (tokens.next(), tokens.next()) {
+            (Some(Token::Word(name)), Some(Token::Word(w))) => is_name(name) && w == "in",
+            (Some(Token::Word(name)), Some(Token::Op(";") | Token::Newline)) => {
+                is_name(name) && matches!(tokens.find(|&token| *token != Token::Newline), Some(Token::Word(w)) if w == "do")
+            }
+            _ => false,
+        }
+    }
+
+    /// A `for` or `select` loop, as `keyword` says.
+    fn for_clause(&mut self, keyword: &str) -> Result<ForClause, ParseError> {
+        self.expect_reserved(keyword)?;
+        let var = match self.next() {
+            Some(Token::Word(w)) if is_name(&w) => w,
+            Some(token) => return Err(ParseError::unexpected(&token)),
+            None => return Err(ParseError::Incomplete),
+        };
+        self.skip_newlines();
+        let mut words = None;
+        if self.at_reserved("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(Token::Word(w)) = self.peek() {
+                list.push(w.clone());
+                self.pos += 1;
+            }
+            match self.next() {
+                Some(Token::Op(";")) | Some(Token::Newline) => {}
+                Some(token) => return Err(ParseError::unexpected(&token)),
+                None => return Err(ParseError::Incomplete),
+            }
+            words = Some(list);
+        } else if self.peek() == Some(&Token::Op(";")) {
+            self.pos += 1;
+        }
+        self.skip_newlines();
+        self.expect_reserved("do")?;
+        let body = self.compound_list(&["done"])?;
+        self.expect_reserved("done")?;
+        Ok(ForClause { var, words, body })
+    }
+
+    fn case_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("case")?;
+        let word = self.word()?;
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.at_reserved("esac") {
+                self.pos += 1;
+                break;
+            }
+            if self.peek() == Some(&Token::Op("(")) {
+                self.pos += 1;
+            }
+            let mut patterns = vec![self.word()?];
+            loop {
+                match self.next() {
+                    Some(Token::Op("|")) => patterns.push(self.word()?),
+                    Some(Token::Op(")")) => break,
+                    Some(token) => return Err(ParseError::unexpected(&token)),
+                    None => return Err(ParseError::Incomplete),
+                }
+            }
+            let body = self.list(&["esac"])?;
+            arms.push((patterns, body));
+            match self.next() {
+                Some(Token::Op(";;")) => continue,
+                Some(Token::Word(w)) if w == "esac" => break,
+                Some(token) => return Err(ParseError::unexpected(&token)),
+                None => return Err(ParseError::Incomplete),
+            }
+        }
+        Ok(CompoundCommand::Case(CaseClause { word, arms }))
+    }
+
+    fn subshell(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += 1;
+        let body = self.list(&[])?;
+        match self.next() {
+            Some(Token::Op(")")) if !body.is_empty() => Ok(CompoundCommand::Subshell(body)),
+            Some(token) => Err(ParseError::unexpected(&token)),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+
+    fn conditional(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("[[")?;
+        let mut words: Vec<String> = Vec::new();
+        loop {
+            match self.next() {
+                Some(Token::Word(w)) if w == "]]" && !words.is_empty() => break,
+                Some(Token::Word(w)) => words.push(w),
+                Some(Token::Op(op)) => words.push(op.to_owned()),
+                Some(Token::Newline) => continue,
+                None => return Err(ParseError::Incomplete),
+            }
+            if words.last().is_some_and(|w| w == "=~") {
+                words.push(self.regex()?);
+            }
+        }
+        Ok(CompoundCommand::Conditional(words))
+    }
+
+    /// The regular expression after a `=~`, in which `(`, `)` and `|` are
+    /// part of the pattern rather than operators. It runs up to the `]]`,
+    /// `&&` or `||` that ends the test, or an unmatched `)`.
+    fn regex(&mut self) -> Result<String, ParseError> {
+        let mut regex = String::new();
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                Some(Token::Word(w)) if w == "]]" => break,
+                Some(Token::Op("&&" | "||")) | Some(Token::Newline) => break,
+                Some(Token::Op(")")) if depth == 0 => break,
+                Some(Token::Word(w)) => regex.push_str(w),
+                Some(Token::Op(op)) => {
+                    match *op {
+                        "(" => depth += 1,
+                        ")" => depth -= 1,
+                        _ => {}
+                    }
+                    regex.push_str(op);
+                }
+                None => return Err(ParseError::Incomplete),
+            }
+            self.pos += 1;
+        }
+        match regex.is_empty() {
+            true => Err(self.next().map_or(ParseError::Incomplete, |token| ParseError::unexpected(&token))),
+            false => Ok(regex),
+        }
+    }
+
+    fn group(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved("{")?;
+        let body = self.compound_list(&["}"])?;
+        self.expect_reserved("}")?;
+        Ok(CompoundCommand::Group(body))
+    }
+
+    /// Take the next token, which must be a word.
+    fn word(&mut self) -> Result<String, ParseError> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            Some(token) => Err(ParseError::unexpected(&token)),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+
+    fn simple_command(&mut self) -> Result<Command, ParseError> {
+        let mut command = SimpleCommand { line: self.line(), ..SimpleCommand::default() };
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    command.words.push(word.clone());
+                    self.pos += 1;
+                }
+                Some(Token::Op(op)) if op.contains('<') || op.contains('>') => {
+                    let op = *op;
+                    self.pos += 1;
+                    command.redirects.push(self.redirect(op)?);
+                }
+                Some(token) => {
+                    if command.words.is_empty() && command.redirects.is_empty() {
+                        return Err(ParseError::unexpected(token));
+                    }
+                    break;
+                }
+                None => {
+                    if command.words.is_empty() && command.redirects.is_empty() {
+                        return Err(ParseError::Incomplete);
+                    }
+                    break;
+                }
+            }
+        }
+        Ok(Command::Simple(command))
+    }
+
+    fn redirect(&mut self, op: &str) -> Result<Redirect, ParseError> {
+        let target = self.word()?;
+        let (fd, op) = match op {
+            "<" => (0, RedirectOp::Read),
+            ">" => (1, RedirectOp::Write),
+            ">>" => (1, RedirectOp::Append),
+            ">|" => (1, RedirectOp::Clobber),
+            "2>" => (2, RedirectOp::Write),
+            "2>>" => (2, RedirectOp::Append),
+            "2>|" => (2, RedirectOp::Clobber),
+            "&>" => (1, RedirectOp::WriteBoth),
+            "&>>" => (1, RedirectOp::AppendBoth),
+            _ => (0, RedirectOp::HereDoc),
+        };
+        if op == RedirectOp::HereDoc {
+            let body = self.heredocs.next().cloned().unwrap_or_default();
+            return Ok(Redirect { fd, op, target: body });
+        }
+        Ok(Redirect { fd, op, target })
+    }
+}
+
+/// Parse a complete command line. `heredocs` are the bodies of its
+/// here-documents in order, `aliases` are expanded in command position,
+/// `keywords` wherever they are words and `comments` tells whether `#`
+/// starts a comment.
+pub fn parse(text: &str, heredocs: &[String], aliases: &Aliases, keywords: &Keywords, comments: bool)
+-> Result<List, ParseError> {
+    let tokens = expand_keywords(tokenize(text, comments)?, keywords);
+    let mut parser = Parser {
+        tokens: tokens.into_iter().map(|(token, line)| (token, Rc::default(), line)).collect(),
+        pos: 0,
+        seen: Cell::new(0),
+        heredocs: heredocs.iter(),
+        aliases,
+        keywords,
+        comments,
+    };
+    let list = parser.list(&[]).map_err(|e| parser.locate(e))?;
+    match parser.peek() {
+        None => Ok(list),
+        Some(token) => Err(parser.locate(ParseError::unexpected(token))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_ok(text: &str) -> List {
+        parse(text, &[], &Aliases::new(), &Keywords::new(), true).unwrap()
+    }
+
+    fn parse_err(text: &str) -> ParseError {
+        parse(text, &[], &Aliases::new(), &Keywords::new(), true).unwrap_err()
+    }
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(simple) => simple,
+            Command::Compound(..) => panic!("not a simple command: {:?}", command),
+        }
+    }
+
+    fn words(pipeline: &Pipeline) -> Vec<Vec<&str>> {
+        pipeline.commands.iter().map(|command| simple(command).words.iter().map(String::as_str).collect()).collect()
+    }
+
+    #[test]
+    fn lists() {
+        let list = parse_ok("a; b & c\nd");
+        let items: Vec<_> = list.iter().map(|item| (words(&item.and_or.first), item.background)).collect();
+        assert_eq!(items, [
+            (vec![vec!["a"]], false),
+            (vec![vec!["b"]], true),
+            (vec![vec!["c"]], false),
+            (vec![vec!["d"]], false),
+        ]);
+        assert!(parse_ok("").is_empty());
+    }
+
+    #[test]
+    fn and_or_lists() {
+        let list = parse_ok("a && b || c");
+        let and_or = &list[0].and_or;
+        assert_eq!(words(&and_or.first), [["a"]]);
+        let rest: Vec<_> = and_or.rest.iter().map(|(connector, pipeline)| (*connector, words(pipeline))).collect();
+        assert_eq!(rest, [(Connector::And, vec![vec!["b"]]), (Connector::Or, vec![vec!["c"]])]);
+    }
+
+    #[test]
+    fn pipelines() {
+        let list = parse_ok("! ls -l | grep x | wc -l");
+        let pipeline = &list[0].and_or.first;
+        assert!(pipeline.negated);
+        assert!(!pipeline.timed);
+        assert_eq!(words(pipeline), [vec!["ls", "-l"], vec!["grep", "x"], vec!["wc", "-l"]]);
+        assert!(parse_ok("time sleep 1 | cat")[0].and_or.first.timed);
+    }
+
+    #[test]
+    fn redirections() {
+        let list = parse_ok("cat < in > out 2>> err >| forced &> both &>> all");
+        let command = simple(&list[0].and_or.first.commands[0]);
+        assert_eq!(command.words, ["cat"]);
+        let redirects: Vec<_> = command.redirects.iter().map(|r| (r.fd, r.op, r.target.as_str())).collect();
+        assert_eq!(redirects, [
+            (0, RedirectOp::Read, "in"),
+            (1, RedirectOp::Write, "out"),
+            (2, RedirectOp::Append, "err"),
+            (1, RedirectOp::Clobber, "forced"),
+            (1, RedirectOp::WriteBoth, "both"),
+            (1, RedirectOp::AppendBoth, "all"),
+        ]);
+        assert_eq!(parse_err("cat > ;"), ParseError::Syntax {
+            line: 1,
+            message: String::from("syntax error near unexpected token ';'"),
+        });
+    }
+
+    #[test]
+    fn groups_and_subshells() {
+        let list = parse_ok("{ a; b; } > f");
+        let Command::Compound(CompoundCommand::Group(body), redirects) = &list[0].and_or.first.commands[0] else {
+            panic!("not a group: {:?}", list);
+        };
+        assert_eq!(body.len(), 2);
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(redirects[0].target, "f");
+        let list = parse_ok("(cd /; ls) | cat");
+        let commands = &list[0].and_or.first.commands;
+        assert!(matches!(&commands[0], Command::Compound(CompoundCommand::Subshell(body), _) if body.len() == 2));
+        assert_eq!(simple(&commands[1]).words, ["cat"]);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for text in ["a |", "a &&", "if true", "for x in a b; do", "{ a;", "(a", "echo 'open"] {
+            assert_eq!(parse_err(text), ParseError::Incomplete, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn syntax_errors_name_their_line() {
+        let error = |line: usize, token: &str| ParseError::Syntax {
+            line,
+            message: format!("syntax error near unexpected token '{}'", token),
+        };
+        assert_eq!(parse_err("echo a\nfi"), error(2, "fi"));
+        assert_eq!(parse_err("echo a\necho b\n)"), error(3, ")"));
+        assert_eq!(parse_err("echo a\n\n  ;;"), error(3, ";;"));
+        assert_eq!(parse_err("| a"), error(1, "|"));
+    }
+
+    #[test]
+    fn commands_know_their_line() {
+        let list = parse_ok("a\n\nb; c\nd");
+        let lines: Vec<usize> = list.iter().map(|item| simple(&item.and_or.first.commands[0]).line).collect();
+        assert_eq!(lines, [1, 3, 3, 4]);
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::builtins::Builtin;
+use crate::completion::CompletionProvider;
+use crate::prompt::PromptSegment;
+use crate::Shell;
+
+/// A bundle of additions to the shell from outside it. A program that
+/// embeds the shell passes its plugins to `ShellBuilder::with_plugin`, or
+/// to `main_with_plugins` to run the shell as the `rust_shell` binary
+/// does; each can then be turned off and on with the `plugin` builtin.
+pub trait Plugin {
+    /// The name the `plugin` builtin knows the plugin by.
+    fn name(&self) -> &str;
+
+    /// Builtins to add, replacing any of the shell's by the same name.
+    fn builtins(&self) -> Vec<(&'static str, Builtin)> {
+        Vec::new()
+    }
+
+    /// Segments to make available to `$PROMPT_SEGMENTS`.
+    fn prompt_segments(&self) -> Vec<Box<dyn PromptSegment>> {
+        Vec::new()
+    }
+
+    /// Sources of completions, tried before the shell's own.
+    fn completion_providers(&self) -> Vec<Box<dyn CompletionProvider>> {
+        Vec::new()
+    }
+}
+
+/// Where a plugin comes from.
+enum Source {
+    Rust(Rc<dyn Plugin>),
+    /// A script in the plugins directory, sourced to enable it.
+    Script(PathBuf),
+}
+
+struct Loaded {
+    name: String,
+    source: Source,
+    enabled: bool,
+}
+
+/// The plugins of the shell, enabled or not.
+#[derive(Default)]
+pub struct Plugins {
+    loaded: Vec<Loaded>,
+    /// Counts the changes to what the enabled plugins provide, so that the
+    /// line editor knows when to ask for their completion providers again.
+    generation: usize,
+}
+
+impl Plugins {
+    /// Each plugin's name, kind (`builtin` or `script`) and whether it is
+    /// enabled.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &'static str, bool)> {
+        self.loaded.iter().map(|plugin| {
+            let kind = match plugin.source {
+                Source::Rust(_) => "builtin",
+                Source::Script(_) => "script",
+            };
+            (plugin.name.as_str(), kind, plugin.enabled)
+        })
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// The completion providers of the enabled plugins.
+    pub fn completion_providers(&self) -> Vec<Box<dyn CompletionProvider>> {
+        self.loaded.iter()
+            .filter(|plugin| plugin.enabled)
+            .filter_map(|plugin| match &plugin.source {
+                Source::Rust(plugin) => Some(plugin.completion_providers()),
+                Source::Script(_) => None,
+            })
+            .flatten()
+            .collect()
+    }
+}
+
+/// Add `plugin` to `shell`, enabled.
+pub fn add(shell: &mut Shell, plugin: Box<dyn Plugin>) {
+    let plugin: Rc<dyn Plugin> = Rc::from(plugin);
+    let name = plugin.name().to_owned();
+    shell.plugins.loaded.retain(|loaded| loaded.name != name);
+    shell.plugins.loaded.push(Loaded { name, source: Source::Rust(plugin.clone()), enabled: false });
+    let _ = enable(shell, plugin.name(), true);
+}
+
+/// The directory script plugins are found in:
+/// `$XDG_CONFIG_HOME/rsh/plugins`, or `~/.config/rsh/plugins`. Each is a
+/// `name.rsh` file, or a `name` directory with an `init.rsh` in it.
+pub fn dir(shell: &Shell) -> Option<PathBuf> {
+    crate::config_home(shell).map(|config| config.join("rsh/plugins"))
+}
+
+/// The file in the plugins directory naming the disabled plugins, one a
+/// line.
+fn disabled_path(shell: &Shell) -> Option<PathBuf> {
+    dir(shell).map(|dir| dir.join("disabled"))
+}
+
+fn disabled(shell: &Shell) -> Vec<String> {
+    disabled_path(shell)
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|text| text.lines().map(str::to_owned).filter(|name| !name.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Find the script plugins and source those enabled, and disable the
+/// plugins of the program that were disabled in an earlier session.
+pub fn load(shell: &mut Shell) {
+    let disabled = disabled(shell);
+    let names: Vec<String> = shell.plugins.loaded.iter().map(|plugin| plugin.name.clone()).collect();
+    for name in names.iter().filter(|name| disabled.contains(name)) {
+        let _ = enable(shell, name, false);
+    }
+    let Some(dir) = dir(shell) else {
+        return;
+    };
+    let mut scripts: Vec<(String, PathBuf)> = fs::read_dir(&dir).into_iter().flatten().flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().into_string().ok()?;
+            match name.strip_suffix(".rsh") {
+                Some(name) if path.is_file() => Some((name.to_owned(), path)),
+                None if path.join("init.rsh").is_file() => Some((name, path.join("init.rsh"))),
+                _ => None,
+            }
+        })
+        .collect();
+    scripts.sort();
+    for (name, path) in scripts {
+        if shell.plugins.loaded.iter().any(|plugin| plugin.name == name) {
+            continue;
+        }
+        shell.plugins.loaded.push(Loaded { name: name.clone(), source: Source::Script(path), enabled: false });
+        if !disabled.contains(&name) {
+            if let Err(e) = enable(shell, &name, true) {
+                eprintln!("shell: plugin: {}", e);
+            }
+        }
+    }
+}
+
+/// Turn the plugin `name` on or off. A script plugin is sourced when it is
+/// enabled, but what it did can't be undone: disabling it only keeps later
+/// sessions from sourcing it.
+pub fn enable(shell: &mut Shell, name: &str, on: bool) -> Result<(), String> {
+    let plugin = shell.plugins.loaded.iter_mut()
+        .find(|plugin| plugin.name == name)
+        .ok_or_else(|| format!("{}: no such plugin", name))?;
+    if plugin.enabled == on {
+        return Ok(());
+    }
+    plugin.enabled = on;
+    match &plugin.source {
+        Source::Rust(plugin) => {
+            let plugin = plugin.clone();
+            for (builtin, f) in plugin.builtins() {
+                match on {
+                    true => shell.builtins.insert(builtin.to_owned(), f),
+                    false => shell.builtins.remove(builtin),
+                };
+            }
+            for segment in plugin.prompt_segments() {
+                match on {
+                    true => shell.prompt.register(segment),
+                    false => shell.prompt.unregister(segment.id()),
+                }
+            }
+            shell.plugins.generation += 1;
+        }
+        Source::Script(path) if on => {
+            let path = path.clone();
+            crate::source(shell, &path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        Source::Script(_) => {}
+    }
+    Ok(())
+}
+
+/// Remember in the plugins directory which plugins are disabled, for later
+/// sessions.
+pub fn save(shell: &Shell) -> io::Result<()> {
+    let path = disabled_path(shell).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME not set"))?;
+    let mut names = disabled(shell);
+    for (name, _, enabled) in shell.plugins.iter() {
+        names.retain(|disabled| disabled != name);
+        if !enabled {
+            names.push(name.to_owned());
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, names.iter().map(|name| format!("{}\n", name)).collect::<String>())
+}
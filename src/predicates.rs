@@ -0,0 +1,166 @@
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+
+use nix::unistd::{access, AccessFlags};
+
+/// The expressions of `test` and `[`, evaluated by recursive descent:
+///
+/// ```text
+/// or      := and ("-o" and)*
+/// and     := not ("-a" not)*
+/// not     := "!" not | primary
+/// primary := "(" or ")" | unary arg | arg binary arg | arg
+/// ```
+struct Parser<'a> {
+    args: &'a [String],
+    pos: usize,
+}
+
+pub const UNARY: &[&str] = &[
+    "-b", "-c", "-d", "-e", "-f", "-g", "-G", "-h", "-k", "-L", "-n", "-O", "-p", "-r", "-s", "-S", "-t", "-u",
+    "-w", "-x", "-z",
+];
+
+pub const BINARY: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let arg = self.peek(0).ok_or("argument expected")?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut res = self.and()?;
+        while self.peek(0) == Some("-o") && self.peek(1).is_some() {
+            self.pos += 1;
+            res |= self.and()?;
+        }
+        Ok(res)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut res = self.not()?;
+        while self.peek(0) == Some("-a") && self.peek(1).is_some() {
+            self.pos += 1;
+            res &= self.not()?;
+        }
+        Ok(res)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // A `!` followed by nothing, or by a binary operator, is a string.
+        let binary = self.peek(1).is_some_and(|op| BINARY.contains(&op)) && self.peek(2).is_some();
+        if self.peek(0) == Some("!") && self.peek(1).is_some() && !binary {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let first = self.next()?;
+        if let (Some(op), Some(_)) = (self.peek(0), self.peek(1)) {
+            if BINARY.contains(&op) {
+                self.pos += 1;
+                let second = self.next()?;
+                return binary(first, op, second);
+            }
+        }
+        if first == "(" && self.peek(0).is_some() {
+            let res = self.or()?;
+            return match self.next() {
+                Ok(")") => Ok(res),
+                Ok(arg) => Err(format!("`{}': expected `)'", arg)),
+                Err(_) => Err(String::from("missing `)'")),
+            };
+        }
+        if UNARY.contains(&first) {
+            if let Ok(arg) = self.next() {
+                return unary(first, arg);
+            }
+        }
+        Ok(!first.is_empty())
+    }
+}
+
+pub fn integer(arg: &str) -> Result<i64, String> {
+    arg.trim().parse().map_err(|_| format!("{}: integer expression expected", arg))
+}
+
+pub fn unary(op: &str, arg: &str) -> Result<bool, String> {
+    let meta = || fs::metadata(arg).ok();
+    let has_mode = |bit: u32| meta().is_some_and(|meta| meta.permissions().mode() & bit != 0);
+    let may = |flags: AccessFlags| access(arg, flags).is_ok();
+    let file_type = |check: fn(&Metadata) -> bool| meta().is_some_and(|meta| check(&meta));
+    Ok(match op {
+        "-z" => arg.is_empty(),
+        "-n" => !arg.is_empty(),
+        "-e" => meta().is_some(),
+        "-f" => file_type(|meta| meta.is_file()),
+        "-d" => file_type(|meta| meta.is_dir()),
+        "-b" => file_type(|meta| meta.file_type().is_block_device()),
+        "-c" => file_type(|meta| meta.file_type().is_char_device()),
+        "-p" => file_type(|meta| meta.file_type().is_fifo()),
+        "-S" => file_type(|meta| meta.file_type().is_socket()),
+        "-h" | "-L" => fs::symlink_metadata(arg).is_ok_and(|meta| meta.file_type().is_symlink()),
+        "-s" => meta().is_some_and(|meta| meta.len() > 0),
+        "-r" => may(AccessFlags::R_OK),
+        "-w" => may(AccessFlags::W_OK),
+        "-x" => may(AccessFlags::X_OK),
+        "-u" => has_mode(0o4000),
+        "-g" => has_mode(0o2000),
+        "-k" => has_mode(0o1000),
+        "-O" => meta().is_some_and(|meta| meta.uid() == users::get_effective_uid()),
+        "-G" => meta().is_some_and(|meta| meta.gid() == users::get_effective_gid()),
+        "-t" => {
+            let fd = i32::try_from(integer(arg)?).map_err(|_| format!("{}: bad file descriptor", arg))?;
+            unsafe { libc::isatty(fd) == 1 }
+        }
+        _ => unreachable!(),
+    })
+}
+
+pub fn binary(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    Ok(match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" => integer(left)? == integer(right)?,
+        "-ne" => integer(left)? != integer(right)?,
+        "-lt" => integer(left)? < integer(right)?,
+        "-le" => integer(left)? <= integer(right)?,
+        "-gt" => integer(left)? > integer(right)?,
+        "-ge" => integer(left)? >= integer(right)?,
+        // A file that doesn't exist is older than one that does.
+        "-nt" => modified(left) > modified(right),
+        "-ot" => modified(left) < modified(right),
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        },
+        _ => unreachable!(),
+    })
+}
+
+/// Evaluate the expression `args` of `test`. An error means the
+/// expression is malformed or compares something that isn't a number.
+pub fn evaluate(args: &[String]) -> Result<bool, String> {
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let mut parser = Parser { args, pos: 0 };
+    let res = parser.or()?;
+    match parser.peek(0) {
+        Some(arg) => Err(format!("{}: unexpected argument", arg)),
+        None => Ok(res),
+    }
+}
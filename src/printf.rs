@@ -0,0 +1,312 @@
+/// Replace the backslash escapes of `printf` formats and `%b` arguments in
+/// `text`: `\n`, `\t`, `\\` and the other C escapes, `\e`, `\0nnn` or
+/// `\nnn` octal and `\xHH` hex. Returns whether a `\c` cut the output
+/// short.
+fn unescape(text: &str, out: &mut Vec<u8>) -> bool {
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            let mut buf = [0; 4];
+            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            out.push(b'\\');
+            break;
+        };
+        let byte = match escape {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            'e' => 0x1b,
+            '\\' => b'\\',
+            'c' => return true,
+            'x' => {
+                let mut value = 0u32;
+                let mut digits = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(16)).filter(|_| digits < 2) {
+                    value = value * 16 + digit;
+                    digits += 1;
+                    chars.next();
+                }
+                if digits == 0 {
+                    out.extend_from_slice(b"\\x");
+                    continue;
+                }
+                value as u8
+            }
+            '0'..='7' => {
+                // `\0nnn` takes up to three digits after the zero.
+                let max = if escape == '0' { 3 } else { 2 };
+                let mut value = escape.to_digit(8).unwrap();
+                let mut digits = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(8)).filter(|_| digits < max) {
+                    value = value * 8 + digit;
+                    digits += 1;
+                    chars.next();
+                }
+                value as u8
+            }
+            other => {
+                out.push(b'\\');
+                let mut buf = [0; 4];
+                out.extend_from_slice(other.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+        };
+        out.push(byte);
+    }
+    false
+}
+
+/// A conversion specification: `%[flags][width][.precision]conversion`.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    zero: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pad `body`, whose sign or `0x` prefix is `prefix`, to the width.
+    fn pad(&self, prefix: &str, body: &str, numeric: bool) -> String {
+        let len = prefix.chars().count() + body.chars().count();
+        let fill = self.width.saturating_sub(len);
+        if self.left {
+            format!("{}{}{}", prefix, body, " ".repeat(fill))
+        } else if self.zero && numeric {
+            format!("{}{}{}", prefix, "0".repeat(fill), body)
+        } else {
+            format!("{}{}{}", " ".repeat(fill), prefix, body)
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        match (negative, self.plus, self.space) {
+            (true, _, _) => "-",
+            (false, true, _) => "+",
+            (false, false, true) => " ",
+            _ => "",
+        }
+    }
+}
+
+/// The number `arg` stands for, reporting an error for text that isn't
+/// one. A leading quote gives the code of the character after it.
+fn integer(arg: &str, errors: &mut Vec<String>) -> i128 {
+    if let Some(ch) = arg.strip_prefix(['\'', '"']).and_then(|rest| rest.chars().next()) {
+        return ch as i128;
+    }
+    let trimmed = arg.trim();
+    let (negative, digits) = match trimmed.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let parsed = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        i128::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    match parsed {
+        Ok(n) if negative => -n,
+        Ok(n) => n,
+        Err(_) if arg.is_empty() => 0,
+        Err(_) => {
+            errors.push(format!("{}: invalid number", arg));
+            0
+        }
+    }
+}
+
+fn float(arg: &str, errors: &mut Vec<String>) -> f64 {
+    if let Some(ch) = arg.strip_prefix(['\'', '"']).and_then(|rest| rest.chars().next()) {
+        return ch as u32 as f64;
+    }
+    match arg.trim().parse() {
+        Ok(x) => x,
+        Err(_) if arg.is_empty() => 0.0,
+        Err(_) => {
+            errors.push(format!("{}: invalid number", arg));
+            0.0
+        }
+    }
+}
+
+/// `x` in C's `%e` style: a mantissa and an exponent of at least two
+/// digits.
+fn exponential(x: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{:.*e}", precision, x);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap_or((&formatted, "0"));
+    let exponent: i32 = exponent.parse().unwrap_or(0);
+    let e = if upper { 'E' } else { 'e' };
+    format!("{}{}{}{:02}", mantissa, e, if exponent < 0 { '-' } else { '+' }, exponent.abs())
+}
+
+/// `x` in C's `%g` style: `%e` for very large or small exponents and `%f`
+/// otherwise, without trailing zeros.
+fn general(x: f64, precision: usize, upper: bool, alternate: bool) -> String {
+    let precision = precision.max(1);
+    if x == 0.0 {
+        return String::from("0");
+    }
+    let exponent = x.abs().log10().floor() as i32;
+    let trim = |s: String| match alternate || !s.contains('.') {
+        true => s,
+        false => s.trim_end_matches('0').trim_end_matches('.').to_owned(),
+    };
+    if exponent < -4 || exponent >= precision as i32 {
+        let formatted = exponential(x, precision - 1, upper);
+        let (mantissa, exponent) = formatted.split_at(formatted.find(['e', 'E']).unwrap_or(formatted.len()));
+        format!("{}{}", trim(mantissa.to_owned()), exponent)
+    } else {
+        trim(format!("{:.*}", (precision as i32 - 1 - exponent).max(0) as usize, x))
+    }
+}
+
+/// Format `args` as `printf` does with `format`, reusing the format while
+/// arguments remain. Returns the output and the problems met on the way,
+/// such as arguments that aren't numbers.
+pub fn printf(format: &str, args: &[String]) -> Result<(Vec<u8>, Vec<String>), String> {
+    let mut out = Vec::new();
+    let mut errors = Vec::new();
+    let mut args = args.iter().map(String::as_str).peekable();
+    loop {
+        let mut consumed = false;
+        let mut rest = format;
+        while let Some(i) = rest.find('%') {
+            if unescape(&rest[..i], &mut out) {
+                return Ok((out, errors));
+            }
+            rest = &rest[i + 1..];
+            let mut spec = Spec::default();
+            while let Some(flag) = rest.chars().next().filter(|c| "-+ 0#".contains(*c)) {
+                match flag {
+                    '-' => spec.left = true,
+                    '+' => spec.plus = true,
+                    ' ' => spec.space = true,
+                    '0' => spec.zero = true,
+                    _ => spec.alternate = true,
+                }
+                rest = &rest[1..];
+            }
+            let mut next_arg = || {
+                consumed = true;
+                args.next().unwrap_or("")
+            };
+            if let Some(after) = rest.strip_prefix('*') {
+                let width = integer(next_arg(), &mut errors);
+                spec.left |= width < 0;
+                spec.width = width.unsigned_abs() as usize;
+                rest = after;
+            } else {
+                let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                spec.width = rest[..len].parse().unwrap_or(0);
+                rest = &rest[len..];
+            }
+            if let Some(after) = rest.strip_prefix('.') {
+                rest = after;
+                if let Some(after) = rest.strip_prefix('*') {
+                    spec.precision = usize::try_from(integer(next_arg(), &mut errors)).ok();
+                    rest = after;
+                } else {
+                    let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                    spec.precision = Some(rest[..len].parse().unwrap_or(0));
+                    rest = &rest[len..];
+                }
+            }
+            let Some(conversion) = rest.chars().next() else {
+                return Err(String::from("missing conversion at the end of the format"));
+            };
+            rest = &rest[conversion.len_utf8()..];
+            let text = match conversion {
+                '%' => String::from("%"),
+                's' => {
+                    let arg = next_arg();
+                    let arg: String = match spec.precision {
+                        Some(precision) => arg.chars().take(precision).collect(),
+                        None => arg.to_owned(),
+                    };
+                    spec.pad("", &arg, false)
+                }
+                'b' => {
+                    let mut bytes = Vec::new();
+                    let stop = unescape(next_arg(), &mut bytes);
+                    out.extend_from_slice(spec.pad("", &String::from_utf8_lossy(&bytes), false).as_bytes());
+                    if stop {
+                        return Ok((out, errors));
+                    }
+                    continue;
+                }
+                'c' => {
+                    let arg: String = next_arg().chars().take(1).collect();
+                    spec.pad("", &arg, false)
+                }
+                'd' | 'i' => {
+                    let n = integer(next_arg(), &mut errors);
+                    let mut digits = n.unsigned_abs().to_string();
+                    if let Some(precision) = spec.precision {
+                        spec.zero = false;
+                        digits = format!("{:0>1$}", digits, precision);
+                    }
+                    spec.pad(spec.sign(n < 0), &digits, true)
+                }
+                'u' | 'x' | 'X' | 'o' => {
+                    // Negative numbers wrap around as in C's 64-bit types.
+                    let n = integer(next_arg(), &mut errors) as u64;
+                    let mut digits = match conversion {
+                        'u' => n.to_string(),
+                        'x' => format!("{:x}", n),
+                        'X' => format!("{:X}", n),
+                        _ => format!("{:o}", n),
+                    };
+                    if let Some(precision) = spec.precision {
+                        spec.zero = false;
+                        digits = format!("{:0>1$}", digits, precision);
+                    }
+                    let prefix = match conversion {
+                        'x' if spec.alternate && n != 0 => "0x",
+                        'X' if spec.alternate && n != 0 => "0X",
+                        'o' if spec.alternate && !digits.starts_with('0') => "0",
+                        _ => "",
+                    };
+                    spec.pad(prefix, &digits, true)
+                }
+                'f' | 'F' | 'e' | 'E' | 'g' | 'G' => {
+                    let x = float(next_arg(), &mut errors);
+                    let precision = spec.precision.unwrap_or(6);
+                    let body = match conversion {
+                        _ if !x.is_finite() => {
+                            let body = if x.is_nan() { "nan" } else { "inf" };
+                            match conversion.is_ascii_uppercase() {
+                                true => body.to_ascii_uppercase(),
+                                false => body.to_owned(),
+                            }
+                        }
+                        'f' | 'F' => format!("{:.*}", precision, x.abs()),
+                        'e' | 'E' => exponential(x.abs(), precision, conversion == 'E'),
+                        _ => general(x.abs(), precision, conversion == 'G', spec.alternate),
+                    };
+                    spec.pad(spec.sign(x.is_sign_negative() && !x.is_nan()), &body, x.is_finite())
+                }
+                other => return Err(format!("%{}: invalid conversion", other)),
+            };
+            out.extend_from_slice(text.as_bytes());
+        }
+        if unescape(rest, &mut out) {
+            return Ok((out, errors));
+        }
+        if !consumed || args.peek().is_none() {
+            return Ok((out, errors));
+        }
+    }
+}
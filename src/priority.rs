@@ -0,0 +1,108 @@
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+/// Scheduling adjustments requested by `nice` and `ionice` prefixes, applied
+/// to the child between fork and exec instead of running the external
+/// wrappers.
+#[derive(Default, Clone, Copy)]
+pub struct Priority {
+    /// Increment added to the niceness.
+    nice: Option<i32>,
+    /// I/O scheduling class (1 realtime, 2 best-effort, 3 idle) and level.
+    io: Option<(i32, i32)>,
+}
+
+/// Parse the options of `nice [-n N | -N] command...`, returning the
+/// increment and the number of words consumed.
+fn parse_nice(argv: &[String]) -> Option<(i32, usize)> {
+    match argv.get(1).map(String::as_str) {
+        Some("-n") => Some((argv.get(2)?.parse().ok()?, 3)),
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => Some((arg[1..].parse().ok()?, 2)),
+        _ => Some((10, 1)),
+    }
+}
+
+/// Parse the options of `ionice [-c class] [-n level] command...`.
+fn parse_ionice(argv: &[String]) -> Option<((i32, i32), usize)> {
+    let mut class = None;
+    let mut level = None;
+    let mut i = 1;
+    loop {
+        match argv.get(i).map(String::as_str) {
+            Some("-c") => {
+                class = Some(match argv.get(i + 1)?.as_str() {
+                    "1" | "realtime" => 1,
+                    "2" | "best-effort" => 2,
+                    "3" | "idle" => 3,
+                    _ => return None,
+                });
+            }
+            Some("-n") => level = Some(argv.get(i + 1)?.parse().ok().filter(|n| (0..8).contains(n))?),
+            Some(arg) if arg.starts_with('-') => return None,
+            _ => break,
+        }
+        i += 2;
+    }
+    let class = class.unwrap_or(2);
+    let level = if class == 3 { 0 } else { level.unwrap_or(4) };
+    Some(((class, level), i))
+}
+
+/// Strip leading `nice`/`ionice` wrappers off `argv`. Wrappers with
+/// options this doesn't understand, or without a command to run, are left
+/// for the external programs to handle.
+pub fn strip_prefixes(argv: &[String]) -> (Priority, &[String]) {
+    let mut priority = Priority::default();
+    let mut rest = argv;
+    loop {
+        let parsed = match rest.first().map(String::as_str) {
+            Some("nice") => parse_nice(rest).map(|(n, len)| {
+                (Priority { nice: Some(priority.nice.unwrap_or(0) + n), ..priority }, len)
+            }),
+            Some("ionice") => parse_ionice(rest).map(|(io, len)| (Priority { io: Some(io), ..priority }, len)),
+            _ => return (priority, rest),
+        };
+        match parsed {
+            Some((next, len)) if len < rest.len() => {
+                priority = next;
+                rest = &rest[len..];
+            }
+            _ => return (priority, rest),
+        }
+    }
+}
+
+impl Priority {
+    /// Arrange for `command` to run with these adjustments.
+    pub fn apply(self, command: &mut Command) {
+        if self.nice.is_none() && self.io.is_none() {
+            return;
+        }
+        unsafe {
+            command.pre_exec(move || self.set());
+        }
+    }
+
+    /// Adjust the calling process. Only async-signal-safe calls are made,
+    /// as this runs in the forked child.
+    fn set(self) -> io::Result<()> {
+        if let Some(increment) = self.nice {
+            unsafe {
+                let current = libc::getpriority(libc::PRIO_PROCESS, 0);
+                if libc::setpriority(libc::PRIO_PROCESS, 0, current + increment) < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        #[cfg(target_os = "linux")]
+        if let Some((class, level)) = self.io {
+            const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+            let prio = ((class << 13) | level) as libc::c_long;
+            if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, prio) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+/// Timings of the steps the shell takes before its first prompt, kept when
+/// started with `--profile-startup`.
+pub struct StartupProfile {
+    enabled: bool,
+    start: Instant,
+    steps: Vec<(&'static str, Duration)>,
+}
+
+impl StartupProfile {
+    pub fn new(enabled: bool) -> Self {
+        StartupProfile { enabled, start: Instant::now(), steps: Vec::new() }
+    }
+
+    /// Run `init`, recording how long it took under `name`.
+    pub fn time<T>(&mut self, name: &'static str, init: impl FnOnce() -> T) -> T {
+        if !self.enabled {
+            return init();
+        }
+        let start = Instant::now();
+        let res = init();
+        self.steps.push((name, start.elapsed()));
+        res
+    }
+
+    /// Print the recorded timings to stderr, once. Later calls do nothing.
+    pub fn report(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        self.enabled = false;
+        for (name, elapsed) in &self.steps {
+            print(name, *elapsed);
+        }
+        print("total", self.start.elapsed());
+    }
+}
+
+/// Print the timing of the step `name` to stderr, as the profile shows
+/// it. Steps put off until needed, such as reading completion specs, are
+/// reported this way when they happen.
+pub fn print(name: &str, elapsed: Duration) {
+    eprintln!("{:<15}\t{:>8.3} ms", name, elapsed.as_secs_f64() * 1000.0);
+}
@@ -0,0 +1,277 @@
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use crate::hooks::{Event, Hook};
+use crate::lexer;
+use crate::sha256::Sha256;
+use crate::{home_dir, Shell};
+
+/// Where a project keeps the settings that apply inside its tree.
+const CONFIG: &str = ".rsh/config.toml";
+
+/// A value in the configuration file.
+enum Value {
+    String(String),
+    Bool(bool),
+    Integer(i64),
+}
+
+/// Parse the subset of TOML the configuration uses: `[section]` headers,
+/// `key = value` pairs with basic or literal strings, booleans and
+/// integers, and `#` comments. Returns `(section, key, value)` triples.
+fn parse(text: &str) -> Result<Vec<(String, String, Value)>, String> {
+    let mut section = String::new();
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let error = |message: &str| format!("line {}: {}", n + 1, message);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[') {
+            let name = name.strip_suffix(']').ok_or_else(|| error("unterminated section header"))?;
+            section = name.trim().to_owned();
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+        let key = key.trim().trim_matches('"').to_owned();
+        let (value, rest) = parse_value(value.trim()).ok_or_else(|| error("invalid value"))?;
+        let rest = rest.trim_start();
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(error("unexpected text after value"));
+        }
+        entries.push((section.clone(), key, value));
+    }
+    Ok(entries)
+}
+
+/// Parse the value at the start of `text`, returning it and what follows.
+fn parse_value(text: &str) -> Option<(Value, &str)> {
+    if let Some(rest) = text.strip_prefix('\'') {
+        let end = rest.find('\'')?;
+        return Some((Value::String(rest[..end].to_owned()), &rest[end + 1..]));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '"' => return Some((Value::String(value), &rest[i + 1..])),
+                '\\' => value.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    'e' => '\x1b',
+                    other => other,
+                }),
+                _ => value.push(ch),
+            }
+        }
+        return None;
+    }
+    let end = text.find(|c: char| c.is_whitespace() || c == '#').unwrap_or(text.len());
+    let value = match &text[..end] {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        word => Value::Integer(word.replace('_', "").parse().ok()?),
+    };
+    Some((value, &text[end..]))
+}
+
+/// The SHA-256 digest of `bytes` in hex, which a changed file can't be
+/// made to share.
+fn fingerprint(bytes: &[u8]) -> String {
+    let mut hasher = Sha256::default();
+    hasher.update(bytes);
+    hasher.finish().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The file recording which configuration files the user trusts, as lines
+/// of `fingerprint path`. A changed file has to be trusted again.
+fn trust_file() -> Option<PathBuf> {
+    home_dir().map(|home| home.join(".rsh_trusted"))
+}
+
+fn is_trusted(path: &Path, fingerprint: &str) -> bool {
+    let entry = format!("{} {}", fingerprint, path.display());
+    trust_file()
+        .and_then(|file| fs::read_to_string(file).ok())
+        .is_some_and(|trusted| trusted.lines().any(|line| line == entry))
+}
+
+/// Ask whether to trust the configuration at `path`, remembering a yes.
+/// Without a terminal to answer from, the answer is no.
+fn ask_trust(path: &Path, fingerprint: &str) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("shell: {} wants to change shell settings. Trust it? [y/N] ", path.display());
+    let mut answer = String::new();
+    if io::stdin().lock().read_line(&mut answer).is_err() || !answer.trim().eq_ignore_ascii_case("y") {
+        return false;
+    }
+    let Some(file) = trust_file() else {
+        return true;
+    };
+    let res = fs::OpenOptions::new().create(true).append(true).open(&file)
+        .and_then(|mut f| writeln!(f, "{} {}", fingerprint, path.display()));
+    if let Err(e) = res {
+        eprintln!("shell: {}: {}", file.display(), e);
+    }
+    true
+}
+
+/// A project's settings in effect, with what they replaced.
+struct Overlay {
+    root: PathBuf,
+    aliases: Vec<(String, Option<String>)>,
+    keywords: Vec<(String, Option<&'static str>)>,
+    options: Vec<(String, bool)>,
+    vars: Vec<(String, Option<String>)>,
+}
+
+/// The shell variable set by each key of the `[prompt]` and `[runner]`
+/// sections.
+fn variable(section: &str, key: &str) -> Option<&'static str> {
+    match (section, key) {
+        ("prompt", "ps1") => Some("PS1"),
+        ("prompt", "segments") => Some("PROMPT_SEGMENTS"),
+        ("prompt", "dirtrim") => Some("PROMPT_DIRTRIM"),
+        ("prompt", "repo_style") => Some("PROMPT_REPO_STYLE"),
+        ("runner", "command") => Some("RSH_RUNNER"),
+        ("runner", "commands") => Some("RSH_RUNNER_COMMANDS"),
+        _ => None,
+    }
+}
+
+impl Overlay {
+    /// Apply the settings in `text`, the configuration of the project at
+    /// `root`, remembering what they replace.
+    fn apply(shell: &mut Shell, root: PathBuf, text: &str) -> Result<Overlay, String> {
+        let entries = parse(text)?;
+        let path = root.join(CONFIG);
+        let mut overlay = Overlay {
+            root,
+            aliases: Vec::new(),
+            keywords: Vec::new(),
+            options: Vec::new(),
+            vars: Vec::new(),
+        };
+        for (section, key, value) in entries {
+            match (section.as_str(), value) {
+                ("aliases", Value::String(value)) => {
+                    let old = shell.aliases.insert(key.clone(), value);
+                    overlay.aliases.push((key, old));
+                }
+                ("keywords", Value::String(value)) => match lexer::operator(&value) {
+                    Some(op) => {
+                        let old = shell.keywords.insert(key.clone(), op);
+                        overlay.keywords.push((key, old));
+                    }
+                    None => eprintln!("shell: {}: {}: not an operator", path.display(), value),
+                },
+                ("options", Value::Bool(value)) => match shell.options.get_mut(&key) {
+                    Some(option) => {
+                        let old = std::mem::replace(option, value);
+                        overlay.options.push((key, old));
+                    }
+                    None => eprintln!("shell: {}: {}: invalid option name", path.display(), key),
+                },
+                (section @ ("prompt" | "runner"), value) => match variable(section, &key) {
+                    Some(name) if shell.vars.writable(name).is_err() => {
+                        eprintln!("shell: {}: {}: readonly variable", path.display(), name);
+                    }
+                    Some(name) => {
+                        let value = match value {
+                            Value::String(value) => value,
+                            Value::Integer(n) => n.to_string(),
+                            Value::Bool(b) => b.to_string(),
+                        };
+                        overlay.vars.push((name.to_owned(), shell.vars.get(name)));
+                        shell.vars.set(name, value);
+                    }
+                    None => eprintln!("shell: {}: {}.{}: unknown setting", path.display(), section, key),
+                },
+                _ => eprintln!("shell: {}: {}.{}: unknown setting", path.display(), section, key),
+            }
+        }
+        Ok(overlay)
+    }
+
+    /// Put back what the settings replaced, most recent first.
+    fn undo(self, shell: &mut Shell) {
+        for (name, old) in self.aliases.into_iter().rev() {
+            match old {
+                Some(value) => shell.aliases.insert(name, value),
+                None => shell.aliases.remove(&name),
+            };
+        }
+        for (name, old) in self.keywords.into_iter().rev() {
+            match old {
+                Some(op) => shell.keywords.insert(name, op),
+                None => shell.keywords.remove(&name),
+            };
+        }
+        for (name, old) in self.options.into_iter().rev() {
+            if let Some(option) = shell.options.get_mut(&name) {
+                *option = old;
+            }
+        }
+        for (name, old) in self.vars.into_iter().rev() {
+            match old {
+                Some(value) => shell.vars.set(&name, value),
+                None => shell.vars.unset(&name),
+            }
+        }
+    }
+}
+
+/// The root of the innermost project containing `dir`.
+fn project_root(dir: &Path) -> Option<&Path> {
+    dir.ancestors().find(|dir| dir.join(CONFIG).is_file())
+}
+
+/// A hook applying the settings of the project the working directory is
+/// in, once the user trusts them, and undoing them on leaving it. A
+/// project whose settings were declined isn't asked about again until the
+/// working directory leaves it. Forked copies of the shell, which aren't
+/// interactive, keep the settings they started with.
+pub fn overlays() -> Hook {
+    let mut current: Option<Overlay> = None;
+    let mut declined: Option<PathBuf> = None;
+    Box::new(move |shell: &mut Shell, event: &Event| {
+        if !shell.interactive || !matches!(event, Event::Startup | Event::Chpwd { .. }) {
+            return;
+        }
+        let cwd = std::env::current_dir().unwrap_or_default();
+        let root = project_root(&cwd).map(Path::to_path_buf);
+        let active = current.as_ref().map(|overlay| &overlay.root).or(declined.as_ref());
+        if active == root.as_ref() {
+            return;
+        }
+        if let Some(overlay) = current.take() {
+            overlay.undo(shell);
+        }
+        declined = None;
+        let Some(root) = root else {
+            return;
+        };
+        let path = root.join(CONFIG);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("shell: {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let fingerprint = fingerprint(text.as_bytes());
+        if !is_trusted(&path, &fingerprint) && !ask_trust(&path, &fingerprint) {
+            declined = Some(root);
+            return;
+        }
+        match Overlay::apply(shell, root, &text) {
+            Ok(overlay) => current = Some(overlay),
+            Err(e) => eprintln!("shell: {}: {}", path.display(), e),
+        }
+    })
+}
@@ -0,0 +1,502 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::{home_dir, Shell};
+
+/// How long a rendered segment stays valid.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Rendered again for every prompt.
+    Never,
+    /// Rendered again when the working directory changes.
+    Directory,
+    /// Rendered once per session.
+    Session,
+}
+
+/// A piece of the prompt, such as the working directory. Segments are
+/// picked by id in `$PROMPT_SEGMENTS`.
+pub trait PromptSegment {
+    fn id(&self) -> &str;
+
+    /// The text of the segment, or `None` to leave it out.
+    fn render(&self, shell: &Shell) -> Option<String>;
+
+    fn cache_policy(&self) -> CachePolicy {
+        CachePolicy::Never
+    }
+}
+
+/// What a segment rendered, and in which working directory when that
+/// matters.
+type Rendered = (Option<PathBuf>, Option<String>);
+
+/// The segments available to the prompt, with what they last rendered.
+pub struct Prompt {
+    segments: Vec<Box<dyn PromptSegment>>,
+    cache: RefCell<HashMap<String, Rendered>>,
+}
+
+impl Default for Prompt {
+    fn default() -> Self {
+        let mut prompt = Prompt { segments: Vec::new(), cache: RefCell::default() };
+        prompt.register(Box::new(User));
+        prompt.register(Box::new(Host));
+        prompt.register(Box::new(WorkingDir));
+        prompt.register(Box::new(Basename));
+        prompt.register(Box::new(Time));
+        prompt.register(Box::new(Git));
+        prompt.register(Box::new(Status));
+        prompt.register(Box::new(Symbol));
+        prompt
+    }
+}
+
+impl Prompt {
+    /// Make `segment` available, replacing any segment with the same id.
+    pub fn register(&mut self, segment: Box<dyn PromptSegment>) {
+        self.segments.retain(|existing| existing.id() != segment.id());
+        self.cache.borrow_mut().remove(segment.id());
+        self.segments.push(segment);
+    }
+
+    /// Make the segment `id` unavailable.
+    pub fn unregister(&mut self, id: &str) {
+        self.segments.retain(|existing| existing.id() != id);
+        self.cache.borrow_mut().remove(id);
+    }
+
+    /// Render the segment `id`, reusing an earlier result its cache policy
+    /// allows. Unknown ids render nothing.
+    fn segment(&self, shell: &Shell, id: &str) -> Option<String> {
+        let segment = self.segments.iter().find(|segment| segment.id() == id)?;
+        let policy = segment.cache_policy();
+        let dir = match policy {
+            CachePolicy::Directory => cwd(shell).map(Path::to_path_buf),
+            _ => None,
+        };
+        if policy != CachePolicy::Never {
+            if let Some((cached_dir, text)) = self.cache.borrow().get(id) {
+                if *cached_dir == dir {
+                    return text.clone();
+                }
+            }
+        }
+        let text = segment.render(shell);
+        if policy != CachePolicy::Never {
+            self.cache.borrow_mut().insert(id.to_owned(), (dir, text.clone()));
+        }
+        text
+    }
+
+    /// The prompt: the segments named in `$PROMPT_SEGMENTS`, separated by
+    /// spaces, or else `$PS1` with its escapes expanded.
+    pub fn render(&self, shell: &Shell) -> String {
+        if let Some(ids) = shell.vars.get("PROMPT_SEGMENTS") {
+            let texts: Vec<String> = ids.split_whitespace()
+                .filter_map(|id| self.segment(shell, id))
+                .collect();
+            return format!("{} ", texts.join(" "));
+        }
+        let template = shell.vars.get("PS1").unwrap_or_else(|| String::from(DEFAULT_PS1));
+        self.expand(shell, &template)
+    }
+
+    /// The right-side prompt: `$RPROMPT` with the escapes of `$PS1`
+    /// expanded, or nothing.
+    pub fn render_right(&self, shell: &Shell) -> String {
+        match shell.vars.get("RPROMPT") {
+            Some(template) => self.expand(shell, &template),
+            None => String::new(),
+        }
+    }
+
+    /// The terminal title shown at the prompt: `$RSH_TITLE` with the
+    /// escapes of `$PS1` expanded, `\u@\h:\w` by default, without colors.
+    pub fn render_title(&self, shell: &Shell) -> String {
+        let template = shell.vars.get("RSH_TITLE").unwrap_or_else(|| String::from(DEFAULT_TITLE));
+        visible(&self.expand(shell, &template))
+    }
+
+    /// The prompt for the continuation lines of an unfinished command:
+    /// `$PS2` with the escapes of `$PS1` expanded, `> ` by default.
+    pub fn render_continuation(&self, shell: &Shell) -> String {
+        let template = shell.vars.get("PS2").unwrap_or_else(|| String::from("> "));
+        self.expand(shell, &template)
+    }
+
+    /// Replace the escapes in a `$PS1` template: `\u` (user), `\h` (host),
+    /// `\w` (working directory), `\W` (its last component), `\$` (`#` for
+    /// root, `$` otherwise), `\t` (time), `\?` (the status of a failed
+    /// command and a space, or nothing), `\g` (a space and the git branch,
+    /// or nothing), `\e` (escape), `\nnn` (the character with octal code
+    /// nnn) and `\\`. `\[` and `\]` are dropped and anything else is kept
+    /// as it is. Colors can also be set with `%F{color}` and `%K{color}`
+    /// for the foreground and background, and reset with `%f` and `%k`;
+    /// `%B` and `%b` turn bold on and off, and `%%` is a `%`.
+    fn expand(&self, shell: &Shell, template: &str) -> String {
+        let mut res = String::new();
+        let mut chars = template.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '%' {
+                match style(chars.as_str()) {
+                    Some((sequence, rest)) => {
+                        res.push_str(&sequence);
+                        chars = rest.chars();
+                    }
+                    None => res.push(ch),
+                }
+                continue;
+            }
+            if ch != '\\' {
+                res.push(ch);
+                continue;
+            }
+            let id = match chars.next() {
+                Some('u') => "user",
+                Some('h') => "host",
+                Some('w') => "path",
+                Some('W') => "dir",
+                Some('$') => "symbol",
+                Some('t') => "time",
+                Some('?') => {
+                    if let Some(status) = self.segment(shell, "status") {
+                        res.push_str(&status);
+                        res.push(' ');
+                    }
+                    continue;
+                }
+                Some('g') => {
+                    if let Some(git) = self.segment(shell, "git") {
+                        res.push(' ');
+                        res.push_str(&git);
+                    }
+                    continue;
+                }
+                Some('\\') => {
+                    res.push('\\');
+                    continue;
+                }
+                // Bash's markers around non-printing text.
+                Some('[' | ']') => continue,
+                Some('e') => {
+                    res.push('\x1b');
+                    continue;
+                }
+                Some(digit @ '0'..='7') => {
+                    let rest = chars.as_str();
+                    let len = rest.chars().take(2).take_while(|c| ('0'..='7').contains(c)).count();
+                    let code = format!("{}{}", digit, &rest[..len]);
+                    res.extend(u8::from_str_radix(&code, 8).ok().map(char::from));
+                    chars = rest[len..].chars();
+                    continue;
+                }
+                Some(other) => {
+                    res.push('\\');
+                    res.push(other);
+                    continue;
+                }
+                None => {
+                    res.push('\\');
+                    break;
+                }
+            };
+            res.push_str(&self.segment(shell, id).unwrap_or_default());
+        }
+        res
+    }
+}
+
+/// The SGR parameter for `color`, a name such as `red` or a number of the
+/// 256-color palette, as a foreground (`base` 30) or background (40).
+fn color(color: &str, base: u8) -> Option<String> {
+    const NAMES: &[&str] = &["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+    match NAMES.iter().position(|name| *name == color) {
+        Some(i) => Some((base + i as u8).to_string()),
+        None => Some(format!("{};5;{}", base + 8, color.parse::<u8>().ok()?)),
+    }
+}
+
+/// The escape sequence for the `%` style at the start of `text`, which
+/// follows the `%`, and the text after it.
+fn style(text: &str) -> Option<(String, &str)> {
+    let sgr = |parameter: &str| format!("\x1b[{}m", parameter);
+    let mut chars = text.chars();
+    let sequence = match chars.next()? {
+        '%' => String::from("%"),
+        'f' => sgr("39"),
+        'k' => sgr("49"),
+        'B' => sgr("1"),
+        'b' => sgr("22"),
+        kind @ ('F' | 'K') => {
+            let rest = chars.as_str().strip_prefix('{')?;
+            let (name, rest) = rest.split_once('}')?;
+            let parameter = color(name, if kind == 'F' { 30 } else { 40 })?;
+            return Some((sgr(&parameter), rest));
+        }
+        _ => return None,
+    };
+    Some((sequence, chars.as_str()))
+}
+
+/// The prompt when `$PS1` is unset: `user@host:path$ `.
+const DEFAULT_PS1: &str = "\\u@\\h:\\w\\$ ";
+
+/// The title template when `$RSH_TITLE` is unset.
+const DEFAULT_TITLE: &str = "\\u@\\h:\\w";
+
+/// What of `text` shows on the terminal: everything but SGR and other CSI
+/// escape sequences and control characters.
+fn visible(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            if chars.next() == Some('[') {
+                chars.by_ref().find(|c| ('@'..='~').contains(c));
+            }
+        } else if !ch.is_control() {
+            res.push(ch);
+        }
+    }
+    res
+}
+
+/// The number of columns `text` takes on the terminal.
+fn visible_width(text: &str) -> usize {
+    visible(text).chars().count()
+}
+
+/// The width of the terminal on stdout.
+fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let res = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    (res == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// `prompt` followed by sequences that draw `right` flush with the right
+/// edge of the terminal and return the cursor to the end of `prompt`.
+/// `right` is left out when it would run into the prompt.
+pub fn with_right(prompt: &str, right: &str) -> Option<String> {
+    if right.is_empty() {
+        return None;
+    }
+    let left = visible_width(prompt.rsplit('\n').next().unwrap_or(prompt));
+    let width = visible_width(right);
+    let columns = terminal_width()?;
+    if left + width + 1 >= columns {
+        return None;
+    }
+    Some(format!("{}\x1b7\x1b[{}G{}\x1b8", prompt, columns - width + 1, right))
+}
+
+/// The working directory, looked up once until `cd` forgets it.
+fn cwd(shell: &Shell) -> Option<&Path> {
+    if let Some(dir) = shell.cwd.get() {
+        return Some(dir);
+    }
+    let dir = crate::builtins::logical_cwd(shell)?;
+    Some(shell.cwd.get_or_init(|| dir))
+}
+
+struct User;
+
+impl PromptSegment for User {
+    fn id(&self) -> &str {
+        "user"
+    }
+
+    fn render(&self, _shell: &Shell) -> Option<String> {
+        Some(whoami::username())
+    }
+
+    // Looking the user up reads the password database.
+    fn cache_policy(&self) -> CachePolicy {
+        CachePolicy::Session
+    }
+}
+
+struct Host;
+
+impl PromptSegment for Host {
+    fn id(&self) -> &str {
+        "host"
+    }
+
+    fn render(&self, _shell: &Shell) -> Option<String> {
+        Some(whoami::hostname())
+    }
+
+    fn cache_policy(&self) -> CachePolicy {
+        CachePolicy::Session
+    }
+}
+
+/// The working directory as shown in the prompt: `~` stands for the home
+/// directory, only the last `$PROMPT_DIRTRIM` components are kept when it
+/// is set, and the root of a git repository is drawn with the SGR
+/// parameters in `$PROMPT_REPO_STYLE` (e.g. `1;34`).
+struct WorkingDir;
+
+impl PromptSegment for WorkingDir {
+    fn id(&self) -> &str {
+        "path"
+    }
+
+    fn render(&self, shell: &Shell) -> Option<String> {
+        let Some(cwd) = cwd(shell) else {
+            return Some(String::from("?"));
+        };
+        let home = home_dir().filter(|home| cwd.starts_with(home));
+        let (head, mut base) = match &home {
+            Some(home) => ("~", home.clone()),
+            None => ("", PathBuf::from("/")),
+        };
+        let style = shell.vars.get("PROMPT_REPO_STYLE").filter(|style| !style.is_empty());
+        let repo_root = style.as_ref()
+            .and_then(|_| cwd.ancestors().find(|dir| dir.join(".git").exists()));
+        let paint = |name: &str, path: &Path| match (&style, repo_root) {
+            (Some(style), Some(root)) if path == root => format!("\x1b[{}m{}\x1b[0m", style, name),
+            _ => name.to_owned(),
+        };
+        let head = paint(head, &base);
+        let mut segments = Vec::new();
+        for component in cwd.strip_prefix(&base).unwrap_or(cwd).components() {
+            base.push(component);
+            segments.push(paint(&component.as_os_str().to_string_lossy(), &base));
+        }
+        let trim = shell.vars.get("PROMPT_DIRTRIM")
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(0);
+        if trim > 0 && segments.len() > trim {
+            segments.drain(..segments.len() - trim);
+            segments.insert(0, String::from("..."));
+        }
+        if home.is_some() && segments.is_empty() {
+            Some(head)
+        } else {
+            Some(format!("{}/{}", head, segments.join("/")))
+        }
+    }
+}
+
+/// The status of the last command in red, when it failed.
+struct Status;
+
+impl PromptSegment for Status {
+    fn id(&self) -> &str {
+        "status"
+    }
+
+    fn render(&self, shell: &Shell) -> Option<String> {
+        (shell.vars.status != 0).then(|| format!("\x1b[31m[{}]\x1b[0m", shell.vars.status))
+    }
+}
+
+/// `#` for root and `$` for everyone else.
+struct Symbol;
+
+impl PromptSegment for Symbol {
+    fn id(&self) -> &str {
+        "symbol"
+    }
+
+    fn render(&self, _shell: &Shell) -> Option<String> {
+        Some(String::from(if whoami::username() == "root" { "#" } else { "$" }))
+    }
+
+    fn cache_policy(&self) -> CachePolicy {
+        CachePolicy::Session
+    }
+}
+
+/// The last component of the working directory, or `~` for the home
+/// directory.
+struct Basename;
+
+impl PromptSegment for Basename {
+    fn id(&self) -> &str {
+        "dir"
+    }
+
+    fn render(&self, shell: &Shell) -> Option<String> {
+        let Some(cwd) = cwd(shell) else {
+            return Some(String::from("?"));
+        };
+        if home_dir().is_some_and(|home| home == cwd) {
+            return Some(String::from("~"));
+        }
+        match cwd.file_name() {
+            Some(name) => Some(name.to_string_lossy().into_owned()),
+            None => Some(String::from("/")),
+        }
+    }
+
+    fn cache_policy(&self) -> CachePolicy {
+        CachePolicy::Directory
+    }
+}
+
+/// The local time as `HH:MM:SS`.
+struct Time;
+
+impl PromptSegment for Time {
+    fn id(&self) -> &str {
+        "time"
+    }
+
+    fn render(&self, _shell: &Shell) -> Option<String> {
+        let now = unsafe { libc::time(std::ptr::null_mut()) };
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if unsafe { libc::localtime_r(&now, &mut tm) }.is_null() {
+            return None;
+        }
+        Some(format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec))
+    }
+}
+
+/// The git branch of the working directory, or the abbreviated commit
+/// when detached, with `*` when tracked files have uncommitted changes:
+/// `(main*)`.
+struct Git;
+
+/// The git directory of the repository containing `dir`, following the
+/// `gitdir:` pointer a `.git` file holds in worktrees and submodules.
+fn git_dir(dir: &Path) -> Option<PathBuf> {
+    let (root, git) = dir.ancestors()
+        .map(|dir| (dir, dir.join(".git")))
+        .find(|(_, git)| git.exists())?;
+    if git.is_dir() {
+        return Some(git);
+    }
+    let pointer = fs::read_to_string(&git).ok()?;
+    Some(root.join(pointer.strip_prefix("gitdir:")?.trim()))
+}
+
+impl PromptSegment for Git {
+    fn id(&self) -> &str {
+        "git"
+    }
+
+    fn render(&self, shell: &Shell) -> Option<String> {
+        let cwd = cwd(shell)?;
+        let head = fs::read_to_string(git_dir(cwd)?.join("HEAD")).ok()?;
+        let head = head.trim();
+        let branch = match head.strip_prefix("ref: ") {
+            Some(reference) => reference.strip_prefix("refs/heads/").unwrap_or(reference),
+            None => head.get(..7).unwrap_or(head),
+        };
+        // Without git, the dirty state is unknown and left out.
+        let dirty = Command::new("git")
+            .args(["status", "--porcelain", "--untracked-files=no"])
+            .current_dir(cwd)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .is_ok_and(|output| output.status.success() && !output.stdout.is_empty());
+        Some(format!("({}{})", branch, if dirty { "*" } else { "" }))
+    }
+}
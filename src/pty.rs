@@ -0,0 +1,171 @@
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use nix::pty::{openpty, Winsize};
+use nix::sys::termios::{self, SetArg, Termios};
+use nix::unistd::{dup, dup2_stderr, dup2_stdin, dup2_stdout};
+
+/// The size of the terminal on `fd`, if it is one.
+pub fn window_size(fd: BorrowedFd) -> Option<Winsize> {
+    let mut size = Winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    let res = unsafe { libc::ioctl(fd.as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+    if res == 0 { Some(size) } else { None }
+}
+
+/// Copy everything readable from `from` into every writer in `to` until
+/// `from` reaches end of file or fails (a pty master reports EIO once the
+/// last slave descriptor is closed).
+fn relay(mut from: File, to: &mut [&mut dyn Write]) {
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = match from.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        for out in to.iter_mut() {
+            let _ = out.write_all(&buf[..n]);
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Forward keystrokes from the real terminal to the pty until `stop`
+/// becomes readable.
+fn relay_input(input: OwnedFd, master: OwnedFd, stop: OwnedFd) {
+    let mut input = File::from(input);
+    let mut master = File::from(master);
+    let mut buf = [0u8; 1024];
+    loop {
+        let mut fds = [
+            PollFd::new(input.as_fd(), PollFlags::POLLIN),
+            PollFd::new(stop.as_fd(), PollFlags::POLLIN),
+        ];
+        if poll(&mut fds, PollTimeout::NONE).is_err() {
+            continue;
+        }
+        if fds[1].any().unwrap_or(true) {
+            return;
+        }
+        match input.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => {
+                if master.write_all(&buf[..n]).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// While alive, the shell's stdin, stdout and stderr are a pty whose
+/// output is copied to the real terminal and to a log file, the way
+/// `script(1)` records a session. Prompt, echoed input and the output of
+/// every command all pass through it. Dropping it restores the terminal.
+pub struct Tee {
+    /// The shell's stdin, stdout and stderr from before the tee started.
+    saved: [OwnedFd; 3],
+    /// Mode of the real terminal, which is raw while the tee is active.
+    saved_termios: Termios,
+    /// Closing this wakes the input relay so that it exits.
+    stop: Option<OwnedFd>,
+}
+
+impl Tee {
+    pub fn start(log: File) -> io::Result<Tee> {
+        let stdin = io::stdin();
+        let saved_termios = termios::tcgetattr(stdin.as_fd())?;
+        let pty = openpty(window_size(stdin.as_fd()).as_ref(), &saved_termios)?;
+        let saved = [dup(io::stdin())?, dup(io::stdout())?, dup(io::stderr())?];
+
+        let mut raw = saved_termios.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(stdin.as_fd(), SetArg::TCSADRAIN, &raw)?;
+
+        io::stdout().flush()?;
+        dup2_stdin(&pty.slave)?;
+        dup2_stdout(&pty.slave)?;
+        dup2_stderr(&pty.slave)?;
+        drop(pty.slave);
+
+        let (stop_read, stop_write) = nix::unistd::pipe()?;
+        let input = saved[0].try_clone()?;
+        let master = pty.master.try_clone()?;
+        thread::spawn(move || relay_input(input, master, stop_read));
+
+        let mut terminal = File::from(saved[1].try_clone()?);
+        let mut log = log;
+        thread::spawn(move || relay(File::from(pty.master), &mut [&mut terminal, &mut log]));
+
+        Ok(Tee { saved, saved_termios, stop: Some(stop_write) })
+    }
+}
+
+impl Drop for Tee {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        let _ = dup2_stdin(&self.saved[0]);
+        let _ = dup2_stdout(&self.saved[1]);
+        let _ = dup2_stderr(&self.saved[2]);
+        let _ = termios::tcsetattr(&self.saved[0], SetArg::TCSADRAIN, &self.saved_termios);
+        // The output relay drains the pty and exits by itself once the
+        // last slave descriptor is gone.
+        self.stop.take();
+    }
+}
+
+/// Run `command` with a fresh pty as its controlling terminal and as its
+/// stdin, stdout and stderr, so that it behaves as it would interactively
+/// (colors, progress bars) even when the shell's own output is a file or
+/// a pipe. Everything it prints is copied to `out`; when the shell's stdin
+/// is a terminal, keystrokes are forwarded to it in raw mode so that
+/// line editing and signals are handled by the pty instead.
+pub fn run(mut command: Command, out: &mut dyn Write) -> io::Result<ExitStatus> {
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let saved_termios = if interactive { Some(termios::tcgetattr(stdin.as_fd())?) } else { None };
+    let size = window_size(stdin.as_fd()).or_else(|| window_size(io::stdout().as_fd()));
+    let pty = openpty(size.as_ref(), saved_termios.as_ref())?;
+
+    command
+        .stdin(Stdio::from(pty.slave.try_clone()?))
+        .stdout(Stdio::from(pty.slave.try_clone()?))
+        .stderr(Stdio::from(pty.slave.try_clone()?));
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    // Only the child may hold the slave, or reading the master would
+    // never see it hang up.
+    drop(command);
+    drop(pty.slave);
+
+    let mut stop = None;
+    if let Some(saved) = &saved_termios {
+        let mut raw = saved.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(stdin.as_fd(), SetArg::TCSADRAIN, &raw)?;
+        let (stop_read, stop_write) = nix::unistd::pipe()?;
+        let input = dup(io::stdin())?;
+        let master = pty.master.try_clone()?;
+        thread::spawn(move || relay_input(input, master, stop_read));
+        stop = Some(stop_write);
+    }
+
+    relay(File::from(pty.master), &mut [out]);
+    let status = child.wait();
+    if let Some(saved) = &saved_termios {
+        let _ = termios::tcsetattr(stdin.as_fd(), SetArg::TCSADRAIN, saved);
+    }
+    drop(stop);
+    status
+}
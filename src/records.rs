@@ -0,0 +1,126 @@
+use std::cmp::Ordering;
+use std::io::{self, IsTerminal};
+
+use crate::json::{self, Value};
+use crate::Shell;
+
+/// The builtins whose output is a stream of records, one JSON value per
+/// line.
+const PRODUCERS: &[&str] = &["from-json", "where", "select"];
+
+/// Whether the records `name` writes at the end of a foreground pipeline
+/// are shown as a table: under `set -o structured`, when stdout is a
+/// terminal.
+pub fn shown_as_table(shell: &Shell, name: &str) -> bool {
+    shell.options.structured && PRODUCERS.contains(&name) && io::stdout().is_terminal()
+}
+
+/// The records of a JSON document: the items of an array, or the document
+/// itself. Text that isn't one document is read as JSON lines.
+pub fn from_json(text: &str) -> Result<Vec<Value>, String> {
+    match json::parse(text) {
+        Ok(Value::Array(items)) => Ok(items),
+        Ok(value) => Ok(vec![value]),
+        Err(_) => read(text),
+    }
+}
+
+/// The records of a stream, one JSON value per non-empty line.
+pub fn read(text: &str) -> Result<Vec<Value>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(json::parse)
+        .collect()
+}
+
+/// The field `name` of `record`; `a.b` is the field `b` of the field `a`.
+fn field<'a>(record: &'a Value, name: &str) -> Option<&'a Value> {
+    json::get(record, &format!(".{}", name)).ok().flatten()
+}
+
+/// `value` as a cell: strings as they are, anything else as JSON.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// Compare as numbers when both sides are, and as text otherwise.
+fn compare(left: &str, right: &str) -> Ordering {
+    match (left.parse::<f64>(), right.parse::<f64>()) {
+        (Ok(left), Ok(right)) => left.partial_cmp(&right).unwrap_or(Ordering::Equal),
+        _ => left.cmp(right),
+    }
+}
+
+/// Whether the field `name` of `record` stands in the relation `op` (`==`,
+/// `!=`, `<`, `<=`, `>`, `>=` or `=~`, which tests for a substring) to
+/// `operand`. A record without the field never matches.
+pub fn matches(record: &Value, name: &str, op: &str, operand: &str) -> Result<bool, String> {
+    let Some(value) = field(record, name).map(text) else {
+        return Ok(false);
+    };
+    let order = compare(&value, operand);
+    Ok(match op {
+        "==" | "=" => order == Ordering::Equal,
+        "!=" => order != Ordering::Equal,
+        "<" => order == Ordering::Less,
+        "<=" => order != Ordering::Greater,
+        ">" => order == Ordering::Greater,
+        ">=" => order != Ordering::Less,
+        "=~" => value.contains(operand),
+        _ => return Err(format!("{}: unknown operator", op)),
+    })
+}
+
+/// `record` with only the fields `names`, in that order; missing ones are
+/// `null`.
+pub fn select(record: &Value, names: &[String]) -> Value {
+    let members = names.iter()
+        .map(|name| (name.clone(), field(record, name).cloned().unwrap_or(Value::Null)))
+        .collect();
+    Value::Object(members)
+}
+
+/// `records` as a table with a column for each field, in the order the
+/// fields first appear. Records that aren't objects fill a `value` column.
+pub fn table(records: &[Value]) -> String {
+    let mut columns: Vec<String> = Vec::new();
+    let mut rows: Vec<Vec<(String, String)>> = Vec::new();
+    for record in records {
+        let cells = match record {
+            Value::Object(members) => members.iter().map(|(key, value)| (key.clone(), text(value))).collect(),
+            value => vec![(String::from("value"), text(value))],
+        };
+        for (key, _) in &cells {
+            if !columns.contains(key) {
+                columns.push(key.clone());
+            }
+        }
+        rows.push(cells);
+    }
+    let cell = |row: &[(String, String)], column: &str| {
+        row.iter().rev().find(|(key, _)| key == column).map_or("", |(_, value)| value.as_str()).replace('\n', " ")
+    };
+    let widths: Vec<usize> = columns.iter()
+        .map(|column| rows.iter()
+            .map(|row| cell(row, column).chars().count())
+            .chain([column.chars().count()])
+            .max()
+            .unwrap_or(0))
+        .collect();
+    let line = |cells: Vec<String>| {
+        let padded: Vec<String> = cells.iter().zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        format!("{}\n", padded.join("  ").trim_end())
+    };
+    let mut res = line(columns.clone());
+    res.push_str(&line(widths.iter().map(|&width| "-".repeat(width)).collect()));
+    for row in &rows {
+        res.push_str(&line(columns.iter().map(|column| cell(row, column)).collect()));
+    }
+    res
+}
@@ -0,0 +1,93 @@
+use std::ffi::CString;
+use std::mem::MaybeUninit;
+
+/// A POSIX extended regular expression, compiled by the C library as
+/// `regcomp` does for `[[ text =~ regex ]]` in other shells.
+pub struct Regex {
+    compiled: libc::regex_t,
+    /// The number of parenthesized groups in the expression.
+    groups: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, String> {
+        let pattern = CString::new(pattern).map_err(|_| String::from("regular expression contains a NUL byte"))?;
+        let mut compiled = MaybeUninit::<libc::regex_t>::uninit();
+        let code = unsafe { libc::regcomp(compiled.as_mut_ptr(), pattern.as_ptr(), libc::REG_EXTENDED) };
+        if code != 0 {
+            let mut message = [0u8; 256];
+            // The error is described from the partly compiled expression,
+            // which needs no freeing.
+            let len = unsafe {
+                libc::regerror(code, compiled.as_ptr(), message.as_mut_ptr().cast(), message.len())
+            };
+            let message = &message[..len.saturating_sub(1).min(message.len())];
+            return Err(String::from_utf8_lossy(message).into_owned());
+        }
+        Ok(Regex { compiled: unsafe { compiled.assume_init() }, groups: groups(pattern.to_bytes()) })
+    }
+
+    /// The text of the first match in `text` and of each group of the
+    /// expression, empty for groups that took no part in it, or `None` if
+    /// nothing matches.
+    pub fn captures(&self, text: &str) -> Option<Vec<String>> {
+        let subject = CString::new(text).ok()?;
+        let mut matches = vec![libc::regmatch_t { rm_so: -1, rm_eo: -1 }; self.groups + 1];
+        let code = unsafe {
+            libc::regexec(&self.compiled, subject.as_ptr(), matches.len(), matches.as_mut_ptr(), 0)
+        };
+        if code != 0 {
+            return None;
+        }
+        Some(matches.iter()
+            .map(|m| match (usize::try_from(m.rm_so), usize::try_from(m.rm_eo)) {
+                (Ok(start), Ok(end)) => String::from_utf8_lossy(&text.as_bytes()[start..end]).into_owned(),
+                _ => String::new(),
+            })
+            .collect())
+    }
+}
+
+/// Count the groups opened in the valid expression `pattern`: the `(`
+/// that are neither escaped nor in a bracket expression.
+fn groups(pattern: &[u8]) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'\\' => i += 1,
+            b'(' => count += 1,
+            b'[' => {
+                // A `]` right after the `[` or `[^` is part of the set.
+                i += 1;
+                if pattern.get(i) == Some(&b'^') {
+                    i += 1;
+                }
+                if pattern.get(i) == Some(&b']') {
+                    i += 1;
+                }
+                while i < pattern.len() && pattern[i] != b']' {
+                    // Classes such as `[:digit:]` may hold a `]` of their own.
+                    if pattern[i] == b'[' && matches!(pattern.get(i + 1), Some(b':' | b'.' | b'=')) {
+                        let close = pattern[i + 1];
+                        i += 2;
+                        while i + 1 < pattern.len() && !(pattern[i] == close && pattern[i + 1] == b']') {
+                            i += 1;
+                        }
+                        i += 1;
+                    }
+                    i += 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    count
+}
+
+impl Drop for Regex {
+    fn drop(&mut self) {
+        unsafe { libc::regfree(&mut self.compiled) };
+    }
+}
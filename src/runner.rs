@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::globbing::Globber;
+use crate::variables::Variables;
+
+fn executable(file: &Path) -> bool {
+    fs::metadata(file).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+/// The executable `name` runs: the file it names itself, when it contains
+/// a `/`, or the first one so named in a directory of `path`.
+pub fn find_command(name: &str, path: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name)).filter(|file| executable(file));
+    }
+    path.split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join(name))
+        .find(|file| executable(file))
+}
+
+/// Whether `name` can be run: it names an executable file itself, or one
+/// in a directory of `path`.
+pub fn is_runnable(name: &str, path: &str) -> bool {
+    find_command(name, path).is_some()
+}
+
+/// The command to run for the external command `argv`: prefixed with the
+/// words of `$RSH_RUNNER` (e.g. `docker exec devbox`) when its name
+/// matches one of the patterns in `$RSH_RUNNER_COMMANDS`, or, when that is
+/// unset, when it isn't found in `$PATH`. `None` runs `argv` as it is.
+pub fn wrap(argv: &[String], vars: &Variables, globber: &Globber) -> Option<Vec<String>> {
+    let runner = vars.get("RSH_RUNNER")?;
+    let runner: Vec<String> = runner.split_whitespace().map(str::to_owned).collect();
+    if runner.is_empty() || runner[0] == argv[0] {
+        return None;
+    }
+    let wanted = match vars.get("RSH_RUNNER_COMMANDS") {
+        Some(patterns) => patterns.split_whitespace()
+            .any(|pattern| globber.matches(pattern, &argv[0]).unwrap_or(false)),
+        None => !is_runnable(&argv[0], &vars.get("PATH").unwrap_or_default()),
+    };
+    wanted.then(|| runner.into_iter().chain(argv.iter().cloned()).collect())
+}
+
+/// Where the commands run so far were found in `$PATH`, with how often
+/// each was run, so that `$PATH` isn't searched every time. Everything is
+/// forgotten when `$PATH` changes.
+#[derive(Default)]
+pub struct CommandHash {
+    /// The `$PATH` the commands were found in.
+    path: String,
+    entries: BTreeMap<String, (PathBuf, usize)>,
+}
+
+impl CommandHash {
+    /// The executable to run for `name`, remembered from an earlier search
+    /// of `path` while it is still there. A name with a `/` is used as it
+    /// is.
+    pub fn lookup(&mut self, name: &str, path: &str) -> Option<PathBuf> {
+        if name.contains('/') {
+            return Some(PathBuf::from(name));
+        }
+        if self.path != path {
+            self.entries.clear();
+            self.path = path.to_owned();
+        }
+        if let Some((program, hits)) = self.entries.get_mut(name) {
+            if executable(program) {
+                *hits += 1;
+                return Some(program.clone());
+            }
+            self.entries.remove(name);
+        }
+        let program = find_command(name, path)?;
+        self.entries.insert(name.to_owned(), (program.clone(), 1));
+        Some(program)
+    }
+
+    /// Look `name` up in `path` and remember it without counting a run.
+    pub fn remember(&mut self, name: &str, path: &str) -> Option<PathBuf> {
+        let program = self.lookup(name, path)?;
+        if let Some((_, hits)) = self.entries.get_mut(name) {
+            *hits -= 1;
+        }
+        Some(program)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.entries.get(name).map(|(program, _)| program.as_path())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The remembered commands with their paths and number of runs, by
+    /// name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Path, usize)> {
+        self.entries.iter().map(|(name, (program, hits))| (name.as_str(), program.as_path(), *hits))
+    }
+}
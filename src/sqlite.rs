@@ -0,0 +1,111 @@
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::{params, Connection};
+
+use crate::history::HistoryStore;
+
+/// How long to wait for another session holding the database locked.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+/// History kept in an SQLite database, one row per entry with the time it
+/// was entered. Sessions share the database, each reading the rows added
+/// since it last looked when it syncs.
+pub struct SqliteStore {
+    conn: Connection,
+    path: PathBuf,
+    /// The rows read or added so far, oldest first, with their ids.
+    entries: Vec<(i64, String, Option<u64>)>,
+}
+
+impl SqliteStore {
+    /// Open the database at `path`, creating it if needed, and read the
+    /// entries in it.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let conn = Connection::open(path).map_err(error)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(error)?;
+        conn.execute_batch("CREATE TABLE IF NOT EXISTS history (id INTEGER PRIMARY KEY, line TEXT NOT NULL, time INTEGER)")
+            .map_err(error)?;
+        let mut store = SqliteStore { conn, path: path.to_owned(), entries: Vec::new() };
+        store.sync()?;
+        Ok(store)
+    }
+
+    /// Delete the rows with `ids`, and the entries for them.
+    fn delete(&mut self, ids: &[i64]) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(error)?;
+        {
+            let mut delete = tx.prepare("DELETE FROM history WHERE id = ?1").map_err(error)?;
+            for id in ids {
+                delete.execute([id]).map_err(error)?;
+            }
+        }
+        tx.commit().map_err(error)?;
+        self.entries.retain(|(id, _, _)| ids.binary_search(id).is_err());
+        Ok(())
+    }
+}
+
+impl HistoryStore for SqliteStore {
+    fn append(&mut self, line: &str, time: u64) -> io::Result<()> {
+        self.conn.execute("INSERT INTO history (line, time) VALUES (?1, ?2)", params![line, time as i64])
+            .map_err(error)?;
+        self.entries.push((self.conn.last_insert_rowid(), line.to_owned(), Some(time)));
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn get(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.entries.get(index).map(|(_, line, _)| Cow::Borrowed(line.as_str()))
+    }
+
+    fn time(&self, index: usize) -> Option<u64> {
+        self.entries.get(index).and_then(|&(_, _, time)| time)
+    }
+
+    fn retain(&mut self, keep: &mut dyn FnMut(&str) -> bool) -> io::Result<()> {
+        self.sync()?;
+        let ids: Vec<i64> = self.entries.iter().filter(|(_, line, _)| !keep(line)).map(|&(id, _, _)| id).collect();
+        self.delete(&ids)
+    }
+
+    fn dedup(&mut self, line: &str) -> io::Result<()> {
+        self.sync()?;
+        let mut ids: Vec<i64> = self.entries.iter().filter(|(_, entry, _)| entry == line).map(|&(id, _, _)| id).collect();
+        ids.pop();
+        self.delete(&ids)
+    }
+
+    fn trim(&mut self, size: usize) -> io::Result<()> {
+        self.sync()?;
+        let excess = self.entries.len().saturating_sub(size);
+        let ids: Vec<i64> = self.entries[..excess].iter().map(|&(id, _, _)| id).collect();
+        self.delete(&ids)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let last = self.entries.last().map_or(0, |&(id, _, _)| id);
+        let mut select = self.conn.prepare("SELECT id, line, time FROM history WHERE id > ?1 ORDER BY id")
+            .map_err(error)?;
+        let rows = select.query_map([last], |row| Ok((row.get(0)?, row.get(1)?, row.get::<_, Option<i64>>(2)?)))
+            .map_err(error)?;
+        for row in rows {
+            let (id, line, time) = row.map_err(error)?;
+            self.entries.push((id, line, time.map(|time| time as u64)));
+        }
+        Ok(())
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.path.display().to_string())
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::json::Value;
+use crate::exec::status_code;
+use crate::Shell;
+
+/// What a process of a pipeline cost, as `wait4` reports it.
+struct Usage {
+    user: Duration,
+    sys: Duration,
+    /// The most memory it had resident at once, in KiB.
+    max_rss: i64,
+}
+
+/// A process reaped by its watcher, with its wall time since it started.
+struct Reaped {
+    wall: Duration,
+    status: io::Result<i32>,
+    usage: Usage,
+}
+
+enum Measure {
+    /// A builtin, which ran inside the shell for this long.
+    Builtin(Duration),
+    Process(JoinHandle<Reaped>),
+}
+
+/// The stages of a foreground pipeline run under `set -o stats` or `time`.
+/// Each process is reaped by a thread of its own, so that the time it ends
+/// is seen whichever stage finishes first.
+#[derive(Default)]
+pub struct PipelineStats {
+    stages: Vec<(String, Measure)>,
+}
+
+fn duration(time: libc::timeval) -> Duration {
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
+
+/// Wait for the process `pid` like `Child::wait`, also collecting what it
+/// used.
+fn wait4(pid: libc::pid_t) -> (io::Result<i32>, Usage) {
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let res = loop {
+        match unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => break Err(io::Error::last_os_error()),
+            _ => break Ok(status_code(ExitStatus::from_raw(status))),
+        }
+    };
+    let usage = Usage { user: duration(usage.ru_utime), sys: duration(usage.ru_stime), max_rss: usage.ru_maxrss };
+    (res, usage)
+}
+
+impl PipelineStats {
+    /// Record a builtin stage that took `wall`.
+    pub fn builtin(&mut self, name: &str, wall: Duration) {
+        self.stages.push((name.to_owned(), Measure::Builtin(wall)));
+    }
+
+    /// Start watching the process `pid`, just started for the stage
+    /// `name`, returning the index of its status in what `finish` returns.
+    /// It is reaped here, so it must not be waited for elsewhere.
+    pub fn process(&mut self, name: &str, pid: u32) -> usize {
+        let pid = pid as libc::pid_t;
+        let start = Instant::now();
+        let watcher = thread::spawn(move || {
+            let (status, usage) = wait4(pid);
+            Reaped { wall: start.elapsed(), status, usage }
+        });
+        self.stages.push((name.to_owned(), Measure::Process(watcher)));
+        self.stages.len() - 1
+    }
+
+    /// Wait for every stage and, under `set -o stats`, report what each
+    /// took, to stderr or as a line of JSON appended to `$RSH_STATS_FILE`.
+    /// Returns the status of each stage that was a process.
+    pub fn finish(self, shell: &Shell) -> Vec<Option<i32>> {
+        let mut statuses = Vec::new();
+        let mut lines = Vec::new();
+        let mut stages = Vec::new();
+        for (name, measure) in self.stages {
+            let (wall, usage) = match measure {
+                Measure::Builtin(wall) => {
+                    statuses.push(None);
+                    (wall, None)
+                }
+                Measure::Process(watcher) => {
+                    let reaped = watcher.join().expect("the watcher thread panicked");
+                    statuses.push(Some(reaped.status.unwrap_or_else(|e| {
+                        eprintln!("shell: {}: {}", name, e);
+                        1
+                    })));
+                    (reaped.wall, Some(reaped.usage))
+                }
+            };
+            let mut members = vec![
+                (String::from("command"), Value::String(name.clone())),
+                (String::from("real"), Value::Number(format!("{:.6}", wall.as_secs_f64()))),
+            ];
+            let mut line = format!("{:<12} real {:>8.3}s", name, wall.as_secs_f64());
+            match usage {
+                Some(usage) => {
+                    members.push((String::from("user"), Value::Number(format!("{:.6}", usage.user.as_secs_f64()))));
+                    members.push((String::from("sys"), Value::Number(format!("{:.6}", usage.sys.as_secs_f64()))));
+                    members.push((String::from("max_rss_kb"), Value::Number(usage.max_rss.to_string())));
+                    line.push_str(&format!("  user {:>8.3}s  sys {:>8.3}s  max rss {:>8}k",
+                                           usage.user.as_secs_f64(), usage.sys.as_secs_f64(), usage.max_rss));
+                }
+                None => line.push_str("  (builtin)"),
+            }
+            stages.push(Value::Object(members));
+            lines.push(line);
+        }
+        if stages.is_empty() || !shell.options.stats {
+            return statuses;
+        }
+        match shell.vars.get("RSH_STATS_FILE").filter(|path| !path.is_empty()) {
+            Some(path) => {
+                let record = Value::Object(vec![(String::from("stages"), Value::Array(stages))]);
+                let res = OpenOptions::new().create(true).append(true).open(&path)
+                    .and_then(|mut file| writeln!(file, "{}", record));
+                if let Err(e) = res {
+                    eprintln!("shell: {}: {}", path, e);
+                }
+            }
+            None => {
+                for line in lines {
+                    eprintln!("stats: {}", line);
+                }
+            }
+        }
+        statuses
+    }
+}
+
+/// The user and system CPU time used so far by the shell and the children
+/// it has reaped.
+pub fn cpu_time() -> (Duration, Duration) {
+    let mut user = Duration::ZERO;
+    let mut sys = Duration::ZERO;
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += duration(usage.ru_utime);
+            sys += duration(usage.ru_stime);
+        }
+    }
+    (user, sys)
+}
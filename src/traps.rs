@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+/// Signals that arrived since `Traps::take_pending` last looked, one bit
+/// per signal number.
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// A pipe `record` writes a byte to, as `[read end, write end]`, so that
+/// `sleep` wakes up whichever thread the signal was delivered to.
+static WAKE: OnceLock<[libc::c_int; 2]> = OnceLock::new();
+
+extern "C" fn record(signal: libc::c_int) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+    if let Some(&[_, write]) = WAKE.get() {
+        unsafe { libc::write(write, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// The lowest-numbered signal that arrived and is waiting for its trap
+/// to run, if any.
+fn pending_signal() -> Option<Signal> {
+    let pending = PENDING.load(Ordering::SeqCst);
+    (pending != 0).then(|| Signal::try_from(pending.trailing_zeros() as i32).ok()).flatten()
+}
+
+/// Wait for `duration`, or until a trapped signal arrives, returning it.
+pub fn sleep(duration: Duration) -> Option<Signal> {
+    // A sleep too long to have a deadline lasts until a signal.
+    let deadline = Instant::now().checked_add(duration);
+    loop {
+        if let Some(signal) = pending_signal() {
+            return Some(signal);
+        }
+        let left = deadline.map_or(Duration::MAX, |deadline| deadline.saturating_duration_since(Instant::now()));
+        if left.is_zero() {
+            return None;
+        }
+        let Some(&[read, _]) = WAKE.get() else {
+            thread::sleep(left);
+            continue;
+        };
+        let mut fd = libc::pollfd { fd: read, events: libc::POLLIN, revents: 0 };
+        // Round up so as not to spin through the last millisecond.
+        let timeout = left.as_nanos().div_ceil(1_000_000).min(libc::c_int::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut fd, 1, timeout) } > 0 {
+            let mut buf = [0u8; 64];
+            while unsafe { libc::read(read, buf.as_mut_ptr().cast(), buf.len()) } > 0 {}
+        }
+    }
+}
+
+/// What a trap names: the shell exiting, or a signal.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    Exit,
+    Signal(Signal),
+}
+
+impl Condition {
+    /// Parse `EXIT`, `0`, a signal name with or without `SIG`, or a
+    /// signal number.
+    pub fn parse(spec: &str) -> Option<Condition> {
+        let upper = spec.to_ascii_uppercase();
+        if upper == "EXIT" || spec == "0" {
+            return Some(Condition::Exit);
+        }
+        let signal = match spec.parse::<i32>() {
+            Ok(n) => Signal::try_from(n).ok()?,
+            Err(_) if upper.starts_with("SIG") => Signal::from_str(&upper).ok()?,
+            Err(_) => Signal::from_str(&format!("SIG{}", upper)).ok()?,
+        };
+        Some(Condition::Signal(signal))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Condition::Exit => "EXIT",
+            Condition::Signal(signal) => signal.as_str(),
+        }
+    }
+}
+
+/// Commands set with the `trap` builtin. An empty command ignores the
+/// signal.
+#[derive(Default)]
+pub struct Traps {
+    actions: BTreeMap<Condition, String>,
+}
+
+impl Traps {
+    /// Run `action` on `condition` from now on.
+    pub fn set(&mut self, condition: Condition, action: String) -> nix::Result<()> {
+        if let Condition::Signal(signal) = condition {
+            if !action.is_empty() && WAKE.get().is_none() {
+                let mut fds = [0; 2];
+                if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+                    return Err(nix::Error::last());
+                }
+                let _ = WAKE.set(fds);
+            }
+            let handler = if action.is_empty() { SigHandler::SigIgn } else { SigHandler::Handler(record) };
+            let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+            unsafe { sigaction(signal, &action)? };
+        }
+        self.actions.insert(condition, action);
+        Ok(())
+    }
+
+    /// Restore the default behavior for `condition`.
+    pub fn reset(&mut self, condition: Condition) -> nix::Result<()> {
+        if let Condition::Signal(signal) = condition {
+            let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+            unsafe { sigaction(signal, &action)? };
+        }
+        self.actions.remove(&condition);
+        Ok(())
+    }
+
+    /// Drop the traps that run commands, as a subshell starts without
+    /// them. Ignored signals stay ignored.
+    pub fn reset_commands(&mut self) {
+        let trapped: Vec<Condition> = self.actions.iter()
+            .filter(|(_, action)| !action.is_empty())
+            .map(|(&condition, _)| condition)
+            .collect();
+        for condition in trapped {
+            let _ = self.reset(condition);
+        }
+    }
+
+    pub fn get(&self, condition: Condition) -> Option<&str> {
+        self.actions.get(&condition).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Condition, &str)> {
+        self.actions.iter().map(|(&condition, action)| (condition, action.as_str()))
+    }
+
+    /// The commands of the traps whose signals arrived since the last call.
+    pub fn take_pending(&self) -> Vec<String> {
+        let pending = PENDING.swap(0, Ordering::SeqCst);
+        if pending == 0 {
+            return Vec::new();
+        }
+        self.actions.iter()
+            .filter_map(|(condition, action)| match condition {
+                Condition::Signal(signal) if pending & (1 << *signal as i32) != 0 => Some(action.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::datetime::{self, Zone};
+use crate::Shell;
+
+/// A file in the trash, as its `.trashinfo` file describes it.
+pub struct Entry {
+    /// The name of the file in the trash.
+    pub name: String,
+    /// Where the file was before it was trashed.
+    pub path: PathBuf,
+    /// When it was trashed, as `YYYY-MM-DDThh:mm:ss` local time.
+    pub deleted: String,
+}
+
+/// The home trash of the FreeDesktop.org trash specification:
+/// `$XDG_DATA_HOME/Trash`, or `~/.local/share/Trash`.
+pub fn home(shell: &Shell) -> io::Result<PathBuf> {
+    let data = crate::data_home(shell)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no home directory"))?;
+    Ok(data.join("Trash"))
+}
+
+/// `path` with the bytes a URI can't hold as they are written `%XX`.
+fn encode(path: &Path) -> String {
+    let mut res = String::new();
+    for &byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => res.push(byte as char),
+            _ => res.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    res
+}
+
+fn decode(text: &str) -> PathBuf {
+    let bytes = text.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                res.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                res.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&res).into_owned())
+}
+
+/// Move `path` to the trash directory `trash`, recording where it came
+/// from so that it can be restored. Files on another file system than the
+/// trash can't be moved there.
+pub fn put(trash: &Path, path: &Path) -> io::Result<()> {
+    fs::create_dir_all(trash.join("files"))?;
+    fs::create_dir_all(trash.join("info"))?;
+    let original = std::path::absolute(path)?;
+    let base = original.file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot trash this path"))?
+        .to_string_lossy()
+        .into_owned();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let deleted = datetime::format(now.as_secs() as i64, 0, &Zone::Local, "%Y-%m-%dT%H:%M:%S")
+        .map_err(io::Error::other)?;
+    // Claim a name by creating its info file, trying `name.2`, `name.3`
+    // and so on while it is taken.
+    let mut name = base.clone();
+    let mut n = 1;
+    let info = loop {
+        let info = trash.join("info").join(format!("{}.trashinfo", name));
+        let taken = trash.join("files").join(&name).symlink_metadata().is_ok();
+        match fs::OpenOptions::new().write(true).create_new(true).open(&info) {
+            Ok(_) if !taken => break info,
+            Ok(_) => fs::remove_file(&info)?,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        n += 1;
+        name = format!("{}.{}", base, n);
+    };
+    let contents = format!("[Trash Info]\nPath={}\nDeletionDate={}\n", encode(&original), deleted);
+    let moved = fs::write(&info, contents).and_then(|_| fs::rename(path, trash.join("files").join(&name)));
+    if let Err(e) = moved {
+        let _ = fs::remove_file(&info);
+        return match e.raw_os_error() {
+            Some(libc::EXDEV) => Err(io::Error::other("on another file system than the trash")),
+            _ => Err(e),
+        };
+    }
+    Ok(())
+}
+
+/// Everything in `trash`, oldest first.
+pub fn list(trash: &Path) -> io::Result<Vec<Entry>> {
+    let info = trash.join("info");
+    let mut entries = Vec::new();
+    let files = match fs::read_dir(&info) {
+        Ok(files) => files,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e),
+    };
+    for file in files {
+        let file = file?.path();
+        let Some(name) = file.file_name().and_then(|name| name.to_str()?.strip_suffix(".trashinfo")) else {
+            continue;
+        };
+        let Ok(contents) = fs::read_to_string(&file) else {
+            continue;
+        };
+        let field = |key: &str| contents.lines().find_map(|line| line.strip_prefix(key)?.strip_prefix('='));
+        if let Some(path) = field("Path") {
+            entries.push(Entry {
+                name: name.to_owned(),
+                path: decode(path),
+                deleted: field("DeletionDate").unwrap_or("").to_owned(),
+            });
+        }
+    }
+    entries.sort_by(|a, b| (&a.deleted, &a.name).cmp(&(&b.deleted, &b.name)));
+    Ok(entries)
+}
+
+/// Move `entry` out of `trash` back where it was, unless something has
+/// taken its place.
+pub fn restore(trash: &Path, entry: &Entry) -> io::Result<()> {
+    if entry.path.symlink_metadata().is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "a file is in the way"));
+    }
+    if let Some(parent) = entry.path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(trash.join("files").join(&entry.name), &entry.path)?;
+    fs::remove_file(trash.join("info").join(format!("{}.trashinfo", entry.name)))
+}
+
+/// Delete everything in `trash` for good.
+pub fn empty(trash: &Path) -> io::Result<()> {
+    for sub in ["files", "info"] {
+        let files = match fs::read_dir(trash.join(sub)) {
+            Ok(files) => files,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for file in files {
+            let file = file?;
+            match file.file_type()?.is_dir() {
+                true => fs::remove_dir_all(file.path())?,
+                false => fs::remove_file(file.path())?,
+            }
+        }
+    }
+    Ok(())
+}
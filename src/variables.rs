@@ -0,0 +1,782 @@
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::arith::{self, Mode, Number};
+use crate::builtins::single_quote;
+use crate::error::ShellError;
+use crate::expand::unquote;
+use crate::options::Options;
+
+/// What a shell variable holds.
+#[derive(Clone)]
+enum Value {
+    Scalar(String),
+    /// An indexed array, whose elements need not be contiguous.
+    Array(BTreeMap<usize, String>),
+    /// An associative array, declared with `declare -A`.
+    Assoc(BTreeMap<String, String>),
+}
+
+/// The value of an assignment, once expanded: a string, or the elements
+/// of a `name=(word ...)` list, each with the subscript it was given as
+/// `[subscript]=word`, unexpanded.
+pub enum Assigned {
+    Scalar(String),
+    Array(Vec<(Option<String>, String)>),
+}
+
+/// Shell variables. Names that were never set in the shell fall back to
+/// the environment it was started with.
+#[derive(Clone)]
+pub struct Variables {
+    values: BTreeMap<String, Value>,
+    /// The variables made read-only by `readonly`.
+    readonly: BTreeSet<String>,
+    /// The variables given the integer attribute by `declare -i`.
+    integer: BTreeSet<String>,
+    /// Exit status of the last pipeline, expanded by `$?`.
+    pub status: i32,
+    /// The exit status of each stage of the last foreground pipeline,
+    /// expanded by `$PIPESTATUS`, and one by one by `${PIPESTATUS[n]}`.
+    pub pipe_status: Vec<i32>,
+    /// The shell's own pid, expanded by `$$`. Subshells keep their
+    /// parent's.
+    pid: u32,
+    /// Pid of the last background job, expanded by `$!`.
+    pub last_background: Option<u32>,
+    /// The name of the shell or script, expanded by `$0`.
+    pub name: String,
+    /// The positional parameters `$1`, `$2`, ...
+    pub positional: Vec<String>,
+    /// The line of the input the running command is on, expanded by
+    /// `$LINENO`.
+    pub line: usize,
+    /// The state of the generator behind `$RANDOM`.
+    random: Cell<u32>,
+    /// What `$SECONDS` was last set to, and since when it has been
+    /// counting up from there. The clock is stopped in deterministic mode.
+    seconds: (i64, Option<Instant>),
+}
+
+impl Default for Variables {
+    fn default() -> Self {
+        Variables {
+            values: BTreeMap::new(),
+            readonly: BTreeSet::new(),
+            integer: BTreeSet::new(),
+            status: 0,
+            pipe_status: vec![0],
+            pid: std::process::id(),
+            last_background: None,
+            name: std::env::args().next().unwrap_or_else(|| String::from("shell")),
+            positional: Vec::new(),
+            line: 0,
+            random: Cell::new(seed(SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos()
+                ^ std::process::id())),
+            seconds: (0, Some(Instant::now())),
+        }
+    }
+}
+
+/// A valid state of the `$RANDOM` generator made from `n`, which must not
+/// be 0 or a multiple of its modulus.
+fn seed(n: u32) -> u32 {
+    match n % 0x7fff_ffff {
+        0 => 123_459_876,
+        n => n,
+    }
+}
+
+impl Variables {
+    pub fn get(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "@" | "*" => Some(self.positional.join(" ")),
+            "0" => Some(self.name.clone()),
+            // The minimal standard generator of Park and Miller, as bash uses.
+            "RANDOM" => {
+                let next = (self.random.get() as u64 * 16807 % 0x7fff_ffff) as u32;
+                self.random.set(next);
+                Some((next & 0x7fff).to_string())
+            }
+            "LINENO" => Some(self.line.to_string()),
+            "PIPESTATUS" => Some(self.pipe_status.iter().map(i32::to_string).collect::<Vec<_>>().join(" ")),
+            "SECONDS" => {
+                let (start, since) = self.seconds;
+                Some((start + since.map_or(0, |since| since.elapsed().as_secs() as i64)).to_string())
+            }
+            _ if name.bytes().all(|b| b.is_ascii_digit()) => {
+                self.positional.get(name.parse::<usize>().ok()? - 1).cloned()
+            }
+            _ => match self.values.get(name) {
+                Some(Value::Scalar(value)) => Some(value.clone()),
+                // An array stands for its element 0.
+                Some(Value::Array(elements)) => elements.get(&0).cloned(),
+                Some(Value::Assoc(entries)) => entries.get("0").cloned(),
+                None => std::env::var(name).ok(),
+            },
+        }
+    }
+
+    /// The elements of the array `name`, in order of their indices. Other
+    /// variables are arrays of their one value.
+    pub fn elements(&self, name: &str) -> Vec<String> {
+        match (name, self.values.get(name)) {
+            ("@" | "*", _) => self.positional.clone(),
+            ("PIPESTATUS", _) => self.pipe_status.iter().map(i32::to_string).collect(),
+            (_, Some(Value::Array(elements))) => elements.values().cloned().collect(),
+            (_, Some(Value::Assoc(entries))) => entries.values().cloned().collect(),
+            _ => self.get(name).into_iter().collect(),
+        }
+    }
+
+    /// Element `index` of the array `name`. Negative indices count back
+    /// from the end.
+    pub fn element(&self, name: &str, index: i64) -> Option<String> {
+        let end = match self.values.get(name) {
+            Some(Value::Array(elements)) => elements.keys().next_back().map_or(0, |&last| last + 1),
+            _ => self.elements(name).len(),
+        };
+        let index = usize::try_from(if index < 0 { end as i64 + index } else { index }).ok()?;
+        match self.values.get(name) {
+            Some(Value::Array(elements)) => elements.get(&index).cloned(),
+            _ => self.elements(name).into_iter().nth(index),
+        }
+    }
+
+    /// Set `name` to `value`. Variables inherited from the environment stay
+    /// exported, so children see the new value too.
+    pub fn set(&mut self, name: &str, value: String) {
+        // Assigning these sets where they count from rather than a value.
+        match name {
+            "RANDOM" => {
+                self.random.set(seed(value.trim().parse::<i64>().unwrap_or(0) as u32));
+                return;
+            }
+            "SECONDS" => {
+                self.seconds = (value.trim().parse().unwrap_or(0), self.seconds.1.map(|_| Instant::now()));
+                return;
+            }
+            _ => {}
+        }
+        match self.values.get_mut(name) {
+            Some(Value::Array(elements)) => {
+                elements.insert(0, value);
+                return;
+            }
+            Some(Value::Assoc(entries)) => {
+                entries.insert(String::from("0"), value);
+                return;
+            }
+            _ => {}
+        }
+        if std::env::var_os(name).is_some() {
+            std::env::set_var(name, &value);
+        }
+        self.values.insert(name.to_owned(), Value::Scalar(value));
+    }
+
+    /// Whether `name` is an array of either kind.
+    pub fn is_array(&self, name: &str) -> bool {
+        matches!(self.values.get(name), Some(Value::Array(_) | Value::Assoc(_)))
+    }
+
+    /// Whether `name` is an associative array.
+    pub fn is_assoc(&self, name: &str) -> bool {
+        matches!(self.values.get(name), Some(Value::Assoc(_)))
+    }
+
+    /// Make `name` an indexed array, or an associative one with `assoc`.
+    /// A variable that is set keeps its value as element 0, but an array
+    /// can't change between the two kinds.
+    pub fn declare(&mut self, name: &str, assoc: bool) -> Result<(), ShellError> {
+        match (self.values.get(name), assoc) {
+            (Some(Value::Array(_)), false) | (Some(Value::Assoc(_)), true) => Ok(()),
+            (Some(Value::Array(_)), true) => {
+                Err(ShellError::expansion(name, "cannot convert indexed to associative array"))
+            }
+            (Some(Value::Assoc(_)), false) => {
+                Err(ShellError::expansion(name, "cannot convert associative to indexed array"))
+            }
+            (_, false) => {
+                self.array_mut(name);
+                Ok(())
+            }
+            (_, true) => {
+                let entries = self.get(name).map(|value| (String::from("0"), value)).into_iter().collect();
+                std::env::remove_var(name);
+                self.values.insert(name.to_owned(), Value::Assoc(entries));
+                Ok(())
+            }
+        }
+    }
+
+    /// Remove every element of the array `name`, keeping its kind, or make
+    /// it an empty indexed array.
+    pub fn clear(&mut self, name: &str) {
+        match self.values.get_mut(name) {
+            Some(Value::Assoc(entries)) => entries.clear(),
+            _ => self.array_mut(name).clear(),
+        }
+    }
+
+    /// Add `value` after the last element of the array `name`.
+    pub fn push(&mut self, name: &str, value: String) -> Result<(), ShellError> {
+        if self.is_assoc(name) {
+            return Err(ShellError::expansion(name, "must use subscript when assigning associative array"));
+        }
+        let array = self.array_mut(name);
+        let end = array.keys().next_back().map_or(0, |&last| last + 1);
+        array.insert(end, value);
+        Ok(())
+    }
+
+    /// Set element `index` of the array `name`. Negative indices count back
+    /// from the end.
+    pub fn set_element(&mut self, name: &str, index: i64, value: String) -> Result<(), ShellError> {
+        let array = self.array_mut(name);
+        let end = array.keys().next_back().map_or(0, |&last| last + 1);
+        let index = usize::try_from(if index < 0 { end as i64 + index } else { index })
+            .map_err(|_| ShellError::expansion(&format!("{}[{}]", name, index), "bad array subscript"))?;
+        array.insert(index, value);
+        Ok(())
+    }
+
+    /// The value at `key` of the associative array `name`.
+    pub fn entry(&self, name: &str, key: &str) -> Option<String> {
+        match self.values.get(name) {
+            Some(Value::Assoc(entries)) => entries.get(key).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Set `key` of the associative array `name` to `value`.
+    pub fn set_entry(&mut self, name: &str, key: String, value: String) {
+        if let Some(Value::Assoc(entries)) = self.values.get_mut(name) {
+            entries.insert(key, value);
+        }
+    }
+
+    /// The keys of the array `name`, in order: the indices of an indexed
+    /// array, or the keys of an associative one.
+    pub fn keys(&self, name: &str) -> Vec<String> {
+        match self.values.get(name) {
+            Some(Value::Array(elements)) => elements.keys().map(usize::to_string).collect(),
+            Some(Value::Assoc(entries)) => entries.keys().cloned().collect(),
+            _ => (0..self.elements(name).len()).map(|index| index.to_string()).collect(),
+        }
+    }
+
+    /// The elements of the indexed array `name`, which it becomes if it
+    /// isn't one, with any value it had as element 0. Arrays can't be
+    /// exported, so one that was leaves the environment.
+    fn array_mut(&mut self, name: &str) -> &mut BTreeMap<usize, String> {
+        if !matches!(self.values.get(name), Some(Value::Array(_))) {
+            let elements = self.get(name).map(|value| (0, value)).into_iter().collect();
+            std::env::remove_var(name);
+            self.values.insert(name.to_owned(), Value::Array(elements));
+        }
+        match self.values.get_mut(name) {
+            Some(Value::Array(elements)) => elements,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Set `name` to `value` and export it to children.
+    pub fn export(&mut self, name: &str, value: String) {
+        std::env::set_var(name, &value);
+        self.values.insert(name.to_owned(), Value::Scalar(value));
+    }
+
+    /// Stop exporting `name` to children. It keeps its value in the shell.
+    pub fn unexport(&mut self, name: &str) {
+        if let Some(value) = std::env::var_os(name) {
+            self.values.entry(name.to_owned()).or_insert_with(|| Value::Scalar(value.to_string_lossy().into_owned()));
+            std::env::remove_var(name);
+        }
+    }
+
+    /// Seed `$RANDOM` the same way on every run and stop `$SECONDS` at 0,
+    /// for deterministic mode.
+    pub fn freeze(&mut self) {
+        self.random.set(seed(0));
+        self.seconds = (0, None);
+    }
+
+    /// Remove `name` from the shell and the environment.
+    pub fn unset(&mut self, name: &str) {
+        self.values.remove(name);
+        self.integer.remove(name);
+        std::env::remove_var(name);
+    }
+
+    /// Remove element `index` of the array `name`. Negative indices count
+    /// back from the end.
+    pub fn unset_element(&mut self, name: &str, index: i64) {
+        if let Some(Value::Array(elements)) = self.values.get_mut(name) {
+            let end = elements.keys().next_back().map_or(0, |&last| last + 1);
+            if let Ok(index) = usize::try_from(if index < 0 { end as i64 + index } else { index }) {
+                elements.remove(&index);
+            }
+        }
+    }
+
+    /// Remove `key` from the associative array `name`.
+    pub fn unset_entry(&mut self, name: &str, key: &str) {
+        if let Some(Value::Assoc(entries)) = self.values.get_mut(name) {
+            entries.remove(key);
+        }
+    }
+
+    /// Keep `name` from being assigned or unset from now on.
+    pub fn set_readonly(&mut self, name: &str) {
+        self.readonly.insert(name.to_owned());
+    }
+
+    /// The read-only variables, by name.
+    pub fn readonly(&self) -> impl Iterator<Item = &str> {
+        self.readonly.iter().map(String::as_str)
+    }
+
+    /// Make assignments to `name` work out their values as arithmetic.
+    pub fn set_integer(&mut self, name: &str) {
+        self.integer.insert(name.to_owned());
+    }
+
+    pub fn is_integer(&self, name: &str) -> bool {
+        self.integer.contains(name)
+    }
+
+    /// The names of the shell's variables and those of its environment.
+    pub fn names(&self) -> Vec<String> {
+        let mut names: BTreeSet<String> = self.values.keys().cloned().collect();
+        names.extend(std::env::vars_os().filter_map(|(name, _)| name.into_string().ok()));
+        names.into_iter().collect()
+    }
+
+    /// `name` as a `declare` command that recreates it with its value and
+    /// attributes, or `None` if it has neither.
+    pub fn declaration(&self, name: &str) -> Option<String> {
+        let mut flags = String::new();
+        let value = match self.values.get(name) {
+            Some(Value::Array(elements)) => {
+                flags.push('a');
+                let elements: Vec<String> = elements.iter()
+                    .map(|(index, value)| format!("[{}]={}", index, single_quote(value)))
+                    .collect();
+                Some(format!("({})", elements.join(" ")))
+            }
+            Some(Value::Assoc(entries)) => {
+                flags.push('A');
+                let entries: Vec<String> = entries.iter()
+                    .map(|(key, value)| match !key.is_empty() && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        true => format!("[{}]={}", key, single_quote(value)),
+                        false => format!("[{}]={}", single_quote(key), single_quote(value)),
+                    })
+                    .collect();
+                Some(format!("({})", entries.join(" ")))
+            }
+            Some(Value::Scalar(value)) => Some(single_quote(value)),
+            None => std::env::var(name).ok().map(|value| single_quote(&value)),
+        };
+        for (flag, set) in [('i', self.is_integer(name)), ('r', self.readonly.contains(name))] {
+            if set {
+                flags.push(flag);
+            }
+        }
+        if std::env::var_os(name).is_some() {
+            flags.push('x');
+        }
+        if value.is_none() && flags.is_empty() {
+            return None;
+        }
+        let flags = if flags.is_empty() { String::from("-") } else { flags };
+        Some(match value {
+            Some(value) => format!("declare -{} {}={}", flags, name, value),
+            None => format!("declare -{} {}", flags, name),
+        })
+    }
+
+    /// Fail if `name` is read-only.
+    pub fn writable(&self, name: &str) -> Result<(), ShellError> {
+        match self.readonly.contains(name) {
+            true => Err(ShellError::Readonly(name.to_owned())),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Whether `word` is a valid variable name: a letter or underscore
+/// followed by letters, digits and underscores.
+pub fn is_name(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split a `name=value` word into its parts, if it is an assignment. The
+/// name may have a subscript, as in `name[index]=value`, and ends in `+`
+/// for `name+=value`.
+pub fn assignment(word: &str) -> Option<(&str, &str)> {
+    word.split_once('=').filter(|(name, _)| {
+        let name = name.strip_suffix('+').unwrap_or(name);
+        is_name(name) || subscript(name).is_some_and(|(_, index)| index != "@" && index != "*")
+    })
+}
+
+/// Split `name[index]` into the array's name and the index as written.
+fn subscript(name: &str) -> Option<(&str, &str)> {
+    name.strip_suffix(']')?
+        .split_once('[')
+        .filter(|(name, index)| is_name(name) && !index.is_empty())
+}
+
+/// Where an element of an array is.
+enum Key {
+    Index(i64),
+    Name(String),
+}
+
+/// Work out the subscript `index` of an element of `name`, after its
+/// variables are substituted: a key of an associative array, or else an
+/// arithmetic expression.
+fn key(name: &str, index: &str, vars: &Variables, options: &Options) -> Result<Key, ShellError> {
+    let expr = unquote(&substitute(index, vars, options)?);
+    if vars.is_assoc(name) {
+        return Ok(Key::Name(expr));
+    }
+    match arith::eval(&expr, Mode::Integer, &|name| vars.get(name)) {
+        Ok(Number::Int(n)) => Ok(Key::Index(n)),
+        Ok(Number::Float(x)) => Ok(Key::Index(x as i64)),
+        Err(e) => Err(ShellError::expansion(&format!("{}[{}]", name, index), e)),
+    }
+}
+
+/// The element of `name` at the subscript `index`.
+fn element(name: &str, index: &str, vars: &Variables, options: &Options) -> Result<Option<String>, ShellError> {
+    Ok(match key(name, index, vars, options)? {
+        Key::Index(index) => vars.element(name, index),
+        Key::Name(key) => vars.entry(name, &key),
+    })
+}
+
+/// The value to store in `name` for the assigned `value`, or for `value`
+/// added to `old` with `+=`: worked out as arithmetic if `name` is an
+/// integer variable, or else appended.
+fn combine(vars: &Variables, name: &str, value: String, old: Option<String>) -> Result<String, ShellError> {
+    if !vars.is_integer(name) {
+        return Ok(old.unwrap_or_default() + &value);
+    }
+    let or_zero = |expr: String| if expr.trim().is_empty() { String::from("0") } else { expr };
+    let expr = match old {
+        Some(old) => format!("({}) + ({})", or_zero(old), or_zero(value)),
+        None => or_zero(value),
+    };
+    match arith::eval(&expr, Mode::Integer, &|name| vars.get(name)) {
+        Ok(n) => Ok(n.to_string()),
+        Err(e) => Err(ShellError::expansion(name, e)),
+    }
+}
+
+/// Set the element of `name` at the subscript `index` to `value`, or add
+/// `value` to it with `append`.
+fn set_element(vars: &mut Variables, options: &Options, name: &str, index: &str, value: String, append: bool)
+-> Result<(), ShellError> {
+    let old = match append {
+        true => Some(element(name, index, vars, options)?.unwrap_or_default()),
+        false => None,
+    };
+    let value = combine(vars, name, value, old)?;
+    match key(name, index, vars, options)? {
+        Key::Index(index) => vars.set_element(name, index, value),
+        Key::Name(key) => {
+            vars.set_entry(name, key, value);
+            Ok(())
+        }
+    }
+}
+
+/// Assign `value` to `target`, the name of an assignment as split off by
+/// `assignment`. A list replaces the elements of an array, or with `+=`
+/// adds to them.
+pub fn assign(vars: &mut Variables, options: &Options, target: &str, value: Assigned) -> Result<(), ShellError> {
+    let (name, append) = match target.strip_suffix('+') {
+        Some(name) => (name, true),
+        None => (target, false),
+    };
+    vars.writable(subscript(name).map_or(name, |(array, _)| array))?;
+    if let Some((array, index)) = subscript(name) {
+        let Assigned::Scalar(value) = value else {
+            return Err(ShellError::expansion(name, "cannot assign list to array member"));
+        };
+        return set_element(vars, options, array, index, value, append);
+    }
+    match (value, append) {
+        (Assigned::Scalar(value), append) => {
+            let old = append.then(|| vars.get(name).unwrap_or_default());
+            let value = combine(vars, name, value, old)?;
+            vars.set(name, value);
+        }
+        (Assigned::Array(elements), append) => {
+            if !append {
+                vars.clear(name);
+            }
+            for (index, value) in elements {
+                match index {
+                    Some(index) => set_element(vars, options, name, &index, value, false)?,
+                    None => {
+                        let value = combine(vars, name, value, None)?;
+                        vars.push(name, value)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Work out the arithmetic expression `expr` of `((expr))` or `let`,
+/// after its variables are substituted, and store the variables it
+/// assigns.
+pub fn arithmetic(vars: &mut Variables, options: &Options, expr: &str) -> Result<Number, ShellError> {
+    let expr = unquote(&substitute(expr, vars, options)?);
+    let (value, assigned) = arith::eval_assigning(&expr, Mode::Integer, &|name| vars.get(name))
+        .map_err(|e| ShellError::expansion(expr.trim(), e))?;
+    for (name, n) in assigned {
+        assign(vars, options, &name, Assigned::Scalar(n.to_string()))?;
+    }
+    Ok(value)
+}
+
+/// Unset `target`: a variable, or one element of an array as
+/// `name[index]`.
+pub fn unset(vars: &mut Variables, options: &Options, target: &str) -> Result<(), ShellError> {
+    let Some((name, index)) = subscript(target) else {
+        vars.writable(target)?;
+        vars.unset(target);
+        return Ok(());
+    };
+    vars.writable(name)?;
+    match key(name, index, vars, options)? {
+        Key::Index(index) => vars.unset_element(name, index),
+        Key::Name(key) => vars.unset_entry(name, &key),
+    }
+    Ok(())
+}
+
+/// Whether `name` is a special parameter such as `?`, or a positional
+/// parameter.
+fn is_special(name: &str) -> bool {
+    matches!(name, "?" | "$" | "!" | "#" | "@" | "*")
+        || (!name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// The parameter named right after a `$`, as `name` or `{name}`, with the
+/// number of characters it spans. Inside braces, the name may also be an
+/// element `name[index]`, start with `#` for the length of the value, or
+/// be `!name[@]` for the keys of an array.
+fn parameter(rest: &[char]) -> Option<(String, usize)> {
+    if rest.first() == Some(&'{') {
+        let end = rest.iter().position(|&c| c == '}')?;
+        let name: String = rest[1..end].iter().collect();
+        let target = name.strip_prefix('#').filter(|target| !target.is_empty()).unwrap_or(&name);
+        let keys = name.strip_prefix('!')
+            .and_then(subscript)
+            .is_some_and(|(_, index)| index == "@" || index == "*");
+        let valid = keys || is_name(target) || is_special(target) || subscript(target).is_some();
+        return valid.then_some((name, end + 1));
+    }
+    if let Some(&c) = rest.first().filter(|c| is_special(&c.to_string())) {
+        return Some((c.to_string(), 1));
+    }
+    let len = rest.iter()
+        .enumerate()
+        .take_while(|&(i, c)| c.is_ascii_alphabetic() || *c == '_' || (i > 0 && c.is_ascii_digit()))
+        .count();
+    (len > 0).then(|| (rest[..len].iter().collect(), len))
+}
+
+/// The expression of a `((expr))` right after a `$`, with the number of
+/// characters it spans. Assignments in it are worked out but not kept;
+/// those of `((expr))` commands and `let` are.
+fn arithmetic_expansion(rest: &[char]) -> Option<(String, usize)> {
+    if !rest.starts_with(&['(', '(']) {
+        return None;
+    }
+    let mut depth = 0;
+    for (i, &c) in rest.iter().enumerate() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => continue,
+        }
+        if depth == 0 {
+            return (i >= 3 && rest[i - 1] == ')').then(|| (rest[2..i - 1].iter().collect(), i + 1));
+        }
+    }
+    None
+}
+
+/// Escape `value` so that quote removal turns it back into the same text.
+/// Outside quotes, glob characters are left active, as POSIX requires for
+/// unquoted expansions; only a leading `~` is kept from being unfolded.
+fn quote_value(value: &str, in_double_quotes: bool) -> String {
+    let special = if in_double_quotes { "\\\"$`" } else { "\\\"'" };
+    let mut res = String::with_capacity(value.len());
+    for (i, c) in value.chars().enumerate() {
+        if special.contains(c) || (!in_double_quotes && i == 0 && c == '~') {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res
+}
+
+/// Replace `$name` and `${name}` in `word` with the variables' values.
+/// Single-quoted and escaped dollars are left alone. Unset variables
+/// expand to nothing, or are an error under `set -u`.
+pub fn substitute(word: &str, vars: &Variables, options: &Options) -> Result<String, ShellError> {
+    Ok(expand_parameters(word, vars, options, None)?.join(" "))
+}
+
+/// Like `substitute`, but `$@` expands to one field per positional
+/// parameter, and `${name[@]}` and `${!name[@]}` to one per element or
+/// key, even inside double quotes; the text around them goes with the
+/// first and last ones. A `"$@"` without parameters leaves no field.
+/// Unquoted expansions are split into fields at the characters of `$IFS`
+/// (blanks and newlines when it is unset).
+pub fn substitute_fields(word: &str, vars: &Variables, options: &Options) -> Result<Vec<String>, ShellError> {
+    let ifs = vars.get("IFS").unwrap_or_else(|| String::from(" \t\n"));
+    expand_parameters(word, vars, options, Some(&ifs))
+}
+
+/// Add the unquoted expansion `value` to the field being built in `res`,
+/// starting new fields at its separators: IFS whitespace around at most
+/// one other IFS character. Runs of whitespace only separate fields, while
+/// other characters also delimit empty ones, so `a::b` with `IFS=:` is
+/// three fields.
+fn split_fields(value: &str, ifs: &str, res: &mut String, fields: &mut Vec<String>) {
+    let space = |c: char| c.is_whitespace() && ifs.contains(c);
+    let mut chars = value.chars().peekable();
+    while chars.peek().is_some() {
+        let mut text = String::new();
+        while let Some(c) = chars.next_if(|&c| !ifs.contains(c)) {
+            text.push(c);
+        }
+        res.push_str(&quote_value(&text, false));
+        let Some(c) = chars.next() else {
+            break;
+        };
+        let mut delimits = !space(c);
+        while let Some(c) = chars.next_if(|&c| space(c) || (!delimits && ifs.contains(c))) {
+            delimits |= !space(c);
+        }
+        if delimits || !res.is_empty() {
+            let field = std::mem::take(res);
+            fields.push(if field.is_empty() { String::from("\"\"") } else { field });
+        }
+    }
+}
+
+/// What a parameter expands to.
+enum Expansion {
+    Value(String),
+    /// One field per element, as `"$@"` and `"${name[@]}"` give.
+    Fields(Vec<String>),
+}
+
+/// Look up the parameter `name` as `parameter` found it.
+fn lookup(name: &str, vars: &Variables, options: &Options) -> Result<Expansion, ShellError> {
+    if let Some(target) = name.strip_prefix('#').filter(|target| !target.is_empty()) {
+        let len = match lookup(target, vars, options)? {
+            Expansion::Value(value) => value.chars().count(),
+            Expansion::Fields(fields) => fields.len(),
+        };
+        return Ok(Expansion::Value(len.to_string()));
+    }
+    match name.strip_prefix('!').and_then(subscript) {
+        Some((array, "@")) => return Ok(Expansion::Fields(vars.keys(array))),
+        Some((array, _)) => return Ok(Expansion::Value(vars.keys(array).join(" "))),
+        None => {}
+    }
+    let value = match subscript(name) {
+        _ if name == "@" => return Ok(Expansion::Fields(vars.positional.clone())),
+        Some((array, "@")) => return Ok(Expansion::Fields(vars.elements(array))),
+        Some((array, "*")) => Some(vars.elements(array).join(" ")),
+        Some((array, index)) => element(array, index, vars, options)?,
+        None => vars.get(name),
+    };
+    match value {
+        Some(value) => Ok(Expansion::Value(value)),
+        None if options.nounset => Err(ShellError::expansion(name, "unbound variable")),
+        None => Ok(Expansion::Value(String::new())),
+    }
+}
+
+fn expand_parameters(word: &str, vars: &Variables, options: &Options, ifs: Option<&str>)
+-> Result<Vec<String>, ShellError> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut fields = Vec::new();
+    let mut res = String::with_capacity(word.len());
+    let mut quote: Option<char> = None;
+    let mut empty_at = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        match (quote, ch) {
+            (Some(q), _) if ch == q => quote = None,
+            (Some('\''), _) => {}
+            (None, '\'' | '"') => quote = Some(ch),
+            (_, '\\') => {
+                res.extend(&chars[i..(i + 2).min(chars.len())]);
+                i += 2;
+                continue;
+            }
+            (_, '$') => {
+                if let Some((expr, len)) = arithmetic_expansion(&chars[i + 1..]) {
+                    let expr = unquote(&substitute(&expr, vars, options)?);
+                    let value = arith::eval(&expr, Mode::Integer, &|name| vars.get(name))
+                        .map_err(|e| ShellError::expansion(expr.trim(), e))?;
+                    res.push_str(&value.to_string());
+                    i += 1 + len;
+                    continue;
+                }
+                if let Some((name, len)) = parameter(&chars[i + 1..]) {
+                    i += 1 + len;
+                    let values = match lookup(&name, vars, options)? {
+                        Expansion::Value(value) => vec![value],
+                        Expansion::Fields(values) => {
+                            empty_at |= values.is_empty();
+                            values
+                        }
+                    };
+                    for (n, value) in values.iter().enumerate() {
+                        if n > 0 {
+                            // Close the quotes around this field and reopen
+                            // them for the next.
+                            let reopen = quote.map(String::from).unwrap_or_default();
+                            res.push_str(&reopen);
+                            fields.push(std::mem::replace(&mut res, reopen));
+                        }
+                        match ifs.filter(|_| quote.is_none()) {
+                            Some(ifs) => split_fields(value, ifs, &mut res, &mut fields),
+                            None => res.push_str(&quote_value(value, quote.is_some())),
+                        }
+                    }
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        res.push(ch);
+        i += 1;
+    }
+    if !(empty_at && fields.is_empty() && res == "\"\"") {
+        fields.push(res);
+    }
+    Ok(fields)
+}
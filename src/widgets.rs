@@ -0,0 +1,203 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use rustyline::{Cmd, ConditionalEventHandler, Event, EventContext, InputMode, Movement, RepeatCount};
+
+use crate::aliases::{Abbreviations, Aliases};
+use crate::error::ShellError;
+use crate::globbing::Globber;
+use crate::history::History;
+use crate::options::Options;
+use crate::variables::{self, Variables};
+use crate::{NamedDirs, PathMatcher, Shell};
+
+/// The shell state line editing widgets work from, copied before each
+/// prompt since the widgets run inside the line editor.
+#[derive(Default)]
+pub struct Snapshot {
+    aliases: Aliases,
+    abbreviations: Abbreviations,
+    vars: Variables,
+    options: Options,
+    named_dirs: NamedDirs,
+    globber: Arc<Globber>,
+    history: Arc<Mutex<History>>,
+}
+
+impl Snapshot {
+    pub fn take(shell: &Shell) -> Self {
+        Snapshot {
+            aliases: shell.aliases.clone(),
+            abbreviations: shell.abbreviations.clone(),
+            vars: shell.vars.clone(),
+            options: shell.options.clone(),
+            named_dirs: shell.named_dirs.clone(),
+            globber: shell.globber.clone(),
+            history: shell.history.clone(),
+        }
+    }
+}
+
+/// A piece of a command line: a word, or the blanks and operators between
+/// words.
+enum Piece<'a> {
+    Word(&'a str),
+    Between(&'a str),
+}
+
+/// Split `line` into words and what separates them, honoring quotes.
+fn pieces(line: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut start = 0;
+    let mut in_word = false;
+    for (i, ch) in line.char_indices() {
+        let separator = quote.is_none() && !escaped && (ch.is_whitespace() || "|;&<>()".contains(ch));
+        match quote {
+            _ if escaped => escaped = false,
+            Some(q) if ch == q => quote = None,
+            Some(_) => {}
+            None if ch == '\\' => escaped = true,
+            None if ch == '"' || ch == '\'' => quote = Some(ch),
+            None => {}
+        }
+        if separator == in_word && i > start {
+            pieces.push(if in_word { Piece::Word(&line[start..i]) } else { Piece::Between(&line[start..i]) });
+            start = i;
+        }
+        in_word = !separator;
+    }
+    if start < line.len() {
+        pieces.push(if in_word { Piece::Word(&line[start..]) } else { Piece::Between(&line[start..]) });
+    }
+    pieces
+}
+
+/// Expand history references, aliases in command position, variables,
+/// `~` and globs in `line`, leaving the rest as typed.
+fn expand_line(snapshot: &Snapshot, line: &str) -> Result<String, ShellError> {
+    let line = match snapshot.history.lock().unwrap().expand(line)? {
+        Some(expanded) => expanded,
+        None => line.to_owned(),
+    };
+    let mut res = String::with_capacity(line.len());
+    let mut command_position = true;
+    for piece in pieces(&line) {
+        let word = match piece {
+            Piece::Between(text) => {
+                res.push_str(text);
+                command_position |= text.contains(['|', ';', '&', '(']);
+                continue;
+            }
+            Piece::Word(word) => word,
+        };
+        let mut word = word.to_owned();
+        if command_position {
+            // An alias may name another, but not itself.
+            let mut seen = BTreeSet::new();
+            while let Some(value) = snapshot.aliases.get(&word).filter(|_| seen.insert(word.clone())) {
+                word = value.clone();
+            }
+            command_position = false;
+        }
+        let substituted = variables::substitute(&word, &snapshot.vars, &snapshot.options)?;
+        let unfolded = substituted.unfold(&snapshot.named_dirs);
+        let globbed = !unfolded.contains(['"', '\'', '\\']) && unfolded.contains(['*', '?', '[']);
+        let matches = match globbed {
+            true => snapshot.globber.glob(&unfolded, snapshot.options.glob()).unwrap_or_default(),
+            false => Vec::new(),
+        };
+        if matches.is_empty() {
+            res.push_str(&unfolded);
+        } else {
+            let quoted: Vec<String> = matches.iter().map(|path| quote(path)).collect();
+            res.push_str(&quoted.join(" "));
+        }
+    }
+    Ok(res)
+}
+
+/// `path` quoted if the shell would otherwise split or expand it.
+fn quote(path: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "%+,-./:=@_^~".contains(c);
+    match path.chars().all(plain) {
+        true => path.to_owned(),
+        false => crate::builtins::single_quote(path),
+    }
+}
+
+/// Replace the line being edited with its expansion, so that the user can
+/// see exactly what would run. Bound to Ctrl-Alt-E.
+pub struct ExpandLine(pub Arc<Mutex<Snapshot>>);
+
+impl ConditionalEventHandler for ExpandLine {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        let snapshot = self.0.lock().unwrap();
+        match expand_line(&snapshot, ctx.line()) {
+            Ok(expanded) if expanded != ctx.line() => Some(Cmd::Replace(Movement::WholeLine, Some(expanded))),
+            _ => Some(Cmd::Noop),
+        }
+    }
+}
+
+/// The text of the abbreviation typed as the word that ends at `pos` in
+/// `line`, and where that word starts, if it is in command position.
+pub fn abbreviation<'a>(abbreviations: &'a Abbreviations, line: &str, pos: usize) -> Option<(usize, &'a str)> {
+    let mut command_position = true;
+    let mut start = 0;
+    let mut last = None;
+    for piece in pieces(&line[..pos]) {
+        match piece {
+            Piece::Between(text) => {
+                command_position |= text.contains(['|', ';', '&', '(']);
+                last = None;
+                start += text.len();
+            }
+            Piece::Word(word) => {
+                last = Some((start, word)).filter(|_| command_position);
+                command_position = false;
+                start += word.len();
+            }
+        }
+    }
+    let (start, word) = last?;
+    abbreviations.get(word).map(|text| (start, text.as_str()))
+}
+
+/// Type the text of a macro bound with `bind`. The editor can't both type
+/// and accept the line, so a macro ending in a newline accepts it and
+/// leaves the rest of its text, with where it goes, in `pending` for the
+/// line reader to put in.
+pub struct Macro {
+    pub text: String,
+    pub pending: Arc<Mutex<Option<(usize, String)>>>,
+}
+
+impl ConditionalEventHandler for Macro {
+    fn handle(&self, _evt: &Event, n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        match self.text.strip_suffix(['\n', '\r']) {
+            Some(text) => {
+                *self.pending.lock().unwrap() = Some((ctx.pos(), text.to_owned()));
+                Some(Cmd::AcceptLine)
+            }
+            None => Some(Cmd::Insert(n, self.text.clone())),
+        }
+    }
+}
+
+/// Replace an abbreviation typed in command position with its text when
+/// Space is pressed after it, by completing it. Enter expands it as the
+/// line is read.
+pub struct ExpandAbbreviation(pub Arc<Mutex<Snapshot>>);
+
+impl ConditionalEventHandler for ExpandAbbreviation {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        let snapshot = self.0.lock().unwrap();
+        // Space moves the cursor in vi command mode.
+        if ctx.input_mode() == InputMode::Command {
+            return None;
+        }
+        abbreviation(&snapshot.abbreviations, ctx.line(), ctx.pos()).map(|_| Cmd::Complete)
+    }
+}
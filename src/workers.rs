@@ -0,0 +1,73 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of threads for work that would otherwise hold up the line
+/// editor, such as scanning directories for completions.
+pub struct Pool {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl Pool {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..threads {
+            let queue = queue.clone();
+            thread::spawn(move || loop {
+                let job = queue.lock().unwrap().recv();
+                match job {
+                    Ok(job) => job(),
+                    Err(_) => return,
+                }
+            });
+        }
+        Pool { jobs }
+    }
+
+    /// Queue `job`. It is skipped if the returned task has been dropped by
+    /// the time a thread picks it up, and can poll `Cancel` to stop early
+    /// after that.
+    pub fn submit<T: Send + 'static>(&self, job: impl FnOnce(&Cancel) -> T + Send + 'static) -> Task<T> {
+        let cancel = Cancel(Arc::new(AtomicBool::new(false)));
+        let (sender, result) = mpsc::channel();
+        let token = Cancel(cancel.0.clone());
+        let _ = self.jobs.send(Box::new(move || {
+            if !token.is_cancelled() {
+                let _ = sender.send(job(&token));
+            }
+        }));
+        Task { result, cancel }
+    }
+}
+
+/// Tells a job whether its result is still wanted.
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The pending result of a job. Dropping it cancels the job.
+pub struct Task<T> {
+    result: mpsc::Receiver<T>,
+    cancel: Cancel,
+}
+
+impl<T> Task<T> {
+    /// The result, if the job finishes within `timeout`.
+    pub fn wait(&self, timeout: Duration) -> Option<T> {
+        self.result.recv_timeout(timeout).ok()
+    }
+}
+
+impl<T> Drop for Task<T> {
+    fn drop(&mut self) {
+        self.cancel.0.store(true, Ordering::Relaxed);
+    }
+}
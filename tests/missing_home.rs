@@ -0,0 +1,34 @@
+//! The shell without `$HOME`, run as a child so that the environment of
+//! the other tests is left alone.
+
+use std::process::Command;
+
+/// What `echo` prints for `words` in a shell started with `home` as
+/// `$HOME`, or without it.
+fn echo(home: Option<&str>, words: &str) -> String {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rust_shell"));
+    command.args(["--norc", "-c", &format!("echo {}", words)]);
+    match home {
+        Some(home) => command.env("HOME", home),
+        None => command.env_remove("HOME"),
+    };
+    let output = command.output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn tilde_without_home() {
+    // The password database may still know the home directory.
+    let expected = match dirs::home_dir() {
+        Some(home) => format!("{} {}\n", home.display(), home.join("dir").display()),
+        None => String::from("~ ~/dir\n"),
+    };
+    assert_eq!(echo(None, "~ ~/dir"), expected);
+    assert_eq!(echo(Some(""), "~ ~/dir"), expected);
+}
+
+#[test]
+fn tilde_with_home() {
+    assert_eq!(echo(Some("/somewhere"), "~ ~/dir"), "/somewhere /somewhere/dir\n");
+}